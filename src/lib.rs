@@ -0,0 +1,7 @@
+//! Nebula, a GPU terminal emulator. The emulation itself can be used
+//! without a window or GPU through [`TermEmulator`]; build with
+//! `default-features = false` to leave out the `gui` feature and its
+//! windowing and rendering dependencies.
+pub mod terminal;
+
+pub use terminal::terminal::{Cell, CellColor, Cursor, TermEmulator};
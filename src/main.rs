@@ -1,6 +1,6 @@
-mod terminal;
+use nebula::terminal;
 
 #[tokio::main]
 async fn main() {
     terminal::run().expect("Terminal runtime error");
-}
\ No newline at end of file
+}
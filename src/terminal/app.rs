@@ -1,87 +1,1702 @@
 use anyhow::Result;
-use std::sync::{Arc, Mutex};
+use clap::Parser;
+use cosmic_text::FontSystem;
+use std::ffi::OsString;
+use std::ops::Range;
 use std::io::Write;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{atomic::Ordering, Arc, Mutex};
+use std::time::{Duration, Instant};
 use winit::{
-    event::{WindowEvent},
-    event_loop::{EventLoop, ActiveEventLoop},
-    window::WindowAttributes,
-    dpi::{LogicalSize},
+    event::{ElementState, Ime, MouseButton, MouseScrollDelta, WindowEvent},
+    event_loop::{EventLoop, ActiveEventLoop, ControlFlow, EventLoopProxy},
+    keyboard::{Key, ModifiersState, NamedKey},
+    window::{CursorIcon, UserAttentionType, WindowAttributes, WindowId},
+    dpi::{LogicalPosition, LogicalSize, PhysicalPosition},
 };
 use wgpu::{
-    Device, DeviceDescriptor, Features, Instance, Limits, Queue, SurfaceConfiguration,
+    Adapter, CompositeAlphaMode, Device, DeviceDescriptor, Features, Instance, Limits, Queue, SurfaceConfiguration,
     TextureFormat, TextureUsages, PresentMode,
 };
+use tracing::{error, info, trace, warn};
 
 use crate::terminal::{
-    config::{ATLAS_SIZE},
-    gpu::GpuResources,
-    input::handle_input,
-    render::render_frame,
+    bell,
+    capabilities,
+    cli::{Cli, Tool},
+    clipboard::{Clipboard, SharedClipboard},
+    colors::ColorSettings,
+    daemon::{self, DaemonServer, OpenRequest},
+    decorations::DecorationMetrics,
+    config::{BellStyle, Config, Geometry, PresentModeConfig, ATLAS_SIZE, CELL_WIDTH, LINE_HEIGHT, TAB_BAR_HEIGHT},
+    fonts::{self, Fonts},
+    gpu::{self, GpuResources},
+    highlight::Highlighter,
+    input::{handle_input, named_key, write_key},
+    journal::{self, Journal, Layout, TabLayout},
+    keybindings::{Action, Keymap},
+    latency::LatencyBench,
+    links::{self, link_at, LinkSpan},
+    mouse::{self, MouseAction, MouseEvent, MouseTracking, ReportButton},
+    logging,
+    magnifier::Magnifier,
+    mirror::Mirror,
+    notices,
+    overlay::{
+        ClosePrompt, CloseTarget, CommandPalette, HintEvent, HintsMode, ImePreedit, KeyTest, KeybindingList, ListEvent,
+        PaletteEvent, PaletteItem, PastePicker, PickerEvent, PromptEvent, RestorePrompt, PALETTE_COMMANDS,
+    },
+    post_process::{PostProcess, ANIMATION_INTERVAL},
+    raster::GlyphRasterizer,
+    terminfo,
+    render::{
+        notice_at, notice_banner_height, render_frame, render_mirror, scrollbar_thumb, tab_width, FrameBuffers, SCROLLBAR_FADE,
+        SCROLLBAR_WIDTH,
+    },
+    search::{SearchBar, SearchEvent},
+    selection::{Selection, SelectionKind},
+    shaper::ShaperPool,
+    software::SoftwareApp,
+    share::ShareServer,
+    signals::{signal_name, SignalWatcher},
+    smooth_scroll::SmoothScroll,
     texture::GlyphAtlas,
+    token,
+    touch::{TouchEvent, Touches},
+    usage::UsageMonitor,
+    web::WebServer,
     window::TerminalWindow,
-    Terminal,
+    Session,
     TerminalState,
+    UserEvent,
 };
 
+/// How often tab titles are re-derived from the foreground process.
+/// Termination signals are also only noticed this often while idle.
+const TITLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How far above or below the window a tab has to be dropped to move it to
+/// a window of its own.
+const TEAR_OFF_DISTANCE: f64 = TAB_BAR_HEIGHT as f64;
+/// Least time between bell sounds, so a stream of BELs doesn't start a
+/// stream of sound players.
+const BELL_SOUND_INTERVAL: Duration = Duration::from_millis(200);
+/// How far a pinch can shrink or enlarge the window's contents.
+const ZOOM_RANGE: std::ops::RangeInclusive<f64> = 0.5..=3.0;
+
+/// Shown when asked for the last command's output without shell
+/// integration marking it.
+const NO_COMMAND_OUTPUT: &str = "No command output to use; it is found from the shell's OSC 133 marks";
+const UNSUPPORTED_SEQUENCES: &str = "A program used escape sequences Nebula doesn't support";
+
+/// Button a mouse report names, for the buttons programs can hear about.
+fn report_button(button: MouseButton) -> Option<ReportButton> {
+    match button {
+        MouseButton::Left => Some(ReportButton::Left),
+        MouseButton::Middle => Some(ReportButton::Middle),
+        MouseButton::Right => Some(ReportButton::Right),
+        _ => None,
+    }
+}
+
+/// Start another Nebula process with `args`.
+fn spawn_nebula(args: &[OsString]) {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            error!("Failed to locate the Nebula executable: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = Command::new(exe).args(args).spawn() {
+        error!("Failed to open new window: {}", e);
+    }
+}
+
+/// Window size that fits `geometry` cells below the tab bar.
+fn window_size(geometry: Geometry) -> LogicalSize<f32> {
+    LogicalSize::new(
+        geometry.columns as f32 * CELL_WIDTH,
+        geometry.rows as f32 * LINE_HEIGHT + TAB_BAR_HEIGHT,
+    )
+}
+
+/// Whether `session` ran a one-shot command that has finished, so its tab
+/// closes.
+fn command_finished(session: &Session) -> bool {
+    session.terminal.exited.load(Ordering::SeqCst) && !session.terminal.config.shell.hold
+}
+
+/// Say once in the banner that the tabs' parsers skipped sequences. Each is
+/// in the log, which the notice links to.
+fn report_unsupported(sessions: &[Session]) {
+    let mut skipped = false;
+    for session in sessions {
+        skipped |= !session.terminal.take_unsupported().is_empty();
+    }
+    if !skipped {
+        return;
+    }
+    match logging::log_file() {
+        Some(path) => notices::warn_with_link(UNSUPPORTED_SEQUENCES, path.display().to_string()),
+        None => notices::warn(UNSUPPORTED_SEQUENCES),
+    }
+}
+
+/// Tabs in every main window, the current one's first.
+fn all_sessions_mut<'a>(sessions: &'a mut [Session], windows: &'a mut [WindowSlot]) -> impl Iterator<Item = &'a mut Session> {
+    sessions.iter_mut().chain(windows.iter_mut().flat_map(|slot| slot.sessions.iter_mut()))
+}
+
+/// A main window and its tabs, put aside while another is the current
+/// one. The app only works on the current window, so the others are
+/// swapped in for their events and for their share of `about_to_wait`.
+pub struct WindowSlot {
+    window: Option<TerminalWindow>,
+    config: SurfaceConfiguration,
+    sessions: Vec<Session>,
+    active_session: usize,
+    journal: Option<Journal>,
+    system_scale: f32,
+    zoom: f64,
+    scale_factor: f32,
+    local_dirty: bool,
+    focused: bool,
+    focused_session: Option<u64>,
+    mouse_position: Option<LogicalPosition<f64>>,
+    dragged_tab: Option<usize>,
+    selecting: bool,
+    dragging_scrollbar: bool,
+    touches: Touches,
+    wheel_lines: f64,
+    mouse_button: Option<ReportButton>,
+    mouse_cell: Option<(usize, usize)>,
+    motion_pending: bool,
+    bell_flash: Option<Instant>,
+    scrollbar_until: Option<Instant>,
+    smooth_scroll: SmoothScroll,
+    paste_picker: Option<PastePicker>,
+    search: Option<SearchBar>,
+    close_prompt: Option<ClosePrompt>,
+    restore_prompt: Option<RestorePrompt>,
+    keybinding_list: Option<KeybindingList>,
+    command_palette: Option<CommandPalette>,
+    hints: Option<HintsMode>,
+    key_test: Option<KeyTest>,
+    ime_preedit: Option<ImePreedit>,
+    ime_cursor_area: Option<(f32, f32)>,
+    hovered_link: Option<LinkSpan>,
+}
+
+impl WindowSlot {
+    fn new(window: TerminalWindow, config: SurfaceConfiguration, session: Session, journal: Option<Journal>) -> Self {
+        let system_scale = window.window.scale_factor() as f32;
+        Self {
+            window: Some(window),
+            config,
+            sessions: vec![session],
+            active_session: 0,
+            journal,
+            system_scale,
+            zoom: 1.0,
+            scale_factor: system_scale,
+            local_dirty: true,
+            focused: false,
+            focused_session: None,
+            mouse_position: None,
+            dragged_tab: None,
+            selecting: false,
+            dragging_scrollbar: false,
+            touches: Touches::default(),
+            wheel_lines: 0.0,
+            mouse_button: None,
+            mouse_cell: None,
+            motion_pending: false,
+            bell_flash: None,
+            scrollbar_until: None,
+            smooth_scroll: SmoothScroll::default(),
+            paste_picker: None,
+            search: None,
+            close_prompt: None,
+            restore_prompt: None,
+            keybinding_list: None,
+            command_palette: None,
+            hints: None,
+            key_test: None,
+            ime_preedit: None,
+            ime_cursor_area: None,
+            hovered_link: None,
+        }
+    }
+
+    fn id(&self) -> Option<WindowId> {
+        self.window.as_ref().map(|window| window.window.id())
+    }
+}
+
 pub struct TerminalApp {
     pub window: Option<TerminalWindow>,
     pub instance: Instance,
+    pub adapter: Adapter,
     pub config: SurfaceConfiguration,
     pub device: Device,
     pub queue: Queue,
     pub state: TerminalState,
-    pub input_writer: Arc<Mutex<dyn Write + Send>>,
-    pub _child_process: Arc<Mutex<Box<dyn portable_pty::Child + Send>>>, // Keep child process alive
+    pub user_config: Config,
+    pub keymap: Keymap,
+    pub clipboard: SharedClipboard,
+    pub sessions: Vec<Session>,
+    pub active_session: usize,
+    pub modifiers: ModifiersState,
+    /// Last mouse position. Can be outside the window while a button is
+    /// held.
+    pub mouse_position: Option<LogicalPosition<f64>>,
+    /// Tab being dragged with the left button, by its current index.
+    pub dragged_tab: Option<usize>,
+    /// The left button is held over the terminal, extending the selection.
+    pub selecting: bool,
+    /// The left button went down on the scrollbar, which follows the mouse
+    /// until it is released.
+    pub dragging_scrollbar: bool,
+    pub touches: Touches,
+    /// Scale factor the window system gives the window.
+    pub system_scale: f32,
+    /// How far pinching has zoomed in, multiplying `system_scale`. Applied
+    /// in steps of a tenth.
+    pub zoom: f64,
+    pub last_title_poll: Instant,
+    pub last_usage_poll: Instant,
+    /// Samples resource use for the badges while they are on.
+    pub usage_monitor: Option<UsageMonitor>,
+    /// Last input or output, for trimming GPU memory when idle.
+    pub last_activity: Instant,
+    /// GPU memory was trimmed since the last activity.
+    pub trimmed: bool,
+    /// Wheel movement in lines not yet scrolled, from touchpads that
+    /// report pixels.
+    pub wheel_lines: f64,
+    /// Button whose press was reported to the program, until its release.
+    pub mouse_button: Option<ReportButton>,
+    /// Cell of the last mouse report, so motion within a cell isn't sent.
+    pub mouse_cell: Option<(usize, usize)>,
+    /// The mouse moved since motion was last reported. Reports go out once
+    /// per batch of events, however many moves it held.
+    pub motion_pending: bool,
+    /// Session last told it has focus, for focus reporting.
+    pub focused_session: Option<u64>,
+    /// When the bell sound last played.
+    pub last_bell_sound: Option<Instant>,
+    /// Glyph atlas generation the row caches were built against.
+    pub atlas_generation: u64,
+    pub signals: SignalWatcher,
+    pub latency_bench: Option<LatencyBench>,
+    /// Last known layout, for restoring after a crash.
+    pub journal: Option<Journal>,
+    /// Handed to new sessions so their reader threads can wake the loop.
+    pub proxy: EventLoopProxy<UserEvent>,
+    /// Shortest time between frames, from `rendering.max_fps`.
+    pub frame_interval: Option<Duration>,
+    /// Read-only windows showing a tab, closed along with it.
+    pub mirrors: Vec<Mirror>,
+    /// Streams the active tab to remote viewers, with --share.
+    pub share: Option<ShareServer>,
+    /// Serves the active tab to web clients, with --web.
+    pub web: Option<WebServer>,
+    /// Opens tabs for other invocations, with --daemon.
+    pub daemon: Option<DaemonServer>,
+    /// The daemon's window is put away until an invocation asks for it.
+    pub hidden: bool,
+    /// Main windows other than `window`, opened in this process.
+    pub windows: Vec<WindowSlot>,
+    /// Windows opened so far, numbering their journals.
+    pub windows_opened: usize,
 }
 
-impl TerminalApp {
-    pub fn new(
-        window_attributes: WindowAttributes,
-        instance: Instance,
-        config: SurfaceConfiguration,
-        device: Device,
-        queue: Queue,
-        state: TerminalState,
-        input_writer: Arc<Mutex<dyn Write + Send>>,
-        child_process: Arc<Mutex<Box<dyn portable_pty::Child + Send>>>,
-    ) -> Self {
-        Self {
-            window: None,
-            instance,
-            config,
-            device,
-            queue,
-            state,
-            input_writer,
-            _child_process: child_process,
+impl TerminalApp {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        instance: Instance,
+        adapter: Adapter,
+        config: SurfaceConfiguration,
+        device: Device,
+        queue: Queue,
+        state: TerminalState,
+        user_config: Config,
+        session: Session,
+        proxy: EventLoopProxy<UserEvent>,
+    ) -> Self {
+        Self {
+            window: None,
+            instance,
+            adapter,
+            config,
+            device,
+            queue,
+            state,
+            keymap: Keymap::new(&user_config.keybindings),
+            frame_interval: user_config
+                .rendering
+                .max_fps
+                .filter(|&fps| fps > 0)
+                .map(|fps| Duration::from_secs_f64(1.0 / fps as f64)),
+            user_config,
+            clipboard: Arc::clone(&session.terminal.clipboard),
+            sessions: vec![session],
+            active_session: 0,
+            modifiers: ModifiersState::empty(),
+            mouse_position: None,
+            dragged_tab: None,
+            selecting: false,
+            dragging_scrollbar: false,
+            touches: Touches::default(),
+            system_scale: 1.0,
+            zoom: 1.0,
+            last_title_poll: Instant::now(),
+            last_usage_poll: Instant::now(),
+            usage_monitor: None,
+            last_activity: Instant::now(),
+            trimmed: false,
+            atlas_generation: 0,
+            wheel_lines: 0.0,
+            mouse_button: None,
+            mouse_cell: None,
+            motion_pending: false,
+            focused_session: None,
+            last_bell_sound: None,
+            signals: SignalWatcher::install(),
+            latency_bench: None,
+            journal: Journal::new(0),
+            proxy,
+            mirrors: Vec::new(),
+            share: None,
+            web: None,
+            daemon: None,
+            hidden: false,
+            windows: Vec::new(),
+            windows_opened: 1,
+        }
+    }
+
+    fn active(&self) -> &Session {
+        &self.sessions[self.active_session]
+    }
+
+    /// Keybindings for the active tab, which a session rule can change.
+    fn keymap(&self) -> &Keymap {
+        self.active().overrides().and_then(|overrides| overrides.keymap.as_ref()).unwrap_or(&self.keymap)
+    }
+
+    fn active_mut(&mut self) -> &mut Session {
+        &mut self.sessions[self.active_session]
+    }
+
+    fn open_tab(&mut self, config: Config, cwd: Option<PathBuf>) {
+        match Session::spawn(config, Arc::clone(&self.clipboard), cwd, self.proxy.clone()) {
+            Ok(mut session) => {
+                session.set_theme(self.state.theme);
+                self.sessions.push(session);
+                self.active_session = self.sessions.len() - 1;
+                // The tab bar may have just appeared
+                self.fit_sessions();
+            }
+            Err(e) => notices::warn(format!("Failed to open new tab: {}", e)),
+        }
+    }
+
+    /// Exchange the current window with `slot`.
+    fn swap_window(&mut self, slot: &mut WindowSlot) {
+        use std::mem::swap;
+        swap(&mut self.window, &mut slot.window);
+        swap(&mut self.config, &mut slot.config);
+        swap(&mut self.sessions, &mut slot.sessions);
+        swap(&mut self.active_session, &mut slot.active_session);
+        swap(&mut self.journal, &mut slot.journal);
+        swap(&mut self.system_scale, &mut slot.system_scale);
+        swap(&mut self.zoom, &mut slot.zoom);
+        swap(&mut self.focused_session, &mut slot.focused_session);
+        swap(&mut self.mouse_position, &mut slot.mouse_position);
+        swap(&mut self.dragged_tab, &mut slot.dragged_tab);
+        swap(&mut self.selecting, &mut slot.selecting);
+        swap(&mut self.dragging_scrollbar, &mut slot.dragging_scrollbar);
+        swap(&mut self.touches, &mut slot.touches);
+        swap(&mut self.wheel_lines, &mut slot.wheel_lines);
+        swap(&mut self.mouse_button, &mut slot.mouse_button);
+        swap(&mut self.mouse_cell, &mut slot.mouse_cell);
+        swap(&mut self.motion_pending, &mut slot.motion_pending);
+        let state = &mut self.state;
+        swap(&mut state.scale_factor, &mut slot.scale_factor);
+        swap(&mut state.local_dirty, &mut slot.local_dirty);
+        swap(&mut state.focused, &mut slot.focused);
+        swap(&mut state.bell_flash, &mut slot.bell_flash);
+        swap(&mut state.scrollbar_until, &mut slot.scrollbar_until);
+        swap(&mut state.smooth_scroll, &mut slot.smooth_scroll);
+        swap(&mut state.paste_picker, &mut slot.paste_picker);
+        swap(&mut state.search, &mut slot.search);
+        swap(&mut state.close_prompt, &mut slot.close_prompt);
+        swap(&mut state.restore_prompt, &mut slot.restore_prompt);
+        swap(&mut state.keybinding_list, &mut slot.keybinding_list);
+        swap(&mut state.command_palette, &mut slot.command_palette);
+        swap(&mut state.hints, &mut slot.hints);
+        swap(&mut state.key_test, &mut slot.key_test);
+        swap(&mut state.ime_preedit, &mut slot.ime_preedit);
+        swap(&mut state.ime_cursor_area, &mut slot.ime_cursor_area);
+        swap(&mut state.hovered_link, &mut slot.hovered_link);
+    }
+
+    fn current_window_id(&self) -> Option<WindowId> {
+        self.window.as_ref().map(|window| window.window.id())
+    }
+
+    /// Make window `id` the current one, if it is one of ours.
+    fn make_current(&mut self, id: WindowId) {
+        if self.current_window_id() == Some(id) {
+            return;
+        }
+        let Some(index) = self.windows.iter().position(|slot| slot.id() == Some(id)) else { return };
+        let mut slot = self.windows.swap_remove(index);
+        self.swap_window(&mut slot);
+        self.windows.push(slot);
+        let last = self.windows.len() - 1;
+        self.windows.swap(index, last);
+    }
+
+    /// A new window sized for the configured geometry, with a surface set
+    /// up like the current one's.
+    fn create_window(&self, event_loop: &ActiveEventLoop, title: &str) -> Option<(TerminalWindow, SurfaceConfiguration)> {
+        let attributes = WindowAttributes::default()
+            .with_title(title)
+            .with_inner_size(window_size(self.user_config.geometry))
+            .with_transparent(self.state.background_opacity < 1.0)
+            .with_blur(self.user_config.window.blur);
+        let window = match TerminalWindow::new(event_loop, attributes, &self.instance) {
+            Ok(window) => window,
+            Err(e) => {
+                notices::warn(format!("Failed to open new window: {}", e));
+                return None;
+            }
+        };
+        let size = window.window.inner_size();
+        let mut config = self.config.clone();
+        config.width = size.width.max(1);
+        config.height = size.height.max(1);
+        if !window.surface.get_capabilities(&self.adapter).present_modes.contains(&config.present_mode) {
+            config.present_mode = PresentMode::Fifo;
+        }
+        window.configure_surface(&self.device, &config);
+        window.window.set_ime_allowed(true);
+        Some((window, config))
+    }
+
+    /// Make `window` the current window, showing `session` as its one tab.
+    fn add_window(&mut self, window: TerminalWindow, config: SurfaceConfiguration, mut session: Session) {
+        session.set_theme(self.state.theme);
+        session.row_cache = Default::default();
+        let journal = Journal::new(self.windows_opened);
+        self.windows_opened += 1;
+        let mut slot = WindowSlot::new(window, config, session, journal);
+        self.swap_window(&mut slot);
+        self.windows.push(slot);
+        self.state.scale_factor = self.system_scale * rounded_zoom(self.zoom);
+        self.fit_sessions();
+    }
+
+    /// Open a window with a tab from `config` in `cwd`.
+    fn open_window(&mut self, event_loop: &ActiveEventLoop, config: Config, cwd: Option<PathBuf>) -> bool {
+        let session = match Session::spawn(config, Arc::clone(&self.clipboard), cwd, self.proxy.clone()) {
+            Ok(session) => session,
+            Err(e) => {
+                notices::warn(format!("Failed to open new window: {}", e));
+                return false;
+            }
+        };
+        let Some((window, config)) = self.create_window(event_loop, &session.window_title()) else {
+            session.shutdown();
+            return false;
+        };
+        self.add_window(window, config, session);
+        true
+    }
+
+    /// Close the current window and its tabs, making another current. The
+    /// last window is hidden with --daemon; otherwise closing it exits.
+    fn close_window(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(mut slot) = self.windows.pop() else {
+            if self.daemon.is_some() {
+                self.hide_window(event_loop);
+            } else {
+                self.shutdown(event_loop);
+            }
+            return;
+        };
+        self.swap_window(&mut slot);
+        for session in &slot.sessions {
+            session.shutdown();
+        }
+        if let Some(journal) = slot.journal.take() {
+            journal.remove();
+        }
+        self.state.local_dirty = true;
+    }
+
+    /// Size of the window in logical pixels, which cells are measured in.
+    fn logical_size(&self) -> (f32, f32) {
+        let scale = self.state.scale_factor;
+        (self.config.width as f32 / scale, self.config.height as f32 / scale)
+    }
+
+    /// Size every tab's grid to the cells that fit in the window.
+    fn fit_sessions(&self) {
+        let content_top = if self.sessions.len() > 1 { TAB_BAR_HEIGHT } else { 0.0 };
+        let (width, height) = self.logical_size();
+        let cols = (width / CELL_WIDTH) as usize;
+        let rows = ((height - content_top) / LINE_HEIGHT) as usize;
+        for session in &self.sessions {
+            session.terminal.resize(cols, rows);
+        }
+    }
+
+    /// Composite the surface with the desktop when the config asks for a
+    /// translucent background and `window`'s surface can do it.
+    fn choose_alpha_mode(&mut self, window: &TerminalWindow) {
+        let opacity = self.user_config.window.opacity.clamp(0.0, 1.0);
+        if opacity >= 1.0 {
+            return;
+        }
+        let modes = window.surface.get_capabilities(&self.adapter).alpha_modes;
+        let mode = [CompositeAlphaMode::PreMultiplied, CompositeAlphaMode::PostMultiplied]
+            .into_iter()
+            .find(|mode| modes.contains(mode));
+        match mode {
+            Some(mode) => {
+                info!("Drawing the background at {:.0}% opacity ({:?})", opacity * 100.0, mode);
+                self.config.alpha_mode = mode;
+                self.state.background_opacity = opacity;
+                self.state.premultiplied_alpha = mode == CompositeAlphaMode::PreMultiplied;
+            }
+            None => notices::warn("Background opacity isn't supported here; the window stays opaque"),
+        }
+    }
+
+    /// Use the configured present mode if the window supports it, or the
+    /// closest one it does.
+    fn choose_present_mode(&mut self, window: &TerminalWindow) {
+        let wanted = self.user_config.rendering.present_mode;
+        let supported = window.surface.get_capabilities(&self.adapter).present_modes;
+        let candidates = wanted.candidates();
+        let mode = candidates
+            .iter()
+            .copied()
+            .find(|mode| supported.contains(mode))
+            .unwrap_or(PresentMode::Fifo);
+        if mode != candidates[0] {
+            notices::warn(format!("Present mode {:?} isn't supported here; using {:?}", candidates[0], mode));
+        }
+        info!("Presenting frames with {:?}", mode);
+        self.config.present_mode = mode;
+    }
+
+    /// Pick the configured theme matching the OS appearance and apply it to
+    /// every tab in every window.
+    fn apply_theme(&mut self, system: Option<winit::window::Theme>) {
+        let system_dark = system.map(|theme| theme == winit::window::Theme::Dark);
+        let theme = self.user_config.theme.resolve(system_dark);
+        if theme == self.state.theme {
+            return;
+        }
+        self.state.theme = theme;
+        for session in all_sessions_mut(&mut self.sessions, &mut self.windows) {
+            session.set_theme(theme);
+        }
+        for slot in &mut self.windows {
+            slot.local_dirty = true;
+        }
+        self.state.local_dirty = true;
+    }
+
+    /// Find the link under the mouse while Ctrl is held, updating the
+    /// underline and mouse cursor when it changes.
+    fn update_hovered_link(&mut self) {
+        let content_top = if self.sessions.len() > 1 { TAB_BAR_HEIGHT } else { 0.0 };
+        let hovered = self
+            .mouse_position
+            .filter(|position| self.modifiers.control_key() && position.y as f32 >= content_top)
+            .and_then(|position| {
+                let row = ((position.y as f32 - content_top) / LINE_HEIGHT) as usize;
+                let col = (position.x as f32 / CELL_WIDTH) as usize;
+                link_at(&self.active().last_links, row, col).cloned()
+            });
+        if hovered == self.state.hovered_link {
+            return;
+        }
+
+        if let Some(window) = &self.window {
+            let icon = if hovered.is_some() { CursorIcon::Pointer } else { CursorIcon::Default };
+            window.window.set_cursor(icon);
+        }
+        self.state.hovered_link = hovered;
+        self.state.local_dirty = true;
+    }
+
+    /// Link of the notice under the mouse, if it has one.
+    fn notice_link_at_mouse(&self) -> Option<String> {
+        let content_top = if self.sessions.len() > 1 { TAB_BAR_HEIGHT } else { 0.0 };
+        let y = self.mouse_position?.y as f32 - content_top;
+        let index = notice_at(y, self.state.notices.len())?;
+        self.state.notices[index].link.clone()
+    }
+
+    /// Whether the mouse is over the notice banner.
+    fn notice_banner_hit(&self) -> bool {
+        if self.state.notices.is_empty() {
+            return false;
+        }
+        let content_top = if self.sessions.len() > 1 { TAB_BAR_HEIGHT } else { 0.0 };
+        let height = notice_banner_height(self.state.notices.len());
+        self.mouse_position
+            .is_some_and(|position| (content_top..content_top + height).contains(&(position.y as f32)))
+    }
+
+    /// The tab under the mouse, if the tab bar is shown.
+    fn tab_at_mouse(&self) -> Option<usize> {
+        let position = self.mouse_position?;
+        if self.sessions.len() < 2 || !(0.0..TAB_BAR_HEIGHT as f64).contains(&position.y) || position.x < 0.0 {
+            return None;
+        }
+        let index = (position.x as f32 / tab_width(self.logical_size().0, self.sessions.len())) as usize;
+        (index < self.sessions.len()).then_some(index)
+    }
+
+    /// Follow the mouse with the dragged tab while it is over the tab bar.
+    fn drag_tab(&mut self) {
+        let (Some(from), Some(position)) = (self.dragged_tab, self.mouse_position) else { return };
+        if !(0.0..TAB_BAR_HEIGHT as f64).contains(&position.y) {
+            return;
+        }
+        let tab_width = tab_width(self.logical_size().0, self.sessions.len());
+        let to = ((position.x.max(0.0) as f32 / tab_width) as usize).min(self.sessions.len() - 1);
+        if to == from {
+            return;
+        }
+        let session = self.sessions.remove(from);
+        self.sessions.insert(to, session);
+        // The dragged tab is the active one
+        self.active_session = to;
+        self.dragged_tab = Some(to);
+        self.state.local_dirty = true;
+    }
+
+    /// Whether the mouse is over the scrollbar while it is shown.
+    fn scrollbar_hit(&self) -> bool {
+        let content_top = if self.sessions.len() > 1 { TAB_BAR_HEIGHT } else { 0.0 };
+        let width = self.logical_size().0;
+        self.state.scrollbar_until.is_some()
+            && self.mouse_position.is_some_and(|position| {
+                position.x as f32 >= width - SCROLLBAR_WIDTH && position.y as f32 >= content_top
+            })
+    }
+
+    /// Scroll so the scrollbar thumb is centred under the mouse.
+    fn drag_scrollbar(&mut self) {
+        let Some(position) = self.mouse_position.filter(|_| self.dragging_scrollbar) else { return };
+        let content_top = if self.sessions.len() > 1 { TAB_BAR_HEIGHT } else { 0.0 };
+        let session = self.active();
+        let rows = session.rows.len();
+        let history = session.scrollback.len();
+        let track = rows as f32 * LINE_HEIGHT;
+        let thumb = scrollbar_thumb(track, rows, history);
+        let fraction = ((position.y as f32 - content_top - thumb / 2.0) / (track - thumb).max(1.0)).clamp(0.0, 1.0);
+        let line = session.scrollback.start + (fraction * history as f32).round() as usize;
+        session.terminal.scroll_view_to(line);
+        self.state.local_dirty = true;
+    }
+
+    /// Zoom the window's contents by `factor` more, as pinching does.
+    fn zoom_by(&mut self, factor: f64) {
+        if !factor.is_finite() {
+            return;
+        }
+        let before = rounded_zoom(self.zoom);
+        self.zoom = (self.zoom * factor).clamp(*ZOOM_RANGE.start(), *ZOOM_RANGE.end());
+        if rounded_zoom(self.zoom) == before {
+            return;
+        }
+        self.state.scale_factor = self.system_scale * rounded_zoom(self.zoom);
+        self.fit_sessions();
+        self.state.local_dirty = true;
+    }
+
+    /// Start selecting from the cell under the mouse, a block of columns
+    /// if Alt is held.
+    fn start_selection(&mut self) {
+        let Some((col, row)) = self.mouse_cell() else { return };
+        let kind = if self.modifiers.alt_key() { SelectionKind::Block } else { SelectionKind::Linear };
+        let session = self.active_mut();
+        session.selection = Some(Selection::new((session.last_view_top + row, col), kind));
+        self.selecting = true;
+        self.state.local_dirty = true;
+    }
+
+    /// Move the end of the selection being dragged to the mouse.
+    fn extend_selection(&mut self) {
+        if !self.selecting {
+            return;
+        }
+        let Some((col, row)) = self.mouse_cell() else { return };
+        let session = self.active_mut();
+        let head = (session.last_view_top + row, col);
+        if let Some(selection) = session.selection.as_mut().filter(|selection| selection.head != head) {
+            selection.head = head;
+            self.state.local_dirty = true;
+        }
+    }
+
+    /// Let go of the selection. A click without a drag clears it; anything
+    /// selected goes to the primary selection.
+    fn finish_selection(&mut self) {
+        if !std::mem::take(&mut self.selecting) {
+            return;
+        }
+        let primary = self.user_config.clipboard.primary_selection;
+        let session = self.active_mut();
+        match session.selection {
+            Some(selection) if selection.is_empty() => {
+                session.selection = None;
+                self.state.local_dirty = true;
+            }
+            Some(selection) if primary => {
+                let text = session.terminal.selected_text(&selection);
+                if !text.is_empty() {
+                    self.clipboard.lock().unwrap().set_primary(text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Finish dragging a tab. Dropped well above or below the window, it
+    /// moves to a new window of its own, program and all.
+    fn drop_tab(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(index) = self.dragged_tab.take() else { return };
+        let height = self.logical_size().1 as f64;
+        let outside = self.mouse_position.is_none_or(|position| {
+            position.y < -TEAR_OFF_DISTANCE || position.y > height + TEAR_OFF_DISTANCE
+        });
+        if !outside || self.sessions.len() < 2 {
+            return;
+        }
+        let Some((window, config)) = self.create_window(event_loop, &self.sessions[index].window_title()) else {
+            return;
+        };
+        let session = self.sessions.remove(index);
+        if index < self.active_session {
+            self.active_session -= 1;
+        }
+        self.active_session = self.active_session.min(self.sessions.len() - 1);
+        self.fit_sessions();
+        if let Some(window) = &self.window {
+            window.window.set_title(&self.active().window_title());
+        }
+        self.state.local_dirty = true;
+        self.add_window(window, config, session);
+    }
+
+    /// Open a read-only window showing the active tab, or with `follow` its
+    /// newest output.
+    fn open_mirror(&mut self, event_loop: &ActiveEventLoop, follow: bool) {
+        let Some(main) = &self.window else { return };
+        let size = main.window.inner_size();
+        let session = self.active();
+        let attributes = WindowAttributes::default()
+            .with_inner_size(size)
+            .with_transparent(self.state.background_opacity < 1.0)
+            .with_blur(self.user_config.window.blur);
+        match TerminalWindow::new(event_loop, attributes, &self.instance) {
+            Ok(window) => {
+                let mut config = self.config.clone();
+                config.width = size.width.max(1);
+                config.height = size.height.max(1);
+                if !window.surface.get_capabilities(&self.adapter).present_modes.contains(&config.present_mode) {
+                    config.present_mode = PresentMode::Fifo;
+                }
+                window.configure_surface(&self.device, &config);
+                info!("Mirroring session {}", session.id);
+                let mirror = Mirror::new(window, config, session.id, follow);
+                mirror.window.window.set_title(&mirror.title(&session.window_title()));
+                self.mirrors.push(mirror);
+                self.update_follow_tails();
+            }
+            Err(e) => error!("Failed to create mirror window: {}", e),
+        }
+    }
+
+    /// Handle an event for one of the mirror windows. They take no input,
+    /// only resizes, redraws and closing.
+    fn mirror_event(&mut self, index: usize, event: WindowEvent) {
+        match event {
+            WindowEvent::Resized(size) => {
+                let mirror = &mut self.mirrors[index];
+                mirror.window.handle_resize(&self.device, &mut mirror.config, size);
+                // Rows are laid out again for the new size
+                mirror.row_cache = Default::default();
+            }
+            WindowEvent::RedrawRequested => {
+                let mirror = &mut self.mirrors[index];
+                let mut sessions = self.sessions.iter().chain(self.windows.iter().flat_map(|slot| &slot.sessions));
+                if let Some(session) = sessions.find(|session| session.id == mirror.session) {
+                    render_mirror(&self.device, &self.queue, mirror, &mut self.state, session);
+                }
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                let mirror = &mut self.mirrors[index];
+                mirror.scale = scale_factor as f32;
+                mirror.window.window.request_redraw();
+            }
+            WindowEvent::CloseRequested => {
+                self.mirrors.remove(index);
+                self.update_follow_tails();
+            }
+            _ => {}
+        }
+    }
+
+    /// Keep mirror windows in step with the tabs they show.
+    fn update_mirrors(&mut self, shaped: bool, check_title: bool) {
+        // A mirror goes away with its tab
+        let ids: Vec<u64> = all_sessions_mut(&mut self.sessions, &mut self.windows).map(|session| session.id).collect();
+        self.mirrors.retain(|mirror| ids.contains(&mirror.session));
+        for mirror in &mut self.mirrors {
+            let mut sessions = all_sessions_mut(&mut self.sessions, &mut self.windows);
+            let Some(session) = sessions.find(|session| session.id == mirror.session) else {
+                continue;
+            };
+            // The active tab was polled already; others are only polled
+            // while something shows them
+            session.poll();
+            if shaped || mirror.drawn_generation != Some(session.snapshot_generation) {
+                mirror.window.window.request_redraw();
+            }
+            if check_title {
+                mirror.window.window.set_title(&mirror.title(&session.window_title()));
+            }
+        }
+    }
+
+    /// Have tabs send their live screen while scrolled back only when a
+    /// follow window shows them.
+    fn update_follow_tails(&self) {
+        for session in self.sessions.iter().chain(self.windows.iter().flat_map(|slot| &slot.sessions)) {
+            let followed = self.mirrors.iter().any(|mirror| mirror.follow && mirror.session == session.id);
+            session.terminal.set_follow_tail(followed);
+        }
+    }
+
+    /// Where the tabs are, leaving out private ones.
+    fn layout(&self) -> Layout {
+        let mut layout = Layout::default();
+        for (index, session) in self.sessions.iter().enumerate() {
+            // Shared windows viewed from here aren't ours to restore
+            if session.is_private() || session.child_process.is_none() {
+                continue;
+            }
+            if index == self.active_session {
+                layout.active = layout.tabs.len();
+            }
+            layout.tabs.push(TabLayout { cwd: session.terminal.current_dir() });
+        }
+        layout
+    }
+
+    fn open_layout_tabs(&mut self, tabs: &[TabLayout]) {
+        for tab in tabs {
+            self.open_tab(self.user_config.clone(), tab.cwd.clone());
+        }
+    }
+
+    /// Reopen windows left by a crash: the first in this window, replacing
+    /// the tab it started with if that is idle, the others in new windows.
+    fn restore(&mut self, layouts: Vec<Layout>, event_loop: &ActiveEventLoop) {
+        let mut layouts = layouts.into_iter();
+        let Some(first) = layouts.next() else { return };
+        for layout in layouts {
+            match journal::hand_off(&layout) {
+                Ok(path) => spawn_nebula(&["--restore-layout".into(), path.into()]),
+                Err(e) => error!("Failed to hand a layout to a new window: {}", e),
+            }
+        }
+
+        let replace = self.sessions.len() == 1 && self.active().running_command().is_none();
+        let mut start = self.sessions.len();
+        self.open_layout_tabs(&first.tabs);
+        if replace && self.sessions.len() > start {
+            self.close_tab(0, event_loop);
+            start -= 1;
+        }
+        self.active_session = (start + first.active).min(self.sessions.len() - 1);
+        if let Some(window) = &self.window {
+            window.window.set_title(&self.active().window_title());
+        }
+    }
+
+    fn perform(&mut self, action: Action, event_loop: &ActiveEventLoop) {
+        match action {
+            Action::NewTab => self.open_tab(self.user_config.clone(), None),
+            Action::NewPrivateTab => {
+                let mut config = self.user_config.clone();
+                config.private = true;
+                self.open_tab(config, None);
+            }
+            Action::NewTabHere => {
+                // Private tabs stay private when opened from one
+                let mut config = self.user_config.clone();
+                config.private = self.active().is_private();
+                let cwd = self.active().terminal.current_dir();
+                self.open_tab(config, cwd);
+            }
+            Action::NewWindowHere => {
+                let mut config = self.user_config.clone();
+                config.private = self.active().is_private();
+                let cwd = self.active().terminal.current_dir();
+                self.open_window(event_loop, config, cwd);
+            }
+            Action::CloseTab => {
+                self.request_close(CloseTarget::Tab, event_loop);
+                if self.sessions.is_empty() {
+                    return;
+                }
+            }
+            Action::NextTab => {
+                self.active_session = (self.active_session + 1) % self.sessions.len();
+            }
+            Action::PreviousTab => {
+                self.active_session =
+                    (self.active_session + self.sessions.len() - 1) % self.sessions.len();
+            }
+            Action::Copy => {
+                let session = self.active();
+                let text = match &session.selection {
+                    Some(selection) => session.terminal.selected_text(selection),
+                    None => {
+                        let top = session.last_view_top;
+                        let rows = session.rows.len();
+                        session.terminal.logical_text(top..top + rows)
+                    }
+                };
+                let text = text.trim_end_matches('\n').to_string();
+                if !text.is_empty() {
+                    self.clipboard.lock().unwrap().copy(text, session.is_private());
+                }
+            }
+            Action::Paste => {
+                let text = self.clipboard.lock().unwrap().get_text();
+                if let Some(text) = text {
+                    self.active_mut().paste(&text);
+                }
+            }
+            Action::PastePicker => {
+                let history = self.clipboard.lock().unwrap().history();
+                self.state.paste_picker = Some(PastePicker::new(history));
+            }
+            Action::PasteMenu => {
+                let names = self.user_config.paste_filter.iter().map(|filter| filter.name.clone()).collect();
+                self.state.paste_picker = Some(PastePicker::filters(names));
+            }
+            Action::PasteAsTyped => {
+                let text = self.clipboard.lock().unwrap().get_text();
+                let delay = Duration::from_secs_f32(self.user_config.clipboard.typed_paste_delay.max(0.0));
+                if let Some(text) = text {
+                    self.active_mut().paste_as_typed(&text, delay);
+                }
+            }
+            Action::Search => self.state.search = Some(SearchBar::new()),
+            Action::ClearScrollback => self.active().terminal.clear_scrollback(),
+            Action::ShowKeybindings => self.state.keybinding_list = Some(KeybindingList::new(self.keymap())),
+            Action::ToggleMagnifier => self.state.magnifier.toggle(),
+            Action::ReloadFonts => self.reload_fonts(),
+            Action::MirrorTab => self.open_mirror(event_loop, false),
+            Action::FollowTab => self.open_mirror(event_loop, true),
+            Action::PreviousPrompt => self.active().terminal.jump_to_prompt(false),
+            Action::NextPrompt => self.active().terminal.jump_to_prompt(true),
+            Action::CopyLastOutput => {
+                let session = self.active();
+                match session.terminal.last_command_output() {
+                    Some(text) => self.clipboard.lock().unwrap().copy(text, session.is_private()),
+                    None => notices::warn(NO_COMMAND_OUTPUT),
+                }
+            }
+            Action::PageLastOutput => self.page_last_output(),
+            Action::Hints => self.start_hints(),
+            Action::BlockSelection => {
+                if let Some(selection) = &mut self.active_mut().selection {
+                    selection.kind = match selection.kind {
+                        SelectionKind::Linear => SelectionKind::Block,
+                        SelectionKind::Block => SelectionKind::Linear,
+                    };
+                    self.state.local_dirty = true;
+                }
+            }
+            Action::CommandPalette => {
+                let commands = self.active().terminal.recent_commands(PALETTE_COMMANDS);
+                self.state.command_palette = Some(CommandPalette::new(self.keymap(), commands));
+            }
+        }
+
+        if let Some(window) = &self.window {
+            window.window.set_title(&self.active().window_title());
+            window.window.request_redraw();
+        }
+        self.state.local_dirty = true;
+    }
+
+    /// Label the links, paths and hashes in view of the active tab, in
+    /// reading order.
+    fn start_hints(&mut self) {
+        let session = self.active();
+        let mut targets: Vec<(usize, Range<usize>, String, bool)> = Vec::new();
+        for link in &session.last_links {
+            // An OSC 8 link split across rows is labelled once
+            if !targets.iter().any(|(_, _, uri, _)| **uri == *link.uri && link.hyperlink.is_some()) {
+                targets.push((link.row, link.columns.clone(), link.uri.to_string(), true));
+            }
+        }
+        for (row, text) in session.rows.iter().enumerate() {
+            for (columns, hash) in links::find_hashes(text) {
+                let overlaps = |(other_row, other, _, _): &(usize, Range<usize>, String, bool)| {
+                    *other_row == row && other.start < columns.end && columns.start < other.end
+                };
+                if !targets.iter().any(overlaps) {
+                    targets.push((row, columns, hash, false));
+                }
+            }
+        }
+        if targets.is_empty() {
+            notices::warn("No links, paths or hashes in view to label");
+            return;
+        }
+        targets.sort_by_key(|(row, columns, _, _)| (*row, columns.start));
+        self.state.hints = Some(HintsMode::new(targets));
+    }
+
+    /// Open a link clicked in the active tab. Without an editor in the
+    /// config, files open in $VISUAL or $EDITOR in a new tab.
+    fn open_link(&mut self, uri: &str) {
+        let cwd = self.active().terminal.current_dir();
+        if self.user_config.editor.is_empty() {
+            let file = links::local_file(uri, cwd.as_deref()).filter(|file| file.path.is_file());
+            if let Some(command) = file.as_ref().and_then(links::terminal_editor) {
+                let mut config = self.user_config.clone();
+                config.private = self.active().is_private();
+                config.shell.program = Some(command[0].clone());
+                config.shell.args = command[1..].to_vec();
+                config.shell.restart = false;
+                config.shell.hold = false;
+                self.open_tab(config, cwd);
+                return;
+            }
+        }
+        links::open(uri, &self.user_config.editor, cwd.as_deref());
+    }
+
+    /// Open the last command's output in $PAGER, or `less`, in a new tab.
+    /// The pager reads it from a temporary file, removed when its tab
+    /// closes, so private tabs can't.
+    fn page_last_output(&mut self) {
+        let session = self.active();
+        if session.is_private() {
+            notices::warn("Private tabs don't write output to disk for a pager to read");
+            return;
+        }
+        let Some(text) = session.terminal.last_command_output() else {
+            notices::warn(NO_COMMAND_OUTPUT);
+            return;
+        };
+        let cwd = session.terminal.current_dir();
+        let file = tempfile::Builder::new()
+            .prefix("nebula-output-")
+            .suffix(".txt")
+            .tempfile()
+            .and_then(|mut file| file.write_all(text.as_bytes()).and(Ok(file)));
+        let file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                notices::warn(format!("Failed to save the output for the pager: {}", e));
+                return;
+            }
+        };
+        let pager = std::env::var("PAGER").unwrap_or_default();
+        let mut words = pager.split_whitespace().map(String::from);
+        let mut config = self.user_config.clone();
+        config.shell.program = Some(words.next().unwrap_or_else(|| String::from("less")));
+        config.shell.args = words.chain([file.path().display().to_string()]).collect();
+        config.shell.restart = false;
+        config.shell.hold = false;
+        let tabs = self.sessions.len();
+        self.open_tab(config, cwd);
+        if self.sessions.len() > tabs {
+            self.active_mut().temp_file = Some(file);
+        }
+    }
+
+    /// Load fonts installed since startup and resolve the configured ones
+    /// again, so a font that was missing is used without a restart.
+    fn reload_fonts(&mut self) {
+        let mut fs = self.state.font_system.lock().unwrap();
+        let mut db = fs.db().clone();
+        let added = fonts::load_new_fonts(&mut db);
+        info!("Reloading fonts, {} new font files", added);
+
+        let fonts = Fonts::resolve(&self.user_config.font, &db);
+        let locale = fs.locale().to_string();
+        self.state.shaper = ShaperPool::new(&locale, &db, &fonts, self.proxy.clone());
+        *fs = fonts.font_system(locale, db);
+        self.state.decoration_metrics = DecorationMetrics::measure(&mut fs, &fonts);
+        self.state.fonts = fonts;
+        drop(fs);
+
+        // Fallback may pick other fonts now, so every row is shaped again
+        for session in all_sessions_mut(&mut self.sessions, &mut self.windows) {
+            session.row_cache = Default::default();
+        }
+        for mirror in &mut self.mirrors {
+            mirror.row_cache = Default::default();
+            mirror.window.window.request_redraw();
+        }
+    }
+
+    /// Input or output happened: the cursor shows again and the idle
+    /// timer restarts.
+    fn note_activity(&mut self) {
+        self.last_activity = Instant::now();
+        self.trimmed = false;
+        if !self.state.cursor_visible {
+            self.state.cursor_visible = true;
+            self.state.local_dirty = true;
+        }
+        self.state.last_blink = self.last_activity;
+    }
+
+    /// The cursor on screen would blink: blinking is on, the window has
+    /// focus and the program didn't hide it or ask for a steady cursor.
+    fn cursor_blinks(&self) -> bool {
+        self.state.cursor_blink
+            && self.state.focused
+            && self.state.ime_preedit.is_none()
+            && !self.active().terminal.modes.lock().unwrap().cursor_hidden
+            && self.active().terminal.cursor_style.lock().unwrap().blinking
+    }
+
+    /// Ring the bell the way the config asks.
+    fn ring_bell(&mut self) {
+        let bell = self.user_config.bell;
+        match bell.style {
+            BellStyle::System => {
+                if self.last_bell_sound.is_none_or(|last| last.elapsed() >= BELL_SOUND_INTERVAL) {
+                    self.last_bell_sound = Some(Instant::now());
+                    bell::system_beep();
+                }
+            }
+            BellStyle::Visual => {
+                self.state.bell_flash = Some(Instant::now() + Duration::from_secs_f32(bell.flash_duration.max(0.0)));
+                self.state.local_dirty = true;
+            }
+            BellStyle::Urgent => {
+                if let (false, Some(window)) = (self.state.focused, &self.window) {
+                    window.window.request_user_attention(Some(UserAttentionType::Informational));
+                }
+            }
+            BellStyle::None => {}
+        }
+    }
+
+    /// Release GPU memory held for drawing after a stretch without input
+    /// or output. The visible tab keeps its rows, which are drawn again at
+    /// the next blink; everything else is allocated again when next drawn.
+    fn trim_gpu_memory(&mut self) {
+        let atlas = self.user_config.idle_trim.atlas;
+        info!("Idle, releasing GPU buffers{}", if atlas { " and the glyph atlas" } else { "" });
+        self.trimmed = true;
+        self.state.frame_buffers.release();
+        for mirror in &mut self.mirrors {
+            mirror.release_buffers();
+        }
+        for (index, session) in self.sessions.iter_mut().enumerate() {
+            if index != self.active_session {
+                session.row_cache = Default::default();
+            }
+        }
+        for session in self.windows.iter_mut().flat_map(|slot| &mut slot.sessions) {
+            session.row_cache = Default::default();
+        }
+        if atlas {
+            self.state.glyph_atlas.shrink(ATLAS_SIZE / 4);
+        }
+    }
+
+    /// Scroll the scrollback with the wheel or, on the alternate screen, send
+    /// arrow keys for the program to scroll with.
+    fn scroll_wheel(&mut self, delta: MouseScrollDelta) {
+        let mouse = self.user_config.mouse;
+        // Programs tracking the mouse get one wheel press per notch
+        let reporting = self.mouse_tracking().is_some_and(|tracking| tracking != MouseTracking::X10);
+        let per_notch = if reporting { 1.0 } else { mouse.wheel_lines as f64 };
+        // Whole lines are scrolled as touchpad pixels add up to them
+        self.wheel_lines += match delta {
+            MouseScrollDelta::LineDelta(_, y) => y as f64 * per_notch,
+            MouseScrollDelta::PixelDelta(position) => {
+                let lines = position.to_logical::<f64>(self.state.scale_factor as f64).y / LINE_HEIGHT as f64;
+                lines * per_notch / mouse.wheel_lines.max(1) as f64
+            }
+        };
+        let lines = self.wheel_lines.trunc();
+        self.wheel_lines -= lines;
+        if reporting {
+            // Content moving right is the wheel turning left
+            let sideways = match delta {
+                MouseScrollDelta::LineDelta(x, _) => x.trunc() as i32,
+                MouseScrollDelta::PixelDelta(_) => 0,
+            };
+            let up = if lines > 0.0 { ReportButton::WheelUp } else { ReportButton::WheelDown };
+            let left = if sideways > 0 { ReportButton::WheelLeft } else { ReportButton::WheelRight };
+            for button in std::iter::repeat_n(up, lines.abs() as usize).chain(std::iter::repeat_n(left, sideways.unsigned_abs() as usize)) {
+                self.report_mouse(MouseAction::Press, Some(button));
+            }
+            return;
+        }
+        if lines == 0.0 {
+            return;
+        }
+
+        let session = self.active();
+        let modes = *session.terminal.modes.lock().unwrap();
+        if !modes.alternate_screen {
+            let moved = session.terminal.scroll_view(lines as isize);
+            if mouse.smooth_scroll && moved != 0 {
+                self.state.smooth_scroll.scrolled(moved);
+            }
+            return;
+        }
+        if !mouse.alternate_scroll {
+            return;
+        }
+        // Up the wheel is up the page
+        let key = if lines > 0.0 { NamedKey::ArrowUp } else { NamedKey::ArrowDown };
+        let Some(arrow) = named_key(key, ModifiersState::empty(), modes) else { return };
+        if let Ok(mut writer) = session.input_writer.lock() {
+            let bytes = arrow.repeat(lines.abs() as usize);
+            if let Err(e) = write_key(&bytes, &mut *writer, &session.terminal.dirty, session.is_private()) {
+                warn!("Failed to write wheel arrows to PTY: {}", e);
+            }
+        }
+    }
+
+    /// Mouse tracking the active tab's program asked for, unless it is off
+    /// or Shift is held to keep the mouse for selecting tabs and links.
+    fn mouse_tracking(&self) -> Option<MouseTracking> {
+        let tracking = self.active().terminal.modes.lock().unwrap().mouse_tracking;
+        (tracking != MouseTracking::Off && !self.modifiers.shift_key()).then_some(tracking)
+    }
+
+    /// Cell under the mouse. None over the tab bar, unless a reported
+    /// button is held, when positions outside the grid are clamped to it.
+    fn mouse_cell(&self) -> Option<(usize, usize)> {
+        let position = self.mouse_position?;
+        let content_top = if self.sessions.len() > 1 { TAB_BAR_HEIGHT } else { 0.0 };
+        let y = position.y as f32 - content_top;
+        if y < 0.0 && self.mouse_button.is_none() {
+            return None;
+        }
+        let terminal = &self.active().terminal;
+        let (cols, rows) = (*terminal.cols.lock().unwrap(), *terminal.rows.lock().unwrap());
+        let col = ((position.x as f32).max(0.0) / CELL_WIDTH) as usize;
+        let row = (y.max(0.0) / LINE_HEIGHT) as usize;
+        Some((col.min(cols.saturating_sub(1)), row.min(rows.saturating_sub(1))))
+    }
+
+    /// Whether a click with `button` goes to the program rather than Nebula.
+    fn reports_click(&self, state: ElementState, button: MouseButton) -> bool {
+        let Some(button) = report_button(button) else { return false };
+        match state {
+            ElementState::Pressed => self.mouse_tracking().is_some() && self.mouse_cell().is_some() && !self.notice_banner_hit(),
+            ElementState::Released => self.mouse_button == Some(button),
+        }
+    }
+
+    fn report_click(&mut self, state: ElementState, button: MouseButton) {
+        let Some(button) = report_button(button) else { return };
+        // Motion before the click arrives before it
+        self.report_motion();
+        match state {
+            ElementState::Pressed => {
+                self.mouse_button = Some(button);
+                self.report_mouse(MouseAction::Press, Some(button));
+            }
+            ElementState::Released => {
+                self.report_mouse(MouseAction::Release, Some(button));
+                self.mouse_button = None;
+            }
+        }
+    }
+
+    /// Report motion since the last batch of events, if the mouse reached
+    /// another cell and the program tracks motion.
+    fn report_motion(&mut self) {
+        if !std::mem::take(&mut self.motion_pending) {
+            return;
+        }
+        if !matches!(self.mouse_tracking(), Some(MouseTracking::ButtonEvent | MouseTracking::AnyEvent)) {
+            return;
+        }
+        if self.mouse_cell().is_some_and(|cell| Some(cell) != self.mouse_cell) {
+            self.report_mouse(MouseAction::Motion, self.mouse_button);
+        }
+    }
+
+    /// Send the active tab's program a mouse event over the cell under the
+    /// mouse, as its modes ask. A release ending a mode 1001 highlight is
+    /// reported as the highlight's end instead.
+    fn report_mouse(&mut self, action: MouseAction, button: Option<ReportButton>) {
+        let Some((col, row)) = self.mouse_cell() else { return };
+        self.mouse_cell = Some((col, row));
+        let session = self.active();
+        let bytes = {
+            let mut modes = session.terminal.modes.lock().unwrap();
+            match modes.highlight.take() {
+                Some(highlight) if action == MouseAction::Release => Some(mouse::highlight_end(&highlight, col, row)),
+                highlight => {
+                    modes.highlight = highlight;
+                    let event = MouseEvent { action, button, col, row, modifiers: self.modifiers.into() };
+                    mouse::report(modes.mouse_tracking, modes.mouse_encoding, &event)
+                }
+            }
+        };
+        let Some(bytes) = bytes else { return };
+        if let Ok(mut writer) = session.input_writer.lock() {
+            if let Err(e) = write_key(&bytes, &mut *writer, &session.terminal.dirty, session.is_private()) {
+                warn!("Failed to write mouse report to PTY: {}", e);
+            }
+        }
+    }
+
+    /// Paste the clipboard through the paste filter at `index`.
+    fn paste_filtered(&mut self, index: usize) {
+        let Some(filter) = self.user_config.paste_filter.get(index).cloned() else {
+            return;
+        };
+        let Some(text) = self.clipboard.lock().unwrap().get_text() else {
+            return;
+        };
+        self.active_mut().paste_filtered(filter, text);
+    }
+
+    /// Close the active tab or the whole window, first asking if that would
+    /// hang up a running command.
+    fn request_close(&mut self, target: CloseTarget, event_loop: &ActiveEventLoop) {
+        if self.user_config.confirm_close {
+            let sessions = match target {
+                CloseTarget::Tab => std::slice::from_ref(self.active()),
+                CloseTarget::Window => &self.sessions[..],
+            };
+            let commands: Vec<String> = sessions.iter().filter_map(Session::running_command).collect();
+            if !commands.is_empty() {
+                self.state.close_prompt = Some(ClosePrompt { target, commands });
+                self.state.local_dirty = true;
+                return;
+            }
+        }
+        self.close(target, event_loop);
+    }
+
+    fn close(&mut self, target: CloseTarget, event_loop: &ActiveEventLoop) {
+        match target {
+            CloseTarget::Window => self.close_window(event_loop),
+            CloseTarget::Tab => self.close_tab(self.active_session, event_loop),
+        }
+    }
+
+    fn close_tab(&mut self, index: usize, event_loop: &ActiveEventLoop) {
+        let session = self.sessions.remove(index);
+        session.shutdown();
+        if self.sessions.is_empty() {
+            self.close_window(event_loop);
+            return;
+        }
+        if index < self.active_session {
+            self.active_session -= 1;
+        }
+        self.fit_sessions();
+        self.active_session = self.active_session.min(self.sessions.len() - 1);
+        if let Some(window) = &self.window {
+            window.window.set_title(&self.active().window_title());
+        }
+        self.state.local_dirty = true;
+    }
+
+    /// With --daemon, put the window away instead of exiting once its tabs
+    /// are closed, with a fresh shell ready for the next invocation.
+    fn hide_window(&mut self, event_loop: &ActiveEventLoop) {
+        for session in self.sessions.drain(..) {
+            session.shutdown();
+        }
+        self.active_session = 0;
+        self.state.search = None;
+        self.hidden = true;
+        if let Some(window) = &self.window {
+            window.window.set_visible(false);
+        }
+        self.open_tab(self.user_config.clone(), None);
+        if self.sessions.is_empty() {
+            event_loop.exit();
+        }
+    }
+
+    /// Open the window an invocation handed to the daemon. While hidden,
+    /// the daemon's own window is shown instead, with the shell kept ready
+    /// if it will do.
+    fn open_requested(&mut self, request: OpenRequest, event_loop: &ActiveEventLoop) {
+        if !(self.hidden && request.is_plain()) {
+            let mut config = self.user_config.clone();
+            if let Some(profile) = &request.profile {
+                config.apply_profile(profile);
+            }
+            config.private |= request.private;
+            if let Some((program, args)) = request.command.as_deref().and_then(<[String]>::split_first) {
+                config.shell.program = Some(program.clone());
+                config.shell.args = args.to_vec();
+                config.shell.restart = false;
+                config.shell.hold = request.hold;
+            }
+            if self.hidden {
+                let tabs = self.sessions.len();
+                self.open_tab(config, request.working_directory);
+                if self.sessions.len() == tabs {
+                    return;
+                }
+                self.sessions.remove(0).shutdown();
+                self.active_session = self.sessions.len() - 1;
+                self.fit_sessions();
+            } else if !self.open_window(event_loop, config, request.working_directory) {
+                return;
+            }
+            if let Some(title) = request.title {
+                let session = self.active_mut();
+                session.base_title = Some(title.clone());
+                session.title = title;
+            }
+        }
+        self.hidden = false;
+        if let Some(window) = &self.window {
+            window.window.set_title(&self.active().window_title());
+            window.window.set_visible(true);
+            window.window.focus_window();
+        }
+        self.state.local_dirty = true;
+    }
+
+    /// Search the active session again for the search bar's query.
+    fn refresh_search(&mut self) {
+        let Some(search) = &mut self.state.search else { return };
+        let session = &self.sessions[self.active_session];
+        let matches = match search.pattern() {
+            Some(pattern) => session.terminal.search(&pattern),
+            None => Vec::new(),
+        };
+        search.set_matches(matches, session.snapshot_generation);
+        self.state.local_dirty = true;
+    }
+
+    /// Scroll the active session to the current search match.
+    fn show_search_match(&self) {
+        if let Some(found) = self.state.search.as_ref().and_then(SearchBar::current_match) {
+            self.active().terminal.scroll_to_line(found.line);
+        }
+    }
+
+    fn handle_search_event(&mut self, event: SearchEvent) {
+        match event {
+            SearchEvent::Changed => {
+                self.refresh_search();
+                self.show_search_match();
+            }
+            SearchEvent::Next | SearchEvent::Previous => {
+                if let Some(search) = &mut self.state.search {
+                    search.step(matches!(event, SearchEvent::Next));
+                }
+                self.show_search_match();
+            }
+            SearchEvent::Close => {
+                self.state.search = None;
+                self.active().terminal.scroll_to_bottom();
+            }
+            SearchEvent::None => {}
+        }
+        self.state.local_dirty = true;
+    }
+
+    fn handle_ime(&mut self, ime: Ime) {
+        match ime {
+            Ime::Preedit(text, cursor) => {
+                self.state.ime_preedit = (!text.is_empty()).then(|| ImePreedit {
+                    text,
+                    cursor: cursor.map(|(start, _)| start),
+                });
+            }
+            Ime::Commit(text) => {
+                self.state.ime_preedit = None;
+                let session = self.active();
+                if let Ok(mut writer) = session.input_writer.lock() {
+                    if let Err(e) = write_key(
+                        text.as_bytes(),
+                        &mut *writer,
+                        &session.terminal.dirty,
+                        session.is_private(),
+                    ) {
+                        warn!("Failed to write IME text to PTY: {}", e);
+                    }
+                }
+            }
+            Ime::Enabled => {}
+            Ime::Disabled => self.state.ime_preedit = None,
+        }
+        self.state.local_dirty = true;
+    }
+
+    /// Rewrite the layout journal when it is due.
+    fn record_layout(&mut self) {
+        if !self.journal.as_ref().is_some_and(Journal::is_due) {
+            return;
+        }
+        let layout = self.layout();
+        if let Some(journal) = &mut self.journal {
+            journal.record(&layout);
+        }
+    }
+
+    /// Hang up every shell and leave the event loop.
+    fn shutdown(&mut self, event_loop: &ActiveEventLoop) {
+        for session in self.sessions.iter().chain(self.windows.iter().flat_map(|slot| &slot.sessions)) {
+            session.shutdown();
         }
+        event_loop.exit();
     }
 
     pub fn run() -> Result<()> {
+        // Before any setup, so --help and bad arguments exit right away
+        let cli = Cli::parse();
+        let _log_guard = logging::init(cli.log_level.as_deref());
+
+        // Needs no window
+        if let Some(Tool::Terminfo { install }) = cli.tool {
+            if install {
+                let dir = terminfo::install()?;
+                println!("Installed the {} terminfo entry into {}", terminfo::NAME, dir.display());
+            } else {
+                print!("{}", terminfo::SOURCE);
+            }
+            return Ok(());
+        }
+        if let Some(Tool::Capabilities) = cli.tool {
+            println!("{}", capabilities::json());
+            return Ok(());
+        }
+        // A running daemon opens the window far sooner than we could
+        if let Some(request) = cli.open_request() {
+            match daemon::request_open(&request) {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                Err(e) => warn!("{:#}; opening a window here instead", e),
+            }
+        }
+
         pollster::block_on(async {
-            let event_loop = EventLoop::new()?;
-            let window_attributes = WindowAttributes::default()
-                .with_title("Nebula")
-                .with_inner_size(LogicalSize::new(1600.0, 900.0));
-
-            let instance = wgpu::Instance::default();
-            let adapter = instance
-                .enumerate_adapters(wgpu::Backends::all())
-                .into_iter()
-                .next()
-                .ok_or_else(|| anyhow::anyhow!("Failed to find suitable GPU adapter"))?;
-
-            let (device, queue) = adapter
-                .request_device(
-                    &DeviceDescriptor {
-                        label: None,
-                        required_features: Features::empty(),
-                        required_limits: Limits::default(),
-                        ..Default::default()
+            let event_loop = EventLoop::<UserEvent>::with_user_event().build()?;
+            // Sleep until there are events, output or a timer is due
+            event_loop.set_control_flow(ControlFlow::Wait);
+            let proxy = event_loop.create_proxy();
+            let notice_proxy = proxy.clone();
+            notices::set_waker(Arc::new(move || {
+                let _ = notice_proxy.send_event(UserEvent::Notice);
+            }));
+
+            let mut user_config = Config::load(cli.config.as_deref());
+            if let Some(profile) = &cli.profile {
+                user_config.apply_profile(profile);
+            }
+            if let Some(backend) = cli.backend {
+                user_config.gpu.backend = Some(backend);
+            }
+            if let Some(preference) = cli.power_preference {
+                user_config.gpu.power_preference = preference;
+            }
+            if let Some(name) = &cli.gpu {
+                user_config.gpu.adapter = Some(name.clone());
+            }
+
+            let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+                backends: user_config.gpu.backend.map_or(wgpu::Backends::all(), |backend| backend.backends()),
+                ..Default::default()
+            });
+            // Without a usable GPU the window is drawn on the CPU instead
+            let gpu = if cli.software {
+                None
+            } else {
+                match request_gpu(&instance, &user_config).await {
+                    Ok(gpu) => Some(gpu),
+                    Err(e) => {
+                        warn!("{:#}; drawing on the CPU instead", e);
+                        None
                     }
-                )
-                .await?;
+                }
+            };
+
+            if cli.private {
+                user_config.private = true;
+            }
+            if cli.unlocked {
+                user_config.rendering.present_mode = PresentModeConfig::Immediate;
+                user_config.rendering.max_fps = None;
+            }
+            if let Some(geometry) = cli.geometry {
+                user_config.geometry = geometry;
+            }
+            // Only the first tab runs the command; new tabs get the shell
+            let mut first_config = user_config.clone();
+            if let Some(command) = &cli.command {
+                first_config.shell.program = Some(command[0].clone());
+                first_config.shell.args = command[1..].to_vec();
+                first_config.shell.restart = false;
+                first_config.shell.hold = cli.hold;
+            }
+            // A window opened to restore part of a crashed session, or one
+            // started normally that finds such sessions to offer
+            let restored = cli.restore_layout.as_deref().and_then(journal::take_layout);
+            let crashed = if gpu.is_some()
+                && restored.is_none()
+                && cli.command.is_none()
+                && cli.join.is_none()
+                && !cli.daemon
+            {
+                journal::take_crashed()
+            } else {
+                Vec::new()
+            };
+            let first_cwd = match &restored {
+                Some(layout) => layout.tabs.first().and_then(|tab| tab.cwd.clone()),
+                None => cli.working_directory.clone(),
+            };
+            let clipboard = Clipboard::shared(user_config.clipboard.history_size);
+            let mut session = match &cli.join {
+                Some(address) => {
+                    let token = cli.share_token.as_deref().unwrap_or_default();
+                    Session::join(first_config, clipboard, address, token, proxy.clone())?
+                }
+                None => Session::spawn(first_config, clipboard, first_cwd, proxy.clone())?,
+            };
+            if let Some(title) = &cli.title {
+                session.base_title = Some(title.clone());
+                session.title = title.clone();
+            }
+            let Some((adapter, device, queue)) = gpu else {
+                return SoftwareApp::new(user_config, session).run(event_loop);
+            };
 
             let config = SurfaceConfiguration {
                 usage: TextureUsages::RENDER_ATTACHMENT,
@@ -96,86 +1711,234 @@ impl TerminalApp {
 
             let glyph_atlas = GlyphAtlas::new(&device, ATLAS_SIZE);
             let gpu_resources = GpuResources::new(
-                            &device, 
+                            &device,
                             &config,
                             glyph_atlas.bind_group_layout()
                         );
 
-            let terminal = Terminal::new();
-            let (input_writer, child_process) = terminal.spawn_pty()?;
-            let start_time = Instant::now();
-            let last_frame_time = start_time;
+            let post_process = user_config.rendering.shader.as_deref().and_then(|path| {
+                let config_dir = cli.config.clone().or_else(Config::path);
+                let config_dir = config_dir.as_deref().and_then(Path::parent);
+                PostProcess::load(&device, &config, path, config_dir)
+                    .map_err(|e| notices::warn(format!("Couldn't load the shader {}: {:#}", path.display(), e)))
+                    .ok()
+            });
 
-            let initial_text = terminal.text_content.lock().unwrap().clone();
+            // System fonts, with the configured ones checked against them
+            let (locale, db) = FontSystem::new().into_locale_and_db();
+            let fonts = Fonts::resolve(&user_config.font, &db);
+            let shaper = ShaperPool::new(&locale, &db, &fonts, proxy.clone());
+            let mut font_system = fonts.font_system(locale, db);
+            let decoration_metrics = DecorationMetrics::measure(&mut font_system, &fonts);
+            let font_system = Arc::new(Mutex::new(font_system));
+            let last_frame_time = Instant::now();
 
             let state = TerminalState {
-                font_system: terminal.font_system,
-                buffer: terminal.buffer,
-                text_content: terminal.text_content,
-                last_text: initial_text, // Track last text for changes
+                font_system,
                 glyph_atlas,
-                swash_cache: terminal.swash_cache,
+                rasterizer: Arc::new(Mutex::new(GlyphRasterizer::new(user_config.rendering.clone()))),
+                shaper,
                 gpu_resources,
-                start_time,
                 last_frame_time,
                 focused: true,
-                shared_dirty: terminal.dirty.clone(),
                 local_dirty: true,
-                cursor_x: terminal.cursor_x.clone(),
-                cursor_y: terminal.cursor_y.clone(),
                 cursor_visible: true,
-                cursor_blink: true,
+                cursor_blink: user_config.cursor.blink,
+                blink_interval: Duration::from_secs_f32(user_config.cursor.blink_interval.max(0.05)),
                 last_blink: Instant::now(),
+                bell_flash: None,
+                scrollbar_until: None,
+                smooth_scroll: SmoothScroll::default(),
+                paste_picker: None,
+                search: None,
+                close_prompt: None,
+                restore_prompt: (!crashed.is_empty()).then_some(RestorePrompt { layouts: crashed }),
+                keybinding_list: None,
+                command_palette: None,
+                hints: None,
+                key_test: (cli.tool == Some(Tool::Keytest)).then(KeyTest::default),
+                ime_preedit: None,
+                ime_cursor_area: None,
+                hovered_link: None,
+                notices: Vec::new(),
+                notice_generation: 0,
+                theme: user_config.theme.resolve(None),
+                magnifier: Magnifier::new(user_config.magnifier),
+                post_process,
+                frame_buffers: FrameBuffers::default(),
+                decoration_metrics,
+                fonts,
+                highlighter: Highlighter::new(&user_config.highlight),
+                command_time: user_config.command_time,
+                color_settings: ColorSettings::new(&user_config.theme),
+                scale_factor: 1.0,
+                background_opacity: 1.0,
+                premultiplied_alpha: false,
             };
 
             let mut app = TerminalApp::new(
-                window_attributes,
                 instance,
+                adapter,
                 config,
                 device,
                 queue,
                 state,
-                input_writer,
-                child_process,
+                user_config,
+                session,
+                proxy,
             );
+            app.latency_bench = cli.latency_samples().map(LatencyBench::new);
+            // --share and --web take the same token
+            let token = match cli.share_token.clone() {
+                Some(token) => token,
+                None if cli.share.is_some() || cli.web.is_some() => token::generate()?,
+                None => String::new(),
+            };
+            if let Some(address) = &cli.share {
+                app.share = Some(ShareServer::start(address, token.clone())?);
+                println!("Sharing this window. To view it: nebula --join {} --share-token {}", address, token);
+            }
+            if let Some(address) = &cli.web {
+                app.web = Some(WebServer::start(address, token.clone(), cli.web_input, app.proxy.clone())?);
+                println!("Serving this window at ws://{}/?token={}", address, token);
+            }
+            // The first tab waits, hidden, for the first invocation
+            if cli.daemon {
+                app.daemon = Some(DaemonServer::start(app.proxy.clone())?);
+                app.hidden = true;
+            }
+            if let Some(layout) = restored {
+                app.open_layout_tabs(layout.tabs.get(1..).unwrap_or_default());
+                app.active_session = layout.active.min(app.sessions.len() - 1);
+            }
 
             event_loop.run_app(&mut app)?;
+            // Reached only on a clean exit; after a crash the journal stays
+            let journals = app.windows.iter_mut().map(|slot| slot.journal.take());
+            for journal in std::iter::once(app.journal.take()).chain(journals).flatten() {
+                journal.remove();
+            }
             Ok(())
         })
     }
 }
 
-impl winit::application::ApplicationHandler for TerminalApp {
-    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: winit::event::StartCause) {
-        if let Some(window) = &self.window {
-            window.window.request_redraw();
+/// The adapter the config asks for and a device on it.
+async fn request_gpu(instance: &Instance, config: &Config) -> Result<(Adapter, Device, Queue)> {
+    let adapter = gpu::choose_adapter(instance, &config.gpu)?;
+    let (device, queue) = adapter
+        .request_device(
+            &DeviceDescriptor {
+                label: None,
+                required_features: Features::empty(),
+                required_limits: Limits::default(),
+                ..Default::default()
+            }
+        )
+        .await?;
+    Ok((adapter, device, queue))
+}
+
+impl TerminalApp {
+    /// The tab with focus is the active one while the window has it; tell
+    /// programs when that changes, whether by window or tab.
+    fn report_session_focus(&mut self) {
+        let focused = self.state.focused.then(|| self.active().id);
+        if focused != self.focused_session {
+            let previous = std::mem::replace(&mut self.focused_session, focused);
+            if let Some(session) = self.sessions.iter().find(|session| Some(session.id) == previous) {
+                session.report_focus(false);
+            }
+            if let Some(session) = self.sessions.iter().find(|session| Some(session.id) == focused) {
+                session.report_focus(true);
+            }
         }
     }
 
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        if self.window.is_none() {
-            match TerminalWindow::new(
-                event_loop,
-                WindowAttributes::default()
-                    .with_title("Nebula")
-                    .with_inner_size(LogicalSize::new(1600.0, 900.0)),
-                &self.instance,
-            ) {
-                Ok(window) => {
-                    window.configure_surface(&self.device, &self.config);
-                    self.window = Some(window);
+    /// Update the current window's tab titles from OSC 0/1/2 or the
+    /// running command.
+    fn refresh_titles(&mut self, check_process: bool) {
+        let active = self.active_session;
+        for (index, session) in self.sessions.iter_mut().enumerate() {
+            // Session rules follow the foreground command on the same schedule
+            if check_process && session.refresh_rules() {
+                self.state.local_dirty = true;
+            }
+            if session.refresh_title(check_process) {
+                self.state.local_dirty = true;
+                if index == active {
+                    if let Some(window) = &self.window {
+                        window.window.set_title(&session.window_title());
+                    }
                 }
-                Err(e) => eprintln!("Failed to create window: {}", e),
             }
         }
     }
 
-    fn window_event(
-        &mut self,
-        event_loop: &ActiveEventLoop,
-        window_id: winit::window::WindowId,
-        event: WindowEvent,
-    ) {
+    /// Keep the windows other than the current one going: close tabs whose
+    /// command finished, pick up output, bells and titles, and redraw.
+    fn tend_other_windows(&mut self, event_loop: &ActiveEventLoop, check_process: bool) {
+        let Some(current) = self.current_window_id() else { return };
+        let others: Vec<WindowId> = self.windows.iter().filter_map(WindowSlot::id).collect();
+        for id in others {
+            self.make_current(id);
+            if self.current_window_id() != Some(id) {
+                continue;
+            }
+            while let Some(index) = self.sessions.iter().position(command_finished) {
+                self.close_tab(index, event_loop);
+                if self.current_window_id() != Some(id) {
+                    break;
+                }
+            }
+            if self.current_window_id() != Some(id) {
+                continue;
+            }
+            let active = self.active_session;
+            if self.sessions[active].poll() {
+                self.state.local_dirty = true;
+            }
+            self.report_session_focus();
+            let mut rang = false;
+            for session in &self.sessions {
+                rang |= session.terminal.bell.swap(false, Ordering::SeqCst);
+            }
+            if rang {
+                self.ring_bell();
+            }
+            report_unsupported(&self.sessions);
+            if self.state.bell_flash.is_some_and(|end| Instant::now() >= end) {
+                self.state.bell_flash = None;
+                self.state.local_dirty = true;
+            }
+            for session in &mut self.sessions {
+                if session.paste_job.as_ref().is_some_and(|job| job.is_finished()) {
+                    session.paste_job = None;
+                }
+                self.state.local_dirty |= session.finish_filter();
+            }
+            self.refresh_titles(check_process);
+            self.record_layout();
+            if let (true, Some(window)) = (self.state.local_dirty, &self.window) {
+                window.window.request_redraw();
+            }
+        }
+        self.make_current(current);
+    }
+
+    /// Handle an event for the current window.
+    fn current_window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {        // Mirrors are read-only, so only the main window sees input
+        if matches!(
+            event,
+            WindowEvent::KeyboardInput { .. }
+                | WindowEvent::MouseInput { .. }
+                | WindowEvent::MouseWheel { .. }
+                | WindowEvent::Touch(_)
+                | WindowEvent::Ime(_)
+        ) {
+            self.note_activity();
+        }
+
         let Some(window) = &self.window else { return };
         if window.window.id() != window_id {
             return;
@@ -184,76 +1947,580 @@ impl winit::application::ApplicationHandler for TerminalApp {
         match event {
             WindowEvent::Resized(size) => {
                 window.handle_resize(&self.device, &mut self.config, size);
-                
-                if let Ok(mut buffer_lock) = self.state.buffer.lock() {
-                    if let Ok(mut fs) = self.state.font_system.lock() {
-                        buffer_lock.set_size(
-                            &mut fs, 
-                            Some(size.width as f32), 
-                            Some(size.height as f32)
-                        );
-                    }
-                }
+                self.fit_sessions();
+                self.state.local_dirty = true;
+            }
+            // Moved to a monitor with another DPI. The new physical size
+            // follows as a resize, and rows are rasterized again at the new
+            // scale when next drawn
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                info!("Scale factor changed to {}", scale_factor);
+                self.system_scale = scale_factor as f32;
+                self.state.scale_factor = self.system_scale * rounded_zoom(self.zoom);
+                self.fit_sessions();
+                self.state.local_dirty = true;
                 window.window.request_redraw();
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::Ime(ime) => self.handle_ime(ime),
+            WindowEvent::ThemeChanged(theme) => self.apply_theme(Some(theme)),
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = position.to_logical(self.state.scale_factor as f64);
+                self.mouse_position = Some(position);
+                self.motion_pending = true;
+                self.drag_tab();
+                self.extend_selection();
+                self.drag_scrollbar();
+                if self.state.magnifier.enabled {
+                    self.state.magnifier.mouse = Some((position.x as f32, position.y as f32));
+                    self.state.local_dirty = true;
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_wheel(delta);
+                self.state.local_dirty = true;
+            }
+            WindowEvent::Touch(touch) => match self.touches.handle(&touch) {
+                TouchEvent::Pan { scroll, zoom } => {
+                    self.scroll_wheel(MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, scroll)));
+                    self.zoom_by(zoom);
+                    self.state.local_dirty = true;
+                }
+                TouchEvent::ExtendSelection(location) => {
+                    self.mouse_position = Some(location.to_logical(self.state.scale_factor as f64));
+                    self.extend_selection();
+                }
+                TouchEvent::FinishSelection => self.finish_selection(),
+                TouchEvent::None => {}
+            },
+            WindowEvent::PinchGesture { delta, .. } => self.zoom_by(1.0 + delta),
+            WindowEvent::CursorLeft { .. } => {
+                self.mouse_position = None;
+                self.state.magnifier.mouse = None;
                 self.state.local_dirty = true;
             }
-            WindowEvent::KeyboardInput { event, .. } => {
-                if self.state.focused {
-                    if let Ok(mut writer) = self.input_writer.lock() {
-                        let _ = handle_input(&event, &mut *writer, &mut self.state);
-                        // State marked dirty in handle_input
+            WindowEvent::MouseInput { state, button, .. } if self.reports_click(state, button) => {
+                self.report_click(state, button);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                // The banner and tab bar are hit-tested before the terminal
+                if self.notice_banner_hit() {
+                    match self.notice_link_at_mouse() {
+                        Some(link) => self.open_link(&link),
+                        None => notices::dismiss(),
+                    }
+                } else if let Some(index) = self.tab_at_mouse() {
+                    self.active_session = index;
+                    self.dragged_tab = Some(index);
+                    window.window.set_title(&self.active().window_title());
+                    self.state.local_dirty = true;
+                } else if let Some(link) = self.state.hovered_link.clone() {
+                    self.open_link(&link.uri);
+                } else if self.scrollbar_hit() {
+                    self.dragging_scrollbar = true;
+                    self.drag_scrollbar();
+                } else {
+                    self.start_selection();
+                }
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.drop_tab(event_loop);
+                self.finish_selection();
+                self.dragging_scrollbar = false;
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Middle,
+                ..
+            } if self.user_config.clipboard.primary_selection => {
+                let text = self.clipboard.lock().unwrap().get_primary();
+                if let Some(text) = text {
+                    self.active_mut().paste(&text);
+                    self.state.local_dirty = true;
+                }
+            }
+            // Keys belong to the input method while it is composing
+            WindowEvent::KeyboardInput { .. } if self.state.ime_preedit.is_some() => {}
+            WindowEvent::KeyboardInput { event, .. } if self.state.focused => {
+                // A pending close prompt has to be answered first
+                if let Some(prompt) = &self.state.close_prompt {
+                    let target = prompt.target;
+                    match prompt.handle_key(&event) {
+                        PromptEvent::Confirm => {
+                            self.state.close_prompt = None;
+                            self.close(target, event_loop);
+                        }
+                        PromptEvent::Cancel => self.state.close_prompt = None,
+                        PromptEvent::None => {}
+                    }
+                    self.state.local_dirty = true;
+                    return;
+                }
+
+                if let Some(prompt) = &self.state.restore_prompt {
+                    match prompt.handle_key(&event) {
+                        PromptEvent::Confirm => {
+                            if let Some(prompt) = self.state.restore_prompt.take() {
+                                self.restore(prompt.layouts, event_loop);
+                            }
+                        }
+                        PromptEvent::Cancel => self.state.restore_prompt = None,
+                        PromptEvent::None => {}
+                    }
+                    self.state.local_dirty = true;
+                    return;
+                }
+
+                // The key test takes every key, bindings included
+                if self.state.key_test.is_some() {
+                    let modes = *self.active().terminal.modes.lock().unwrap();
+                    if let Some(key_test) = &mut self.state.key_test {
+                        key_test.record(&event, self.modifiers, modes);
+                    }
+                    self.state.local_dirty = true;
+                    return;
+                }
+
+                // Escape stops a large paste that is still being written
+                let escape = event.state == ElementState::Pressed && event.logical_key == Key::Named(NamedKey::Escape);
+                if let Some(job) = self.active().paste_job.as_ref().filter(|job| escape && !job.is_cancelled()) {
+                    job.cancel();
+                    info!("Paste cancelled after {} of {} bytes", job.written(), job.total);
+                    self.state.local_dirty = true;
+                    return;
+                }
+
+                // The paste picker takes all keys while it is open
+                if let Some(picker) = &mut self.state.paste_picker {
+                    match picker.handle_key(&event) {
+                        PickerEvent::Paste(text) => {
+                            self.state.paste_picker = None;
+                            self.sessions[self.active_session].paste(&text);
+                        }
+                        PickerEvent::Filter(index) => {
+                            self.state.paste_picker = None;
+                            self.paste_filtered(index);
+                        }
+                        PickerEvent::Close => self.state.paste_picker = None,
+                        PickerEvent::None => {}
+                    }
+                    self.state.local_dirty = true;
+                    return;
+                }
+
+                // And the keybinding list
+                if let Some(list) = &mut self.state.keybinding_list {
+                    if let ListEvent::Close = list.handle_key(&event) {
+                        self.state.keybinding_list = None;
+                    }
+                    self.state.local_dirty = true;
+                    return;
+                }
+
+                // Hints mode takes every key until a label is typed
+                if let Some(hints) = &mut self.state.hints {
+                    match hints.handle_key(&event) {
+                        HintEvent::Open(target) => {
+                            self.state.hints = None;
+                            self.open_link(&target);
+                        }
+                        HintEvent::Copy(target) => {
+                            self.state.hints = None;
+                            let private = self.active().is_private();
+                            self.clipboard.lock().unwrap().copy(target, private);
+                        }
+                        HintEvent::Close => self.state.hints = None,
+                        HintEvent::None => {}
+                    }
+                    self.state.local_dirty = true;
+                    return;
+                }
+
+                // And the command palette
+                if let Some(palette) = &mut self.state.command_palette {
+                    match palette.handle_key(&event) {
+                        PaletteEvent::Choose(item) => {
+                            self.state.command_palette = None;
+                            match item {
+                                PaletteItem::Action(action) => self.perform(action, event_loop),
+                                // Typed for the user to check and run
+                                PaletteItem::Command(command) => self.active_mut().paste(&command),
+                            }
+                        }
+                        PaletteEvent::Close => self.state.command_palette = None,
+                        PaletteEvent::None => {}
                     }
+                    self.state.local_dirty = true;
+                    return;
+                }
+
+                // So does the search bar
+                if let Some(search) = &mut self.state.search {
+                    let search_event = search.handle_key(&event, self.modifiers);
+                    self.handle_search_event(search_event);
+                    return;
+                }
+
+                if let Some(action) = self.keymap().action_for(&event, self.modifiers) {
+                    self.perform(action, event_loop);
+                    return;
+                }
+
+                let session = self.active();
+                if let Ok(mut writer) = session.input_writer.lock() {
+                    let _ = handle_input(
+                        &event,
+                        &mut *writer,
+                        &session.terminal.dirty,
+                        session.is_private(),
+                        self.modifiers,
+                        *session.terminal.modes.lock().unwrap(),
+                    );
+                    // Session marked dirty in handle_input
                 }
             }
             WindowEvent::RedrawRequested => {
-                render_frame(
+                let presented = render_frame(
                     &self.device,
-                    &self.queue,                                                         
+                    &self.queue,
                     &self.config,
                     window,
-                    &mut self.state
+                    &mut self.state,
+                    &mut self.sessions,
+                    self.active_session,
                 );
+                if let Some(bench) = self.latency_bench.as_mut().filter(|_| presented) {
+                    bench.frame_presented(&self.sessions[self.active_session]);
+                }
             }
-            WindowEvent::CloseRequested => {
-                event_loop.exit();
-            }
+            WindowEvent::CloseRequested => self.request_close(CloseTarget::Window, event_loop),
             WindowEvent::Focused(f) => {
                 self.state.focused = f;
+                // Attention asked for by the bell is no longer needed
+                if f {
+                    if let Some(window) = &self.window {
+                        window.window.request_user_attention(None);
+                    }
+                }
             }
             _ => {}
         }
     }
+}
+
+impl winit::application::ApplicationHandler<UserEvent> for TerminalApp {
+    // Whatever a thread woke us for is picked up in about_to_wait
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: UserEvent) {
+        trace!("Woken by {:?}", event);
+    }
+
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_none() {
+            match TerminalWindow::new(
+                event_loop,
+                WindowAttributes::default()
+                    .with_title(self.active().window_title())
+                    .with_inner_size(window_size(self.user_config.geometry))
+                    .with_transparent(self.user_config.window.opacity < 1.0)
+                    .with_blur(self.user_config.window.blur)
+                    .with_visible(!self.hidden),
+                &self.instance,
+            ) {
+                Ok(window) => {
+                    self.choose_alpha_mode(&window);
+                    self.choose_present_mode(&window);
+                    window.configure_surface(&self.device, &self.config);
+                    window.window.set_ime_allowed(true);
+                    self.system_scale = window.window.scale_factor() as f32;
+                    self.state.scale_factor = self.system_scale * rounded_zoom(self.zoom);
+                    let system_theme = window.window.theme();
+                    self.window = Some(window);
+                    self.apply_theme(system_theme);
+                }
+                Err(e) => error!("Failed to create window: {}", e),
+            }
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        if let Some(index) = self.mirrors.iter().position(|mirror| mirror.window.window.id() == window_id) {
+            self.mirror_event(index, event);
+            return;
+        }
+        // Other main windows are swapped in for their events. Focus and
+        // input leave them current; after anything else, the window that
+        // was current goes back
+        let previous = self.current_window_id().filter(|&id| id != window_id);
+        let stays = matches!(
+            event,
+            WindowEvent::Focused(true)
+                | WindowEvent::KeyboardInput { .. }
+                | WindowEvent::MouseInput { .. }
+                | WindowEvent::MouseWheel { .. }
+                | WindowEvent::CursorMoved { .. }
+                | WindowEvent::Touch(_)
+                | WindowEvent::PinchGesture { .. }
+                | WindowEvent::Ime(_)
+        );
+        self.make_current(window_id);
+        self.current_window_event(event_loop, window_id, event);
+        if let Some(previous) = previous.filter(|_| !stays && self.current_window_id() == Some(window_id)) {
+            self.make_current(previous);
+        }
+    }
 
     fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
-        // Check if shared dirty flag was set by other threads
-        if *self.state.shared_dirty.lock().unwrap() {
+        // Tear down the PTY if we were asked to terminate
+        if let Some(signal) = self.signals.take() {
+            info!("Received {}, shutting down", signal_name(signal));
+            self.shutdown(event_loop);
+            return;
+        }
+
+        if let Some(bench) = &mut self.latency_bench {
+            if bench.is_done() {
+                bench.report();
+                self.shutdown(event_loop);
+                return;
+            }
+            // Before polling, so a detected echo is drawn by the next frame
+            bench.tick(&self.sessions[self.active_session]);
+        }
+
+        let requests = self.daemon.as_ref().map(DaemonServer::take_requests).unwrap_or_default();
+        for request in requests {
+            self.open_requested(request, event_loop);
+        }
+
+        // Close tabs whose one-shot command has finished
+        while let Some(index) = self.sessions.iter().position(command_finished) {
+            self.close_tab(index, event_loop);
+            if self.sessions.is_empty() {
+                return;
+            }
+        }
+
+        // Check for new output in the visible tab
+        let active = self.active_session;
+        if self.sessions[active].poll() {
             self.state.local_dirty = true;
-            *self.state.shared_dirty.lock().unwrap() = false;
-        }
-        
-        // Check for text content changes
-        let mut text_changed = false;
-        if let Ok(text) = self.state.text_content.lock() {
-            if *text != self.state.last_text {
-                self.state.last_text = text.clone();
-                text_changed = true;
-                self.state.local_dirty = true;
+            self.note_activity();
+        }
+
+        // Mouse motion is reported once per batch of moves
+        self.report_motion();
+
+        self.report_session_focus();
+
+        // Ring for bells from any tab, once per batch however many arrived
+        let mut rang = false;
+        for session in &self.sessions {
+            rang |= session.terminal.bell.swap(false, Ordering::SeqCst);
+        }
+        if rang {
+            self.ring_bell();
+        }
+        report_unsupported(&self.sessions);
+        if self.state.bell_flash.is_some_and(|end| Instant::now() >= end) {
+            self.state.bell_flash = None;
+            self.state.local_dirty = true;
+        }
+
+        // Show the progress of large pastes and forget them once written,
+        // and paste text whose filter is done
+        if self.sessions[active].paste_job.is_some() {
+            self.state.local_dirty = true;
+        }
+        for session in &mut self.sessions {
+            if session.paste_job.as_ref().is_some_and(|job| job.is_finished()) {
+                session.paste_job = None;
+            }
+            self.state.local_dirty |= session.finish_filter();
+        }
+
+        let idle = self.user_config.idle_trim;
+        let idle_after = Duration::from_secs_f32(idle.minutes.max(0.0) * 60.0);
+        if idle.enabled && !self.trimmed && self.last_activity.elapsed() >= idle_after {
+            self.trim_gpu_memory();
+        }
+        // Glyphs moved in the atlas, so rows are rasterized again
+        if self.state.glyph_atlas.generation() != self.atlas_generation {
+            self.atlas_generation = self.state.glyph_atlas.generation();
+            for session in all_sessions_mut(&mut self.sessions, &mut self.windows) {
+                session.row_cache = Default::default();
+            }
+            for mirror in &mut self.mirrors {
+                mirror.row_cache = Default::default();
+                mirror.window.window.request_redraw();
+            }
+            self.state.local_dirty = true;
+        }
+        let shaped = self.state.shaper.collect();
+        if shaped {
+            self.state.local_dirty = true;
+        }
+        // Send the active tab to share and web viewers, and type what web
+        // viewers sent into it
+        if let Some(share) = &mut self.share {
+            share.publish(&self.sessions[active]);
+        }
+        if let Some(web) = &mut self.web {
+            for text in web.take_input() {
+                if let Err(e) = self.sessions[active].remote_input(&text) {
+                    notices::warn(format!("Dropped web input: {}", e));
+                }
+            }
+            web.publish(&self.sessions[active]);
+        }
+        // Keep search matches in step with new output
+        let generation = self.sessions[active].snapshot_generation;
+        if self.state.search.as_ref().is_some_and(|search| search.is_stale(generation)) {
+            self.refresh_search();
+        }
+        // Pick up warnings reported since the last batch, from any thread
+        let notice_generation = notices::generation();
+        if notice_generation != self.state.notice_generation {
+            self.state.notice_generation = notice_generation;
+            self.state.notices = notices::current();
+            self.state.local_dirty = true;
+        }
+
+        // After every batch of events, so mouse moves, Ctrl presses, new
+        // output and tab switches all update the hovered link
+        self.update_hovered_link();
+
+        // Update tab titles from OSC 0/1/2 or the running command
+        let check_process = self.last_title_poll.elapsed() >= TITLE_POLL_INTERVAL;
+        if check_process {
+            self.last_title_poll = Instant::now();
+        }
+        self.refresh_titles(check_process);
+
+        // Resource badges, sampled on their own, slower schedule
+        let badge = self.user_config.resource_badge;
+        let usage_interval = Duration::from_secs_f32(badge.interval.max(0.5));
+        if !badge.enabled {
+            self.usage_monitor = None;
+        } else if self.usage_monitor.as_ref().is_none_or(|monitor| monitor.interval() != usage_interval) {
+            self.usage_monitor = Some(UsageMonitor::start(usage_interval));
+        }
+        if let Some(monitor) = &self.usage_monitor {
+            if self.last_usage_poll.elapsed() >= usage_interval {
+                self.last_usage_poll = Instant::now();
+                monitor.watch(self.sessions.iter().filter_map(Session::usage_root).collect());
+                for session in &mut self.sessions {
+                    self.state.local_dirty |= session.refresh_usage(monitor);
+                }
             }
         }
-        
-        // Handle cursor blinking
+
+        self.record_layout();
+        self.tend_other_windows(event_loop, check_process);
+        self.update_mirrors(shaped, check_process);
+
+        // Blink the cursor, leaving it on whenever it doesn't blink
+        let blinks = self.cursor_blinks();
         let now = Instant::now();
-        if now.duration_since(self.state.last_blink).as_millis() > 500 {
+        if !blinks {
+            self.state.cursor_visible = true;
+        } else if now.duration_since(self.state.last_blink) >= self.state.blink_interval {
             self.state.cursor_visible = !self.state.cursor_visible;
             self.state.last_blink = now;
             self.state.local_dirty = true;
         }
-        
-        // Request redraw if needed
-        if self.state.local_dirty {
+
+        // A finger held still on a touchscreen starts selecting
+        if let Some(location) = self.touches.long_press() {
+            self.mouse_position = Some(location.to_logical(self.state.scale_factor as f64));
+            self.start_selection();
+        }
+
+        if self.state.smooth_scroll.tick() {
+            self.state.local_dirty = true;
+        }
+
+        // Keep the scrollbar up while scrolled back, then let it fade
+        let scrolled = self.user_config.scrollbar.enabled && (self.active().scrolled_back() || self.dragging_scrollbar);
+        if scrolled {
+            let delay = Duration::from_secs_f32(self.user_config.scrollbar.fade_delay.max(0.0));
+            self.state.scrollbar_until = Some(now + delay + SCROLLBAR_FADE);
+        } else if let Some(until) = self.state.scrollbar_until {
+            if now >= until {
+                self.state.scrollbar_until = None;
+                self.state.local_dirty = true;
+            } else if now + SCROLLBAR_FADE >= until && self.state.last_frame_time.elapsed() >= ANIMATION_INTERVAL {
+                self.state.local_dirty = true;
+            }
+        }
+
+        // A shader that reads the time changes every frame
+        let animated = self.state.post_process.as_ref().is_some_and(|post| post.animated);
+        if animated && self.state.last_frame_time.elapsed() >= ANIMATION_INTERVAL {
+            self.state.local_dirty = true;
+        }
+
+        // Request redraw if needed, no sooner than the frame rate cap allows
+        let next_frame = self.frame_interval.map(|interval| self.state.last_frame_time + interval);
+        let frame_due = next_frame.is_none_or(|at| Instant::now() >= at);
+        if self.state.local_dirty && frame_due {
             if let Some(window) = &self.window {
                 window.window.request_redraw();
             }
         }
+
+        // The benchmark injects keys on its own schedule; otherwise sleep
+        // until the next timer unless woken earlier
+        if self.latency_bench.is_some() {
+            event_loop.set_control_flow(ControlFlow::Poll);
+        } else {
+            let mut wake = self.last_title_poll + TITLE_POLL_INTERVAL;
+            if blinks {
+                wake = wake.min(self.state.last_blink + self.state.blink_interval);
+            }
+            if badge.enabled {
+                wake = wake.min(self.last_usage_poll + usage_interval);
+            }
+            if idle.enabled && !self.trimmed {
+                wake = wake.min(self.last_activity + idle_after);
+            }
+            if let Some(end) = self.state.bell_flash {
+                wake = wake.min(end);
+            }
+            if animated {
+                wake = wake.min(self.state.last_frame_time + ANIMATION_INTERVAL);
+            }
+            if let Some(at) = self.touches.long_press_at() {
+                wake = wake.min(at);
+            }
+            if let Some(until) = self.state.scrollbar_until.filter(|_| !scrolled) {
+                let fade_start = until - SCROLLBAR_FADE;
+                wake = wake.min(if now < fade_start { fade_start } else { self.state.last_frame_time + ANIMATION_INTERVAL });
+            }
+            if let Some(at) = next_frame.filter(|_| self.state.local_dirty && !frame_due) {
+                wake = wake.min(at);
+            }
+            event_loop.set_control_flow(ControlFlow::WaitUntil(wake));
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Zoom as applied, in steps of a tenth so a pinch doesn't rasterize the
+/// glyphs at every size in between.
+fn rounded_zoom(zoom: f64) -> f32 {
+    ((zoom * 10.0).round() / 10.0) as f32
+}
@@ -0,0 +1,34 @@
+// src/terminal/bell.rs
+use std::{process::Command, thread};
+use tracing::warn;
+
+/// Play the desktop's alert sound. There is no portable API for it, so a
+/// small helper that every desktop ships is run instead.
+pub fn system_beep() {
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("osascript");
+        command.args(["-e", "beep"]);
+        command
+    };
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("rundll32");
+        command.arg("user32.dll,MessageBeep");
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = {
+        let mut command = Command::new("canberra-gtk-play");
+        command.args(["--id", "bell"]);
+        command
+    };
+
+    match command.spawn() {
+        // Reap the helper once it exits
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => warn!("Failed to play the bell sound: {}", e),
+    }
+}
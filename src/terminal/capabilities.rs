@@ -0,0 +1,54 @@
+// src/terminal/capabilities.rs
+use serde::Serialize;
+
+/// What this build of Nebula understands, for tools that need more than
+/// the terminfo entry or the DA replies tell them. Printed by
+/// `nebula capabilities` and sent in reply to `DCS + c ST`. Keep it in step
+/// with the parser: an entry here is a promise that it works.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub name: &'static str,
+    pub version: &'static str,
+    /// DEC private modes set and reset with `CSI ? Pm h` and `l`, and
+    /// reported by DECRQM.
+    pub private_modes: &'static [u16],
+    /// OSC commands acted on.
+    pub osc: &'static [u16],
+    /// Queries, reports and other control functions beyond VT100 basics.
+    pub protocols: &'static [&'static str],
+    /// SGR underline styles, set with `4:n`.
+    pub underline_styles: &'static [&'static str],
+    /// Character encodings a profile's `encoding` can name.
+    pub encodings: &'static [&'static str],
+}
+
+pub const MANIFEST: Manifest = Manifest {
+    name: "nebula",
+    version: env!("CARGO_PKG_VERSION"),
+    private_modes: &[1, 5, 6, 7, 9, 25, 47, 1000, 1001, 1002, 1003, 1004, 1005, 1006, 1015, 1047, 1049, 2004, 2031],
+    osc: &[0, 1, 2, 7, 8, 10, 11, 52, 133],
+    protocols: &[
+        "da1",
+        "da2",
+        "xtversion",
+        "decrqm",
+        "xtgettcap",
+        "capabilities",
+        "color-scheme-report",
+        "decscusr",
+        "decsca",
+        "decsed",
+        "decsel",
+        "decsera",
+        "mouse-highlight",
+        "truecolor",
+        "underline-color",
+    ],
+    underline_styles: &["single", "double", "curly", "dotted", "dashed"],
+    encodings: &["utf-8", "latin-1", "shift-jis"],
+};
+
+/// The manifest as one line of JSON.
+pub fn json() -> String {
+    serde_json::to_string(&MANIFEST).expect("the manifest always serializes")
+}
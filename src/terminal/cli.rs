@@ -0,0 +1,169 @@
+// src/terminal/cli.rs
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::terminal::{
+    config::{Geometry, GpuBackend, PowerPreference},
+    daemon::OpenRequest,
+    latency::DEFAULT_SAMPLES,
+};
+
+/// Options passed on the command line.
+#[derive(Debug, Parser)]
+#[command(name = "nebula", version, about = "A GPU-accelerated terminal emulator")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub tool: Option<Tool>,
+    /// Start in private mode: no scrollback on disk, no logged input or output.
+    #[arg(long)]
+    pub private: bool,
+    /// Run the keystroke latency benchmark, then exit.
+    #[arg(long = "latency-bench", value_name = "SAMPLES", num_args = 0..=1, require_equals = true)]
+    latency_bench: Option<Option<usize>>,
+    /// Stay running in the background with the GPU set up and fonts
+    /// loaded. Later `nebula` invocations hand their windows over to it
+    /// through a local socket, so they open at once.
+    #[arg(long, conflicts_with_all = ["join", "command", "software"])]
+    pub daemon: bool,
+    /// Draw on the CPU instead of the GPU, with a single tab and no
+    /// overlays. Used automatically when no GPU can be set up.
+    #[arg(long)]
+    pub software: bool,
+    /// Draw frames as fast as possible, without waiting for vsync or the
+    /// configured frame rate cap. Meant for latency benchmarks.
+    #[arg(long)]
+    pub unlocked: bool,
+    /// Directory to start the first shell in.
+    #[arg(long, value_name = "DIR")]
+    pub working_directory: Option<PathBuf>,
+    /// Run a command in the first tab instead of the shell. Takes every
+    /// argument after it.
+    #[arg(
+        short = 'e',
+        long,
+        value_name = "COMMAND",
+        num_args = 1..,
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    pub command: Option<Vec<String>>,
+    /// Window title, until the program sets its own.
+    #[arg(short = 'T', long)]
+    pub title: Option<String>,
+    /// Read this config file instead of the default one.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Run tabs with a profile from the config's `[profiles]` section.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Terminal size in cells, such as 120x40.
+    #[arg(long, value_name = "COLSxROWS", value_parser = parse_geometry)]
+    pub geometry: Option<Geometry>,
+    /// Graphics API to draw with, overriding `gpu.backend`.
+    #[arg(long, value_enum)]
+    pub backend: Option<GpuBackend>,
+    /// Kind of GPU to prefer, overriding `gpu.power_preference`.
+    #[arg(long, value_enum)]
+    pub power_preference: Option<PowerPreference>,
+    /// Use the GPU whose name contains this text, overriding `gpu.adapter`.
+    #[arg(long, value_name = "NAME")]
+    pub gpu: Option<String>,
+    /// Keep the tab open after the command exits.
+    #[arg(long)]
+    pub hold: bool,
+    /// What to write to the log file: a level such as `debug`, or per-module
+    /// filters like `info,nebula::terminal::render=trace`.
+    #[arg(long, value_name = "FILTER", env = "NEBULA_LOG")]
+    pub log_level: Option<String>,
+    /// Let other Nebula windows view this one over TCP, listening on an
+    /// address such as 127.0.0.1:7700. The connection isn't encrypted, so
+    /// reach it from elsewhere through an SSH tunnel rather than listening
+    /// on a public address. Viewers need the token printed at startup.
+    #[arg(long, value_name = "ADDRESS")]
+    pub share: Option<String>,
+    /// View a window started with --share, read-only.
+    #[arg(long, value_name = "ADDRESS", conflicts_with_all = ["share", "command"])]
+    pub join: Option<String>,
+    /// Serve this window over WebSocket at an address such as
+    /// 127.0.0.1:7701, for web/viewer.html. Clients need the token printed
+    /// at startup.
+    #[arg(long, value_name = "ADDRESS")]
+    pub web: Option<String>,
+    /// Let --web clients type into the active tab.
+    #[arg(long, requires = "web")]
+    pub web_input: bool,
+    /// Token for --share, --join and --web. Generated for --share when not given.
+    #[arg(long, value_name = "TOKEN", env = "NEBULA_SHARE_TOKEN", hide_env_values = true)]
+    pub share_token: Option<String>,
+    /// Open the tabs of a layout written by another Nebula window restoring
+    /// a crashed session.
+    #[arg(long, value_name = "PATH", hide = true)]
+    pub restore_layout: Option<PathBuf>,
+}
+
+/// Diagnostic screens run instead of a shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Subcommand)]
+pub enum Tool {
+    /// Show each key press as winit reports it and the bytes Nebula would
+    /// send for it, for debugging keyboard layouts.
+    Keytest,
+    /// Print Nebula's terminfo entry, for compiling with `tic -x`.
+    Terminfo {
+        /// Compile it into ~/.terminfo instead.
+        #[arg(long)]
+        install: bool,
+    },
+    /// Print the modes, OSC commands and protocols this build supports, as
+    /// JSON, for scripts that need to detect features.
+    Capabilities,
+}
+
+impl Cli {
+    /// What to ask a running daemon for, or None if these options need a
+    /// process of their own, such as another config or GPU.
+    pub fn open_request(&self) -> Option<OpenRequest> {
+        let own_process = self.tool.is_some()
+            || self.daemon
+            || self.software
+            || self.unlocked
+            || self.latency_bench.is_some()
+            || self.config.is_some()
+            || self.geometry.is_some()
+            || self.backend.is_some()
+            || self.power_preference.is_some()
+            || self.gpu.is_some()
+            || self.share.is_some()
+            || self.join.is_some()
+            || self.web.is_some()
+            || self.restore_layout.is_some();
+        if own_process {
+            return None;
+        }
+        Some(OpenRequest {
+            // Relative to where this was run, not where the daemon was
+            working_directory: self.working_directory.as_ref().map(|dir| std::path::absolute(dir).unwrap_or(dir.clone())),
+            command: self.command.clone(),
+            title: self.title.clone(),
+            profile: self.profile.clone(),
+            hold: self.hold,
+            private: self.private,
+        })
+    }
+
+
+    /// Number of samples for the latency benchmark, if it was asked for.
+    pub fn latency_samples(&self) -> Option<usize> {
+        self.latency_bench.map(|samples| samples.unwrap_or(DEFAULT_SAMPLES))
+    }
+}
+
+fn parse_geometry(value: &str) -> Result<Geometry, String> {
+    let (columns, rows) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| String::from("expected COLSxROWS, such as 120x40"))?;
+    let parse = |n: &str| match n.trim().parse::<u16>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid size '{}'", n)),
+    };
+    Ok(Geometry { columns: parse(columns)?, rows: parse(rows)? })
+}
@@ -0,0 +1,104 @@
+// src/terminal/clipboard.rs
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+pub type SharedClipboard = Arc<Mutex<Clipboard>>;
+
+/// Thin wrapper over the system clipboard. The backend is created lazily
+/// so a missing clipboard (e.g. no display server) only disables copy/paste.
+/// Copies made inside Nebula are also remembered for the paste picker.
+///
+/// On X11 and Wayland the primary selection (middle-click paste) is
+/// available too. Wayland goes through the data-control protocol, which
+/// needs a compositor that supports it; elsewhere the primary selection
+/// does nothing.
+pub struct Clipboard {
+    backend: Option<arboard::Clipboard>,
+    history: VecDeque<String>,
+    history_size: usize,
+}
+
+impl Clipboard {
+    pub fn shared(history_size: usize) -> SharedClipboard {
+        Arc::new(Mutex::new(Self {
+            backend: None,
+            history: VecDeque::new(),
+            history_size,
+        }))
+    }
+
+    fn backend(&mut self) -> Option<&mut arboard::Clipboard> {
+        if self.backend.is_none() {
+            match arboard::Clipboard::new() {
+                Ok(backend) => self.backend = Some(backend),
+                Err(e) => warn!("Clipboard unavailable: {}", e),
+            }
+        }
+        self.backend.as_mut()
+    }
+
+    pub fn get_text(&mut self) -> Option<String> {
+        match self.backend()?.get_text() {
+            Ok(text) => Some(text),
+            Err(e) => {
+                warn!("Failed to read clipboard: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        if let Some(backend) = self.backend() {
+            if let Err(e) = backend.set_text(text) {
+                warn!("Failed to write clipboard: {}", e);
+            }
+        }
+    }
+
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "android"))))]
+    pub fn get_primary(&mut self) -> Option<String> {
+        use arboard::{GetExtLinux, LinuxClipboardKind};
+        match self.backend()?.get().clipboard(LinuxClipboardKind::Primary).text() {
+            Ok(text) => Some(text),
+            Err(e) => {
+                warn!("Failed to read primary selection: {}", e);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(all(unix, not(any(target_os = "macos", target_os = "android")))))]
+    pub fn get_primary(&mut self) -> Option<String> {
+        None
+    }
+
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "android"))))]
+    pub fn set_primary(&mut self, text: String) {
+        use arboard::{LinuxClipboardKind, SetExtLinux};
+        if let Some(backend) = self.backend() {
+            if let Err(e) = backend.set().clipboard(LinuxClipboardKind::Primary).text(text) {
+                warn!("Failed to write primary selection: {}", e);
+            }
+        }
+    }
+
+    #[cfg(not(all(unix, not(any(target_os = "macos", target_os = "android")))))]
+    pub fn set_primary(&mut self, _text: String) {}
+
+    /// Copy text made inside Nebula, remembering it in the history unless it
+    /// came from a private session.
+    pub fn copy(&mut self, text: String, private: bool) {
+        if !private && self.history_size > 0 && !text.is_empty() {
+            self.history.retain(|entry| *entry != text);
+            self.history.push_front(text.clone());
+            self.history.truncate(self.history_size);
+        }
+        self.set_text(text);
+    }
+
+    /// Previous copies, newest first.
+    pub fn history(&self) -> Vec<String> {
+        self.history.iter().cloned().collect()
+    }
+}
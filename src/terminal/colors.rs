@@ -0,0 +1,233 @@
+// src/terminal/colors.rs
+use std::ops::Range;
+
+use crate::terminal::{
+    config::{Rgb, Theme, ThemeConfig},
+    terminal::{BackgroundSpan, ForegroundSpan},
+};
+
+const WHITE: Rgb = Rgb(255, 255, 255);
+const BLACK: Rgb = Rgb(0, 0, 0);
+/// How far dim text is taken toward its background.
+const DIM_AMOUNT: f32 = 0.4;
+
+/// Settings from `[theme]` that change how text colors come out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorSettings {
+    pub minimum_contrast: f32,
+    pub bold_as_bright: bool,
+}
+
+impl ColorSettings {
+    pub fn new(config: &ThemeConfig) -> Self {
+        Self {
+            minimum_contrast: config.minimum_contrast,
+            bold_as_bright: config.bold_as_bright,
+        }
+    }
+}
+
+/// Colors the text in view is drawn in, worked out from the program's
+/// colors, intensity and reverse video, the theme and its color settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextColors {
+    /// The theme as the screen shows it, swapped under DECSCNM.
+    pub theme: Theme,
+    /// Color of text with no color of its own on the screen's background.
+    pub default: Rgb,
+    /// For each row, runs of columns drawn in something else.
+    pub rows: Vec<Vec<(Range<usize>, Rgb)>>,
+    /// Cells to fill with a background of their own, including reversed
+    /// cells, which take their text color.
+    pub backgrounds: Vec<BackgroundSpan>,
+}
+
+impl TextColors {
+    pub fn resolve(
+        rows: usize,
+        foregrounds: &[ForegroundSpan],
+        backgrounds: &[BackgroundSpan],
+        theme: Theme,
+        settings: ColorSettings,
+        reverse_screen: bool,
+    ) -> Self {
+        let theme = if reverse_screen {
+            Theme { foreground: theme.background, background: theme.foreground }
+        } else {
+            theme
+        };
+        let minimum_contrast = settings.minimum_contrast;
+        let default = ensure_contrast(theme.foreground, theme.background, minimum_contrast);
+        let mut colors: Vec<Vec<(Range<usize>, Rgb)>> = vec![Vec::new(); rows];
+        let mut filled: Vec<BackgroundSpan> = Vec::new();
+        for (row, runs) in colors.iter_mut().enumerate() {
+            let foregrounds: Vec<&ForegroundSpan> = foregrounds.iter().filter(|span| span.row == row).collect();
+            let backgrounds: Vec<&BackgroundSpan> = backgrounds.iter().filter(|span| span.row == row).collect();
+            // Colors only change where a span starts or ends
+            let mut edges: Vec<usize> = foregrounds
+                .iter()
+                .map(|span| &span.columns)
+                .chain(backgrounds.iter().map(|span| &span.columns))
+                .flat_map(|columns| [columns.start, columns.end])
+                .collect();
+            edges.sort_unstable();
+            edges.dedup();
+            for pair in edges.windows(2) {
+                let (col, end) = (pair[0], pair[1]);
+                let text = foregrounds.iter().find(|span| span.columns.contains(&col));
+                // Later backgrounds, such as highlight rules, win
+                let background = backgrounds.iter().rev().find(|span| span.columns.contains(&col)).map(|span| span.color);
+                let mut foreground = text
+                    .and_then(|span| Some(span.color?.rgb(span.bold && settings.bold_as_bright)))
+                    .unwrap_or(theme.foreground);
+                let mut fill = background;
+                if text.is_some_and(|span| span.reverse) {
+                    (foreground, fill) = (background.unwrap_or(theme.background), Some(foreground));
+                }
+                let background = fill.unwrap_or(theme.background);
+                if let Some(color) = fill {
+                    match filled.last_mut() {
+                        Some(last) if last.row == row && last.columns.end == col && last.color == color => {
+                            last.columns.end = end
+                        }
+                        _ => filled.push(BackgroundSpan { row, columns: col..end, color }),
+                    }
+                }
+                if text.is_some_and(|span| span.dim) {
+                    foreground = mix(foreground, background, DIM_AMOUNT);
+                }
+                let color = ensure_contrast(foreground, background, minimum_contrast);
+                if color == default {
+                    continue;
+                }
+                match runs.last_mut() {
+                    Some((columns, last)) if columns.end == col && *last == color => columns.end = end,
+                    _ => runs.push((col..end, color)),
+                }
+            }
+        }
+        Self { theme, default, rows: colors, backgrounds: filled }
+    }
+}
+
+/// WCAG relative luminance.
+fn luminance(color: Rgb) -> f32 {
+    let [r, g, b, _] = color.to_linear();
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// WCAG contrast ratio, from 1 for the same color to 21 for black on white.
+pub fn contrast_ratio(a: Rgb, b: Rgb) -> f32 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn mix(from: Rgb, to: Rgb, amount: f32) -> Rgb {
+    let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+    Rgb(channel(from.0, to.0), channel(from.1, to.1), channel(from.2, to.2))
+}
+
+/// `foreground`, moved toward white or black as little as it takes to
+/// reach a contrast of `minimum` against `background`. Where neither gets
+/// there, whichever of the two stands out more.
+pub fn ensure_contrast(foreground: Rgb, background: Rgb, minimum: f32) -> Rgb {
+    if contrast_ratio(foreground, background) >= minimum {
+        return foreground;
+    }
+    // Smallest step toward `target` that is enough, by bisection
+    let toward = |target: Rgb| {
+        if contrast_ratio(target, background) < minimum {
+            return None;
+        }
+        let (mut enough, mut short) = (1.0, 0.0);
+        for _ in 0..10 {
+            let amount = (enough + short) / 2.0;
+            if contrast_ratio(mix(foreground, target, amount), background) >= minimum {
+                enough = amount;
+            } else {
+                short = amount;
+            }
+        }
+        Some((enough, mix(foreground, target, enough)))
+    };
+    match (toward(WHITE), toward(BLACK)) {
+        (Some((up, lighter)), Some((down, darker))) => if up <= down { lighter } else { darker },
+        (Some((_, color)), None) | (None, Some((_, color))) => color,
+        (None, None) if contrast_ratio(WHITE, background) >= contrast_ratio(BLACK, background) => WHITE,
+        (None, None) => BLACK,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::{config::ansi_color, terminal::CellColor};
+
+    fn span(columns: Range<usize>, color: Option<CellColor>) -> ForegroundSpan {
+        ForegroundSpan { row: 0, columns, color, bold: false, dim: false, reverse: false }
+    }
+
+    const SETTINGS: ColorSettings = ColorSettings { minimum_contrast: 1.0, bold_as_bright: false };
+
+    #[test]
+    fn text_colors() {
+        let theme = Theme::dark();
+        let foregrounds = [
+            span(0..3, Some(CellColor::Named(1))),
+            span(10..14, Some(CellColor::Rgb(ansi_color(234)))),
+        ];
+        // Text too dark for the dark theme is lightened to the contrast asked
+        // for, and left alone without a minimum
+        let colors = TextColors::resolve(1, &foregrounds, &[], theme, SETTINGS, false);
+        assert_eq!(colors.rows[0], [(0..3, ansi_color(1)), (10..14, ansi_color(234))]);
+        let settings = ColorSettings { minimum_contrast: 4.5, ..SETTINGS };
+        let colors = TextColors::resolve(1, &foregrounds, &[], theme, settings, false);
+        let columns: Vec<_> = colors.rows[0].iter().map(|(columns, _)| columns.clone()).collect();
+        assert_eq!(columns, [0..3, 10..14]);
+        for (_, color) in &colors.rows[0] {
+            assert!(contrast_ratio(*color, theme.background) >= 4.5);
+        }
+        assert_eq!(colors.default, theme.foreground);
+
+        // Bold brightens the first eight colors only when asked to; dim text
+        // moves toward the background
+        let green = span(0..2, Some(CellColor::Named(2)));
+        let foregrounds = [
+            ForegroundSpan { bold: true, ..green.clone() },
+            ForegroundSpan { columns: 2..3, ..green.clone() },
+            ForegroundSpan { columns: 3..8, dim: true, ..green },
+        ];
+        let colors = TextColors::resolve(1, &foregrounds, &[], theme, SETTINGS, false);
+        assert_eq!(colors.rows[0][0], (0..3, ansi_color(2)));
+        let settings = ColorSettings { bold_as_bright: true, ..SETTINGS };
+        let colors = TextColors::resolve(1, &foregrounds, &[], theme, settings, false);
+        assert_eq!(colors.rows[0][0], (0..2, ansi_color(10)));
+        let (columns, faint) = colors.rows[0][2].clone();
+        assert_eq!(columns, 3..8);
+        assert!(contrast_ratio(faint, theme.background) < contrast_ratio(theme.foreground, theme.background));
+    }
+
+    #[test]
+    fn reverse_video() {
+        let theme = Theme::dark();
+        // Reversed cells swap their colors, falling back on the theme's
+        let foregrounds = [
+            ForegroundSpan { reverse: true, ..span(0..2, None) },
+            ForegroundSpan { reverse: true, ..span(3..4, Some(CellColor::Named(1))) },
+        ];
+        let backgrounds = [BackgroundSpan { row: 0, columns: 3..4, color: ansi_color(2) }];
+        let colors = TextColors::resolve(1, &foregrounds, &backgrounds, theme, SETTINGS, false);
+        assert_eq!(colors.theme, theme);
+        let fills: Vec<_> = colors.backgrounds.iter().map(|span| (span.columns.clone(), span.color)).collect();
+        assert_eq!(fills, [(0..2, theme.foreground), (3..4, ansi_color(1))]);
+        assert_eq!(colors.rows[0], [(0..2, theme.background), (3..4, ansi_color(2))]);
+
+        // A reversed screen swaps the theme, so a reversed cell comes out in
+        // the theme's own colors
+        let colors = TextColors::resolve(1, &foregrounds, &[], theme, SETTINGS, true);
+        assert_eq!((colors.theme.foreground, colors.theme.background), (theme.background, theme.foreground));
+        assert_eq!(colors.default, theme.background);
+        assert_eq!(colors.backgrounds[0].color, theme.background);
+        assert_eq!(colors.rows[0][0], (0..2, theme.foreground));
+    }
+}
@@ -1,4 +1,875 @@
 // src/terminal/config.rs
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+#[cfg(feature = "gui")]
+use wgpu::{Backends, PresentMode};
+
+use crate::terminal::{
+    encoding::Encoding,
+    notices,
+    terminal::{UnderlineStyle, DEFAULT_COLS, DEFAULT_ROWS},
+    terminfo,
+};
+
 pub const ATLAS_SIZE: u32 = 2048;
 pub const FONT_SIZE: f32 = 14.0;
 pub const LINE_HEIGHT: f32 = 20.0;
+/// Width of one terminal cell. Double-width characters take two.
+pub const CELL_WIDTH: f32 = FONT_SIZE;
+pub const TAB_BAR_HEIGHT: f32 = 28.0;
+
+/// User configuration, read from `nebula.toml` in the platform config
+/// directory. Every section falls back to its defaults when omitted.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub clipboard: ClipboardConfig,
+    pub scrollback: ScrollbackConfig,
+    pub rendering: RenderingConfig,
+    pub font: FontConfig,
+    pub magnifier: MagnifierConfig,
+    pub theme: ThemeConfig,
+    /// Extra styling for rows matching patterns, such as log levels and
+    /// timestamps, written as `[[highlight]]` tables.
+    pub highlight: Vec<HighlightRule>,
+    pub command_time: CommandTimeConfig,
+    pub cursor: CursorConfig,
+    pub mouse: MouseConfig,
+    pub scrollbar: ScrollbarConfig,
+    pub bell: BellConfig,
+    pub window: WindowConfig,
+    pub gpu: GpuConfig,
+    pub resource_badge: ResourceBadgeConfig,
+    pub idle_trim: IdleTrimConfig,
+    /// Ways of rewriting clipboard text offered by the paste menu, written
+    /// as `[[paste_filter]]` tables.
+    pub paste_filter: Vec<PasteFilter>,
+    /// Titles programs set that are dropped or rewritten, written as
+    /// `[[title_rule]]` tables. The first rule that matches applies.
+    pub title_rule: Vec<TitleRule>,
+    /// Theme, title and keybindings switched while a tab runs a matching
+    /// command or is on a matching host, written as `[[session_rule]]`
+    /// tables. The first rule that matches applies.
+    pub session_rule: Vec<SessionRule>,
+    pub shell: ShellConfig,
+    /// Named alternatives to `[shell]`, written as `[profiles.NAME]`, such
+    /// as one for ssh sessions to hosts with an old terminfo database.
+    /// Settings a profile leaves out take their defaults, not `[shell]`'s.
+    pub profiles: BTreeMap<String, ShellConfig>,
+    /// Profile tabs use in place of `[shell]`, also picked with `--profile`.
+    pub profile: Option<String>,
+    /// Chords mapped to action names, such as `"Ctrl+Shift+T" = "NewTab"`,
+    /// applied over the defaults. `"None"` unbinds a chord.
+    pub keybindings: BTreeMap<String, String>,
+    /// Size of the terminal in a new window.
+    pub geometry: Geometry,
+    /// Private sessions never write scrollback to disk and never log
+    /// terminal input or output. Meant for handling secrets.
+    pub private: bool,
+    /// Ask before closing a tab or window while a command is still running
+    /// in it.
+    pub confirm_close: bool,
+    /// Program and arguments links to local files and paths in output
+    /// open with, such as `["code", "--goto", "{file}:{line}:{column}"]`.
+    /// Without one, files open in $VISUAL or $EDITOR in a new tab, or go to
+    /// the system's opener if neither is set.
+    pub editor: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clipboard: ClipboardConfig::default(),
+            scrollback: ScrollbackConfig::default(),
+            rendering: RenderingConfig::default(),
+            font: FontConfig::default(),
+            magnifier: MagnifierConfig::default(),
+            theme: ThemeConfig::default(),
+            highlight: Vec::new(),
+            command_time: CommandTimeConfig::default(),
+            cursor: CursorConfig::default(),
+            mouse: MouseConfig::default(),
+            scrollbar: ScrollbarConfig::default(),
+            bell: BellConfig::default(),
+            window: WindowConfig::default(),
+            gpu: GpuConfig::default(),
+            resource_badge: ResourceBadgeConfig::default(),
+            idle_trim: IdleTrimConfig::default(),
+            paste_filter: Vec::new(),
+            title_rule: Vec::new(),
+            session_rule: Vec::new(),
+            shell: ShellConfig::default(),
+            profiles: BTreeMap::new(),
+            profile: None,
+            keybindings: BTreeMap::new(),
+            geometry: Geometry::default(),
+            private: false,
+            confirm_close: true,
+            editor: Vec::new(),
+        }
+    }
+}
+
+/// The program each tab runs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ShellConfig {
+    /// Program to start, such as `zsh`, `fish`, `pwsh` or `nu`. Without one
+    /// tabs run `bash --login -i`, or on Windows PowerShell, falling back to
+    /// `cmd.exe`.
+    pub program: Option<String>,
+    /// Arguments passed to `program`.
+    pub args: Vec<String>,
+    /// Environment variables set on top of the ones Nebula provides.
+    pub env: BTreeMap<String, String>,
+    /// Directory new tabs start in unless they open in another tab's.
+    pub working_directory: Option<PathBuf>,
+    /// Start the program again when it exits. When off the tab closes.
+    pub restart: bool,
+    /// With `restart` off, leave the tab open showing the program's last
+    /// output instead of closing it.
+    pub hold: bool,
+    /// `TERM` given to programs. `nebula` falls back to `xterm-256color`
+    /// until `nebula terminfo --install` has been run. Hosts reached over
+    /// ssh need the entry too; a profile with `xterm-256color` suits those.
+    pub term: String,
+    /// Set `COLORTERM=truecolor` so programs use 24-bit color.
+    pub colorterm: bool,
+    /// Encoding the program speaks: `utf-8`, `latin-1` or `shift-jis`, for
+    /// legacy hosts that don't use UTF-8.
+    pub encoding: Encoding,
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        Self {
+            program: None,
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            working_directory: None,
+            restart: true,
+            hold: false,
+            term: String::from(terminfo::NAME),
+            colorterm: true,
+            encoding: Encoding::Utf8,
+        }
+    }
+}
+
+/// Terminal size in cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Geometry {
+    pub columns: u16,
+    pub rows: u16,
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Self { columns: DEFAULT_COLS, rows: DEFAULT_ROWS }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    /// What programs may do with the clipboard through OSC 52.
+    pub osc52: Osc52Access,
+    /// Copies remembered for the paste picker. Zero disables the history.
+    pub history_size: usize,
+    /// Seconds between characters for the `PasteAsTyped` action.
+    pub typed_paste_delay: f32,
+    /// On X11 and Wayland, put selected text in the primary selection and
+    /// paste it with the middle button.
+    pub primary_selection: bool,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            osc52: Osc52Access::default(),
+            history_size: 20,
+            typed_paste_delay: 0.02,
+            primary_selection: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScrollbackConfig {
+    /// Lines of history kept in memory. Older ones are spilled to disk or
+    /// dropped; 0 keeps none.
+    pub lines: usize,
+    /// Move older history to a temporary file instead of discarding it.
+    /// Disable to guarantee terminal output never touches the disk.
+    pub disk_spill: bool,
+}
+
+impl Default for ScrollbackConfig {
+    fn default() -> Self {
+        Self {
+            lines: 10_000,
+            disk_spill: true,
+        }
+    }
+}
+
+/// How glyphs are rasterized. Font rendering is largely a matter of taste,
+/// so none of these are right for everyone.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RenderingConfig {
+    /// Snap outlines to the pixel grid. Crisper at small sizes, at the cost
+    /// of slightly distorted letter shapes.
+    pub hinting: bool,
+    pub antialiasing: Antialiasing,
+    pub strokes: StrokeWeight,
+    /// WGSL file with a fragment shader run over every frame, for effects
+    /// such as CRT curvature or scanlines. It defines
+    /// `@fragment fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>`
+    /// and can sample `frame` with `frame_sampler` at `in.uv`, and read
+    /// `uniforms.resolution` and `uniforms.time`. Relative paths are
+    /// looked up next to this file.
+    pub shader: Option<PathBuf>,
+    pub present_mode: PresentModeConfig,
+    /// Most frames drawn per second, to save battery. Without one, frames
+    /// are drawn as often as the present mode allows.
+    pub max_fps: Option<u32>,
+}
+
+impl Default for RenderingConfig {
+    fn default() -> Self {
+        Self {
+            hinting: true,
+            antialiasing: Antialiasing::default(),
+            strokes: StrokeWeight::default(),
+            shader: None,
+            present_mode: PresentModeConfig::default(),
+            max_fps: None,
+        }
+    }
+}
+
+/// Styling drawn over text matching `pattern`. Only how the text looks
+/// changes; selecting, searching and copying see it as printed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HighlightRule {
+    /// Regular expression matched against the text of each row.
+    pub pattern: String,
+    /// Style the whole row instead of just the match.
+    #[serde(default)]
+    pub line: bool,
+    pub background: Option<Rgb>,
+    pub underline: Option<UnderlineStyle>,
+    pub underline_color: Option<Rgb>,
+    #[serde(default)]
+    pub strikethrough: bool,
+}
+
+/// Rewrites clipboard text before it is pasted, for REPLs that mangle
+/// indentation or text copied with colors in it. The steps run in the
+/// order listed here, then the text is wrapped in `prepend` and `append`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PasteFilter {
+    /// Shown in the paste menu.
+    pub name: String,
+    /// Remove escape sequences, such as colors copied from another terminal.
+    pub strip_ansi: bool,
+    /// Program and arguments to pipe the text through, pasting its output.
+    pub command: Vec<String>,
+    /// Remove the indentation every line shares, and trailing whitespace.
+    pub dedent: bool,
+    /// Drop blank lines, which end a block in REPLs such as Python's.
+    pub drop_blank_lines: bool,
+    /// Join everything into one line, with runs of whitespace made one space.
+    pub collapse_whitespace: bool,
+    pub prepend: String,
+    pub append: String,
+}
+
+/// Drops or rewrites titles set with OSC 0 and 2, for programs that change
+/// the title constantly. A rule needs `pattern`, `process` or both to match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TitleRule {
+    /// Regular expression matched against the title.
+    pub pattern: Option<String>,
+    /// Name of the foreground process setting the title, such as `"vim"`.
+    pub process: Option<String>,
+    /// What replaces the text `pattern` matched, with `$1` for its groups,
+    /// or the whole title without a pattern. Left out, the title is ignored
+    /// and the tab keeps the one it had.
+    pub replace: Option<String>,
+}
+
+/// Changes a tab gets while its foreground command or the host it reported
+/// through OSC 7 matches, such as a red theme for `ssh prod-`. A rule needs
+/// `command`, `host` or both to match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SessionRule {
+    /// Regular expression matched against the foreground command line.
+    pub command: Option<String>,
+    /// Regular expression matched against the host name from OSC 7.
+    pub host: Option<String>,
+    pub theme: Option<Theme>,
+    /// Tab title shown in place of the program's own.
+    pub title: Option<String>,
+    /// Chords mapped to action names, applied over `[keybindings]`.
+    pub keybindings: BTreeMap<String, String>,
+}
+
+/// How long the previous command took, shown faintly at the right of the
+/// prompt after it. Needs a shell that sends OSC 133 marks, and is never
+/// part of copied text.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct CommandTimeConfig {
+    pub enabled: bool,
+    /// Commands that finish quicker than this, in seconds, aren't shown.
+    pub min_seconds: f32,
+    /// Show the time the command finished as well.
+    pub finish_time: bool,
+}
+
+impl Default for CommandTimeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_seconds: 1.0,
+            finish_time: true,
+        }
+    }
+}
+
+/// How the cursor blinks. Programs can still ask for a steady cursor with
+/// DECSCUSR, and it never blinks while the window is unfocused.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct CursorConfig {
+    pub blink: bool,
+    /// Seconds the cursor stays on, and then off, while blinking.
+    pub blink_interval: f32,
+}
+
+impl Default for CursorConfig {
+    fn default() -> Self {
+        Self {
+            blink: true,
+            blink_interval: 0.5,
+        }
+    }
+}
+
+/// How the window sits on the desktop.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    /// Opacity of the terminal background, from 0 to 1. Text and colored
+    /// cells stay opaque. Needs a compositor that supports transparent
+    /// windows.
+    pub opacity: f32,
+    /// Blur what shows through a translucent background. Supported on
+    /// macOS and KDE on Wayland; ignored elsewhere.
+    pub blur: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self { opacity: 1.0, blur: false }
+    }
+}
+
+/// Which GPU draws the window, for machines with more than one.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct GpuConfig {
+    /// Graphics API to use. Without one, every API the platform has is
+    /// considered.
+    pub backend: Option<GpuBackend>,
+    pub power_preference: PowerPreference,
+    /// Use the adapter whose name contains this text, ignoring case, such
+    /// as `"intel"` or `"rtx"`. The names found are in the log.
+    pub adapter: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum GpuBackend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+#[cfg(feature = "gui")]
+impl GpuBackend {
+    pub fn backends(self) -> Backends {
+        match self {
+            GpuBackend::Vulkan => Backends::VULKAN,
+            GpuBackend::Metal => Backends::METAL,
+            GpuBackend::Dx12 => Backends::DX12,
+            GpuBackend::Gl => Backends::GL,
+        }
+    }
+}
+
+/// Kind of GPU preferred when several are found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerPreference {
+    /// An integrated GPU, easier on laptop batteries.
+    #[default]
+    LowPower,
+    /// A discrete GPU.
+    HighPerformance,
+}
+
+/// What happens when a program rings the bell (BEL).
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct BellConfig {
+    pub style: BellStyle,
+    /// Seconds the window flashes for the `visual` style.
+    pub flash_duration: f32,
+}
+
+impl Default for BellConfig {
+    fn default() -> Self {
+        Self {
+            style: BellStyle::Visual,
+            flash_duration: 0.15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BellStyle {
+    /// Play the desktop's alert sound.
+    System,
+    /// Briefly tint the window.
+    #[default]
+    Visual,
+    /// Ask for attention in the taskbar or dock while the window is
+    /// unfocused.
+    Urgent,
+    None,
+}
+
+/// What the mouse wheel does.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct MouseConfig {
+    /// Lines scrolled, or arrow keys sent, per notch of the wheel.
+    pub wheel_lines: usize,
+    /// On the alternate screen, where there is no scrollback, send the
+    /// wheel as Up and Down arrow keys so `less`, `man` and vim scroll.
+    pub alternate_scroll: bool,
+    /// Glide the rows into place when the wheel scrolls the view, rather
+    /// than jumping a line at a time.
+    pub smooth_scroll: bool,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self {
+            wheel_lines: 3,
+            alternate_scroll: true,
+            smooth_scroll: false,
+        }
+    }
+}
+
+/// Bar along the right edge showing where the view is in the scrollback.
+/// Click or drag it to jump there.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct ScrollbarConfig {
+    pub enabled: bool,
+    /// Seconds it stays up after returning to the bottom, before fading.
+    pub fade_delay: f32,
+}
+
+impl Default for ScrollbarConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fade_delay: 1.5,
+        }
+    }
+}
+
+/// CPU and memory use of the command running in each tab, shown next to
+/// its title in the tab bar. Only sampled on Linux.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct ResourceBadgeConfig {
+    pub enabled: bool,
+    /// Seconds between samples.
+    pub interval: f32,
+}
+
+impl Default for ResourceBadgeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 2.0,
+        }
+    }
+}
+
+/// Give back GPU memory once a window has had no input or output for a
+/// while, for long-lived terminals on systems short of video memory.
+/// Everything released is allocated again when next needed.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct IdleTrimConfig {
+    pub enabled: bool,
+    /// Minutes without input or output before trimming.
+    pub minutes: f32,
+    /// Also start the glyph atlas over at a quarter of its size. Glyphs are
+    /// rasterized again, so rows may flicker once when trimmed.
+    pub atlas: bool,
+}
+
+impl Default for IdleTrimConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            minutes: 10.0,
+            atlas: false,
+        }
+    }
+}
+
+/// Fonts rows are drawn with, by family name. Fonts that aren't installed
+/// are skipped with a warning.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FontConfig {
+    /// Primary family, such as `"JetBrains Mono"`.
+    pub family: Option<String>,
+    /// Families tried in order for characters the primary one lacks, such
+    /// as a CJK font, `"Symbols Nerd Font Mono"` and an emoji font.
+    pub fallback: Vec<String>,
+    /// Draw the font's programming ligatures, such as `=>` and `!=`, across
+    /// the cells they join. Off, every character gets its own glyph.
+    pub ligatures: bool,
+}
+
+/// The lens toggled with `ToggleMagnifier`, which shows the area around the
+/// mouse, or the cursor when the mouse is elsewhere, enlarged.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MagnifierConfig {
+    pub zoom: f32,
+    /// Size of the lens in pixels.
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for MagnifierConfig {
+    fn default() -> Self {
+        Self {
+            zoom: 2.0,
+            width: 480.0,
+            height: 200.0,
+        }
+    }
+}
+
+/// How finished frames are handed to the display.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentModeConfig {
+    /// Wait for vertical sync. Supported everywhere.
+    #[default]
+    Fifo,
+    /// Wait for vertical sync, but replace a frame still waiting with a
+    /// newer one instead of blocking.
+    Mailbox,
+    /// Show frames as soon as they are drawn, which may tear.
+    Immediate,
+}
+
+#[cfg(feature = "gui")]
+impl PresentModeConfig {
+    /// Modes to try in order, ending with one every surface supports.
+    pub fn candidates(self) -> &'static [PresentMode] {
+        match self {
+            PresentModeConfig::Fifo => &[PresentMode::Fifo],
+            PresentModeConfig::Mailbox => &[PresentMode::Mailbox, PresentMode::Fifo],
+            PresentModeConfig::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox, PresentMode::Fifo],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Antialiasing {
+    /// Smooth edges with partial coverage.
+    #[default]
+    Grayscale,
+    /// Hard, aliased edges.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrokeWeight {
+    #[default]
+    Normal,
+    /// Lighter strokes, similar to macOS.
+    Thin,
+    /// Embolden stems so light text stays readable on dark backgrounds.
+    Darkened,
+}
+
+/// A 24-bit sRGB color, written `"#rrggbb"` in the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .ok_or_else(|| format!("expected a color like \"#rrggbb\", got {:?}", value))?;
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("invalid color {:?}", value))
+        };
+        Ok(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl Rgb {
+    /// Linear RGBA as the renderer expects; the surface converts back to sRGB.
+    pub fn to_linear(self) -> [f32; 4] {
+        let linear = |c: u8| {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        [linear(self.0), linear(self.1), linear(self.2), 1.0]
+    }
+
+    /// The color as written in the config file.
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+
+    /// Relative luminance, from 0 (black) to 1 (white).
+    pub fn luminance(self) -> f32 {
+        let [r, g, b, _] = self.to_linear();
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    }
+}
+
+/// The 16 ANSI colors, as xterm shows them.
+const ANSI_COLORS: [Rgb; 16] = [
+    Rgb(0x00, 0x00, 0x00),
+    Rgb(0xcd, 0x00, 0x00),
+    Rgb(0x00, 0xcd, 0x00),
+    Rgb(0xcd, 0xcd, 0x00),
+    Rgb(0x00, 0x00, 0xee),
+    Rgb(0xcd, 0x00, 0xcd),
+    Rgb(0x00, 0xcd, 0xcd),
+    Rgb(0xe5, 0xe5, 0xe5),
+    Rgb(0x7f, 0x7f, 0x7f),
+    Rgb(0xff, 0x00, 0x00),
+    Rgb(0x00, 0xff, 0x00),
+    Rgb(0xff, 0xff, 0x00),
+    Rgb(0x5c, 0x5c, 0xff),
+    Rgb(0xff, 0x00, 0xff),
+    Rgb(0x00, 0xff, 0xff),
+    Rgb(0xff, 0xff, 0xff),
+];
+
+/// Color `index` of the 256-color palette: the ANSI colors, a 6x6x6 color
+/// cube and a gray ramp.
+pub fn ansi_color(index: u8) -> Rgb {
+    match index {
+        0..=15 => ANSI_COLORS[index as usize],
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let cube = index - 16;
+            Rgb(level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            Rgb(gray, gray, gray)
+        }
+    }
+}
+
+/// Foreground and background colors of the terminal. Both must be given
+/// when a theme is set in the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Theme {
+    pub foreground: Rgb,
+    pub background: Rgb,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            foreground: Rgb(0xff, 0xff, 0xff),
+            background: Rgb(0x00, 0x00, 0x00),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            foreground: Rgb(0x1e, 0x1e, 0x1e),
+            background: Rgb(0xfa, 0xfa, 0xfa),
+        }
+    }
+
+    pub fn is_dark(&self) -> bool {
+        self.background.luminance() < 0.5
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub mode: ThemeMode,
+    pub dark: Theme,
+    pub light: Theme,
+    /// Least WCAG contrast ratio between text and what is behind it, from 1
+    /// to 21. Text falling short is lightened or darkened until it reaches
+    /// it, so dark-on-dark output stays readable. 1 leaves colors alone;
+    /// 4.5 is the WCAG AA level.
+    pub minimum_contrast: f32,
+    /// Draw bold text in ANSI colors 0 to 7 in their bright versions, as
+    /// many older programs expect.
+    pub bold_as_bright: bool,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            mode: ThemeMode::default(),
+            dark: Theme::dark(),
+            light: Theme::light(),
+            minimum_contrast: 1.0,
+            bold_as_bright: false,
+        }
+    }
+}
+
+impl ThemeConfig {
+    /// Theme to use given whether the OS is in dark mode, if known.
+    pub fn resolve(&self, system_dark: Option<bool>) -> Theme {
+        let dark = match self.mode {
+            ThemeMode::Dark => true,
+            ThemeMode::Light => false,
+            ThemeMode::System => system_dark.unwrap_or(true),
+        };
+        if dark {
+            self.dark
+        } else {
+            self.light
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeMode {
+    #[default]
+    Dark,
+    Light,
+    /// Follow the OS appearance, switching while running.
+    System,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Osc52Access {
+    #[default]
+    Disabled,
+    Copy,
+    Paste,
+    CopyPaste,
+}
+
+impl Osc52Access {
+    pub fn can_copy(self) -> bool {
+        matches!(self, Osc52Access::Copy | Osc52Access::CopyPaste)
+    }
+
+    pub fn can_paste(self) -> bool {
+        matches!(self, Osc52Access::Paste | Osc52Access::CopyPaste)
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("nebula").join("nebula.toml"))
+    }
+
+    /// Run tabs with the shell settings of profile `name`.
+    pub fn apply_profile(&mut self, name: &str) {
+        match self.profiles.get(name) {
+            Some(shell) => {
+                self.shell = shell.clone();
+                self.profile = Some(name.to_string());
+            }
+            None => {
+                notices::warn(format!("No profile named {:?} in the config", name));
+                self.profile = None;
+            }
+        }
+    }
+
+    /// Load the config file, or the default one if `path` is None, falling
+    /// back to defaults if it is missing or invalid.
+    pub fn load(path: Option<&Path>) -> Self {
+        let explicit = path.is_some();
+        let Some(path) = path.map(Path::to_path_buf).or_else(Self::path) else {
+            return Self::default();
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => match toml::from_str::<Self>(&contents) {
+                Ok(mut config) => {
+                    info!("Loaded config from {}", path.display());
+                    if let Some(profile) = config.profile.clone() {
+                        config.apply_profile(&profile);
+                    }
+                    config
+                }
+                Err(e) => {
+                    notices::warn(format!("Invalid config {}: {}", path.display(), e));
+                    Self::default()
+                }
+            },
+            // Only a file asked for on the command line has to exist
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !explicit => Self::default(),
+            Err(e) => {
+                notices::warn(format!("Failed to read config {}: {}", path.display(), e));
+                Self::default()
+            }
+        }
+    }
+}
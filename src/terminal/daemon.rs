@@ -0,0 +1,197 @@
+// src/terminal/daemon.rs
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+use tracing::{debug, info, warn};
+use winit::event_loop::EventLoopProxy;
+
+use crate::terminal::UserEvent;
+
+/// How long either side waits on the other before giving up, so a client
+/// that never sends its request can't hold up the ones after it.
+const EXCHANGE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest request line read, which is mostly the command to run.
+const MAX_REQUEST: u64 = 64 * 1024;
+
+/// A tab asked for by a `nebula` invocation that handed over to a running
+/// daemon, carrying the options it was started with. Sent as one line of
+/// JSON, answered by `ok` or an error on one line.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpenRequest {
+    pub working_directory: Option<PathBuf>,
+    pub command: Option<Vec<String>>,
+    pub title: Option<String>,
+    pub profile: Option<String>,
+    pub hold: bool,
+    pub private: bool,
+}
+
+impl OpenRequest {
+    /// Asks for nothing a fresh shell doesn't already have.
+    pub fn is_plain(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Where the daemon listens: the user's runtime directory or, without one,
+/// a directory of the user's own in the temp directory. Only the user who
+/// started the daemon can connect.
+#[cfg(unix)]
+fn socket_path() -> Result<PathBuf> {
+    let user = unsafe { libc::getuid() };
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir,
+        None => private_dir(&std::env::temp_dir().join(format!("nebula-{}", user)), user)?,
+    };
+    Ok(dir.join(format!("nebula-{}.sock", user)))
+}
+
+/// `dir`, created if missing with access for `user` alone. Anyone can make
+/// paths in the temp directory, so one found there already has to be a
+/// directory `user` owns that nobody else can get into.
+#[cfg(unix)]
+fn private_dir(dir: &std::path::Path, user: u32) -> Result<PathBuf> {
+    use std::{
+        fs::{self, DirBuilder},
+        io::ErrorKind,
+        os::unix::fs::{DirBuilderExt, MetadataExt},
+    };
+
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to create {}", dir.display())),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != user || metadata.mode() & 0o077 != 0 {
+        return Err(anyhow!("{} isn't a private directory of this user, so the daemon can't use it", dir.display()));
+    }
+    Ok(dir.to_path_buf())
+}
+
+/// Hand `request` to a running daemon. Ok(false) if none is running, so
+/// the caller starts a window of its own.
+pub fn request_open(request: &OpenRequest) -> Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::{
+            fs,
+            io::{BufRead, BufReader, ErrorKind, Write},
+            os::unix::{
+                fs::{FileTypeExt, MetadataExt},
+                net::UnixStream,
+            },
+        };
+
+        // Only a socket of our own is told where we are and what we run
+        let path = socket_path()?;
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_socket() && metadata.uid() == unsafe { libc::getuid() } => {}
+            Ok(_) => return Err(anyhow!("{} isn't this user's daemon socket", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e).with_context(|| format!("Failed to check {}", path.display())),
+        }
+        let mut stream = match UnixStream::connect(&path) {
+            Ok(stream) => stream,
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => return Ok(false),
+            Err(e) => return Err(e).context("Failed to reach the Nebula daemon"),
+        };
+        stream.set_read_timeout(Some(EXCHANGE_TIMEOUT))?;
+        stream.set_write_timeout(Some(EXCHANGE_TIMEOUT))?;
+        writeln!(stream, "{}", serde_json::to_string(request)?)?;
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        match reply.trim_end() {
+            "ok" => Ok(true),
+            error => Err(anyhow!("The Nebula daemon refused the window: {}", error)),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = request;
+        Ok(false)
+    }
+}
+
+/// Listens with --daemon for invocations handing over their windows, so
+/// they open without setting up the GPU or loading fonts again.
+pub struct DaemonServer {
+    requests: Receiver<OpenRequest>,
+    path: PathBuf,
+}
+
+impl DaemonServer {
+    #[cfg(unix)]
+    pub fn start(proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        use std::{
+            fs,
+            io::{BufRead, BufReader, Read, Write},
+            os::unix::net::{UnixListener, UnixStream},
+            thread,
+        };
+
+        let path = socket_path()?;
+        if UnixStream::connect(&path).is_ok() {
+            return Err(anyhow!("A Nebula daemon is already listening on {}", path.display()));
+        }
+        // Left behind by a daemon that didn't exit cleanly
+        let _ = fs::remove_file(&path);
+        // Created without access for anyone else, rather than fixed up
+        // after it is already listening
+        let mask = unsafe { libc::umask(0o177) };
+        let bound = UnixListener::bind(&path);
+        unsafe { libc::umask(mask) };
+        let listener = bound.with_context(|| format!("Failed to listen on {}", path.display()))?;
+        info!("Waiting for windows to open on {}", path.display());
+
+        let (sender, requests) = mpsc::channel();
+        thread::Builder::new().name("daemon-listener".into()).spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Failed to accept a daemon client: {}", e);
+                        continue;
+                    }
+                };
+                let _ = stream.set_read_timeout(Some(EXCHANGE_TIMEOUT));
+                let _ = stream.set_write_timeout(Some(EXCHANGE_TIMEOUT));
+                let mut line = String::new();
+                if let Err(e) = BufReader::new((&stream).take(MAX_REQUEST)).read_line(&mut line) {
+                    debug!("Daemon client went away: {}", e);
+                    continue;
+                }
+                let reply = match serde_json::from_str::<OpenRequest>(&line) {
+                    Ok(request) => {
+                        let _ = sender.send(request);
+                        let _ = proxy.send_event(UserEvent::Open);
+                        String::from("ok")
+                    }
+                    Err(e) => format!("bad request: {}", e),
+                };
+                let _ = writeln!(stream, "{}", reply);
+            }
+        })?;
+        Ok(Self { requests, path })
+    }
+
+    #[cfg(not(unix))]
+    pub fn start(_proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        Err(anyhow!("--daemon needs Unix domain sockets, which this platform lacks"))
+    }
+
+    /// Windows asked for since the last call.
+    pub fn take_requests(&self) -> Vec<OpenRequest> {
+        self.requests.try_iter().collect()
+    }
+}
+
+impl Drop for DaemonServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,113 @@
+// src/terminal/decorations.rs
+use cosmic_text::{Buffer, FontSystem, Metrics, Shaping};
+use tracing::debug;
+
+use crate::terminal::{
+    config::{Rgb, CELL_WIDTH, FONT_SIZE, LINE_HEIGHT},
+    fonts::Fonts,
+    render::{push_quad, Quad, Screen},
+    terminal::{DecorationSpan, UnderlineStyle},
+};
+
+/// How each line is drawn by `decorations.wgsl`, passed in the first
+/// texture coordinate.
+const STRAIGHT: f32 = 0.0;
+const CURLY: f32 = 1.0;
+const DOTTED: f32 = 2.0;
+const DASHED: f32 = 3.0;
+
+/// Where underlines and strikethrough go in a cell, in pixels from its top,
+/// taken from the font the rows are shaped with.
+#[derive(Debug, Clone, Copy)]
+pub struct DecorationMetrics {
+    pub underline_top: f32,
+    pub strikeout_top: f32,
+    pub thickness: f32,
+}
+
+impl Default for DecorationMetrics {
+    fn default() -> Self {
+        Self {
+            underline_top: LINE_HEIGHT * 0.8,
+            strikeout_top: LINE_HEIGHT * 0.5,
+            thickness: 1.0,
+        }
+    }
+}
+
+impl DecorationMetrics {
+    /// Read the metrics of the font a row of text is shaped with.
+    pub fn measure(fs: &mut FontSystem, fonts: &Fonts) -> Self {
+        let mut buffer = Buffer::new(fs, Metrics::new(FONT_SIZE, LINE_HEIGHT));
+        buffer.set_text(fs, "x", &fonts.attrs(), Shaping::Advanced);
+        buffer.shape_until_scroll(fs, true);
+        let Some((baseline, font_id)) = buffer
+            .layout_runs()
+            .find_map(|run| Some((run.line_y - run.line_top, run.glyphs.first()?.font_id)))
+        else {
+            return Self::default();
+        };
+        let Some(font) = fs.get_font(font_id) else { return Self::default() };
+        let metrics = font.as_swash().metrics(&[]).scale(FONT_SIZE);
+        // Offsets are to the top of each stroke, up from the baseline
+        let thickness = metrics.stroke_size.round().max(1.0);
+        let decorations = Self {
+            underline_top: (baseline - metrics.underline_offset).round().min(LINE_HEIGHT - thickness),
+            strikeout_top: (baseline - metrics.strikeout_offset).round(),
+            thickness,
+        };
+        debug!("Decoration metrics: {:?}", decorations);
+        decorations
+    }
+}
+
+/// Quads for the underlines and strikethrough of `spans`, for drawing with
+/// the decoration pipeline over the text.
+pub fn push_decorations(
+    quads: &mut Vec<Quad>,
+    screen: Screen,
+    top: f32,
+    metrics: DecorationMetrics,
+    spans: &[DecorationSpan],
+    foreground: Rgb,
+) {
+    let thickness = metrics.thickness;
+    for span in spans {
+        let x = span.columns.start as f32 * CELL_WIDTH;
+        let width = span.columns.len() as f32 * CELL_WIDTH;
+        let y = top + span.row as f32 * LINE_HEIGHT;
+        // The shader needs the size in pixels to place dots and waves
+        let mut line = |line_top: f32, height: f32, style: f32, color: Rgb| {
+            push_quad(quads, screen, (x, y + line_top, width, height), [style, thickness, width, height], color.to_linear());
+        };
+
+        let underline_color = span.underline_color.unwrap_or(foreground);
+        let underline_top = metrics.underline_top;
+        match span.underline {
+            Some(UnderlineStyle::Single) => line(underline_top, thickness, STRAIGHT, underline_color),
+            Some(UnderlineStyle::Double) => {
+                // The second line goes above if there is no room below
+                let gap = 2.0 * thickness;
+                let second = if underline_top + gap + thickness <= LINE_HEIGHT {
+                    underline_top + gap
+                } else {
+                    underline_top - gap
+                };
+                line(underline_top, thickness, STRAIGHT, underline_color);
+                line(second, thickness, STRAIGHT, underline_color);
+            }
+            // A wave a few pixels tall, kept inside the cell
+            Some(UnderlineStyle::Curly) => {
+                let height = (3.0 * thickness).max(4.0);
+                let wave_top = (underline_top - height / 2.0).min(LINE_HEIGHT - height);
+                line(wave_top, height, CURLY, underline_color);
+            }
+            Some(UnderlineStyle::Dotted) => line(underline_top, thickness, DOTTED, underline_color),
+            Some(UnderlineStyle::Dashed) => line(underline_top, thickness, DASHED, underline_color),
+            None => (),
+        }
+        if span.strikethrough {
+            line(metrics.strikeout_top, thickness, STRAIGHT, foreground);
+        }
+    }
+}
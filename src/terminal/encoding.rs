@@ -0,0 +1,153 @@
+// src/terminal/encoding.rs
+use serde::Deserialize;
+use std::io::{self, Write};
+
+/// Character encoding a profile's programs read and write. The grid is
+/// always UTF-8; PTY traffic is converted on the way in and out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Encoding {
+    #[default]
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    /// ISO 8859-1, where every byte is the code point of the same value.
+    #[serde(rename = "latin-1", alias = "latin1", alias = "iso-8859-1")]
+    Latin1,
+    #[serde(rename = "shift-jis", alias = "shift_jis", alias = "sjis")]
+    ShiftJis,
+}
+
+impl Encoding {
+    /// Locale telling programs to write this encoding.
+    pub fn locale(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "en_US.UTF-8",
+            Encoding::Latin1 => "en_US.ISO-8859-1",
+            Encoding::ShiftJis => "ja_JP.SJIS",
+        }
+    }
+
+    fn codec(self) -> Option<&'static encoding_rs::Encoding> {
+        match self {
+            Encoding::ShiftJis => Some(encoding_rs::SHIFT_JIS),
+            Encoding::Utf8 | Encoding::Latin1 => None,
+        }
+    }
+}
+
+/// Turns PTY output in a profile's encoding into UTF-8 for the parser.
+pub struct OutputDecoder {
+    encoding: Encoding,
+    decoder: Option<encoding_rs::Decoder>,
+    text: String,
+}
+
+impl OutputDecoder {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            decoder: encoding.codec().map(|codec| codec.new_decoder_without_bom_handling()),
+            text: String::new(),
+        }
+    }
+
+    /// `bytes` as UTF-8. The start of a character split across reads is
+    /// held until the rest arrives.
+    pub fn decode<'a>(&'a mut self, bytes: &'a [u8]) -> &'a [u8] {
+        self.text.clear();
+        match (self.encoding, &mut self.decoder) {
+            (Encoding::Latin1, _) => self.text.extend(bytes.iter().map(|&byte| byte as char)),
+            (_, Some(decoder)) => {
+                let needed = decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3 + 4);
+                // With room for the worst case the whole input is decoded
+                self.text.reserve(needed);
+                let _ = decoder.decode_to_string(bytes, &mut self.text, false);
+            }
+            _ => return bytes,
+        }
+        self.text.as_bytes()
+    }
+}
+
+/// Writes UTF-8 input to the PTY in a profile's encoding. Characters the
+/// encoding lacks are sent as `?`.
+pub struct EncodingWriter<W> {
+    inner: W,
+    encoding: Encoding,
+    encoder: Option<encoding_rs::Encoder>,
+    /// The start of a UTF-8 character split across writes.
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    pub fn new(inner: W, encoding: Encoding) -> Self {
+        Self {
+            inner,
+            encoding,
+            encoder: encoding.codec().map(|codec| codec.new_encoder()),
+            pending: Vec::new(),
+        }
+    }
+
+    fn encode(&mut self, text: &str) -> Vec<u8> {
+        let Some(encoder) = &mut self.encoder else {
+            return text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect();
+        };
+        let mut encoded = Vec::new();
+        let mut rest = text;
+        loop {
+            let needed = encoder.max_buffer_length_from_utf8_without_replacement(rest.len()).unwrap_or(rest.len() * 4);
+            encoded.reserve(needed);
+            let (result, read) = encoder.encode_from_utf8_to_vec_without_replacement(rest, &mut encoded, false);
+            rest = &rest[read..];
+            match result {
+                encoding_rs::EncoderResult::InputEmpty => return encoded,
+                encoding_rs::EncoderResult::OutputFull => {}
+                encoding_rs::EncoderResult::Unmappable(_) => encoded.push(b'?'),
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.encoding == Encoding::Utf8 {
+            return self.inner.write(buf);
+        }
+        self.pending.extend_from_slice(buf);
+        let complete = match std::str::from_utf8(&self.pending) {
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            _ => self.pending.len(),
+        };
+        let text = String::from_utf8_lossy(&self.pending[..complete]).into_owned();
+        self.pending.drain(..complete);
+        let encoded = self.encode(&text);
+        self.inner.write_all(&encoded)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_encodings() {
+        // A Shift-JIS character split across reads is held until complete
+        let mut decoder = OutputDecoder::new(Encoding::ShiftJis);
+        assert_eq!(decoder.decode(b"\x93\xfa"), "日".as_bytes());
+        assert_eq!(decoder.decode(b"\x96"), b"");
+        assert_eq!(decoder.decode(b"\x7b\x1b[m"), "本\x1b[m".as_bytes());
+        let mut decoder = OutputDecoder::new(Encoding::Latin1);
+        assert_eq!(decoder.decode(b"caf\xe9"), "café".as_bytes());
+
+        let mut written = Vec::new();
+        let mut writer = EncodingWriter::new(&mut written, Encoding::Latin1);
+        writer.write_all(&"café €".as_bytes()[..4]).unwrap();
+        writer.write_all(&"café €".as_bytes()[4..]).unwrap();
+        assert_eq!(written, b"caf\xe9 ?");
+    }
+}
@@ -0,0 +1,148 @@
+// src/terminal/fonts.rs
+use cosmic_text::{fontdb, Attrs, Fallback, Family, FeatureTag, FontFeatures, FontSystem, PlatformFallback};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Mutex,
+};
+use tracing::info;
+use unicode_script::Script;
+
+use crate::terminal::{config::FontConfig, notices};
+
+/// The configured fonts that are installed, by the names the font database
+/// knows them by.
+#[derive(Debug, Clone, Default)]
+pub struct Fonts {
+    /// Rows are shaped with this, or cosmic-text's default without one.
+    pub family: Option<String>,
+    /// Tried in order for characters `family` lacks, before the platform's
+    /// own fallbacks. Leaked, since cosmic-text wants static names.
+    pub fallback: &'static [&'static str],
+    /// Let fonts join characters into ligatures spanning their cells.
+    pub ligatures: bool,
+}
+
+impl Fonts {
+    /// Check the configured families against `db`, leaving out and warning
+    /// about any that aren't installed.
+    pub fn resolve(config: &FontConfig, db: &fontdb::Database) -> Self {
+        let family = config.family.as_deref().and_then(|name| {
+            let found = installed_family(db, name);
+            if found.is_none() {
+                notices::warn(format!("Font \"{}\" is not installed, using the default font", name));
+            }
+            found
+        });
+
+        let mut fallback = Vec::new();
+        for name in &config.fallback {
+            match installed_family(db, name) {
+                Some(found) => fallback.push(&*Box::leak(found.into_boxed_str())),
+                None => notices::warn(format!("Fallback font \"{}\" is not installed, skipping it", name)),
+            }
+        }
+        if family.is_some() || !fallback.is_empty() {
+            info!("Using font {:?} with fallbacks {:?}", family, fallback);
+        }
+        Self {
+            family,
+            fallback: Box::leak(fallback.into_boxed_slice()),
+            ligatures: config.ligatures,
+        }
+    }
+
+    /// Attributes rows of terminal text are shaped with. Without ligatures
+    /// the features fonts build them from are turned off, as the kerning
+    /// and joins they need don't survive being snapped to cells.
+    pub fn attrs(&self) -> Attrs<'_> {
+        let attrs = match &self.family {
+            Some(name) => Attrs::new().family(Family::Name(name)),
+            None => Attrs::new(),
+        };
+        if self.ligatures {
+            return attrs;
+        }
+        let mut features = FontFeatures::new();
+        features
+            .disable(FeatureTag::STANDARD_LIGATURES)
+            .disable(FeatureTag::CONTEXTUAL_ALTERNATES)
+            .disable(FeatureTag::DISCRETIONARY_LIGATURES);
+        attrs.font_features(features)
+    }
+
+    /// A font system over `db` that falls back through the configured fonts.
+    pub fn font_system(&self, locale: String, db: fontdb::Database) -> FontSystem {
+        if self.fallback.is_empty() {
+            return FontSystem::new_with_locale_and_db(locale, db);
+        }
+        let platform = PlatformFallback;
+        let common = [self.fallback, platform.common_fallback()].concat();
+        let fallback = ConfiguredFallback {
+            configured: self.fallback,
+            common: Box::leak(common.into_boxed_slice()),
+            platform,
+            scripts: Mutex::new(HashMap::new()),
+        };
+        FontSystem::new_with_locale_and_db_and_fallback(locale, db, fallback)
+    }
+}
+
+/// Load fonts installed since `db` was, such as a Nerd Font added while
+/// Nebula runs. The faces already in `db` keep their ids, so glyphs
+/// rasterized from them stay valid. Returns the number of files added.
+pub fn load_new_fonts(db: &mut fontdb::Database) -> usize {
+    let path = |face: &fontdb::FaceInfo| match &face.source {
+        fontdb::Source::File(path) | fontdb::Source::SharedFile(path, _) => Some(path.clone()),
+        fontdb::Source::Binary(_) => None,
+    };
+    let mut known: HashSet<PathBuf> = db.faces().filter_map(path).collect();
+    let mut system = fontdb::Database::new();
+    system.load_system_fonts();
+
+    let mut added = 0;
+    for new in system.faces().filter_map(path) {
+        if known.insert(new.clone()) && db.load_font_file(&new).is_ok() {
+            added += 1;
+        }
+    }
+    added
+}
+
+/// The name `db` has for the family called `name`, ignoring case.
+fn installed_family(db: &fontdb::Database, name: &str) -> Option<String> {
+    db.faces()
+        .flat_map(|face| face.families.iter())
+        .find(|(family, _)| family.eq_ignore_ascii_case(name))
+        .map(|(family, _)| family.clone())
+}
+
+/// Puts the configured fonts ahead of the platform's, both for the script
+/// of the text and in the common list tried after it, so a configured CJK
+/// font wins over the one the platform picks for Han.
+struct ConfiguredFallback {
+    configured: &'static [&'static str],
+    common: &'static [&'static str],
+    platform: PlatformFallback,
+    /// Joined lists, made once per script. The locale never changes for a
+    /// font system, so it isn't part of the key.
+    scripts: Mutex<HashMap<Script, &'static [&'static str]>>,
+}
+
+impl Fallback for ConfiguredFallback {
+    fn common_fallback(&self) -> &[&'static str] {
+        self.common
+    }
+
+    fn forbidden_fallback(&self) -> &[&'static str] {
+        self.platform.forbidden_fallback()
+    }
+
+    fn script_fallback(&self, script: Script, locale: &str) -> &[&'static str] {
+        let mut scripts = self.scripts.lock().unwrap();
+        scripts.entry(script).or_insert_with(|| {
+            let joined = [self.configured, self.platform.script_fallback(script, locale)].concat();
+            Box::leak(joined.into_boxed_slice())
+        })
+    }
+}
@@ -1,10 +1,27 @@
+use anyhow::{anyhow, Result};
+use tracing::info;
 use wgpu::{
-    Device, RenderPipeline, SurfaceConfiguration, VertexBufferLayout, VertexAttribute,
-    VertexStepMode, VertexFormat, BindGroupLayout, PipelineLayout, ShaderModule,
+    util::DeviceExt, Adapter, BindGroupLayout, Buffer, BufferDescriptor, BufferUsages, Device, DeviceType, Instance,
+    PipelineLayout, Queue, RenderPass, RenderPipeline, ShaderModule, SurfaceConfiguration, VertexAttribute,
+    VertexBufferLayout, VertexFormat, VertexStepMode,
 };
+use crate::terminal::{
+    config::{GpuConfig, PowerPreference},
+    notices,
+    render::Quad,
+};
+
+/// Corners of the one quad every instance is drawn from, as two triangles.
+pub const QUAD_CORNERS: [[f32; 2]; 6] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
 
 pub struct GpuResources {
     pub pipeline: RenderPipeline,
+    /// Copies a texture as is, for the magnifier.
+    pub magnifier_pipeline: RenderPipeline,
+    /// Underlines and strikethrough, drawn from their size rather than a
+    /// texture.
+    pub decoration_pipeline: RenderPipeline,
+    corners: Buffer,
 }
 
 impl GpuResources {
@@ -22,7 +39,111 @@ impl GpuResources {
         });
 
         let pipeline = create_pipeline(device, config, &shader, &pipeline_layout);
-        Self { pipeline }
+        let magnifier_shader = device.create_shader_module(wgpu::include_wgsl!("shaders/magnifier.wgsl"));
+        let magnifier_pipeline = create_pipeline(device, config, &magnifier_shader, &pipeline_layout);
+        let decoration_shader = device.create_shader_module(wgpu::include_wgsl!("shaders/decorations.wgsl"));
+        let decoration_pipeline = create_pipeline(device, config, &decoration_shader, &pipeline_layout);
+        let corners = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Quad Corners"),
+            contents: bytemuck::cast_slice(&QUAD_CORNERS),
+            usage: BufferUsages::VERTEX,
+        });
+        Self { pipeline, magnifier_pipeline, decoration_pipeline, corners }
+    }
+
+    /// Bind the shared quad. Instances are bound separately, in slot 1.
+    pub fn bind_quad(&self, rpass: &mut RenderPass) {
+        rpass.set_vertex_buffer(0, self.corners.slice(..));
+    }
+}
+
+/// The adapter `config` asks for among those `instance` finds: one whose
+/// name matches, of the preferred kind, and never a software rasterizer
+/// while there is a real GPU.
+pub fn choose_adapter(instance: &Instance, config: &GpuConfig) -> Result<Adapter> {
+    let backends = config.backend.map_or(wgpu::Backends::all(), |backend| backend.backends());
+    let mut adapters = instance.enumerate_adapters(backends);
+    for adapter in &adapters {
+        let info = adapter.get_info();
+        info!("Found GPU adapter {:?} ({:?}, {:?})", info.name, info.backend, info.device_type);
+    }
+    if adapters.is_empty() {
+        return Err(anyhow!("Failed to find suitable GPU adapter"));
+    }
+
+    if let Some(wanted) = &config.adapter {
+        let wanted = wanted.to_lowercase();
+        let matches = |adapter: &Adapter| adapter.get_info().name.to_lowercase().contains(&wanted);
+        if adapters.iter().any(matches) {
+            adapters.retain(matches);
+        } else {
+            notices::warn(format!("No GPU adapter named like {:?}; choosing another", wanted));
+        }
+    }
+
+    let rank = |device_type: DeviceType| match (config.power_preference, device_type) {
+        (PowerPreference::LowPower, DeviceType::IntegratedGpu) => 0,
+        (PowerPreference::LowPower, DeviceType::DiscreteGpu) => 1,
+        (PowerPreference::HighPerformance, DeviceType::DiscreteGpu) => 0,
+        (PowerPreference::HighPerformance, DeviceType::IntegratedGpu) => 1,
+        (_, DeviceType::VirtualGpu) => 2,
+        (_, DeviceType::Other) => 3,
+        (_, DeviceType::Cpu) => 4,
+    };
+    // Stable, so ties keep the order the platform listed them in
+    adapters.sort_by_key(|adapter| rank(adapter.get_info().device_type));
+    let adapter = adapters.swap_remove(0);
+    let info = adapter.get_info();
+    info!(
+        "Using GPU adapter {:?} ({:?}, {:?}, driver {:?})",
+        info.name, info.backend, info.device_type, info.driver
+    );
+    Ok(adapter)
+}
+
+/// Quads written with `Queue::write_buffer` into a buffer kept across
+/// frames, which only grows when they no longer fit.
+pub struct QuadBuffer {
+    label: &'static str,
+    buffer: Option<Buffer>,
+    len: u32,
+}
+
+impl QuadBuffer {
+    pub fn new(label: &'static str) -> Self {
+        Self { label, buffer: None, len: 0 }
+    }
+
+    pub fn write(&mut self, device: &Device, queue: &Queue, quads: &[Quad]) {
+        self.len = quads.len() as u32;
+        if quads.is_empty() {
+            return;
+        }
+        let size = std::mem::size_of_val(quads) as u64;
+        if self.buffer.as_ref().is_none_or(|buffer| buffer.size() < size) {
+            self.buffer = Some(device.create_buffer(&BufferDescriptor {
+                label: Some(self.label),
+                size: size.next_power_of_two(),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let Some(buffer) = &self.buffer {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(quads));
+        }
+    }
+
+    /// Free the buffer, which only ever grows, until quads are next written.
+    pub fn release(&mut self) {
+        self.buffer = None;
+        self.len = 0;
+    }
+
+    /// Draw the quads in `range`, all of them by default.
+    pub fn draw(&self, rpass: &mut RenderPass, range: Option<std::ops::Range<u32>>) {
+        let Some(buffer) = self.buffer.as_ref().filter(|_| self.len > 0) else { return };
+        rpass.set_vertex_buffer(1, buffer.slice(..));
+        rpass.draw(0..QUAD_CORNERS.len() as u32, range.unwrap_or(0..self.len));
     }
 }
 
@@ -38,22 +159,38 @@ fn create_pipeline(
         vertex: wgpu::VertexState {
             module: shader,
             entry_point: Some("vs_main"),
-            buffers: &[VertexBufferLayout {
-    array_stride: std::mem::size_of::<[f32; 4]>() as u64,
-    step_mode: VertexStepMode::Vertex,
-    attributes: &[
-        VertexAttribute { // position
-            format: VertexFormat::Float32x2,
-            offset: 0,
-            shader_location: 0,
-        },
-        VertexAttribute { // tex_coord
-            format: VertexFormat::Float32x2,
-            offset: std::mem::size_of::<[f32; 2]>() as u64,
-            shader_location: 1,
-        },
-    ],
-}],
+            buffers: &[
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 2]>() as u64,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &[VertexAttribute { // corner
+                        format: VertexFormat::Float32x2,
+                        offset: 0,
+                        shader_location: 0,
+                    }],
+                },
+                VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Quad>() as u64,
+                    step_mode: VertexStepMode::Instance,
+                    attributes: &[
+                        VertexAttribute { // rect: left, top, right, bottom
+                            format: VertexFormat::Float32x4,
+                            offset: 0,
+                            shader_location: 1,
+                        },
+                        VertexAttribute { // tex_rect
+                            format: VertexFormat::Float32x4,
+                            offset: std::mem::size_of::<[f32; 4]>() as u64,
+                            shader_location: 2,
+                        },
+                        VertexAttribute { // color
+                            format: VertexFormat::Float32x4,
+                            offset: std::mem::size_of::<[f32; 8]>() as u64,
+                            shader_location: 3,
+                        },
+                    ],
+                },
+            ],
             compilation_options: Default::default(),
         },
         fragment: Some(wgpu::FragmentState {
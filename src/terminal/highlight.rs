@@ -0,0 +1,98 @@
+// src/terminal/highlight.rs
+use regex::Regex;
+use std::{collections::HashMap, ops::Range};
+
+use crate::terminal::{
+    config::HighlightRule,
+    links::column_at,
+    notices,
+    terminal::{BackgroundSpan, DecorationSpan},
+};
+
+/// Columns of a row a rule matched, and the rule.
+type Highlight = (Range<usize>, usize);
+
+/// Styling for rows in view from the config's highlight rules, drawn over
+/// what the program printed.
+#[derive(Debug, Default)]
+pub struct Highlighter {
+    rules: Vec<(Regex, HighlightRule)>,
+    /// Matches by row text, so rows that haven't changed since the last
+    /// frame are not searched again.
+    cache: HashMap<String, Vec<Highlight>>,
+}
+
+/// Backgrounds and lines to draw along with the session's own.
+#[derive(Debug, Default)]
+pub struct Highlights {
+    pub backgrounds: Vec<BackgroundSpan>,
+    pub decorations: Vec<DecorationSpan>,
+}
+
+impl Highlighter {
+    /// Compile `rules`, skipping with a warning any whose pattern is invalid.
+    pub fn new(rules: &[HighlightRule]) -> Self {
+        let rules = rules
+            .iter()
+            .filter_map(|rule| match Regex::new(&rule.pattern) {
+                Ok(regex) => Some((regex, rule.clone())),
+                Err(e) => {
+                    notices::warn(format!("Ignoring highlight pattern {:?}: {}", rule.pattern, e));
+                    None
+                }
+            })
+            .collect();
+        Self { rules, cache: HashMap::new() }
+    }
+
+    /// Spans for the rules matching `rows`. Only the rows passed in are
+    /// remembered for next time.
+    pub fn annotate(&mut self, rows: &[String]) -> Highlights {
+        let mut highlights = Highlights::default();
+        if self.rules.is_empty() {
+            return highlights;
+        }
+        let mut previous = std::mem::take(&mut self.cache);
+        for (row, text) in rows.iter().enumerate() {
+            let matches = previous
+                .remove(text)
+                .or_else(|| self.cache.get(text).cloned())
+                .unwrap_or_else(|| find_highlights(&self.rules, text));
+            for (columns, index) in &matches {
+                let rule = &self.rules[*index].1;
+                if let Some(color) = rule.background {
+                    highlights.backgrounds.push(BackgroundSpan { row, columns: columns.clone(), color });
+                }
+                if rule.underline.is_some() || rule.strikethrough {
+                    highlights.decorations.push(DecorationSpan {
+                        row,
+                        columns: columns.clone(),
+                        underline: rule.underline,
+                        underline_color: rule.underline_color,
+                        strikethrough: rule.strikethrough,
+                    });
+                }
+            }
+            self.cache.insert(text.clone(), matches);
+        }
+        highlights
+    }
+}
+
+/// Every rule's matches in a row, in rule order so later rules are drawn
+/// over earlier ones.
+fn find_highlights(rules: &[(Regex, HighlightRule)], text: &str) -> Vec<Highlight> {
+    let mut matches = Vec::new();
+    for (index, (regex, rule)) in rules.iter().enumerate() {
+        if rule.line {
+            if regex.is_match(text) {
+                matches.push((0..column_at(text, text.len()), index));
+            }
+            continue;
+        }
+        for found in regex.find_iter(text).filter(|found| !found.is_empty()) {
+            matches.push((column_at(text, found.start())..column_at(text, found.end()), index));
+        }
+    }
+    matches
+}
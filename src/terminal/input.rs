@@ -1,51 +1,269 @@
 use winit::{
     event::{ElementState, KeyEvent},
-    keyboard::{Key, NamedKey},
+    keyboard::{Key, KeyLocation, ModifiersState, NamedKey},
 };
 use std::io::Write;
-use crate::terminal::TerminalState;
+use std::sync::Mutex;
 use anyhow::Result;
+use tracing::trace;
+
+use crate::terminal::terminal::TerminalModes;
+
+/// xterm modifier parameter: 1 plus a bit each for Shift, Alt and Ctrl.
+/// `None` when no modifier is held.
+fn modifier_param(modifiers: ModifiersState) -> Option<u8> {
+    let mut param = 0;
+    if modifiers.shift_key() {
+        param |= 1;
+    }
+    if modifiers.alt_key() {
+        param |= 2;
+    }
+    if modifiers.control_key() {
+        param |= 4;
+    }
+    (param != 0).then_some(param + 1)
+}
+
+/// Key with an SS3/CSI final byte (arrows, Home, End, F1-F4). Unmodified
+/// keys send `CSI code`, or `SS3 code` when `ss3` is set; modified keys send
+/// `CSI 1 ; mod code`.
+fn letter_key(code: u8, ss3: bool, modifiers: ModifiersState) -> Vec<u8> {
+    match modifier_param(modifiers) {
+        Some(param) => format!("\x1B[1;{}{}", param, code as char).into_bytes(),
+        None if ss3 => vec![0x1B, b'O', code],
+        None => vec![0x1B, b'[', code],
+    }
+}
+
+/// Key sent as `CSI number ~`, or `CSI number ; mod ~` when modified.
+fn tilde_key(number: u8, modifiers: ModifiersState) -> Vec<u8> {
+    match modifier_param(modifiers) {
+        Some(param) => format!("\x1B[{};{}~", number, param).into_bytes(),
+        None => format!("\x1B[{}~", number).into_bytes(),
+    }
+}
+
+/// `SS3` sequence sent by a numeric keypad key in application keypad mode.
+fn keypad_key(key: &Key) -> Option<[u8; 3]> {
+    let code = match key.as_ref() {
+        Key::Named(NamedKey::Enter) => b'M',
+        Key::Character(ch) => match ch.as_bytes() {
+            [digit @ b'0'..=b'9'] => b'p' + (digit - b'0'),
+            b"." => b'n',
+            b"+" => b'k',
+            b"-" => b'm',
+            b"*" => b'j',
+            b"/" => b'o',
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some([0x1B, b'O', code])
+}
+
+/// Control byte produced by Ctrl plus `ch`, following xterm.
+fn control_byte(ch: char) -> Option<u8> {
+    match ch.to_ascii_lowercase() {
+        c @ 'a'..='z' => Some(c as u8 - b'a' + 1),
+        '@' | ' ' | '2' => Some(0x00),
+        '[' | '3' => Some(0x1B),
+        '\\' | '4' => Some(0x1C),
+        ']' | '5' => Some(0x1D),
+        '^' | '6' => Some(0x1E),
+        '_' | '-' | '7' => Some(0x1F),
+        '?' | '8' => Some(0x7F),
+        _ => None,
+    }
+}
+
+/// Bytes for a named (non-text) key, or `None` if it sends nothing.
+pub fn named_key(named: NamedKey, modifiers: ModifiersState, modes: TerminalModes) -> Option<Vec<u8>> {
+    let app_cursor = modes.app_cursor;
+    let bytes = match named {
+        NamedKey::ArrowUp => letter_key(b'A', app_cursor, modifiers),
+        NamedKey::ArrowDown => letter_key(b'B', app_cursor, modifiers),
+        NamedKey::ArrowRight => letter_key(b'C', app_cursor, modifiers),
+        NamedKey::ArrowLeft => letter_key(b'D', app_cursor, modifiers),
+        NamedKey::Home => letter_key(b'H', app_cursor, modifiers),
+        NamedKey::End => letter_key(b'F', app_cursor, modifiers),
+        NamedKey::Insert => tilde_key(2, modifiers),
+        NamedKey::Delete => tilde_key(3, modifiers),
+        NamedKey::PageUp => tilde_key(5, modifiers),
+        NamedKey::PageDown => tilde_key(6, modifiers),
+        NamedKey::F1 => letter_key(b'P', true, modifiers),
+        NamedKey::F2 => letter_key(b'Q', true, modifiers),
+        NamedKey::F3 => letter_key(b'R', true, modifiers),
+        NamedKey::F4 => letter_key(b'S', true, modifiers),
+        NamedKey::F5 => tilde_key(15, modifiers),
+        NamedKey::F6 => tilde_key(17, modifiers),
+        NamedKey::F7 => tilde_key(18, modifiers),
+        NamedKey::F8 => tilde_key(19, modifiers),
+        NamedKey::F9 => tilde_key(20, modifiers),
+        NamedKey::F10 => tilde_key(21, modifiers),
+        NamedKey::F11 => tilde_key(23, modifiers),
+        NamedKey::F12 => tilde_key(24, modifiers),
+        NamedKey::Tab if modifiers.shift_key() => b"\x1B[Z".to_vec(),
+        NamedKey::Tab => vec![0x09],
+        NamedKey::Enter => vec![0x0D],
+        // Ctrl+Backspace deletes a word in readline, which expects ^H
+        NamedKey::Backspace if modifiers.control_key() => vec![0x08],
+        NamedKey::Backspace => vec![0x7F],
+        NamedKey::Escape => vec![0x1B],
+        NamedKey::Space if modifiers.control_key() => vec![0x00],
+        NamedKey::Space => vec![b' '],
+        _ => return None,
+    };
+
+    // Keys without their own modified form take Alt as an ESC prefix
+    let has_modified_form = bytes.len() > 1;
+    if modifiers.alt_key() && !has_modified_form {
+        Some([&[0x1B], bytes.as_slice()].concat())
+    } else {
+        Some(bytes)
+    }
+}
+
+/// Bytes sent to the PTY for a key press.
+pub fn encode_key(key_event: &KeyEvent, modifiers: ModifiersState, modes: TerminalModes) -> Vec<u8> {
+    if modes.app_keypad && key_event.location == KeyLocation::Numpad {
+        if let Some(sequence) = keypad_key(&key_event.logical_key) {
+            return sequence.to_vec();
+        }
+    }
+
+    match key_event.logical_key.as_ref() {
+        Key::Named(named) => named_key(named, modifiers, modes).unwrap_or_default(),
+        Key::Character(ch) => {
+            let mut bytes = Vec::new();
+            if modifiers.alt_key() {
+                bytes.push(0x1B);
+            }
+
+            let control = ch
+                .chars()
+                .next()
+                .filter(|_| modifiers.control_key())
+                .and_then(control_byte);
+            match control {
+                Some(byte) => bytes.push(byte),
+                None => {
+                    // Prefer the text winit produced, which accounts for Shift and layout
+                    let text = key_event.text.as_deref().unwrap_or(ch);
+                    bytes.extend_from_slice(text.as_bytes());
+                }
+            }
+            bytes
+        }
+        _ => Vec::new(),
+    }
+}
 
 pub fn handle_input(
     key_event: &KeyEvent,
     writer: &mut dyn Write,
-    state: &mut TerminalState
+    dirty: &Mutex<bool>,
+    private: bool,
+    modifiers: ModifiersState,
+    modes: TerminalModes,
 ) -> Result<()> {
     if key_event.state == ElementState::Pressed {
-        let mut input_bytes = Vec::new();
-        
-        // Handle both text and Character variants
-        if let Some(text) = key_event.logical_key.to_text() {
-            input_bytes.extend_from_slice(text.as_bytes());
-        } else if let Key::Character(ch) = &key_event.logical_key {
-            input_bytes.extend_from_slice(ch.as_bytes());
-        }
-        
-        // Handle special keys
-        match key_event.logical_key.as_ref() {
-            Key::Named(named) => match named {
-                NamedKey::Backspace => input_bytes.push(0x08),
-                NamedKey::Enter => {
-                    input_bytes.push(0x0D); // CR
-                    input_bytes.push(0x0A); // LF
-                },
-                NamedKey::Tab => input_bytes.push(0x09),
-                NamedKey::Escape => input_bytes.push(0x1B),
-                NamedKey::ArrowUp => input_bytes.extend_from_slice(b"\x1B[A"),
-                NamedKey::ArrowDown => input_bytes.extend_from_slice(b"\x1B[B"),
-                NamedKey::ArrowRight => input_bytes.extend_from_slice(b"\x1B[C"),
-                NamedKey::ArrowLeft => input_bytes.extend_from_slice(b"\x1B[D"),
-                _ => (),
-            },
-            _ => (),
-        }
+        let input_bytes = encode_key(key_event, modifiers, modes);
+        write_key(&input_bytes, writer, dirty, private)?;
+    }
+    Ok(())
+}
 
-        if !input_bytes.is_empty() {
-            println!("Writing to PTY: {:?}", input_bytes);
-            writer.write_all(&input_bytes)?;
-            writer.flush()?;
-            *state.shared_dirty.lock().unwrap() = true;
+/// Send the bytes of one key press to the PTY.
+pub fn write_key(
+    input_bytes: &[u8],
+    writer: &mut dyn Write,
+    dirty: &Mutex<bool>,
+    private: bool,
+) -> Result<()> {
+    if !input_bytes.is_empty() {
+        if !private {
+            trace!("Writing to PTY: {:?}", input_bytes);
         }
+        writer.write_all(input_bytes)?;
+        writer.flush()?;
+        *dirty.lock().unwrap() = true;
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn application_cursor_keys() {
+        let none = ModifiersState::empty();
+        let normal = TerminalModes::default();
+        let app = TerminalModes { app_cursor: true, ..normal };
+        assert_eq!(named_key(NamedKey::ArrowUp, none, normal).unwrap(), b"\x1B[A");
+        assert_eq!(named_key(NamedKey::ArrowUp, none, app).unwrap(), b"\x1BOA");
+        assert_eq!(named_key(NamedKey::Home, none, app).unwrap(), b"\x1BOH");
+        assert_eq!(named_key(NamedKey::End, none, normal).unwrap(), b"\x1B[F");
+        // F1-F4 are SS3 keys in either mode
+        assert_eq!(named_key(NamedKey::F1, none, normal).unwrap(), b"\x1BOP");
+        // Modified keys use the CSI form whatever the mode
+        assert_eq!(named_key(NamedKey::ArrowLeft, ModifiersState::SHIFT, app).unwrap(), b"\x1B[1;2D");
+    }
+
+    #[test]
+    fn application_keypad() {
+        let key = |text: &str| Key::Character(text.into());
+        assert_eq!(keypad_key(&key("0")), Some(*b"\x1BOp"));
+        assert_eq!(keypad_key(&key("9")), Some(*b"\x1BOy"));
+        assert_eq!(keypad_key(&key(".")), Some(*b"\x1BOn"));
+        assert_eq!(keypad_key(&key("+")), Some(*b"\x1BOk"));
+        assert_eq!(keypad_key(&key("/")), Some(*b"\x1BOo"));
+        assert_eq!(keypad_key(&Key::Named(NamedKey::Enter)), Some(*b"\x1BOM"));
+        // Keys the keypad doesn't have fall back to their normal bytes
+        assert_eq!(keypad_key(&key("a")), None);
+        assert_eq!(keypad_key(&Key::Named(NamedKey::ArrowUp)), None);
+    }
+
+    #[test]
+    fn modified_keys() {
+        let modes = TerminalModes::default();
+        let ctrl = ModifiersState::CONTROL;
+        let ctrl_shift = ModifiersState::CONTROL | ModifiersState::SHIFT;
+        let alt = ModifiersState::ALT;
+        assert_eq!(named_key(NamedKey::ArrowUp, ctrl, modes).unwrap(), b"\x1B[1;5A");
+        assert_eq!(named_key(NamedKey::ArrowRight, alt, modes).unwrap(), b"\x1B[1;3C");
+        assert_eq!(named_key(NamedKey::Home, ctrl_shift, modes).unwrap(), b"\x1B[1;6H");
+        assert_eq!(named_key(NamedKey::F2, ModifiersState::SHIFT, modes).unwrap(), b"\x1B[1;2Q");
+        // Tilde keys put the modifier after the number
+        assert_eq!(named_key(NamedKey::Delete, ModifiersState::empty(), modes).unwrap(), b"\x1B[3~");
+        assert_eq!(named_key(NamedKey::Delete, ctrl, modes).unwrap(), b"\x1B[3;5~");
+        assert_eq!(named_key(NamedKey::PageUp, ModifiersState::SHIFT, modes).unwrap(), b"\x1B[5;2~");
+        assert_eq!(named_key(NamedKey::F5, ctrl_shift | alt, modes).unwrap(), b"\x1B[15;8~");
+        // Keys without a modified form take Alt as an ESC prefix
+        assert_eq!(named_key(NamedKey::Enter, alt, modes).unwrap(), b"\x1B\r");
+        assert_eq!(named_key(NamedKey::Backspace, alt, modes).unwrap(), b"\x1B\x7F");
+        assert_eq!(named_key(NamedKey::Backspace, ctrl, modes).unwrap(), b"\x08");
+        assert_eq!(named_key(NamedKey::Tab, ModifiersState::SHIFT, modes).unwrap(), b"\x1B[Z");
+        assert_eq!(named_key(NamedKey::Space, ctrl, modes).unwrap(), b"\0");
+    }
+
+    #[test]
+    fn control_bytes() {
+        assert_eq!(control_byte('a'), Some(0x01));
+        assert_eq!(control_byte('Z'), Some(0x1A));
+        assert_eq!(control_byte('@'), Some(0x00));
+        assert_eq!(control_byte('['), Some(0x1B));
+        assert_eq!(control_byte('\\'), Some(0x1C));
+        assert_eq!(control_byte(']'), Some(0x1D));
+        assert_eq!(control_byte('^'), Some(0x1E));
+        assert_eq!(control_byte('_'), Some(0x1F));
+        assert_eq!(control_byte('?'), Some(0x7F));
+        // The digit row stands in for the symbols, as in xterm
+        assert_eq!(control_byte('2'), Some(0x00));
+        assert_eq!(control_byte('6'), Some(0x1E));
+        assert_eq!(control_byte('8'), Some(0x7F));
+        assert_eq!(control_byte('1'), None);
+        assert_eq!(control_byte('é'), None);
+    }
+}
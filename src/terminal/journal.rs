@@ -0,0 +1,136 @@
+// src/terminal/journal.rs
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::{debug, warn};
+
+/// How often the layout is written out.
+pub const JOURNAL_INTERVAL: Duration = Duration::from_secs(5);
+/// A journal not rewritten for this long belongs to a window that didn't
+/// shut down cleanly.
+const STALE_AFTER: Duration = Duration::from_secs(3 * JOURNAL_INTERVAL.as_secs());
+
+/// Where a window's tabs were, not what was in them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    /// Index of the active tab.
+    pub active: usize,
+    pub tabs: Vec<TabLayout>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TabLayout {
+    pub cwd: Option<PathBuf>,
+}
+
+/// Directory the journals are kept in, next to the log file.
+fn journal_dir() -> Option<PathBuf> {
+    dirs::state_dir().or_else(dirs::cache_dir).map(|dir| dir.join("nebula").join("layouts"))
+}
+
+/// A window's last known layout, rewritten every `JOURNAL_INTERVAL` and
+/// removed when the window closes cleanly. One file per window, named
+/// after the process and the window's number in it.
+pub struct Journal {
+    path: PathBuf,
+    last_write: Option<Instant>,
+}
+
+impl Journal {
+    pub fn new(window: usize) -> Option<Self> {
+        let dir = journal_dir()?;
+        if let Err(e) = fs::create_dir_all(&dir) {
+            warn!("Failed to create {}: {}", dir.display(), e);
+            return None;
+        }
+        Some(Self {
+            path: dir.join(format!("{}-{}.toml", std::process::id(), window)),
+            last_write: None,
+        })
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.last_write.is_none_or(|last| last.elapsed() >= JOURNAL_INTERVAL)
+    }
+
+    /// Write `layout` out. A layout without tabs, such as one of only
+    /// private tabs, removes the journal instead.
+    pub fn record(&mut self, layout: &Layout) {
+        self.last_write = Some(Instant::now());
+        if layout.tabs.is_empty() {
+            let _ = fs::remove_file(&self.path);
+            return;
+        }
+        if let Err(e) = write_layout(&self.path, layout) {
+            warn!("Failed to write layout journal: {}", e);
+        }
+    }
+
+    /// Remove the journal once the window closes cleanly.
+    pub fn remove(self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Write `layout` to `path`, replacing it in one step so a crash mid-write
+/// leaves the previous layout intact.
+pub fn write_layout(path: &Path, layout: &Layout) -> anyhow::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut partial = tempfile::NamedTempFile::new_in(dir)?;
+    partial.write_all(toml::to_string(layout)?.as_bytes())?;
+    partial.persist(path)?;
+    Ok(())
+}
+
+/// Write `layout` to a new file in the journal directory for another
+/// process to pick up with `take_layout`.
+pub fn hand_off(layout: &Layout) -> anyhow::Result<PathBuf> {
+    let dir = journal_dir().ok_or_else(|| anyhow::anyhow!("No directory to keep layouts in"))?;
+    fs::create_dir_all(&dir)?;
+    let mut file = tempfile::Builder::new().prefix("restore-").suffix(".toml").tempfile_in(&dir)?;
+    file.write_all(toml::to_string(layout)?.as_bytes())?;
+    let (_, path) = file.keep()?;
+    Ok(path)
+}
+
+/// Read a layout written by `write_layout` and remove the file.
+pub fn take_layout(path: &Path) -> Option<Layout> {
+    let text = fs::read_to_string(path).ok()?;
+    // Whoever removes it gets it, so two windows starting at once don't
+    // both restore the same layout
+    fs::remove_file(path).ok()?;
+    match toml::from_str(&text) {
+        Ok(layout) => Some(layout),
+        Err(e) => {
+            warn!("Ignoring unreadable layout {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Layouts of windows that went away without a clean exit, taken out of the
+/// journal directory.
+pub fn take_crashed() -> Vec<Layout> {
+    let Some(entries) = journal_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let now = SystemTime::now();
+    let stale = |path: &Path| {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+        modified.is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() >= STALE_AFTER)
+    };
+    let layouts: Vec<Layout> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml") && stale(path))
+        .filter_map(|path| take_layout(&path))
+        .filter(|layout| !layout.tabs.is_empty())
+        .collect();
+    if !layouts.is_empty() {
+        debug!("Found {} layouts left by a crash", layouts.len());
+    }
+    layouts
+}
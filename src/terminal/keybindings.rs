@@ -0,0 +1,379 @@
+// src/terminal/keybindings.rs
+use std::{collections::BTreeMap, fmt};
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, ModifiersState, NamedKey},
+};
+
+use crate::terminal::notices;
+
+/// Application-level actions that are handled by Nebula instead of being
+/// sent to the shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    NewTab,
+    NewPrivateTab,
+    /// New tab in the active session's working directory.
+    NewTabHere,
+    /// New Nebula window in the active session's working directory.
+    NewWindowHere,
+    CloseTab,
+    NextTab,
+    PreviousTab,
+    /// Copy the selection, or the rows in view if nothing is selected, with
+    /// soft-wrapped lines joined.
+    Copy,
+    Paste,
+    PastePicker,
+    /// Paste the clipboard through a filter chosen from a menu.
+    PasteMenu,
+    /// Type the clipboard one character at a time, for serial consoles and
+    /// firmware prompts that drop fast input.
+    PasteAsTyped,
+    Search,
+    /// Forget the active tab's scrollback, keeping the screen.
+    ClearScrollback,
+    /// List every binding.
+    ShowKeybindings,
+    ToggleMagnifier,
+    /// Pick up fonts installed since Nebula started.
+    ReloadFonts,
+    /// Read-only copy of the active tab in a window of its own.
+    MirrorTab,
+    /// Read-only window that keeps showing the active tab's newest output
+    /// while the tab itself is scrolled back.
+    FollowTab,
+    /// Scroll to the prompt above, as marked by shell integration.
+    PreviousPrompt,
+    NextPrompt,
+    /// Copy what the last command printed, as marked by shell integration.
+    CopyLastOutput,
+    /// Open what the last command printed in $PAGER, in a new tab.
+    PageLastOutput,
+    /// Find an action or a recent command by typing part of it.
+    CommandPalette,
+    /// Label the links, paths and hashes in view to open or copy by typing.
+    Hints,
+    /// Switch the selection between runs of text and a block of columns.
+    BlockSelection,
+}
+
+impl Action {
+    pub const ALL: [Action; 26] = [
+        Action::NewTab,
+        Action::NewPrivateTab,
+        Action::NewTabHere,
+        Action::NewWindowHere,
+        Action::CloseTab,
+        Action::NextTab,
+        Action::PreviousTab,
+        Action::Copy,
+        Action::Paste,
+        Action::PastePicker,
+        Action::PasteMenu,
+        Action::PasteAsTyped,
+        Action::Search,
+        Action::ClearScrollback,
+        Action::ShowKeybindings,
+        Action::ToggleMagnifier,
+        Action::ReloadFonts,
+        Action::MirrorTab,
+        Action::FollowTab,
+        Action::PreviousPrompt,
+        Action::NextPrompt,
+        Action::CopyLastOutput,
+        Action::PageLastOutput,
+        Action::CommandPalette,
+        Action::Hints,
+        Action::BlockSelection,
+    ];
+
+    /// Name used in the `[keybindings]` config section.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::NewTab => "NewTab",
+            Action::NewPrivateTab => "NewPrivateTab",
+            Action::NewTabHere => "NewTabHere",
+            Action::NewWindowHere => "NewWindowHere",
+            Action::CloseTab => "CloseTab",
+            Action::NextTab => "NextTab",
+            Action::PreviousTab => "PreviousTab",
+            Action::Copy => "Copy",
+            Action::Paste => "Paste",
+            Action::PastePicker => "PastePicker",
+            Action::PasteMenu => "PasteMenu",
+            Action::PasteAsTyped => "PasteAsTyped",
+            Action::Search => "Search",
+            Action::ClearScrollback => "ClearScrollback",
+            Action::ShowKeybindings => "ShowKeybindings",
+            Action::ToggleMagnifier => "ToggleMagnifier",
+            Action::ReloadFonts => "ReloadFonts",
+            Action::MirrorTab => "MirrorTab",
+            Action::FollowTab => "FollowTab",
+            Action::PreviousPrompt => "PreviousPrompt",
+            Action::NextPrompt => "NextPrompt",
+            Action::CopyLastOutput => "CopyLastOutput",
+            Action::PageLastOutput => "PageLastOutput",
+            Action::CommandPalette => "CommandPalette",
+            Action::Hints => "Hints",
+            Action::BlockSelection => "BlockSelection",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::NewTab => "Open a new tab",
+            Action::NewPrivateTab => "Open a new private tab",
+            Action::NewTabHere => "Open a new tab in the current directory",
+            Action::NewWindowHere => "Open a new window in the current directory",
+            Action::CloseTab => "Close the current tab",
+            Action::NextTab => "Switch to the next tab",
+            Action::PreviousTab => "Switch to the previous tab",
+            Action::Copy => "Copy the selection, or the text in view",
+            Action::Paste => "Paste from the clipboard",
+            Action::PastePicker => "Paste from the clipboard history",
+            Action::PasteMenu => "Paste the clipboard through a paste filter",
+            Action::PasteAsTyped => "Type the clipboard slowly, one character at a time",
+            Action::Search => "Search the scrollback",
+            Action::ClearScrollback => "Clear the scrollback",
+            Action::ShowKeybindings => "List the keybindings",
+            Action::ToggleMagnifier => "Show or hide the magnifier",
+            Action::ReloadFonts => "Load newly installed fonts",
+            Action::MirrorTab => "Open a read-only mirror of the current tab in a new window",
+            Action::FollowTab => "Open a window following the newest output of the current tab",
+            Action::PreviousPrompt => "Scroll to the previous shell prompt",
+            Action::NextPrompt => "Scroll to the next shell prompt",
+            Action::CopyLastOutput => "Copy the output of the last command",
+            Action::PageLastOutput => "Open the output of the last command in the pager",
+            Action::CommandPalette => "Search actions and recent commands",
+            Action::Hints => "Open or copy a link, path or hash in view by its label",
+            Action::BlockSelection => "Switch the selection between lines and a block",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Named keys that can be bound, with the names used in the config.
+const NAMED_KEYS: &[(&str, NamedKey)] = &[
+    ("Enter", NamedKey::Enter),
+    ("Tab", NamedKey::Tab),
+    ("Space", NamedKey::Space),
+    ("Escape", NamedKey::Escape),
+    ("Backspace", NamedKey::Backspace),
+    ("Delete", NamedKey::Delete),
+    ("Insert", NamedKey::Insert),
+    ("Home", NamedKey::Home),
+    ("End", NamedKey::End),
+    ("PageUp", NamedKey::PageUp),
+    ("PageDown", NamedKey::PageDown),
+    ("Up", NamedKey::ArrowUp),
+    ("Down", NamedKey::ArrowDown),
+    ("Left", NamedKey::ArrowLeft),
+    ("Right", NamedKey::ArrowRight),
+    ("F1", NamedKey::F1),
+    ("F2", NamedKey::F2),
+    ("F3", NamedKey::F3),
+    ("F4", NamedKey::F4),
+    ("F5", NamedKey::F5),
+    ("F6", NamedKey::F6),
+    ("F7", NamedKey::F7),
+    ("F8", NamedKey::F8),
+    ("F9", NamedKey::F9),
+    ("F10", NamedKey::F10),
+    ("F11", NamedKey::F11),
+    ("F12", NamedKey::F12),
+];
+
+/// Modifiers a chord can require. Others, such as Caps Lock, are ignored.
+const CHORD_MODIFIERS: ModifiersState = ModifiersState::CONTROL
+    .union(ModifiersState::SHIFT)
+    .union(ModifiersState::ALT)
+    .union(ModifiersState::SUPER);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChordKey {
+    Named(NamedKey),
+    /// A character key, lowercase.
+    Character(String),
+}
+
+/// A key with the exact modifiers held, such as Ctrl+Shift+T.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyChord {
+    pub modifiers: ModifiersState,
+    pub key: ChordKey,
+}
+
+impl KeyChord {
+    fn new(modifiers: ModifiersState, key: ChordKey) -> Self {
+        Self { modifiers: modifiers & CHORD_MODIFIERS, key }
+    }
+
+    /// Parse a chord written like `Ctrl+Shift+T` or `Alt+Enter`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        // "Ctrl++" binds the plus key
+        if text.ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+        let key = parts.pop()?;
+
+        let mut modifiers = ModifiersState::empty();
+        for part in parts {
+            modifiers |= match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => ModifiersState::CONTROL,
+                "shift" => ModifiersState::SHIFT,
+                "alt" | "option" => ModifiersState::ALT,
+                "super" | "cmd" | "command" | "win" => ModifiersState::SUPER,
+                _ => return None,
+            };
+        }
+
+        let key = match NAMED_KEYS.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)) {
+            Some((_, named)) => ChordKey::Named(*named),
+            None if key.chars().count() == 1 => ChordKey::Character(key.to_lowercase()),
+            None => return None,
+        };
+        Some(Self::new(modifiers, key))
+    }
+
+    fn from_event(key_event: &KeyEvent, modifiers: ModifiersState) -> Option<Self> {
+        let key = match key_event.logical_key.as_ref() {
+            Key::Named(named) => ChordKey::Named(named),
+            Key::Character(ch) => ChordKey::Character(ch.to_lowercase()),
+            _ => return None,
+        };
+        Some(Self::new(modifiers, key))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (ModifiersState::CONTROL, "Ctrl+"),
+            (ModifiersState::SHIFT, "Shift+"),
+            (ModifiersState::ALT, "Alt+"),
+            (ModifiersState::SUPER, "Super+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match &self.key {
+            ChordKey::Named(named) => {
+                let name = NAMED_KEYS.iter().find(|(_, key)| key == named).map_or("?", |(name, _)| name);
+                f.write_str(name)
+            }
+            ChordKey::Character(ch) => f.write_str(&ch.to_uppercase()),
+        }
+    }
+}
+
+const DEFAULT_BINDINGS: &[(&str, Action)] = &[
+    ("Ctrl+Shift+T", Action::NewTab),
+    ("Ctrl+Shift+N", Action::NewPrivateTab),
+    ("Ctrl+Shift+Enter", Action::NewTabHere),
+    ("Ctrl+Shift+Alt+Enter", Action::NewWindowHere),
+    ("Ctrl+Shift+W", Action::CloseTab),
+    ("Ctrl+Tab", Action::NextTab),
+    ("Ctrl+Shift+Tab", Action::PreviousTab),
+    ("Ctrl+Shift+C", Action::Copy),
+    ("Ctrl+Shift+V", Action::Paste),
+    ("Ctrl+Shift+H", Action::PastePicker),
+    ("Ctrl+Shift+Alt+V", Action::PasteMenu),
+    ("Ctrl+Shift+F", Action::Search),
+    ("Ctrl+Shift+K", Action::ShowKeybindings),
+    ("Ctrl+Shift+M", Action::ToggleMagnifier),
+    ("Ctrl+Shift+O", Action::MirrorTab),
+    ("Ctrl+Shift+L", Action::FollowTab),
+    ("Ctrl+Shift+Up", Action::PreviousPrompt),
+    ("Ctrl+Shift+Down", Action::NextPrompt),
+    ("Ctrl+Shift+Alt+C", Action::CopyLastOutput),
+    ("Ctrl+Shift+G", Action::PageLastOutput),
+    ("Ctrl+Shift+P", Action::CommandPalette),
+    ("Ctrl+Shift+E", Action::Hints),
+    ("Ctrl+Shift+B", Action::BlockSelection),
+];
+
+/// Chords bound to actions: the defaults with the `[keybindings]` config
+/// section applied on top.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(KeyChord, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&BTreeMap::new())
+    }
+}
+
+impl Keymap {
+    /// Build the keymap from config entries mapping a chord to an action
+    /// name, or to "None" to unbind it. Invalid entries and chords bound to
+    /// more than one action are reported as notices; the first binding of a
+    /// chord wins.
+    pub fn new(config: &BTreeMap<String, String>) -> Self {
+        let mut bindings: Vec<(KeyChord, Action)> = DEFAULT_BINDINGS
+            .iter()
+            .filter_map(|(chord, action)| Some((KeyChord::parse(chord)?, *action)))
+            .collect();
+
+        let mut configured: Vec<(KeyChord, Option<Action>)> = Vec::new();
+        for (chord_text, action_name) in config {
+            let Some(chord) = KeyChord::parse(chord_text) else {
+                notices::warn(format!("Invalid keybinding '{}'", chord_text));
+                continue;
+            };
+            let action = match Action::from_name(action_name) {
+                Some(action) => Some(action),
+                None if action_name.eq_ignore_ascii_case("none") => None,
+                None => {
+                    notices::warn(format!("Unknown action '{}' bound to {}", action_name, chord));
+                    continue;
+                }
+            };
+            // Different spellings of one chord, such as "Shift+Ctrl+T" and
+            // "ctrl+shift+t"
+            if let Some((_, existing)) = configured.iter().find(|(other, _)| *other == chord) {
+                if *existing != action {
+                    let name = |action: Option<Action>| action.map_or("None", Action::name);
+                    notices::warn(format!(
+                        "Keybinding conflict: {} is bound to both {} and {}, using {}",
+                        chord,
+                        name(*existing),
+                        name(action),
+                        name(*existing)
+                    ));
+                }
+                continue;
+            }
+            configured.push((chord, action));
+        }
+
+        for (chord, action) in configured {
+            bindings.retain(|(bound, _)| *bound != chord);
+            if let Some(action) = action {
+                bindings.push((chord, action));
+            }
+        }
+        Self { bindings }
+    }
+
+    pub fn action_for(&self, key_event: &KeyEvent, modifiers: ModifiersState) -> Option<Action> {
+        if key_event.state != ElementState::Pressed {
+            return None;
+        }
+        let chord = KeyChord::from_event(key_event, modifiers)?;
+        self.bindings.iter().find(|(bound, _)| *bound == chord).map(|(_, action)| *action)
+    }
+
+    /// Chords that trigger `action`.
+    pub fn chords(&self, action: Action) -> impl Iterator<Item = &KeyChord> {
+        self.bindings.iter().filter(move |(_, bound)| *bound == action).map(|(chord, _)| chord)
+    }
+}
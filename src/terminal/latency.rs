@@ -0,0 +1,138 @@
+// src/terminal/latency.rs
+use std::time::{Duration, Instant};
+use winit::keyboard::{ModifiersState, NamedKey};
+use tracing::error;
+
+use crate::terminal::{
+    input::{named_key, write_key},
+    Session,
+};
+
+/// Samples taken by `--latency-bench` without an explicit count.
+pub const DEFAULT_SAMPLES: usize = 200;
+/// Time given to the shell to start and print its prompt.
+const STARTUP_DELAY: Duration = Duration::from_secs(2);
+/// Pause between samples so the erase of the previous key has been echoed.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+/// A key whose echo takes longer than this is counted as lost.
+const ECHO_TIMEOUT: Duration = Duration::from_secs(1);
+
+enum Phase {
+    Idle { since: Instant },
+    AwaitingEcho { injected: Instant },
+    AwaitingPresent { injected: Instant, echoed: Instant },
+}
+
+/// Measures keystroke latency by typing synthetic keys into the active
+/// session. Each sample records when the shell's echo was read from the PTY
+/// and when the first frame containing it was presented.
+pub struct LatencyBench {
+    samples: usize,
+    phase: Phase,
+    echo: Vec<Duration>,
+    present: Vec<Duration>,
+    timeouts: usize,
+    next_key: u8,
+}
+
+impl LatencyBench {
+    pub fn new(samples: usize) -> Self {
+        println!("Latency benchmark: measuring {} keystrokes", samples);
+        Self {
+            samples,
+            phase: Phase::Idle {
+                since: Instant::now() + STARTUP_DELAY - SETTLE_TIME,
+            },
+            echo: Vec::new(),
+            present: Vec::new(),
+            timeouts: 0,
+            next_key: b'a',
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.present.len() + self.timeouts >= self.samples
+    }
+
+    /// Advance the benchmark. Must run before the session is polled, so an
+    /// echo seen here is part of the snapshot drawn by the next frame.
+    pub fn tick(&mut self, session: &Session) {
+        let now = Instant::now();
+        match self.phase {
+            Phase::Idle { since } if now >= since + SETTLE_TIME && !self.is_done() => {
+                let key = [self.next_key];
+                self.next_key = if self.next_key == b'z' { b'a' } else { self.next_key + 1 };
+                let injected = Instant::now();
+                send(session, &key);
+                self.phase = Phase::AwaitingEcho { injected };
+            }
+            Phase::AwaitingEcho { injected } => {
+                let last_output = *session.terminal.last_output.lock().unwrap();
+                match last_output {
+                    Some(echoed) if echoed >= injected => {
+                        self.phase = Phase::AwaitingPresent { injected, echoed };
+                    }
+                    _ if now - injected > ECHO_TIMEOUT => {
+                        self.timeouts += 1;
+                        self.phase = Phase::Idle { since: now };
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record that a frame was presented, completing the pending sample.
+    pub fn frame_presented(&mut self, session: &Session) {
+        let Phase::AwaitingPresent { injected, echoed } = self.phase else { return };
+        let presented = Instant::now();
+        self.echo.push(echoed - injected);
+        self.present.push(presented - injected);
+
+        // Erase the typed character so the prompt doesn't fill up
+        let backspace = named_key(NamedKey::Backspace, ModifiersState::empty(), Default::default())
+            .unwrap_or_default();
+        send(session, &backspace);
+        self.phase = Phase::Idle { since: presented };
+    }
+
+    pub fn report(&self) {
+        println!(
+            "Latency over {} keystrokes ({} timed out):",
+            self.present.len(),
+            self.timeouts
+        );
+        println!("{:<10}{:>9}{:>9}{:>9}{:>9}{:>9}", "", "min", "p50", "p90", "p99", "max");
+        print_row("echo", &self.echo);
+        print_row("present", &self.present);
+    }
+}
+
+fn send(session: &Session, bytes: &[u8]) {
+    if let Ok(mut writer) = session.input_writer.lock() {
+        if let Err(e) = write_key(bytes, &mut *writer, &session.terminal.dirty, session.is_private()) {
+            error!("Latency benchmark failed to write key: {}", e);
+        }
+    }
+}
+
+fn print_row(label: &str, samples: &[Duration]) {
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    let Some(&max) = sorted.last() else {
+        println!("{:<10}no samples", label);
+        return;
+    };
+    let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+    let ms = |d: Duration| format!("{:.2}ms", d.as_secs_f64() * 1000.0);
+    println!(
+        "{:<10}{:>9}{:>9}{:>9}{:>9}{:>9}",
+        label,
+        ms(sorted[0]),
+        ms(percentile(50)),
+        ms(percentile(90)),
+        ms(percentile(99)),
+        ms(max)
+    );
+}
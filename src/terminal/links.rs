@@ -0,0 +1,483 @@
+// src/terminal/links.rs
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, LazyLock},
+    thread,
+};
+use unicode_segmentation::UnicodeSegmentation;
+use tracing::warn;
+
+use crate::terminal::{
+    notices,
+    terminal::{cluster_width, percent_decode},
+};
+
+/// Schemes a click will hand to the OS. OSC 8 lets programs attach any URI
+/// to text, so everything else is refused.
+const OPENABLE_SCHEMES: &[&str] = &["http", "https", "ftp", "file", "mailto"];
+
+/// URLs, and Windows drive paths such as `C:\src\main.rs:12:5`.
+static URL_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(?:https?|ftp|file)://[^\s<>"'`]+|mailto:[^\s<>"'`]+|\b[a-z]:\\[^\s<>"'`|]+"#).unwrap()
+});
+
+/// Paths: absolute ones, ones from `~`, `.` or `..`, relative ones and
+/// bare words, each with any `:line:column` after it. Relative paths and
+/// bare words are only taken as paths by `find_paths` when they look like
+/// files.
+static PATH_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?:^|[\s"'`(\[<{=])((?:~|\.\.?)?(?:/[\w.~+@%-]+)+|(?:[\w.~+@%-]+/)+[\w.~+@%-]+|[\w.~+@%-]+)(:\d+(?::\d+)?)?"#,
+    )
+    .unwrap()
+});
+
+/// Hex strings that could be commit or object hashes.
+static HASH_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b[0-9a-f]{7,64}\b").unwrap());
+
+/// A `:line` or `:line:column` suffix, as compilers and grep print.
+static LOCATION_SUFFIX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r":(\d+)(?::(\d+))?$").unwrap());
+
+/// A hyperlink target set with OSC 8.
+#[derive(Debug, Clone)]
+pub struct Hyperlink {
+    pub uri: Arc<str>,
+    /// The `id=` parameter. Text printed under the same id and URI is one
+    /// link, even when it is split across rows or printed in pieces.
+    pub id: Option<String>,
+}
+
+/// OSC 8 hyperlinks referenced from grid cells by id.
+#[derive(Debug, Default)]
+pub struct HyperlinkTable {
+    links: HashMap<u32, Hyperlink>,
+    next_id: u32,
+}
+
+impl HyperlinkTable {
+    /// Id for a link, reusing the existing one if the program named it.
+    pub fn insert(&mut self, uri: &str, id: Option<&str>) -> u32 {
+        if let Some(id) = id {
+            let existing = self
+                .links
+                .iter()
+                .find(|(_, link)| link.id.as_deref() == Some(id) && &*link.uri == uri);
+            if let Some((&existing, _)) = existing {
+                return existing;
+            }
+        }
+
+        self.next_id = self.next_id.wrapping_add(1);
+        self.links.insert(
+            self.next_id,
+            Hyperlink {
+                uri: uri.into(),
+                id: id.map(str::to_string),
+            },
+        );
+        self.next_id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Hyperlink> {
+        self.links.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Forget links no cell refers to any more.
+    pub fn retain(&mut self, in_use: impl Fn(u32) -> bool) {
+        self.links.retain(|&id, _| in_use(id));
+    }
+
+    pub fn clear(&mut self) {
+        self.links.clear();
+    }
+}
+
+/// A link in view: columns `columns` of visible row `row`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkSpan {
+    pub row: usize,
+    pub columns: Range<usize>,
+    pub uri: Arc<str>,
+    /// OSC 8 link the span came from. Spans of the same link are
+    /// highlighted together.
+    pub hyperlink: Option<u32>,
+}
+
+impl LinkSpan {
+    /// Whether this span is part of the same link as `other`.
+    pub fn same_link(&self, other: &LinkSpan) -> bool {
+        match (self.hyperlink, other.hyperlink) {
+            (Some(a), Some(b)) => a == b,
+            _ => self == other,
+        }
+    }
+}
+
+/// The link under a cell. Explicit OSC 8 links win over detected URLs.
+pub fn link_at(links: &[LinkSpan], row: usize, col: usize) -> Option<&LinkSpan> {
+    let mut under = links
+        .iter()
+        .filter(|link| link.row == row && link.columns.contains(&col));
+    let first = under.next()?;
+    Some(under.find(|link| link.hyperlink.is_some()).unwrap_or(first))
+}
+
+/// Columns of a URL found in a row, and the URL.
+type UrlMatch = (Range<usize>, Arc<str>);
+
+/// Finds URLs in the plain text of rows. Rows whose text hasn't changed
+/// since the last scan are not searched again.
+#[derive(Debug, Default)]
+pub struct UrlScanner {
+    cache: HashMap<String, Vec<UrlMatch>>,
+}
+
+impl UrlScanner {
+    /// Column ranges and URLs found in each row. Only the rows passed in are
+    /// remembered for next time.
+    pub fn scan(&mut self, rows: &[String]) -> Vec<Vec<UrlMatch>> {
+        let mut previous = std::mem::take(&mut self.cache);
+        rows.iter()
+            .map(|row| {
+                let urls = previous
+                    .remove(row)
+                    .or_else(|| self.cache.get(row).cloned())
+                    .unwrap_or_else(|| find_links(row));
+                self.cache.insert(row.clone(), urls.clone());
+                urls
+            })
+            .collect()
+    }
+}
+
+/// URLs in a row's text, then paths that aren't part of one.
+fn find_links(text: &str) -> Vec<UrlMatch> {
+    let urls = find_urls(text);
+    if !text.contains('/') && !text.contains(':') {
+        return urls;
+    }
+    let paths: Vec<UrlMatch> = find_paths(text)
+        .into_iter()
+        .filter(|(path, _)| !urls.iter().any(|(url, _)| url.start < path.end && path.start < url.end))
+        .collect();
+    urls.into_iter().chain(paths).collect()
+}
+
+fn find_urls(text: &str) -> Vec<UrlMatch> {
+    if !text.contains(':') {
+        return Vec::new();
+    }
+    URL_PATTERN
+        .find_iter(text)
+        .filter_map(|found| {
+            let url = trim_url(found.as_str());
+            // A bare scheme is not a link
+            if url.ends_with("//") || url.ends_with(':') {
+                return None;
+            }
+            let start = column_at(text, found.start());
+            let end = column_at(text, found.start() + url.len());
+            Some((start..end, url.into()))
+        })
+        .collect()
+}
+
+/// Paths printed in `text`, as `src/main.rs:12:5`. A path needs a
+/// directory and a file name with an extension unless it starts from `/`,
+/// `~`, `.` or `..`; a bare file name needs an extension and a line.
+fn find_paths(text: &str) -> Vec<UrlMatch> {
+    PATH_PATTERN
+        .captures_iter(text)
+        .filter_map(|captures| {
+            let found = captures.get(1)?;
+            let location = captures.get(2).map_or("", |location| location.as_str());
+            let path = if location.is_empty() { trim_url(found.as_str()) } else { found.as_str() };
+            let rooted = path.starts_with(['/', '~', '.']) && path.contains('/');
+            let name = path.rsplit('/').next().unwrap_or(path);
+            let extension = name.trim_start_matches('.').contains('.') && !name.ends_with('.');
+            let looks_like_file = match (path.contains('/'), !location.is_empty()) {
+                _ if rooted => true,
+                (true, _) => extension,
+                (false, has_line) => extension && has_line,
+            };
+            if !looks_like_file {
+                return None;
+            }
+            let end = found.start() + path.len() + location.len();
+            let link = format!("{}{}", path, location);
+            Some((column_at(text, found.start())..column_at(text, end), link.into()))
+        })
+        .collect()
+}
+
+/// Columns and text of hashes in a row, such as abbreviated commit ids.
+/// Words without both a digit and a letter, like `deadbeef`, are left out.
+pub fn find_hashes(text: &str) -> Vec<(Range<usize>, String)> {
+    HASH_PATTERN
+        .find_iter(text)
+        .filter(|found| {
+            let hash = found.as_str();
+            hash.contains(|c: char| c.is_ascii_digit()) && hash.contains(|c: char| c.is_ascii_alphabetic())
+        })
+        .map(|found| (column_at(text, found.start())..column_at(text, found.end()), found.as_str().to_string()))
+        .collect()
+}
+
+/// Drop punctuation that ends the sentence rather than the URL. Closing
+/// brackets stay if the URL opened them, as in Wikipedia links.
+fn trim_url(mut url: &str) -> &str {
+    loop {
+        let mut trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        if let Some(close @ (')' | ']' | '}')) = trimmed.chars().last() {
+            let open = match close {
+                ')' => '(',
+                ']' => '[',
+                _ => '{',
+            };
+            if trimmed.matches(open).count() < trimmed.matches(close).count() {
+                trimmed = &trimmed[..trimmed.len() - 1];
+            }
+        }
+        if trimmed.len() == url.len() {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// Cell column of the byte at `index` of a row's text.
+pub fn column_at(text: &str, index: usize) -> usize {
+    text[..index].graphemes(true).map(cluster_width).sum()
+}
+
+/// A local file a link points at, with the position in it from a
+/// `:line:column` suffix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLocation {
+    pub path: PathBuf,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl FileLocation {
+    /// Read a `file://` URI on this machine or a Windows drive path.
+    /// `file:///C:/src` names a drive path on any platform, and paths use
+    /// backslashes on Windows, forward slashes elsewhere.
+    pub fn parse(link: &str) -> Option<Self> {
+        let (path, line, column) = split_location(link);
+
+        let path = if is_drive_path(path) {
+            path.to_string()
+        } else {
+            let rest = path.get(..7).filter(|scheme| scheme.eq_ignore_ascii_case("file://")).map(|_| &path[7..])?;
+            let (host, path) = rest.split_at(rest.find('/')?);
+            if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+                return None;
+            }
+            let path = String::from_utf8(percent_decode(path.as_bytes())).ok()?;
+            match path.strip_prefix('/') {
+                Some(drive) if is_drive_path(drive) => drive.to_string(),
+                _ => path,
+            }
+        };
+        let path = if cfg!(windows) { path.replace('/', "\\") } else { path.replace('\\', "/") };
+        Some(Self { path: PathBuf::from(path), line, column })
+    }
+
+    /// Read a path printed in output, as `src/main.rs:12:5`. Relative paths
+    /// are taken from `cwd`, the directory of the program that printed them.
+    pub fn from_path(link: &str, cwd: Option<&Path>) -> Option<Self> {
+        let (path, line, column) = split_location(link);
+        // URLs and other schemes
+        if path.is_empty() || path.contains(':') {
+            return None;
+        }
+        let path = match (path.strip_prefix("~/"), dirs::home_dir(), cwd) {
+            (Some(rest), Some(home), _) => home.join(rest),
+            (None, _, Some(cwd)) => cwd.join(path),
+            _ => PathBuf::from(path),
+        };
+        Some(Self { path, line, column })
+    }
+
+    /// `file://` URI for the file itself, for the system's opener.
+    pub fn uri(&self) -> String {
+        let path = self.path.to_string_lossy().replace('\\', "/");
+        let path = path.replace('%', "%25").replace(' ', "%20");
+        if path.starts_with('/') {
+            format!("file://{}", path)
+        } else {
+            format!("file:///{}", path)
+        }
+    }
+}
+
+/// A link split from its `:line:column` suffix, if it has one.
+fn split_location(link: &str) -> (&str, Option<u32>, Option<u32>) {
+    match LOCATION_SUFFIX.captures(link) {
+        Some(suffix) => {
+            let number = |index: usize| suffix.get(index).and_then(|n| n.as_str().parse().ok());
+            (&link[..suffix.get(0).map_or(link.len(), |all| all.start())], number(1), number(2))
+        }
+        None => (link, None, None),
+    }
+}
+
+/// The local file a link points at: a `file://` URI, a drive path or a
+/// path printed in output, relative to `cwd`.
+pub fn local_file(link: &str, cwd: Option<&Path>) -> Option<FileLocation> {
+    FileLocation::parse(link).or_else(|| FileLocation::from_path(link, cwd))
+}
+
+/// Program and arguments opening `file` in $VISUAL or $EDITOR, at its line
+/// in the `+line` form vi, nano and emacs take.
+pub fn terminal_editor(file: &FileLocation) -> Option<Vec<String>> {
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).ok()?;
+    let mut command: Vec<String> = editor.split_whitespace().map(String::from).collect();
+    if command.is_empty() {
+        return None;
+    }
+    if let Some(line) = file.line {
+        command.push(format!("+{}", line));
+    }
+    command.push(file.path.to_string_lossy().into_owned());
+    Some(command)
+}
+
+/// Whether `path` starts with a drive letter, as in `C:\` or `C:/`.
+fn is_drive_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && matches!(bytes[2], b'\\' | b'/')
+}
+
+/// Open a link in the default browser or handler. Links to local files go
+/// to `editor` instead when it is set, at the line and column they name.
+/// Paths printed in output are relative to `cwd`.
+pub fn open(uri: &str, editor: &[String], cwd: Option<&Path>) {
+    let file = local_file(uri, cwd);
+    if let Some(missing) = file.as_ref().filter(|file| !file.path.exists()) {
+        notices::warn(format!("No such file: {}", missing.path.display()));
+        return;
+    }
+    if let (Some(file), false) = (&file, editor.is_empty()) {
+        open_in_editor(file, editor);
+        return;
+    }
+    let uri = match file {
+        Some(file) => file.uri(),
+        None => uri.to_string(),
+    };
+
+    let scheme = uri.split_once(':').map(|(scheme, _)| scheme.to_ascii_lowercase());
+    match scheme {
+        Some(scheme) if OPENABLE_SCHEMES.contains(&scheme.as_str()) => {}
+        Some(scheme) => {
+            notices::warn(format!("Not opening link with unsupported scheme '{}'", scheme));
+            return;
+        }
+        None => return,
+    }
+
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = Command::new("xdg-open");
+
+    match command.arg(&uri).spawn() {
+        // Reap the opener once it exits
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => warn!("Failed to open link: {}", e),
+    }
+}
+
+/// Run `editor` with `{file}`, `{line}` and `{column}` in its arguments
+/// filled in, taking the start of the file where the link names no line.
+fn open_in_editor(file: &FileLocation, editor: &[String]) {
+    let path = file.path.to_string_lossy();
+    let line = file.line.unwrap_or(1).to_string();
+    let column = file.column.unwrap_or(1).to_string();
+    let args: Vec<String> = editor
+        .iter()
+        .map(|arg| arg.replace("{file}", &path).replace("{line}", &line).replace("{column}", &column))
+        .collect();
+    match Command::new(&args[0]).args(&args[1..]).spawn() {
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => notices::warn(format!("Failed to run editor {:?}: {}", args[0], e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `path` with the separators this platform uses.
+    fn native(path: &str) -> PathBuf {
+        PathBuf::from(if cfg!(windows) { path.replace('/', "\\") } else { path.to_string() })
+    }
+
+    #[test]
+    fn file_uris() {
+        let file = FileLocation::parse("file:///home/ada/src/main.rs:12:5").unwrap();
+        assert_eq!(file, FileLocation { path: native("/home/ada/src/main.rs"), line: Some(12), column: Some(5) });
+        let file = FileLocation::parse("FILE://localhost/tmp/a%20b.txt:7").unwrap();
+        assert_eq!((file.path, file.line, file.column), (native("/tmp/a b.txt"), Some(7), None));
+        // A drive inside the URI names a Windows path on any platform
+        let file = FileLocation::parse("file:///C:/src/lib.rs").unwrap();
+        assert_eq!((file.path, file.line), (native("C:/src/lib.rs"), None));
+
+        // Files on other hosts and other schemes aren't local
+        assert_eq!(FileLocation::parse("file://build-host/tmp/a.txt"), None);
+        assert_eq!(FileLocation::parse("https://example.com/a.rs:3"), None);
+        assert_eq!(FileLocation::parse("file:"), None);
+    }
+
+    #[test]
+    fn drive_paths() {
+        let file = FileLocation::parse(r"C:\src\main.rs:12:5").unwrap();
+        assert_eq!((file.path, file.line, file.column), (native("C:/src/main.rs"), Some(12), Some(5)));
+        assert_eq!(FileLocation::parse("d:/notes.txt").unwrap().path, native("d:/notes.txt"));
+        let uri = FileLocation::parse(r"C:\My Files\a.rs").unwrap().uri();
+        assert_eq!(uri, "file:///C:/My%20Files/a.rs");
+
+        // Found in output with the suffix, and without the sentence's period
+        let text = r"error at C:\src\main.rs:12:5.";
+        assert_eq!(find_urls(text), [(9..28, r"C:\src\main.rs:12:5".into())]);
+    }
+
+    #[test]
+    fn printed_paths() {
+        let cwd = Path::new("/work");
+        let file = local_file("src/main.rs:3:9", Some(cwd)).unwrap();
+        assert_eq!((file.path, file.line, file.column), (cwd.join("src/main.rs"), Some(3), Some(9)));
+        // URIs aren't printed paths
+        assert_eq!(FileLocation::from_path("https://example.com/a.rs", Some(cwd)), None);
+
+        let links: Vec<_> = find_links("--> src/main.rs:3:9 and (notes.txt) and lib.rs:4")
+            .into_iter()
+            .map(|(columns, link)| (columns, link.to_string()))
+            .collect();
+        assert_eq!(links, [(4..19, "src/main.rs:3:9".to_string()), (40..48, "lib.rs:4".to_string())]);
+    }
+}
@@ -0,0 +1,50 @@
+// src/terminal/logging.rs
+use std::path::PathBuf;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// What goes to the log file unless `--log-level` or `NEBULA_LOG` say
+/// otherwise.
+const DEFAULT_FILTER: &str = "info";
+const LOG_FILE: &str = "nebula.log";
+
+/// Directory the log file is written to.
+pub fn log_dir() -> Option<PathBuf> {
+    dirs::state_dir().or_else(dirs::cache_dir).map(|dir| dir.join("nebula"))
+}
+
+/// The log file itself.
+pub fn log_file() -> Option<PathBuf> {
+    log_dir().map(|dir| dir.join(LOG_FILE))
+}
+
+/// Send logs to `nebula.log` at the level `filter` asks for, such as
+/// `debug` or `info,nebula::terminal::render=trace`, and warnings and errors
+/// to stderr as well. Keep the guard alive until exit so buffered lines are
+/// written out.
+pub fn init(filter: Option<&str>) -> Option<WorkerGuard> {
+    let file_filter = match EnvFilter::try_new(filter.unwrap_or(DEFAULT_FILTER)) {
+        Ok(file_filter) => file_filter,
+        Err(e) => {
+            eprintln!("Invalid log level '{}': {}", filter.unwrap_or_default(), e);
+            EnvFilter::new(DEFAULT_FILTER)
+        }
+    };
+    let stderr = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .with_filter(LevelFilter::WARN);
+
+    let dir = log_dir().filter(|dir| std::fs::create_dir_all(dir).is_ok());
+    let Some(dir) = dir else {
+        tracing_subscriber::registry().with(stderr).init();
+        tracing::warn!("No writable log directory, logging to stderr only");
+        return None;
+    };
+    let (writer, guard) = tracing_appender::non_blocking(tracing_appender::rolling::never(&dir, LOG_FILE));
+    let file = fmt::layer().with_writer(writer).with_ansi(false).with_filter(file_filter);
+    tracing_subscriber::registry().with(stderr).with(file).init();
+    tracing::info!("Logging to {}", dir.join(LOG_FILE).display());
+    Some(guard)
+}
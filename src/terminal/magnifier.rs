@@ -0,0 +1,153 @@
+// src/terminal/magnifier.rs
+use wgpu::{
+    BindGroup, CommandEncoder, Device, Extent3d, Queue, SurfaceConfiguration, TextureDescriptor, TextureDimension,
+    TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+use crate::terminal::{
+    config::MagnifierConfig,
+    gpu::QuadBuffer,
+    render::{push_quad, Screen, Quad, SOLID},
+    GlyphAtlas, GpuResources,
+};
+
+/// Width of the line around the lens.
+const BORDER_THICKNESS: f32 = 2.0;
+
+/// The frame rendered offscreen, for the lens to sample from.
+struct Frame {
+    view: TextureView,
+    bind_group: BindGroup,
+    size: (u32, u32),
+}
+
+/// A lens showing part of the window enlarged, for reading small text
+/// without changing the font size. While it is on, frames are rendered
+/// into a texture first and then copied to the window with the lens on top.
+pub struct Magnifier {
+    pub enabled: bool,
+    /// Mouse position in the window the lens follows. Without one it
+    /// follows the cursor.
+    pub mouse: Option<(f32, f32)>,
+    config: MagnifierConfig,
+    frame: Option<Frame>,
+    lens_quads: QuadBuffer,
+    border_quads: QuadBuffer,
+}
+
+impl Magnifier {
+    pub fn new(config: MagnifierConfig) -> Self {
+        Self {
+            enabled: false,
+            mouse: None,
+            config,
+            frame: None,
+            lens_quads: QuadBuffer::new("Magnifier Quads"),
+            border_quads: QuadBuffer::new("Magnifier Border Quads"),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        // Nothing to keep around while the lens is off
+        if !self.enabled {
+            self.frame = None;
+        }
+    }
+
+    /// Texture to render the frame into, sized to the surface.
+    pub fn frame_view(&mut self, device: &Device, config: &SurfaceConfiguration, atlas: &GlyphAtlas) -> &TextureView {
+        let size = (config.width, config.height);
+        if self.frame.as_ref().is_none_or(|frame| frame.size != size) {
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some("Magnifier Frame"),
+                size: Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: config.format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&TextureViewDescriptor::default());
+            let bind_group = atlas.bind_texture(&view);
+            self.frame = Some(Frame { view, bind_group, size });
+        }
+        &self.frame.as_ref().unwrap().view
+    }
+
+    /// Copy the offscreen frame to `target` with the lens centered on the
+    /// mouse, or on `cursor` when the mouse is outside the window.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        gpu: &GpuResources,
+        atlas: &GlyphAtlas,
+        screen: Screen,
+        cursor: (f32, f32),
+        border_color: [f32; 4],
+    ) {
+        let Some(frame) = &self.frame else { return };
+        let (focus_x, focus_y) = self.mouse.unwrap_or(cursor);
+        let width = self.config.width.min(screen.width);
+        let height = self.config.height.min(screen.height);
+        let zoom = self.config.zoom.max(1.0);
+        // Keep both the lens and the area it shows inside the window
+        let centered = |center: f32, size: f32, limit: f32| (center - size / 2.0).clamp(0.0, limit - size);
+        let lens = (centered(focus_x, width, screen.width), centered(focus_y, height, screen.height), width, height);
+        let (source_width, source_height) = (width / zoom, height / zoom);
+        let source_x = centered(focus_x, source_width, screen.width);
+        let source_y = centered(focus_y, source_height, screen.height);
+        let uv = [
+            source_x / screen.width,
+            source_y / screen.height,
+            (source_x + source_width) / screen.width,
+            (source_y + source_height) / screen.height,
+        ];
+
+        let mut frame_quads: Vec<Quad> = Vec::new();
+        push_quad(&mut frame_quads, screen, (0.0, 0.0, screen.width, screen.height), [0.0, 0.0, 1.0, 1.0], [1.0; 4]);
+        push_quad(&mut frame_quads, screen, lens, uv, [1.0; 4]);
+        let (x, y, w, h) = lens;
+        let t = BORDER_THICKNESS;
+        let mut border_quads: Vec<Quad> = Vec::new();
+        push_quad(&mut border_quads, screen, (x - t, y - t, w + 2.0 * t, t), SOLID, border_color);
+        push_quad(&mut border_quads, screen, (x - t, y + h, w + 2.0 * t, t), SOLID, border_color);
+        push_quad(&mut border_quads, screen, (x - t, y, t, h), SOLID, border_color);
+        push_quad(&mut border_quads, screen, (x + w, y, t, h), SOLID, border_color);
+
+        self.lens_quads.write(device, queue, &frame_quads);
+        self.border_quads.write(device, queue, &border_quads);
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Magnifier Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        gpu.bind_quad(&mut rpass);
+        rpass.set_pipeline(&gpu.magnifier_pipeline);
+        rpass.set_bind_group(0, &frame.bind_group, &[]);
+        self.lens_quads.draw(&mut rpass, None);
+
+        rpass.set_pipeline(&gpu.pipeline);
+        rpass.set_bind_group(0, atlas.bind_group(), &[]);
+        self.border_quads.draw(&mut rpass, None);
+    }
+}
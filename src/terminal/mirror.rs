@@ -0,0 +1,61 @@
+// src/terminal/mirror.rs
+use wgpu::SurfaceConfiguration;
+
+use crate::terminal::{gpu::QuadBuffer, row_cache::RowCache, window::TerminalWindow};
+
+/// A read-only window showing one tab's screen, for a second monitor or for
+/// someone following along. It draws the same snapshots as the tab but has
+/// no input writer and never resizes the PTY.
+pub struct Mirror {
+    pub window: TerminalWindow,
+    pub config: SurfaceConfiguration,
+    /// `Session::id` of the tab shown.
+    pub session: u64,
+    /// Show the newest output even while the tab is scrolled back, like
+    /// `tail -f`, instead of what the tab shows.
+    pub follow: bool,
+    /// Scale factor of the monitor the window is on.
+    pub scale: f32,
+    /// Snapshot last drawn, so new output triggers a redraw.
+    pub drawn_generation: Option<u64>,
+    /// The tab's own cache is laid out for the main window, so the mirror
+    /// keeps one for its size.
+    pub row_cache: RowCache,
+    pub background: QuadBuffer,
+    pub images: QuadBuffer,
+    pub decorations: QuadBuffer,
+    pub overlay: QuadBuffer,
+}
+
+impl Mirror {
+    pub fn new(window: TerminalWindow, config: SurfaceConfiguration, session: u64, follow: bool) -> Self {
+        let scale = window.window.scale_factor() as f32;
+        Self {
+            window,
+            config,
+            session,
+            follow,
+            scale,
+            drawn_generation: None,
+            row_cache: RowCache::default(),
+            background: QuadBuffer::new("Mirror Background Quads"),
+            images: QuadBuffer::new("Mirror Large Glyph Quads"),
+            decorations: QuadBuffer::new("Mirror Decoration Quads"),
+            overlay: QuadBuffer::new("Mirror Overlay Quads"),
+        }
+    }
+
+    /// Free the instance buffers, which the next redraw allocates again.
+    pub fn release_buffers(&mut self) {
+        self.background.release();
+        self.images.release();
+        self.decorations.release();
+        self.overlay.release();
+    }
+
+    /// Window title for a tab titled `title`.
+    pub fn title(&self, title: &str) -> String {
+        let kind = if self.follow { "following" } else { "mirror" };
+        format!("{} ({})", title, kind)
+    }
+}
@@ -1,43 +1,105 @@
 // src/terminal/mod.rs
+#[cfg(feature = "gui")]
 pub mod app;
+#[cfg(feature = "gui")]
+pub mod bell;
+pub mod capabilities;
+#[cfg(feature = "gui")]
+pub mod cli;
+pub mod clipboard;
+pub mod colors;
 pub mod config;
+#[cfg(feature = "gui")]
+pub mod daemon;
+#[cfg(feature = "gui")]
+pub mod decorations;
+pub mod encoding;
+#[cfg(feature = "gui")]
+pub mod fonts;
+#[cfg(feature = "gui")]
 pub mod gpu;
+#[cfg(feature = "gui")]
+pub mod highlight;
+#[cfg(feature = "gui")]
 pub mod input;
+#[cfg(feature = "gui")]
+pub mod journal;
+#[cfg(feature = "gui")]
+pub mod keybindings;
+#[cfg(feature = "gui")]
+pub mod latency;
+pub mod links;
+#[cfg(feature = "gui")]
+pub mod logging;
+#[cfg(feature = "gui")]
+pub mod magnifier;
+#[cfg(feature = "gui")]
+pub mod mirror;
+pub mod mouse;
+pub mod notices;
+#[cfg(feature = "gui")]
+pub mod overlay;
+#[cfg(feature = "gui")]
+pub mod paste;
+#[cfg(feature = "gui")]
+pub mod post_process;
+#[cfg(feature = "gui")]
+pub mod raster;
+#[cfg(feature = "gui")]
 pub mod render;
+#[cfg(feature = "gui")]
+pub mod row_cache;
+#[cfg(feature = "gui")]
+pub mod rules;
+pub mod scrollback;
+#[cfg(feature = "gui")]
+pub mod search;
+pub mod selection;
+#[cfg(feature = "gui")]
+pub mod session;
+#[cfg(feature = "gui")]
+pub mod shaper;
+#[cfg(feature = "gui")]
+pub mod share;
+#[cfg(feature = "gui")]
+pub mod signals;
+#[cfg(feature = "gui")]
+pub mod smooth_scroll;
+#[cfg(feature = "gui")]
+pub mod software;
+#[cfg(feature = "gui")]
+mod state;
+#[allow(clippy::module_inception)]
 pub mod terminal;
+pub mod terminfo;
+#[cfg(feature = "gui")]
 pub mod texture;
+#[cfg(feature = "gui")]
+pub mod timing;
+#[cfg(feature = "gui")]
+pub mod titles;
+#[cfg(feature = "gui")]
+pub mod token;
+#[cfg(feature = "gui")]
+pub mod touch;
+#[cfg(feature = "gui")]
+pub mod usage;
+#[cfg(feature = "gui")]
+pub mod web;
+#[cfg(feature = "gui")]
 pub mod window;
 
+#[cfg(feature = "gui")]
 pub use gpu::GpuResources;
+#[cfg(feature = "gui")]
+pub use session::Session;
+#[cfg(feature = "gui")]
+pub use state::{TerminalState, UserEvent};
 pub use terminal::Terminal;
+#[cfg(feature = "gui")]
 pub use texture::GlyphAtlas;
 
-use cosmic_text::{FontSystem, SwashCache};
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
-
-pub use cosmic_text::Buffer;
-
-pub struct TerminalState {
-    pub font_system: Arc<Mutex<FontSystem>>,
-    pub buffer: Arc<Mutex<Buffer>>,
-    pub text_content: Arc<Mutex<String>>,
-    pub last_text: String,
-    pub glyph_atlas: GlyphAtlas,
-    pub swash_cache: Arc<Mutex<SwashCache>>,
-    pub gpu_resources: GpuResources,
-    pub start_time: Instant,
-    pub last_frame_time: Instant,
-    pub focused: bool,
-    pub shared_dirty: Arc<Mutex<bool>>,
-    pub local_dirty: bool,
-    pub cursor_x: Arc<Mutex<f32>>,
-    pub cursor_y: Arc<Mutex<f32>>,
-    pub cursor_visible: bool,
-    pub cursor_blink: bool,
-    pub last_blink: Instant,
-}
-
+#[cfg(feature = "gui")]
 pub fn run() -> Result<(), anyhow::Error> {
     app::TerminalApp::run()
 }
\ No newline at end of file
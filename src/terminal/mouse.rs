@@ -0,0 +1,211 @@
+// src/terminal/mouse.rs
+/// Mouse events a program asked to have reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MouseTracking {
+    #[default]
+    Off,
+    /// Mode 9: presses only, without modifiers.
+    X10,
+    /// Mode 1000: presses, releases and the wheel.
+    Normal,
+    /// Mode 1001: as `Normal`, but the program can answer a press with a
+    /// range to highlight, and then hears where the drag ended instead of
+    /// the release.
+    Highlight,
+    /// Mode 1002: also motion while a button is held.
+    ButtonEvent,
+    /// Mode 1003: also motion with no button held.
+    AnyEvent,
+}
+
+/// How reports are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MouseEncoding {
+    /// `CSI M` and three bytes offset by 32. Positions past 223 can't be
+    /// reported.
+    #[default]
+    X10,
+    /// Mode 1005: as `X10`, with values written as UTF-8 characters, up
+    /// to 2015.
+    Utf8,
+    /// Mode 1006: `CSI < b ; x ; y M`, or `m` for a release.
+    Sgr,
+    /// Mode 1015: `CSI b ; x ; y M`, with the button offset by 32.
+    Urxvt,
+}
+
+/// Highlight a program asked for with `CSI func ; x ; y ; first ; last T`
+/// in answer to a press under mode 1001. Zero-based cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Highlight {
+    pub start: (usize, usize),
+    pub first_row: usize,
+    /// The row below the last one the highlight may reach.
+    pub last_row: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+impl ReportButton {
+    fn is_wheel(self) -> bool {
+        matches!(self, Self::WheelUp | Self::WheelDown | Self::WheelLeft | Self::WheelRight)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseAction {
+    Press,
+    Release,
+    Motion,
+}
+
+/// Modifier keys held during a mouse event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseModifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub control: bool,
+}
+
+#[cfg(feature = "gui")]
+impl From<winit::keyboard::ModifiersState> for MouseModifiers {
+    fn from(state: winit::keyboard::ModifiersState) -> Self {
+        Self { shift: state.shift_key(), alt: state.alt_key(), control: state.control_key() }
+    }
+}
+
+/// A mouse event over a zero-based cell.
+#[derive(Debug, Clone, Copy)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    /// The button pressed or released, or held during motion.
+    pub button: Option<ReportButton>,
+    pub col: usize,
+    pub row: usize,
+    pub modifiers: MouseModifiers,
+}
+
+/// Bytes reporting `event`, or None if `tracking` doesn't cover it or the
+/// position can't be written in `encoding`.
+pub fn report(tracking: MouseTracking, encoding: MouseEncoding, event: &MouseEvent) -> Option<Vec<u8>> {
+    let wheel = event.button.is_some_and(ReportButton::is_wheel);
+    let wanted = match (tracking, event.action) {
+        (MouseTracking::Off, _) => false,
+        (MouseTracking::X10, MouseAction::Press) => !wheel,
+        (MouseTracking::X10, _) => false,
+        (_, MouseAction::Press) => true,
+        // Wheel notches are presses with no release
+        (_, MouseAction::Release) => !wheel,
+        (MouseTracking::ButtonEvent, MouseAction::Motion) => event.button.is_some(),
+        (MouseTracking::AnyEvent, MouseAction::Motion) => true,
+        (_, MouseAction::Motion) => false,
+    };
+    if !wanted {
+        return None;
+    }
+
+    let mut code = match event.button {
+        Some(ReportButton::Left) => 0,
+        Some(ReportButton::Middle) => 1,
+        Some(ReportButton::Right) => 2,
+        Some(ReportButton::WheelUp) => 64,
+        Some(ReportButton::WheelDown) => 65,
+        Some(ReportButton::WheelLeft) => 66,
+        Some(ReportButton::WheelRight) => 67,
+        None => 3,
+    };
+    // Only SGR says which button was released
+    if event.action == MouseAction::Release && encoding != MouseEncoding::Sgr {
+        code = 3;
+    }
+    if event.action == MouseAction::Motion {
+        code += 32;
+    }
+    if tracking != MouseTracking::X10 {
+        let modifiers = event.modifiers;
+        code += 4 * modifiers.shift as u32 + 8 * modifiers.alt as u32 + 16 * modifiers.control as u32;
+    }
+
+    let (x, y) = (event.col as u32 + 1, event.row as u32 + 1);
+    match encoding {
+        MouseEncoding::Sgr => {
+            let end = if event.action == MouseAction::Release { 'm' } else { 'M' };
+            Some(format!("\x1B[<{};{};{}{}", code, x, y, end).into_bytes())
+        }
+        MouseEncoding::Urxvt => Some(format!("\x1B[{};{};{}M", code + 32, x, y).into_bytes()),
+        MouseEncoding::X10 => {
+            let mut bytes = b"\x1B[M".to_vec();
+            for value in [code, x, y] {
+                bytes.push(u8::try_from(value + 32).ok()?);
+            }
+            Some(bytes)
+        }
+        MouseEncoding::Utf8 => {
+            let mut bytes = b"\x1B[M".to_vec();
+            for value in [code, x, y] {
+                let c = char::from_u32(value + 32).filter(|c| c.len_utf8() <= 2)?;
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            Some(bytes)
+        }
+    }
+}
+
+/// Bytes ending `highlight` with the button released over `(col, row)`:
+/// `CSI t x y` if the mouse is back where it started, otherwise `CSI T`
+/// with the start, end and mouse positions. The end is kept within the
+/// rows the program allowed.
+pub fn highlight_end(highlight: &Highlight, col: usize, row: usize) -> Vec<u8> {
+    let position = |(col, row): (usize, usize)| [(col + 1 + 32).min(255) as u8, (row + 1 + 32).min(255) as u8];
+    if (col, row) == highlight.start {
+        return [b"\x1B[t".as_slice(), &position((col, row))].concat();
+    }
+    let end_row = row.clamp(highlight.first_row, highlight.last_row.max(highlight.first_row + 1) - 1);
+    [b"\x1B[T".as_slice(), &position(highlight.start), &position((col, end_row)), &position((col, row))].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse_reports() {
+        let modifiers = MouseModifiers { control: true, ..MouseModifiers::default() };
+        let event = |action, button, col| MouseEvent { action, button, col, row: 4, modifiers };
+        let press = event(MouseAction::Press, Some(ReportButton::Left), 9);
+        let release = event(MouseAction::Release, Some(ReportButton::Right), 9);
+        let wheel = event(MouseAction::Press, Some(ReportButton::WheelDown), 9);
+        let motion = event(MouseAction::Motion, None, 300);
+
+        let (tracking, encoding) = (MouseTracking::AnyEvent, MouseEncoding::Sgr);
+        assert_eq!(report(tracking, encoding, &press).unwrap(), b"\x1B[<16;10;5M");
+        assert_eq!(report(tracking, encoding, &release).unwrap(), b"\x1B[<18;10;5m");
+        assert_eq!(report(tracking, encoding, &wheel).unwrap(), b"\x1B[<81;10;5M");
+        assert_eq!(report(tracking, encoding, &motion).unwrap(), b"\x1B[<51;301;5M");
+
+        // X10 encoding can't say which button was released, nor reach column 300
+        let tracking = MouseTracking::ButtonEvent;
+        assert_eq!(report(tracking, MouseEncoding::X10, &release).unwrap(), b"\x1B[M3*%");
+        assert_eq!(report(tracking, MouseEncoding::X10, &motion), None);
+        let drag = event(MouseAction::Motion, Some(ReportButton::Left), 300);
+        assert_eq!(report(tracking, MouseEncoding::Utf8, &drag).unwrap(), "\x1B[MP\u{14d}%".as_bytes());
+    }
+
+    #[test]
+    fn highlight_reports() {
+        // Released where it started: just that position. Elsewhere the
+        // start, the end kept within the allowed rows, and the mouse
+        let highlight = Highlight { start: (2, 1), first_row: 0, last_row: 3 };
+        assert_eq!(highlight_end(&highlight, 2, 1), b"\x1B[t#\"");
+        assert_eq!(highlight_end(&highlight, 5, 4), b"\x1B[T#\"&#&%");
+    }
+}
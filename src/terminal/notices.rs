@@ -0,0 +1,92 @@
+// src/terminal/notices.rs
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock, Mutex, OnceLock,
+    },
+};
+
+use crate::terminal::terminal::Waker;
+
+/// Notices shown at once. The oldest is dropped to make room past this.
+const MAX_NOTICES: usize = 4;
+
+/// A warning from Nebula itself, such as an invalid config file, shown in a
+/// banner at the top of the window until dismissed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notice {
+    pub message: String,
+    /// How many times it was reported while on display.
+    pub count: usize,
+    /// Opened when the notice is clicked, such as the log file with the
+    /// details.
+    pub link: Option<String>,
+}
+
+#[derive(Default)]
+struct Notices {
+    shown: Vec<Notice>,
+    /// Messages dismissed once aren't shown again.
+    dismissed: HashSet<String>,
+}
+
+static NOTICES: LazyLock<Mutex<Notices>> = LazyLock::new(Mutex::default);
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static WAKER: OnceLock<Waker> = OnceLock::new();
+
+/// Call `waker` whenever a notice is reported, so the UI can show it.
+pub fn set_waker(waker: Waker) {
+    let _ = WAKER.set(waker);
+}
+
+/// Report a warning. It goes to stderr and, unless it was dismissed before,
+/// to the banner. Can be called from any thread.
+pub fn warn(message: impl Into<String>) {
+    report(message.into(), None);
+}
+
+/// Report a warning whose details are at `link`, opened by clicking it.
+pub fn warn_with_link(message: impl Into<String>, link: impl Into<String>) {
+    report(message.into(), Some(link.into()));
+}
+
+fn report(message: String, link: Option<String>) {
+    tracing::warn!("{}", message);
+
+    let mut notices = NOTICES.lock().unwrap();
+    if notices.dismissed.contains(&message) {
+        return;
+    }
+    match notices.shown.iter_mut().find(|notice| notice.message == message) {
+        Some(notice) => notice.count += 1,
+        None => {
+            if notices.shown.len() >= MAX_NOTICES {
+                notices.shown.remove(0);
+            }
+            notices.shown.push(Notice { message, count: 1, link });
+        }
+    }
+    GENERATION.fetch_add(1, Ordering::Release);
+    drop(notices);
+    if let Some(wake) = WAKER.get() {
+        wake();
+    }
+}
+
+/// Bumped whenever the notices change, so the UI only copies them then.
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::Acquire)
+}
+
+pub fn current() -> Vec<Notice> {
+    NOTICES.lock().unwrap().shown.clone()
+}
+
+/// Hide every notice on display for the rest of the run.
+pub fn dismiss() {
+    let mut notices = NOTICES.lock().unwrap();
+    let shown = std::mem::take(&mut notices.shown);
+    notices.dismissed.extend(shown.into_iter().map(|notice| notice.message));
+    GENERATION.fetch_add(1, Ordering::Release);
+}
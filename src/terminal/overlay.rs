@@ -0,0 +1,511 @@
+// src/terminal/overlay.rs
+use std::{collections::VecDeque, ops::Range};
+use tracing::info;
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, ModifiersState, NamedKey},
+};
+
+use crate::terminal::{
+    input::encode_key,
+    journal::Layout,
+    keybindings::{Action, Keymap},
+    terminal::TerminalModes,
+};
+
+/// Lines the key test remembers, enough to fill a tall window.
+const KEY_TEST_LINES: usize = 200;
+
+/// Text being composed by an input method, shown at the cursor until it is
+/// committed.
+pub struct ImePreedit {
+    pub text: String,
+    /// Byte offset of the input method's cursor within `text`, if shown.
+    pub cursor: Option<usize>,
+}
+
+/// What a close prompt is about to close.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseTarget {
+    Tab,
+    Window,
+}
+
+pub enum PromptEvent {
+    Confirm,
+    Cancel,
+    None,
+}
+
+/// Asks before closing a tab or window that still has commands running.
+pub struct ClosePrompt {
+    pub target: CloseTarget,
+    /// Names of the commands that would be hung up.
+    pub commands: Vec<String>,
+}
+
+/// Enter or Y confirms a prompt, Escape or N cancels it.
+fn prompt_key(key_event: &KeyEvent) -> PromptEvent {
+    if key_event.state != ElementState::Pressed {
+        return PromptEvent::None;
+    }
+
+    match key_event.logical_key.as_ref() {
+        Key::Named(NamedKey::Enter) => PromptEvent::Confirm,
+        Key::Named(NamedKey::Escape) => PromptEvent::Cancel,
+        Key::Character(ch) if ch.eq_ignore_ascii_case("y") => PromptEvent::Confirm,
+        Key::Character(ch) if ch.eq_ignore_ascii_case("n") => PromptEvent::Cancel,
+        _ => PromptEvent::None,
+    }
+}
+
+impl ClosePrompt {
+    pub fn handle_key(&self, key_event: &KeyEvent) -> PromptEvent {
+        prompt_key(key_event)
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self.target {
+            CloseTarget::Tab => "Close this tab?",
+            CloseTarget::Window => "Close Nebula?",
+        }
+    }
+
+    /// Which commands are still running, for example "vim is still running".
+    pub fn message(&self) -> String {
+        match self.commands.as_slice() {
+            [command] => format!("{} is still running", command),
+            commands => format!("{} are still running", commands.join(", ")),
+        }
+    }
+}
+
+/// Offers to reopen the tabs of windows that didn't shut down cleanly.
+pub struct RestorePrompt {
+    pub layouts: Vec<Layout>,
+}
+
+impl RestorePrompt {
+    pub fn handle_key(&self, key_event: &KeyEvent) -> PromptEvent {
+        prompt_key(key_event)
+    }
+
+    /// What would be reopened, for example "Reopen 3 tabs in 2 windows?".
+    pub fn message(&self) -> String {
+        let tabs: usize = self.layouts.iter().map(|layout| layout.tabs.len()).sum();
+        let plural = |n: usize, word: &str| if n == 1 { format!("1 {}", word) } else { format!("{} {}s", n, word) };
+        match self.layouts.len() {
+            1 => format!("Reopen {}?", plural(tabs, "tab")),
+            windows => format!("Reopen {} in {}?", plural(tabs, "tab"), plural(windows, "window")),
+        }
+    }
+}
+
+/// Maximum number of entries shown at once.
+pub const PICKER_VISIBLE_ENTRIES: usize = 10;
+
+pub enum PickerEvent {
+    Paste(String),
+    /// Paste the clipboard through the paste filter at this index.
+    Filter(usize),
+    Close,
+    None,
+}
+
+/// What a paste picker lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickerKind {
+    History,
+    Filters,
+}
+
+/// Overlay listing recent copies, or the paste filters by name; choosing
+/// one pastes it, or the clipboard through it, into the active session.
+pub struct PastePicker {
+    pub kind: PickerKind,
+    pub entries: Vec<String>,
+    pub selected: usize,
+}
+
+impl PastePicker {
+    pub fn new(entries: Vec<String>) -> Self {
+        Self { kind: PickerKind::History, entries, selected: 0 }
+    }
+
+    pub fn filters(names: Vec<String>) -> Self {
+        Self { kind: PickerKind::Filters, entries: names, selected: 0 }
+    }
+
+    pub fn handle_key(&mut self, key_event: &KeyEvent) -> PickerEvent {
+        if key_event.state != ElementState::Pressed {
+            return PickerEvent::None;
+        }
+
+        match key_event.logical_key.as_ref() {
+            Key::Named(NamedKey::Escape) => PickerEvent::Close,
+            Key::Named(NamedKey::ArrowUp) => {
+                self.selected = self.selected.saturating_sub(1);
+                PickerEvent::None
+            }
+            Key::Named(NamedKey::ArrowDown) => {
+                if self.selected + 1 < self.entries.len() {
+                    self.selected += 1;
+                }
+                PickerEvent::None
+            }
+            Key::Named(NamedKey::Enter) => self.choose(self.selected),
+            // 1-9 pick an entry directly
+            Key::Character(ch) => match ch.parse::<usize>() {
+                Ok(n @ 1..=9) => self.choose(n - 1),
+                _ => PickerEvent::None,
+            },
+            _ => PickerEvent::None,
+        }
+    }
+
+    fn choose(&self, index: usize) -> PickerEvent {
+        match (self.kind, self.entries.get(index)) {
+            (_, None) => PickerEvent::None,
+            (PickerKind::History, Some(entry)) => PickerEvent::Paste(entry.clone()),
+            (PickerKind::Filters, Some(_)) => PickerEvent::Filter(index),
+        }
+    }
+
+    /// Index of the first entry to show so the selection stays in view.
+    pub fn first_visible(&self) -> usize {
+        (self.selected + 1).saturating_sub(PICKER_VISIBLE_ENTRIES)
+    }
+
+    /// Single-line preview of an entry.
+    pub fn preview(entry: &str) -> String {
+        entry
+            .trim()
+            .chars()
+            .map(|c| match c {
+                '\n' => '⏎',
+                '\t' | '\r' => ' ',
+                c => c,
+            })
+            .collect()
+    }
+}
+
+pub enum ListEvent {
+    Close,
+    None,
+}
+
+/// Overlay listing every action with its bindings, filtered as you type.
+pub struct KeybindingList {
+    pub query: String,
+    /// Each action with the chords bound to it, in display form.
+    bindings: Vec<(Action, Vec<String>)>,
+    /// First matching entry shown, for scrolling.
+    pub first: usize,
+}
+
+impl KeybindingList {
+    pub fn new(keymap: &Keymap) -> Self {
+        let bindings = Action::ALL
+            .into_iter()
+            .map(|action| (action, keymap.chords(action).map(ToString::to_string).collect()))
+            .collect();
+        Self { query: String::new(), bindings, first: 0 }
+    }
+
+    pub fn handle_key(&mut self, key_event: &KeyEvent) -> ListEvent {
+        if key_event.state != ElementState::Pressed {
+            return ListEvent::None;
+        }
+
+        match key_event.logical_key.as_ref() {
+            Key::Named(NamedKey::Escape) => return ListEvent::Close,
+            Key::Named(NamedKey::ArrowUp) => self.first = self.first.saturating_sub(1),
+            Key::Named(NamedKey::ArrowDown) => {
+                if self.first + PICKER_VISIBLE_ENTRIES < self.entries().len() {
+                    self.first += 1;
+                }
+            }
+            Key::Named(NamedKey::Backspace) => {
+                self.query.pop();
+                self.first = 0;
+            }
+            _ => match &key_event.text {
+                Some(text) if !text.chars().any(char::is_control) => {
+                    self.query.push_str(text);
+                    self.first = 0;
+                }
+                _ => {}
+            },
+        }
+        ListEvent::None
+    }
+
+    /// Lines for the actions matching the query by name, description or
+    /// chord, like "Search  Ctrl+Shift+F  Search the scrollback".
+    pub fn entries(&self) -> Vec<String> {
+        let query = self.query.to_lowercase();
+        self.bindings
+            .iter()
+            .map(|(action, chords)| {
+                let chords = if chords.is_empty() { String::from("unbound") } else { chords.join(", ") };
+                format!("{:<16}  {:<22}  {}", action.name(), chords, action.description())
+            })
+            .filter(|entry| entry.to_lowercase().contains(&query))
+            .collect()
+    }
+}
+
+/// Commands from earlier prompts the command palette offers.
+pub const PALETTE_COMMANDS: usize = 50;
+
+/// Something the command palette can do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteItem {
+    Action(Action),
+    /// A command line typed at an earlier prompt, typed again when chosen.
+    Command(String),
+}
+
+pub enum PaletteEvent {
+    Choose(PaletteItem),
+    Close,
+    None,
+}
+
+/// Overlay listing every action and, when the shell marks its prompts,
+/// recent commands, narrowed by fuzzy matching as you type.
+pub struct CommandPalette {
+    pub query: String,
+    /// Each item with the line shown for it.
+    items: Vec<(PaletteItem, String)>,
+    /// Indexes of the items matching the query, best first.
+    pub matches: Vec<usize>,
+    pub selected: usize,
+}
+
+impl CommandPalette {
+    pub fn new(keymap: &Keymap, commands: Vec<String>) -> Self {
+        let actions = Action::ALL.into_iter().map(|action| {
+            let chords: Vec<String> = keymap.chords(action).map(ToString::to_string).collect();
+            (PaletteItem::Action(action), format!("{:<52}  {}", action.description(), chords.join(", ")))
+        });
+        let commands = commands.into_iter().map(|command| {
+            // Multi-line commands are shown on one line
+            let label = format!("$ {}", command.split_whitespace().collect::<Vec<_>>().join(" "));
+            (PaletteItem::Command(command), label)
+        });
+        let items = actions.chain(commands).collect();
+        let mut palette = Self { query: String::new(), items, matches: Vec::new(), selected: 0 };
+        palette.filter();
+        palette
+    }
+
+    pub fn handle_key(&mut self, key_event: &KeyEvent) -> PaletteEvent {
+        if key_event.state != ElementState::Pressed {
+            return PaletteEvent::None;
+        }
+
+        match key_event.logical_key.as_ref() {
+            Key::Named(NamedKey::Escape) => return PaletteEvent::Close,
+            Key::Named(NamedKey::Enter) => {
+                if let Some(&index) = self.matches.get(self.selected) {
+                    return PaletteEvent::Choose(self.items[index].0.clone());
+                }
+            }
+            Key::Named(NamedKey::ArrowUp) => self.selected = self.selected.saturating_sub(1),
+            Key::Named(NamedKey::ArrowDown) => {
+                if self.selected + 1 < self.matches.len() {
+                    self.selected += 1;
+                }
+            }
+            Key::Named(NamedKey::Backspace) => {
+                self.query.pop();
+                self.filter();
+            }
+            _ => match &key_event.text {
+                Some(text) if !text.chars().any(char::is_control) => {
+                    self.query.push_str(text);
+                    self.filter();
+                }
+                _ => {}
+            },
+        }
+        PaletteEvent::None
+    }
+
+    /// Line shown for the item at `index` of `matches`.
+    pub fn label(&self, index: usize) -> &str {
+        &self.items[self.matches[index]].1
+    }
+
+    /// Index of the first match to show so the selection stays in view.
+    pub fn first_visible(&self) -> usize {
+        (self.selected + 1).saturating_sub(PICKER_VISIBLE_ENTRIES)
+    }
+
+    fn filter(&mut self) {
+        let mut scored: Vec<(i32, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (_, label))| Some((fuzzy_score(&self.query, label)?, index)))
+            .collect();
+        // Best score first; the sort is stable, so ties keep their order
+        scored.sort_by_key(|&(score, _)| -score);
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.selected = 0;
+    }
+}
+
+/// How well `text` matches `query` typed as a subsequence of it, ignoring
+/// case, or None if it doesn't. Matches at the start of words and runs of
+/// matching characters score higher; so does a shorter text.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut position = 0;
+    for wanted in query.chars().flat_map(char::to_lowercase).filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|&c| c == wanted)?;
+        score += 1;
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 4;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score * 16 - text.len().min(255) as i32 / 16)
+}
+
+/// Letters hint labels are made of, home row first.
+const HINT_ALPHABET: &[u8] = b"asdfghjklqwertyuiopzxcvbnm";
+
+/// Something in view that hints mode labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hint {
+    pub row: usize,
+    pub columns: Range<usize>,
+    pub text: String,
+    /// A link or path, opened when chosen. Others are copied.
+    pub openable: bool,
+    pub label: String,
+}
+
+pub enum HintEvent {
+    Open(String),
+    Copy(String),
+    Close,
+    None,
+}
+
+/// Labels over every link, path and hash in view. Typing a label opens
+/// its target, or copies it if typed in capitals or if it can't be opened.
+pub struct HintsMode {
+    pub hints: Vec<Hint>,
+    pub typed: String,
+    copy: bool,
+}
+
+impl HintsMode {
+    /// Label `targets`, given as row, columns, text and whether it opens.
+    pub fn new(targets: Vec<(usize, Range<usize>, String, bool)>) -> Self {
+        let labels = hint_labels(targets.len());
+        let hints = targets
+            .into_iter()
+            .zip(labels)
+            .map(|((row, columns, text, openable), label)| Hint { row, columns, text, openable, label })
+            .collect();
+        Self { hints, typed: String::new(), copy: false }
+    }
+
+    pub fn handle_key(&mut self, key_event: &KeyEvent) -> HintEvent {
+        if key_event.state != ElementState::Pressed {
+            return HintEvent::None;
+        }
+
+        match key_event.logical_key.as_ref() {
+            Key::Named(NamedKey::Escape) => return HintEvent::Close,
+            Key::Named(NamedKey::Backspace) => {
+                self.typed.pop();
+            }
+            _ => {
+                let Some(letter) = key_event.text.as_deref().and_then(|text| text.chars().next()) else {
+                    return HintEvent::None;
+                };
+                self.copy |= letter.is_uppercase();
+                self.typed.push(letter.to_ascii_lowercase());
+                if let Some(hint) = self.hints.iter().find(|hint| hint.label == self.typed) {
+                    let text = hint.text.clone();
+                    return if hint.openable && !self.copy { HintEvent::Open(text) } else { HintEvent::Copy(text) };
+                }
+                // Keys that match no label are ignored
+                if self.visible().next().is_none() {
+                    self.typed.pop();
+                }
+            }
+        }
+        HintEvent::None
+    }
+
+    /// Hints whose label starts with what was typed.
+    pub fn visible(&self) -> impl Iterator<Item = &Hint> {
+        self.hints.iter().filter(|hint| hint.label.starts_with(&self.typed))
+    }
+}
+
+/// `count` labels of equal length, so none is the start of another.
+fn hint_labels(count: usize) -> Vec<String> {
+    let base = HINT_ALPHABET.len();
+    let mut length = 1;
+    while base.pow(length) < count {
+        length += 1;
+    }
+    (0..count)
+        .map(|mut n| {
+            let mut label = vec![0; length as usize];
+            for letter in label.iter_mut().rev() {
+                *letter = HINT_ALPHABET[n % base];
+                n /= base;
+            }
+            String::from_utf8(label).expect("the alphabet is ASCII")
+        })
+        .collect()
+}
+
+/// Screen of `nebula keytest`: every key press as winit reported it, the
+/// keys it resolved to and the bytes it would send, newest last. Keys are
+/// never sent to the shell while it is open.
+#[derive(Default)]
+pub struct KeyTest {
+    pub lines: VecDeque<String>,
+}
+
+impl KeyTest {
+    pub fn record(&mut self, key_event: &KeyEvent, modifiers: ModifiersState, modes: TerminalModes) {
+        if key_event.state != ElementState::Pressed {
+            return;
+        }
+        info!("Key test: {:?}", key_event);
+        let bytes = encode_key(key_event, modifiers, modes);
+        let escaped: String = bytes.iter().flat_map(|&byte| std::ascii::escape_default(byte)).map(char::from).collect();
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let repeat = if key_event.repeat { "  (repeat)" } else { "" };
+
+        self.lines.extend([
+            format!("{:?}", key_event),
+            format!(
+                "  logical {:?}  physical {:?}  {:?}{}",
+                key_event.logical_key, key_event.physical_key, key_event.location, repeat
+            ),
+            format!("  text {:?}  modifiers {:?}", key_event.text.as_deref(), modifiers),
+            format!("  sends \"{}\"  [{}]", escaped, hex.join(" ")),
+        ]);
+        while self.lines.len() > KEY_TEST_LINES {
+            self.lines.pop_front();
+        }
+    }
+}
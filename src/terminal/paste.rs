@@ -0,0 +1,325 @@
+// src/terminal/paste.rs
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use std::{
+    io::{Read, Write},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, LazyLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::terminal::{
+    config::PasteFilter,
+    notices,
+    terminal::{PtyWriter, Waker},
+};
+
+/// Pastes larger than this are written from a thread, with their progress
+/// shown and Escape cancelling them, so the window stays responsive.
+pub const BACKGROUND_PASTE_SIZE: usize = 64 * 1024;
+/// Bytes written at a time, about what a PTY takes in one go (PIPE_BUF).
+const CHUNK_SIZE: usize = 4096;
+/// Bytes written between redraws of the progress bar.
+const PROGRESS_STEP: usize = 64 * 1024;
+/// Markers around a bracketed paste (mode 2004).
+const PASTE_START: &str = "\x1B[200~";
+const PASTE_END: &str = "\x1B[201~";
+
+/// How long a filter command may take before it is killed and the paste
+/// given up on.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often a filter command is checked on while it runs.
+const COMMAND_POLL: Duration = Duration::from_millis(10);
+
+/// CSI, OSC, DCS and other escape sequences, and stray C1 introducers.
+static ESCAPE_SEQUENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b[\]PX^_][^\x07\x1b]*(?:\x07|\x1b\\)?|\x1b[ -/]*[0-~]|\x1b|[\u{80}-\u{9f}]")
+        .unwrap()
+});
+
+/// `text` rewritten by each step `filter` enables, in the order the config
+/// lists them.
+pub fn apply(filter: &PasteFilter, text: &str) -> Result<String> {
+    let mut text = text.to_string();
+    if filter.strip_ansi {
+        text = ESCAPE_SEQUENCE.replace_all(&text, "").into_owned();
+    }
+    if !filter.command.is_empty() {
+        text = run_command(&filter.command, text)?;
+    }
+    if filter.dedent {
+        text = dedent(&text);
+    }
+    if filter.drop_blank_lines {
+        text = text.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<_>>().join("\n");
+    }
+    if filter.collapse_whitespace {
+        text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    Ok(format!("{}{}{}", filter.prepend, text, filter.append))
+}
+
+/// Lines with the indentation they all share and trailing whitespace removed.
+fn dedent(text: &str) -> String {
+    let indent = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+    let common = text.lines().filter(|line| !line.trim().is_empty()).map(indent).min().unwrap_or(0);
+    text.lines()
+        .map(|line| line.get(common.min(indent(line))..).unwrap_or_default().trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Output of `command` given `text` on its input. A command still running
+/// after `COMMAND_TIMEOUT` is killed.
+fn run_command(command: &[String], text: String) -> Result<String> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run paste filter {:?}", command[0]))?;
+    let mut stdin = child.stdin.take().context("Paste filter has no input")?;
+    thread::spawn(move || stdin.write_all(text.as_bytes()));
+    let mut stdout = child.stdout.take().context("Paste filter has no output")?;
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= COMMAND_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("Paste filter {:?} took longer than {}s", command[0], COMMAND_TIMEOUT.as_secs());
+        }
+        thread::sleep(COMMAND_POLL);
+    };
+    if !status.success() {
+        bail!("Paste filter {:?} failed with {}", command[0], status);
+    }
+    let output = reader.join().map_err(|_| anyhow!("Paste filter {:?} output was lost", command[0]))??;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// `text` marked as a bracketed paste. End markers inside it are dropped so
+/// it can't end the paste early, until none are left, since dropping one
+/// can join the text around it into another.
+pub fn bracket(text: &str) -> String {
+    let mut body = text.to_string();
+    while body.contains(PASTE_END) {
+        body = body.replace(PASTE_END, "");
+    }
+    format!("{}{}{}", PASTE_START, body, PASTE_END)
+}
+
+/// Text being run through a paste filter on a thread of its own, so a slow
+/// filter command doesn't hold up the window.
+pub struct FilterJob {
+    result: Receiver<Result<String>>,
+}
+
+impl FilterJob {
+    /// Start filtering `text`, calling `waker` once done.
+    pub fn start(filter: PasteFilter, text: String, waker: Option<Waker>) -> Self {
+        let (sender, result) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(apply(&filter, &text));
+            if let Some(wake) = waker {
+                wake();
+            }
+        });
+        Self { result }
+    }
+
+    /// The filtered text, or why there is none, once the filter is done.
+    pub fn try_finish(&self) -> Option<Result<String>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!("Paste filter stopped without a result"))),
+        }
+    }
+}
+
+/// A large paste being written to the PTY in chunks. Other input can get
+/// between chunks, so typing stays possible while it runs.
+pub struct PasteJob {
+    pub total: usize,
+    /// Written a character at a time, as `PasteAsTyped` does.
+    pub typed: bool,
+    written: Arc<AtomicUsize>,
+    cancelled: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl PasteJob {
+    /// Start writing `text` to `writer`, calling `waker` as it goes. With a
+    /// `delay` the text is typed one character at a time, pausing that long
+    /// after each. A bracketed paste that is cancelled still gets its end
+    /// marker, so the program isn't left waiting for the rest.
+    pub fn start(writer: PtyWriter, text: String, delay: Option<Duration>, waker: Option<Waker>) -> Self {
+        let total = text.len();
+        let written = Arc::new(AtomicUsize::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (written, cancelled) = (Arc::clone(&written), Arc::clone(&cancelled));
+            thread::spawn(move || {
+                let mut last_wake = 0;
+                // The markers go out whole, so a cancelled paste never ends
+                // halfway through one
+                let bracketed = text.len() >= PASTE_START.len() + PASTE_END.len()
+                    && text.starts_with(PASTE_START)
+                    && text.ends_with(PASTE_END);
+                let (start, body, end) = match bracketed {
+                    true => (PASTE_START, &text[PASTE_START.len()..text.len() - PASTE_END.len()], PASTE_END),
+                    false => ("", text.as_str(), ""),
+                };
+                let body_chunks: Vec<&[u8]> = match delay {
+                    Some(_) => body.char_indices().map(|(i, c)| &body.as_bytes()[i..i + c.len_utf8()]).collect(),
+                    None => body.as_bytes().chunks(CHUNK_SIZE).collect(),
+                };
+                let chunks = [start.as_bytes()].into_iter().chain(body_chunks).chain([end.as_bytes()]);
+                for chunk in chunks.filter(|chunk| !chunk.is_empty()) {
+                    if cancelled.load(Ordering::SeqCst) {
+                        if bracketed && written.load(Ordering::SeqCst) > 0 {
+                            if let Ok(mut writer) = writer.lock() {
+                                let _ = writer.write_all(PASTE_END.as_bytes()).and_then(|_| writer.flush());
+                            }
+                        }
+                        break;
+                    }
+                    let result = writer.lock().map_err(|_| std::io::Error::other("writer poisoned")).and_then(|mut writer| {
+                        writer.write_all(chunk)?;
+                        writer.flush()
+                    });
+                    let done = written.fetch_add(chunk.len(), Ordering::SeqCst) + chunk.len();
+                    if let Err(e) = result {
+                        notices::warn(format!("Paste stopped after {} of {} bytes: {}", done - chunk.len(), total, e));
+                        break;
+                    }
+                    // Typing is slow enough to show every character
+                    if done - last_wake >= PROGRESS_STEP || delay.is_some() {
+                        last_wake = done;
+                        if let Some(wake) = &waker {
+                            wake();
+                        }
+                    }
+                    if let Some(delay) = delay {
+                        thread::sleep(delay);
+                    }
+                }
+                if let Some(wake) = &waker {
+                    wake();
+                }
+            })
+        };
+        Self { total, typed: delay.is_some(), written, cancelled, thread }
+    }
+
+    /// Bytes handed to the PTY so far.
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::SeqCst).min(self.total)
+    }
+
+    /// Stop after the chunk being written.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn dedent_lines() {
+        // Blank lines don't count toward the shared indentation
+        let text = "    if x:\n\n        y()   \n    z()\n";
+        assert_eq!(dedent(text), "if x:\n\n    y()\nz()");
+        assert_eq!(dedent("\tone\n\t\ttwo"), "one\n\ttwo");
+        // A line indented less than the rest keeps what it has
+        assert_eq!(dedent("  a\n b"), " a\nb");
+        assert_eq!(dedent(""), "");
+    }
+
+    #[test]
+    fn filter_steps() {
+        let filter = |edit: fn(&mut PasteFilter)| {
+            let mut filter = PasteFilter::default();
+            edit(&mut filter);
+            filter
+        };
+        let colored = "\x1B[1;31merror\x1B[0m: \x1B]8;;file:///a\x07a.rs\x1B]8;;\x07\n";
+        let stripped = apply(&filter(|f| f.strip_ansi = true), colored).unwrap();
+        assert_eq!(stripped, "error: a.rs\n");
+        assert_eq!(apply(&PasteFilter::default(), colored).unwrap(), colored);
+
+        let text = "  first\n\n   \n  second  line\n";
+        assert_eq!(apply(&filter(|f| f.drop_blank_lines = true), text).unwrap(), "  first\n  second  line");
+        assert_eq!(apply(&filter(|f| f.collapse_whitespace = true), text).unwrap(), "first second line");
+
+        // Dedenting runs before the text is wrapped
+        let wrapped = filter(|f| {
+            f.dedent = true;
+            f.prepend = "```\n".into();
+            f.append = "\n```".into();
+        });
+        assert_eq!(apply(&wrapped, "  a\n    b").unwrap(), "```\na\n  b\n```");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn filter_commands() {
+        let command = |args: &[&str]| PasteFilter {
+            command: args.iter().map(|arg| arg.to_string()).collect(),
+            ..PasteFilter::default()
+        };
+        assert_eq!(apply(&command(&["tr", "a-z", "A-Z"]), "echo hi").unwrap(), "ECHO HI");
+        // A command that fails gives up the paste rather than pasting nothing
+        assert!(apply(&command(&["false"]), "echo hi").is_err());
+        assert!(apply(&command(&["/nonexistent/filter"]), "echo hi").is_err());
+    }
+
+    #[test]
+    fn bracketed_paste_markers() {
+        assert_eq!(bracket("ls\r"), "\x1B[200~ls\r\x1B[201~");
+        // Dropping the inner marker joins the rest into another one
+        let pasted = bracket("echo \x1B[20\x1B[201~1~; rm -rf ~\r");
+        assert_eq!(pasted, "\x1B[200~echo ; rm -rf ~\r\x1B[201~");
+    }
+
+    #[test]
+    fn cancelled_paste_ends_bracket() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let writer: PtyWriter = output.clone();
+        let text = bracket(&"x".repeat(200));
+        let job = PasteJob::start(writer, text.clone(), Some(Duration::from_millis(5)), None);
+        thread::sleep(Duration::from_millis(50));
+        job.cancel();
+        while !job.is_finished() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.len() < text.len());
+        assert!(output.starts_with(PASTE_START) && output.ends_with(PASTE_END), "{:?}", output);
+    }
+}
@@ -0,0 +1,227 @@
+// src/terminal/post_process.rs
+use anyhow::{anyhow, Context, Result};
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, CommandEncoder, Device, Extent3d, Queue, RenderPipeline, Sampler,
+    SurfaceConfiguration, TextureDescriptor, TextureDimension, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+/// Bindings and the vertex stage every user shader is compiled with.
+const PRELUDE: &str = include_str!("shaders/post_process.wgsl");
+
+/// How often an animated shader is redrawn.
+pub const ANIMATION_INTERVAL: Duration = Duration::from_millis(16);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Uniforms {
+    resolution: [f32; 2],
+    time: f32,
+    _padding: f32,
+}
+
+/// The frame rendered offscreen, for the shader to sample from.
+struct Frame {
+    view: TextureView,
+    bind_group: BindGroup,
+    size: (u32, u32),
+}
+
+/// A user's WGSL fragment shader run over the whole window, for effects
+/// such as CRT curvature, scanlines or glow. While one is loaded, frames
+/// are rendered into a texture that the shader draws to the window.
+pub struct PostProcess {
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+    uniforms: Buffer,
+    frame: Option<Frame>,
+    start: Instant,
+    /// The shader reads the time, so frames are drawn even when nothing
+    /// else changed.
+    pub animated: bool,
+}
+
+impl PostProcess {
+    /// Compile the shader at `path`. Relative paths are looked up next to
+    /// the config file.
+    pub fn load(device: &Device, config: &SurfaceConfiguration, path: &Path, config_dir: Option<&Path>) -> Result<Self> {
+        let path = match config_dir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_path_buf(),
+        };
+        let source = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Process Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Process Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Mistakes in the shader are reported here rather than taking the
+        // device down with them
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Post Process Shader"),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", PRELUDE, source).into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Post Process Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(anyhow!("{}", error));
+        }
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Process Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Process Uniforms"),
+            size: std::mem::size_of::<Uniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            uniforms,
+            frame: None,
+            start: Instant::now(),
+            animated: source.contains("uniforms.time"),
+        })
+    }
+
+    /// Texture to render the frame into, sized to the surface.
+    pub fn frame_view(&mut self, device: &Device, config: &SurfaceConfiguration) -> &TextureView {
+        let size = (config.width, config.height);
+        if self.frame.as_ref().is_none_or(|frame| frame.size != size) {
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some("Post Process Frame"),
+                size: Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: config.format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&TextureViewDescriptor::default());
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Post Process Bind Group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.uniforms.as_entire_binding(),
+                    },
+                ],
+            });
+            self.frame = Some(Frame { view, bind_group, size });
+        }
+        &self.frame.as_ref().unwrap().view
+    }
+
+    /// Run the shader over the offscreen frame, drawing to `target`.
+    pub fn draw(&self, queue: &Queue, encoder: &mut CommandEncoder, target: &TextureView) {
+        let Some(frame) = &self.frame else { return };
+        let uniforms = Uniforms {
+            resolution: [frame.size.0 as f32, frame.size.1 as f32],
+            time: self.start.elapsed().as_secs_f32(),
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniforms, 0, bytemuck::bytes_of(&uniforms));
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Process Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &frame.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
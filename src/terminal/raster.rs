@@ -0,0 +1,83 @@
+// src/terminal/raster.rs
+use cosmic_text::{CacheKey, FontSystem, SwashImage};
+use std::collections::HashMap;
+use swash::{
+    scale::{image::Content, Render, ScaleContext, Source, StrikeWith},
+    zeno::{Format, Vector},
+};
+
+use crate::terminal::config::{Antialiasing, RenderingConfig, StrokeWeight};
+
+/// Outline emboldening applied for `StrokeWeight::Darkened`, in pixels.
+const DARKEN_STRENGTH: f32 = 0.35;
+/// Coverage exponent applied for `StrokeWeight::Thin`.
+const THIN_GAMMA: f32 = 1.45;
+
+/// Rasterizes glyphs with swash according to the user's rendering settings.
+/// Stands in for cosmic-text's `SwashCache`, which always hints and always
+/// antialiases.
+pub struct GlyphRasterizer {
+    context: ScaleContext,
+    settings: RenderingConfig,
+    cache: HashMap<CacheKey, Option<SwashImage>>,
+}
+
+impl GlyphRasterizer {
+    pub fn new(settings: RenderingConfig) -> Self {
+        Self {
+            context: ScaleContext::new(),
+            settings,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn get_image(&mut self, fs: &mut FontSystem, key: CacheKey) -> Option<&SwashImage> {
+        if !self.cache.contains_key(&key) {
+            let image = self.render(fs, key);
+            self.cache.insert(key, image);
+        }
+        self.cache.get(&key)?.as_ref()
+    }
+
+    fn render(&mut self, fs: &mut FontSystem, key: CacheKey) -> Option<SwashImage> {
+        let font = fs.get_font(key.font_id)?;
+        let mut scaler = self
+            .context
+            .builder(font.as_swash())
+            .size(f32::from_bits(key.font_size_bits))
+            .hint(self.settings.hinting)
+            .build();
+
+        let mut render = Render::new(&[
+            Source::ColorOutline(0),
+            Source::ColorBitmap(StrikeWith::BestFit),
+            Source::Outline,
+        ]);
+        render
+            .format(Format::Alpha)
+            .offset(Vector::new(key.x_bin.as_float(), key.y_bin.as_float()));
+        if self.settings.strokes == StrokeWeight::Darkened {
+            render.embolden(DARKEN_STRENGTH);
+        }
+        let mut image = render.render(&mut scaler, key.glyph_id)?;
+
+        // Coverage adjustments only make sense for plain outline masks
+        if image.content == Content::Mask {
+            self.adjust_coverage(&mut image.data);
+        }
+        Some(image)
+    }
+
+    fn adjust_coverage(&self, data: &mut [u8]) {
+        if self.settings.strokes == StrokeWeight::Thin {
+            for alpha in data.iter_mut() {
+                *alpha = ((*alpha as f32 / 255.0).powf(THIN_GAMMA) * 255.0).round() as u8;
+            }
+        }
+        if self.settings.antialiasing == Antialiasing::None {
+            for alpha in data.iter_mut() {
+                *alpha = if *alpha >= 128 { 255 } else { 0 };
+            }
+        }
+    }
+}
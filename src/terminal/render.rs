@@ -1,15 +1,1105 @@
 use wgpu::{Device, Queue, SurfaceConfiguration};
+use tracing::{debug, error, trace, trace_span, warn};
 use crate::terminal::{
+    terminal::{BackgroundSpan, CursorShape, CursorStyle},
+    decorations::push_decorations,
+    gpu::QuadBuffer,
+    GpuResources,
+    links::column_at,
+    mirror::Mirror,
+    row_cache::{RowCache, RowColors},
+    Session,
     TerminalState,
     window::TerminalWindow,
-    texture::GlyphKey,
-    config::{ATLAS_SIZE, FONT_SIZE, LINE_HEIGHT},
+    texture::{GlyphAtlas, GlyphKey, GlyphLocation},
+    colors::TextColors,
+    config::{CommandTimeConfig, Rgb, Theme, CELL_WIDTH, FONT_SIZE, LINE_HEIGHT, TAB_BAR_HEIGHT},
+    shaper::{layout_glyphs, ShapedGlyph},
+    notices::Notice,
+    paste::PasteJob,
+    overlay::{CommandPalette, HintsMode, ImePreedit, KeyTest, KeybindingList, PastePicker, PickerKind, PICKER_VISIBLE_ENTRIES},
+    raster::GlyphRasterizer,
+    row_cache::PlacedGlyph,
+    search::SearchBar,
+    selection::Selection,
+    timing,
+    usage::format_bytes,
 };
-use std::time::Instant;
-use wgpu::util::DeviceExt;
-use bytemuck;
-use cosmic_text::CacheKey;
-use cosmic_text::CacheKeyFlags;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use winit::dpi::{LogicalPosition, LogicalSize};
+use cosmic_text::{Attrs, Buffer, CacheKey, Family, FontSystem, Metrics, Shaping};
+
+/// One instance of the shared quad: its corners in clip space (left, top,
+/// right, bottom), texture rectangle and color.
+pub type Quad = [f32; 12];
+
+// Texture coordinates that tell the shader to fill with a solid color
+pub const SOLID: [f32; 4] = [-1.0, -1.0, -1.0, -1.0];
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const TAB_BAR_COLOR: [f32; 4] = [0.08, 0.08, 0.1, 1.0];
+const TAB_COLOR: [f32; 4] = [0.14, 0.14, 0.17, 1.0];
+const ACTIVE_TAB_COLOR: [f32; 4] = [0.24, 0.24, 0.3, 1.0];
+const PRIVATE_TAB_COLOR: [f32; 4] = [0.2, 0.1, 0.24, 1.0];
+const ACTIVE_PRIVATE_TAB_COLOR: [f32; 4] = [0.36, 0.18, 0.42, 1.0];
+const INACTIVE_TAB_TEXT_COLOR: [f32; 4] = [0.65, 0.65, 0.7, 1.0];
+const OVERLAY_COLOR: [f32; 4] = [0.12, 0.12, 0.15, 0.97];
+const OVERLAY_SELECTED_COLOR: [f32; 4] = [0.26, 0.3, 0.42, 1.0];
+const OVERLAY_HINT_COLOR: [f32; 4] = [0.55, 0.55, 0.6, 1.0];
+/// Opacity of the visual bell's tint.
+const BELL_FLASH_ALPHA: f32 = 0.2;
+const NOTICE_COLOR: [f32; 4] = [0.38, 0.27, 0.06, 0.97];
+const SEARCH_MATCH_COLOR: [f32; 4] = [0.42, 0.36, 0.08, 1.0];
+const CURRENT_SEARCH_MATCH_COLOR: [f32; 4] = [0.85, 0.5, 0.08, 1.0];
+const SCROLLBAR_TRACK_COLOR: [f32; 4] = [0.5, 0.5, 0.55, 0.15];
+const SCROLLBAR_THUMB_COLOR: [f32; 4] = [0.65, 0.65, 0.7, 0.6];
+/// Width of the scrollbar, which is also how far from the right edge it
+/// takes clicks.
+pub const SCROLLBAR_WIDTH: f32 = 8.0;
+const SCROLLBAR_MIN_THUMB: f32 = 16.0;
+/// How long the scrollbar takes to fade out.
+pub const SCROLLBAR_FADE: Duration = Duration::from_millis(300);
+const SELECTION_COLOR: Rgb = Rgb(124, 153, 196);
+const HINT_COLOR: [f32; 4] = [0.85, 0.5, 0.08, 1.0];
+const HINT_TARGET_COLOR: [f32; 4] = [0.26, 0.3, 0.42, 0.6];
+const CURSOR_THICKNESS: f32 = 2.0;
+const PREEDIT_UNDERLINE_THICKNESS: f32 = 1.0;
+const LINK_UNDERLINE_THICKNESS: f32 = 1.0;
+const HOLLOW_CURSOR_THICKNESS: f32 = 1.0;
+const MAX_TAB_WIDTH: f32 = 220.0;
+const TAB_PADDING: f32 = 10.0;
+/// Room kept at the right of a tab for its resource badge.
+const BADGE_WIDTH: f32 = 72.0;
+const PICKER_MAX_WIDTH: f32 = 640.0;
+const PICKER_PADDING: f32 = 8.0;
+const KEYBINDING_LIST_WIDTH: f32 = 880.0;
+const SEARCH_BAR_PADDING: f32 = 4.0;
+/// Space at the right of the search bar for the match count.
+const SEARCH_STATUS_WIDTH: f32 = 220.0;
+
+/// Instance buffers for the quads rebuilt every frame.
+pub struct FrameBuffers {
+    background: QuadBuffer,
+    images: QuadBuffer,
+    decorations: QuadBuffer,
+    overlay: QuadBuffer,
+}
+
+impl FrameBuffers {
+    /// Free the buffers, which the next frame allocates at the size it needs.
+    pub fn release(&mut self) {
+        self.background.release();
+        self.images.release();
+        self.decorations.release();
+        self.overlay.release();
+    }
+}
+
+impl Default for FrameBuffers {
+    fn default() -> Self {
+        Self {
+            background: QuadBuffer::new("Background Quads"),
+            images: QuadBuffer::new("Large Glyph Quads"),
+            decorations: QuadBuffer::new("Decoration Quads"),
+            overlay: QuadBuffer::new("Overlay Quads"),
+        }
+    }
+}
+
+/// Screen size in logical pixels, which everything is laid out in, used to
+/// convert to normalized device coordinates. Glyphs are rasterized at
+/// `scale` times their size so they stay sharp on HiDPI displays.
+#[derive(Clone, Copy)]
+pub struct Screen {
+    pub width: f32,
+    pub height: f32,
+    pub scale: f32,
+}
+
+impl Screen {
+    pub fn new(config: &SurfaceConfiguration, scale: f32) -> Self {
+        Self {
+            width: config.width as f32 / scale,
+            height: config.height as f32 / scale,
+            scale,
+        }
+    }
+
+    fn ndc_x(&self, x: f32) -> f32 {
+        (x / self.width) * 2.0 - 1.0
+    }
+
+    fn ndc_y(&self, y: f32) -> f32 {
+        1.0 - (y / self.height) * 2.0
+    }
+}
+
+/// Push a quad covering the pixel rectangle `(x, y, w, h)`.
+pub fn push_quad(
+    quads: &mut Vec<Quad>,
+    screen: Screen,
+    (x, y, w, h): (f32, f32, f32, f32),
+    [u0, v0, u1, v1]: [f32; 4],
+    [r, g, b, a]: [f32; 4],
+) {
+    let left = screen.ndc_x(x);
+    let right = screen.ndc_x(x + w);
+    let top = screen.ndc_y(y);
+    let bottom = screen.ndc_y(y + h);
+
+    quads.push([left, top, right, bottom, u0, v0, u1, v1, r, g, b, a]);
+}
+
+/// Draw the cursor in its requested shape, or as a hollow block when the
+/// window is unfocused.
+fn push_cursor(
+    quads: &mut Vec<Quad>,
+    screen: Screen,
+    (x, y, w, h): (f32, f32, f32, f32),
+    style: CursorStyle,
+    focused: bool,
+    color: [f32; 4],
+) {
+    if !focused {
+        let t = HOLLOW_CURSOR_THICKNESS;
+        push_quad(quads, screen, (x, y, w, t), SOLID, color);
+        push_quad(quads, screen, (x, y + h - t, w, t), SOLID, color);
+        push_quad(quads, screen, (x, y, t, h), SOLID, color);
+        push_quad(quads, screen, (x + w - t, y, t, h), SOLID, color);
+        return;
+    }
+
+    let rect = match style.shape {
+        CursorShape::Block => (x, y, w, h),
+        CursorShape::Underline => (x, y + h - CURSOR_THICKNESS, w, CURSOR_THICKNESS),
+        CursorShape::Bar => (x, y, CURSOR_THICKNESS, h),
+    };
+    push_quad(quads, screen, rect, SOLID, color);
+}
+
+/// Rasterize laid out glyphs into the atlas at `scale` physical pixels per
+/// logical one. Placements come back in logical pixels.
+fn rasterize_glyphs(
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    glyphs: &[ShapedGlyph],
+    scale: f32,
+) -> Vec<PlacedGlyph> {
+    let mut placed = Vec::new();
+
+    for glyph in glyphs {
+        // Glyphs are cached per physical size
+        let font_size = glyph.font_size * scale;
+        let key = GlyphKey {
+            font_id: glyph.font_id,
+            glyph_id: glyph.glyph_id,
+            font_size: font_size as u16,
+        };
+
+        // Create cache key for swash
+        let cache_key = CacheKey::new(
+            glyph.font_id,
+            glyph.glyph_id,
+            font_size,
+            (0.0, 0.0),
+            cosmic_text::CacheKeyFlags::empty(),
+        );
+
+        // Get the swash image
+        let Some(image) = rasterizer.get_image(fs, cache_key.0) else {
+            continue;
+        };
+
+        // Skip zero-sized images
+        if image.placement.width == 0 || image.placement.height == 0 {
+            continue;
+        }
+
+        // Add to atlas or get existing
+        let x = glyph.x + image.placement.left as f32 / scale;
+        let y = glyph.y - image.placement.top as f32 / scale;
+        match atlas.add_glyph(queue, key, image) {
+            Ok(GlyphLocation::Atlas(atlas_x, atlas_y, w, h)) => {
+                // Calculate texture coordinates
+                let size = atlas.size() as f32;
+                let u = atlas_x as f32 / size;
+                let v = atlas_y as f32 / size;
+                let atlas_w = w as f32 / size;
+                let atlas_h = h as f32 / size;
+
+                placed.push(PlacedGlyph {
+                    x,
+                    y,
+                    w: w as f32 / scale,
+                    h: h as f32 / scale,
+                    uv: [u, v, u + atlas_w, v + atlas_h],
+                    texture: None,
+                });
+            }
+            Ok(GlyphLocation::Texture(texture, w, h)) => {
+                placed.push(PlacedGlyph {
+                    x,
+                    y,
+                    w: w as f32 / scale,
+                    h: h as f32 / scale,
+                    uv: [0.0, 0.0, 1.0, 1.0],
+                    texture: Some(texture),
+                });
+            }
+            Err(e) => debug!("Glyph atlas error: {}", e),
+        }
+    }
+
+    placed
+}
+
+/// Rasterize every glyph of a shaped buffer, positioned relative to the
+/// buffer's top-left corner.
+fn place_glyphs(
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    buffer: &Buffer,
+    scale: f32,
+) -> Vec<PlacedGlyph> {
+    let glyphs = layout_glyphs(buffer, |glyph| glyph.x);
+    rasterize_glyphs(queue, atlas, rasterizer, fs, &glyphs, scale)
+}
+
+/// Shape a single line of text and emit its glyphs at `origin`, clipped at `max_x`.
+#[allow(clippy::too_many_arguments)]
+fn push_label(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    text: &str,
+    family: Family,
+    origin: (f32, f32),
+    max_x: f32,
+    color: [f32; 4],
+) {
+    let mut label = Buffer::new(fs, Metrics::new(FONT_SIZE, LINE_HEIGHT));
+    label.set_text(fs, text, &Attrs::new().family(family), Shaping::Advanced);
+    label.shape_until_scroll(fs, true);
+
+    let (origin_x, origin_y) = origin;
+    for glyph in place_glyphs(queue, atlas, rasterizer, fs, &label, screen.scale) {
+        // UI labels are small; glyphs outside the atlas are not worth a pass here
+        if glyph.texture.is_some() || origin_x + glyph.x + glyph.w > max_x {
+            continue;
+        }
+        push_quad(
+            quads,
+            screen,
+            (origin_x + glyph.x, origin_y + glyph.y, glyph.w, glyph.h),
+            glyph.uv,
+            color,
+        );
+    }
+}
+
+/// Faint, right-aligned timings at prompts that followed a command, on rows
+/// with room to spare after their text.
+#[allow(clippy::too_many_arguments)]
+fn push_command_timings(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    top: f32,
+    session: &Session,
+    config: CommandTimeConfig,
+    theme: Theme,
+) {
+    let [fr, fg, fb, _] = theme.foreground.to_linear();
+    let [br, bg, bb, _] = theme.background.to_linear();
+    let color = [(fr + br) / 2.0, (fg + bg) / 2.0, (fb + bb) / 2.0, 1.0];
+    let columns = (screen.width / CELL_WIDTH) as usize;
+    for (row, timing) in &session.command_timings {
+        if timing.duration.as_secs_f32() < config.min_seconds {
+            continue;
+        }
+        let Some(text) = session.rows.get(*row) else { continue };
+        let label = timing::annotation(timing, config.finish_time);
+        let used = column_at(text, text.trim_end().len());
+        // Leave a gap after the prompt
+        if used + label.len() + 2 > columns {
+            continue;
+        }
+        let x = (columns - label.len() - 1) as f32 * CELL_WIDTH;
+        let y = top + *row as f32 * LINE_HEIGHT;
+        push_label(quads, queue, atlas, rasterizer, fs, screen, &label, Family::Monospace, (x, y), screen.width, color);
+    }
+}
+
+/// Draw the input method's uncommitted text at the cursor, underlined and
+/// covering the cells beneath it, with a bar at the input method's cursor.
+#[allow(clippy::too_many_arguments)]
+fn push_preedit(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    preedit: &ImePreedit,
+    attrs: &Attrs,
+    (x, y): (f32, f32),
+    theme: Theme,
+) {
+    let color = theme.foreground.to_linear();
+    let mut buffer = Buffer::new(fs, Metrics::new(FONT_SIZE, LINE_HEIGHT));
+    buffer.set_text(fs, &preedit.text, attrs, Shaping::Advanced);
+    buffer.shape_until_scroll(fs, true);
+
+    let mut width = 0.0f32;
+    let mut cursor_x = None;
+    for run in buffer.layout_runs() {
+        width = width.max(run.line_w);
+        if let Some(cursor) = preedit.cursor {
+            cursor_x = run
+                .glyphs
+                .iter()
+                .find(|glyph| glyph.start >= cursor)
+                .map(|glyph| glyph.x)
+                .or(Some(run.line_w));
+        }
+    }
+
+    push_quad(quads, screen, (x, y, width, LINE_HEIGHT), SOLID, theme.background.to_linear());
+    for glyph in place_glyphs(queue, atlas, rasterizer, fs, &buffer, screen.scale) {
+        if glyph.texture.is_none() {
+            push_quad(quads, screen, (x + glyph.x, y + glyph.y, glyph.w, glyph.h), glyph.uv, color);
+        }
+    }
+    let underline_y = y + LINE_HEIGHT - PREEDIT_UNDERLINE_THICKNESS;
+    push_quad(quads, screen, (x, underline_y, width, PREEDIT_UNDERLINE_THICKNESS), SOLID, color);
+    if let Some(cursor_x) = cursor_x {
+        push_quad(quads, screen, (x + cursor_x, y, CURSOR_THICKNESS, LINE_HEIGHT), SOLID, color);
+    }
+}
+
+/// Width of each of `tabs` tabs in a window `screen_width` wide.
+pub fn tab_width(screen_width: f32, tabs: usize) -> f32 {
+    (screen_width / tabs as f32).min(MAX_TAB_WIDTH)
+}
+
+/// Draw one tab per session along the top of the window.
+#[allow(clippy::too_many_arguments)]
+fn push_tab_bar(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    sessions: &[Session],
+    active: usize,
+) {
+    push_quad(quads, screen, (0.0, 0.0, screen.width, TAB_BAR_HEIGHT), SOLID, TAB_BAR_COLOR);
+
+    let tab_width = tab_width(screen.width, sessions.len());
+    let text_y = (TAB_BAR_HEIGHT - LINE_HEIGHT) / 2.0;
+
+    for (index, session) in sessions.iter().enumerate() {
+        let tab_x = index as f32 * tab_width;
+        let (background, text_color) = match (index == active, session.is_private()) {
+            (true, false) => (ACTIVE_TAB_COLOR, TEXT_COLOR),
+            (true, true) => (ACTIVE_PRIVATE_TAB_COLOR, TEXT_COLOR),
+            (false, false) => (TAB_COLOR, INACTIVE_TAB_TEXT_COLOR),
+            (false, true) => (PRIVATE_TAB_COLOR, INACTIVE_TAB_TEXT_COLOR),
+        };
+        // Leave a one pixel gap between tabs
+        push_quad(quads, screen, (tab_x, 0.0, tab_width - 1.0, TAB_BAR_HEIGHT), SOLID, background);
+
+        // Narrow tabs keep their room for the title
+        let mut title_end = tab_x + tab_width - TAB_PADDING;
+        if let Some(usage) = session.usage.filter(|_| tab_width >= 2.0 * BADGE_WIDTH + 2.0 * TAB_PADDING) {
+            let badge_x = title_end - BADGE_WIDTH;
+            push_label(
+                quads,
+                queue,
+                atlas,
+                rasterizer,
+                fs,
+                screen,
+                &usage.badge(),
+                Family::Monospace,
+                (badge_x, text_y),
+                title_end,
+                INACTIVE_TAB_TEXT_COLOR,
+            );
+            title_end = badge_x - TAB_PADDING / 2.0;
+        }
+
+        push_label(
+            quads,
+            queue,
+            atlas,
+            rasterizer,
+            fs,
+            screen,
+            &session.title,
+            Family::SansSerif,
+            (tab_x + TAB_PADDING, text_y),
+            title_end,
+            text_color,
+        );
+    }
+}
+
+/// Draw how much of a large paste has been written as a bar along the
+/// bottom of the window, above `bottom`.
+#[allow(clippy::too_many_arguments)]
+fn push_paste_progress(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    bottom: f32,
+    job: &PasteJob,
+) {
+    let height = LINE_HEIGHT + 2.0 * SEARCH_BAR_PADDING;
+    let y = bottom - height;
+    let written = job.written();
+    let fraction = written as f32 / job.total.max(1) as f32;
+    push_quad(quads, screen, (0.0, y, screen.width, height), SOLID, OVERLAY_COLOR);
+    push_quad(quads, screen, (0.0, y, screen.width * fraction, height), SOLID, OVERLAY_SELECTED_COLOR);
+    let status = if job.is_cancelled() { "cancelling" } else { "Esc to cancel" };
+    push_label(
+        quads,
+        queue,
+        atlas,
+        rasterizer,
+        fs,
+        screen,
+        &format!(
+            "{} {} of {}, {}",
+            if job.typed { "Typing" } else { "Pasting" },
+            format_bytes(written as u64),
+            format_bytes(job.total as u64),
+            status
+        ),
+        Family::SansSerif,
+        (SEARCH_BAR_PADDING, y + SEARCH_BAR_PADDING),
+        screen.width - SEARCH_BAR_PADDING,
+        TEXT_COLOR,
+    );
+}
+
+/// Draw the clipboard history as a list near the top of the window, with
+/// the selected entry highlighted.
+#[allow(clippy::too_many_arguments)]
+fn push_paste_picker(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    top: f32,
+    picker: &PastePicker,
+) {
+    let first = picker.first_visible();
+    let shown = picker.entries.len().saturating_sub(first).min(PICKER_VISIBLE_ENTRIES);
+    let width = PICKER_MAX_WIDTH.min(screen.width - 2.0 * PICKER_PADDING);
+    let height = (shown + 1) as f32 * LINE_HEIGHT + 2.0 * PICKER_PADDING;
+    let x = (screen.width - width) / 2.0;
+    let y = top + LINE_HEIGHT;
+    let text_x = x + PICKER_PADDING;
+    let max_x = x + width - PICKER_PADDING;
+
+    push_quad(quads, screen, (x, y, width, height), SOLID, OVERLAY_COLOR);
+
+    let header = match (picker.kind, picker.entries.is_empty()) {
+        (PickerKind::History, true) => "Clipboard history is empty",
+        (PickerKind::History, false) => "Clipboard history: Enter or 1-9 to paste, Esc to cancel",
+        (PickerKind::Filters, true) => "No paste filters are configured",
+        (PickerKind::Filters, false) => "Paste through: Enter or 1-9 to paste, Esc to cancel",
+    };
+    push_label(
+        quads,
+        queue,
+        atlas,
+        rasterizer,
+        fs,
+        screen,
+        header,
+        Family::SansSerif,
+        (text_x, y + PICKER_PADDING),
+        max_x,
+        OVERLAY_HINT_COLOR,
+    );
+
+    let visible = picker.entries.iter().enumerate().skip(first).take(shown);
+    for (row, (index, entry)) in visible.enumerate() {
+        let row_y = y + PICKER_PADDING + (row + 1) as f32 * LINE_HEIGHT;
+        if index == picker.selected {
+            push_quad(quads, screen, (x, row_y, width, LINE_HEIGHT), SOLID, OVERLAY_SELECTED_COLOR);
+        }
+        let label = if index < 9 {
+            format!("{}  {}", index + 1, PastePicker::preview(entry))
+        } else {
+            format!("   {}", PastePicker::preview(entry))
+        };
+        push_label(
+            quads,
+            queue,
+            atlas,
+            rasterizer,
+            fs,
+            screen,
+            &label,
+            Family::Monospace,
+            (text_x, row_y),
+            max_x,
+            TEXT_COLOR,
+        );
+    }
+}
+
+/// Draw a yes/no question in the middle of the window.
+#[allow(clippy::too_many_arguments)]
+fn push_prompt(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    title: &str,
+    message: &str,
+    hint: &str,
+) {
+    let width = PICKER_MAX_WIDTH.min(screen.width - 2.0 * PICKER_PADDING);
+    let height = 3.0 * LINE_HEIGHT + 2.0 * PICKER_PADDING;
+    let x = (screen.width - width) / 2.0;
+    let y = ((screen.height - height) / 2.0).max(0.0);
+    let text_x = x + PICKER_PADDING;
+    let max_x = x + width - PICKER_PADDING;
+    push_quad(quads, screen, (x, y, width, height), SOLID, OVERLAY_COLOR);
+
+    let lines = [(title, TEXT_COLOR), (message, TEXT_COLOR), (hint, OVERLAY_HINT_COLOR)];
+    for (index, (text, color)) in lines.into_iter().enumerate() {
+        let line_y = y + PICKER_PADDING + index as f32 * LINE_HEIGHT;
+        push_label(quads, queue, atlas, rasterizer, fs, screen, text, Family::SansSerif, (text_x, line_y), max_x, color);
+    }
+}
+
+/// Draw the keybinding list below the tab bar: a filter line, then one
+/// line per matching action.
+#[allow(clippy::too_many_arguments)]
+fn push_keybinding_list(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    top: f32,
+    list: &KeybindingList,
+) {
+    let entries = list.entries();
+    let shown = entries.len().saturating_sub(list.first).min(PICKER_VISIBLE_ENTRIES);
+    let width = KEYBINDING_LIST_WIDTH.min(screen.width - 2.0 * PICKER_PADDING);
+    let height = (shown.max(1) + 1) as f32 * LINE_HEIGHT + 2.0 * PICKER_PADDING;
+    let x = (screen.width - width) / 2.0;
+    let y = top + LINE_HEIGHT;
+    let text_x = x + PICKER_PADDING;
+    let max_x = x + width - PICKER_PADDING;
+    push_quad(quads, screen, (x, y, width, height), SOLID, OVERLAY_COLOR);
+
+    let header = format!("Keybindings: {}▏", list.query);
+    push_label(quads, queue, atlas, rasterizer, fs, screen, &header, Family::Monospace, (text_x, y + PICKER_PADDING), max_x, OVERLAY_HINT_COLOR);
+
+    if entries.is_empty() {
+        let row_y = y + PICKER_PADDING + LINE_HEIGHT;
+        push_label(quads, queue, atlas, rasterizer, fs, screen, "No matching actions", Family::SansSerif, (text_x, row_y), max_x, OVERLAY_HINT_COLOR);
+    }
+    for (row, entry) in entries.iter().skip(list.first).take(shown).enumerate() {
+        let row_y = y + PICKER_PADDING + (row + 1) as f32 * LINE_HEIGHT;
+        push_label(quads, queue, atlas, rasterizer, fs, screen, entry, Family::Monospace, (text_x, row_y), max_x, TEXT_COLOR);
+    }
+}
+
+/// Draw the command palette below the tab bar: the query, then the best
+/// matches with the selected one highlighted.
+#[allow(clippy::too_many_arguments)]
+fn push_command_palette(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    top: f32,
+    palette: &CommandPalette,
+) {
+    let first = palette.first_visible();
+    let shown = palette.matches.len().saturating_sub(first).min(PICKER_VISIBLE_ENTRIES);
+    let width = KEYBINDING_LIST_WIDTH.min(screen.width - 2.0 * PICKER_PADDING);
+    let height = (shown.max(1) + 1) as f32 * LINE_HEIGHT + 2.0 * PICKER_PADDING;
+    let x = (screen.width - width) / 2.0;
+    let y = top + LINE_HEIGHT;
+    let text_x = x + PICKER_PADDING;
+    let max_x = x + width - PICKER_PADDING;
+    push_quad(quads, screen, (x, y, width, height), SOLID, OVERLAY_COLOR);
+
+    let header = format!("> {}▏", palette.query);
+    push_label(quads, queue, atlas, rasterizer, fs, screen, &header, Family::Monospace, (text_x, y + PICKER_PADDING), max_x, OVERLAY_HINT_COLOR);
+
+    if palette.matches.is_empty() {
+        let row_y = y + PICKER_PADDING + LINE_HEIGHT;
+        push_label(quads, queue, atlas, rasterizer, fs, screen, "Nothing matches", Family::SansSerif, (text_x, row_y), max_x, OVERLAY_HINT_COLOR);
+    }
+    for row in 0..shown {
+        let index = first + row;
+        let row_y = y + PICKER_PADDING + (row + 1) as f32 * LINE_HEIGHT;
+        if index == palette.selected {
+            push_quad(quads, screen, (x, row_y, width, LINE_HEIGHT), SOLID, OVERLAY_SELECTED_COLOR);
+        }
+        push_label(quads, queue, atlas, rasterizer, fs, screen, palette.label(index), Family::Monospace, (text_x, row_y), max_x, TEXT_COLOR);
+    }
+}
+
+/// Draw hints mode: each target still matching what was typed is shaded,
+/// with the rest of its label over its first cells.
+#[allow(clippy::too_many_arguments)]
+fn push_hints(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    top: f32,
+    hints: &HintsMode,
+) {
+    for hint in hints.visible() {
+        let x = hint.columns.start as f32 * CELL_WIDTH;
+        let y = top + hint.row as f32 * LINE_HEIGHT;
+        let width = hint.columns.len() as f32 * CELL_WIDTH;
+        push_quad(quads, screen, (x, y, width, LINE_HEIGHT), SOLID, HINT_TARGET_COLOR);
+        let label = &hint.label[hints.typed.len()..];
+        let label_width = label.len() as f32 * CELL_WIDTH;
+        push_quad(quads, screen, (x, y, label_width, LINE_HEIGHT), SOLID, HINT_COLOR);
+        push_label(quads, queue, atlas, rasterizer, fs, screen, label, Family::Monospace, (x, y), screen.width, TEXT_COLOR);
+    }
+}
+
+/// Draw the key test over the whole terminal area, newest key press at
+/// the bottom.
+#[allow(clippy::too_many_arguments)]
+fn push_key_test(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    top: f32,
+    key_test: &KeyTest,
+) {
+    push_quad(quads, screen, (0.0, top, screen.width, screen.height - top), SOLID, OVERLAY_COLOR);
+    let max_x = screen.width - PICKER_PADDING;
+    let header = "Key test: press keys to see what they send, close the window to quit";
+    push_label(quads, queue, atlas, rasterizer, fs, screen, header, Family::SansSerif, (PICKER_PADDING, top + PICKER_PADDING), max_x, OVERLAY_HINT_COLOR);
+
+    let first_y = top + PICKER_PADDING + LINE_HEIGHT;
+    let fits = ((screen.height - first_y) / LINE_HEIGHT).max(0.0) as usize;
+    let skip = key_test.lines.len().saturating_sub(fits);
+    for (row, line) in key_test.lines.iter().skip(skip).enumerate() {
+        let y = first_y + row as f32 * LINE_HEIGHT;
+        push_label(quads, queue, atlas, rasterizer, fs, screen, line, Family::Monospace, (PICKER_PADDING, y), max_x, TEXT_COLOR);
+    }
+}
+
+/// Height of the notice banner showing `count` notices.
+pub fn notice_banner_height(count: usize) -> f32 {
+    (count + 1) as f32 * LINE_HEIGHT + 2.0 * PICKER_PADDING
+}
+
+/// The notice on the banner row `y` below its top, if any.
+pub fn notice_at(y: f32, count: usize) -> Option<usize> {
+    let row = (y - PICKER_PADDING) / LINE_HEIGHT;
+    (row >= 0.0 && (row as usize) < count).then_some(row as usize)
+}
+
+/// Draw Nebula's own warnings across the top of the terminal.
+#[allow(clippy::too_many_arguments)]
+fn push_notice_banner(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    top: f32,
+    notices: &[Notice],
+) {
+    let height = notice_banner_height(notices.len());
+    push_quad(quads, screen, (0.0, top, screen.width, height), SOLID, NOTICE_COLOR);
+
+    let max_x = screen.width - PICKER_PADDING;
+    for (index, notice) in notices.iter().enumerate() {
+        let mut text = match notice.count {
+            1 => notice.message.clone(),
+            count => format!("{} (×{})", notice.message, count),
+        };
+        if notice.link.is_some() {
+            text.push_str(" — click for details");
+        }
+        let y = top + PICKER_PADDING + index as f32 * LINE_HEIGHT;
+        push_label(quads, queue, atlas, rasterizer, fs, screen, &text, Family::SansSerif, (PICKER_PADDING, y), max_x, TEXT_COLOR);
+    }
+    let y = top + PICKER_PADDING + notices.len() as f32 * LINE_HEIGHT;
+    push_label(
+        quads,
+        queue,
+        atlas,
+        rasterizer,
+        fs,
+        screen,
+        "Click to dismiss",
+        Family::SansSerif,
+        (PICKER_PADDING, y),
+        max_x,
+        OVERLAY_HINT_COLOR,
+    );
+}
+
+/// Color frames are cleared to: the theme background at the opacity the
+/// window is drawn with.
+fn clear_color(state: &TerminalState, theme: Theme) -> wgpu::Color {
+    let [r, g, b, _] = theme.background.to_linear();
+    let alpha = state.background_opacity;
+    let scale = if state.premultiplied_alpha { alpha } else { 1.0 };
+    wgpu::Color {
+        r: (r * scale) as f64,
+        g: (g * scale) as f64,
+        b: (b * scale) as f64,
+        a: alpha as f64,
+    }
+}
+
+/// Fill the content area with the theme background, then each cell with a
+/// background of its own.
+fn push_cell_backgrounds(quads: &mut Vec<Quad>, screen: Screen, top: f32, theme: Theme, opaque: bool, spans: &[BackgroundSpan]) {
+    // A translucent background is left to the clear color, since a second
+    // translucent layer over it would come out more opaque
+    if opaque {
+        push_quad(quads, screen, (0.0, top, screen.width, screen.height - top), SOLID, theme.background.to_linear());
+    }
+    for span in spans {
+        let rect = (
+            span.columns.start as f32 * CELL_WIDTH,
+            top + span.row as f32 * LINE_HEIGHT,
+            span.columns.len() as f32 * CELL_WIDTH,
+            LINE_HEIGHT,
+        );
+        push_quad(quads, screen, rect, SOLID, span.color.to_linear());
+    }
+}
+
+/// Highlight search matches in view, behind the text.
+fn push_search_matches(
+    quads: &mut Vec<Quad>,
+    screen: Screen,
+    top: f32,
+    search: &SearchBar,
+    view_top: usize,
+    rows: usize,
+) {
+    for (index, found) in search.matches.iter().enumerate() {
+        let Some(row) = found.line.checked_sub(view_top).filter(|&row| row < rows) else {
+            continue;
+        };
+        let color = if search.current == Some(index) {
+            CURRENT_SEARCH_MATCH_COLOR
+        } else {
+            SEARCH_MATCH_COLOR
+        };
+        let rect = (
+            found.columns.start as f32 * CELL_WIDTH,
+            top + row as f32 * LINE_HEIGHT,
+            found.columns.len() as f32 * CELL_WIDTH,
+            LINE_HEIGHT,
+        );
+        push_quad(quads, screen, rect, SOLID, color);
+    }
+}
+
+/// The selected cells in view as backgrounds, so the text on them gets
+/// its colors worked out against the highlight. Linear selections reach
+/// the right edge on every line but their last.
+fn selection_spans(selection: &Selection, view_top: usize, rows: usize, cols: usize) -> Vec<BackgroundSpan> {
+    (0..rows)
+        .filter_map(|row| {
+            let columns = selection.columns(view_top + row)?;
+            let columns = columns.start..columns.end.min(cols);
+            (!columns.is_empty()).then_some(BackgroundSpan { row, columns, color: SELECTION_COLOR })
+        })
+        .collect()
+}
+
+/// Height of the scrollbar thumb on a track `track` high, for `rows` in
+/// view out of `history` more in the scrollback.
+pub fn scrollbar_thumb(track: f32, rows: usize, history: usize) -> f32 {
+    let total = (rows + history).max(1) as f32;
+    (track * rows as f32 / total).max(SCROLLBAR_MIN_THUMB).min(track)
+}
+
+/// Draw the scrollbar down the right edge, with the thumb where the view
+/// is between the oldest line kept and the live screen.
+fn push_scrollbar(quads: &mut Vec<Quad>, screen: Screen, top: f32, session: &Session, alpha: f32) {
+    let rows = session.rows.len();
+    let history = session.scrollback.len();
+    let track = rows as f32 * LINE_HEIGHT;
+    let height = scrollbar_thumb(track, rows, history);
+    let offset = session.last_view_top.saturating_sub(session.scrollback.start).min(history);
+    let y = top + (track - height) * offset as f32 / history.max(1) as f32;
+    let x = screen.width - SCROLLBAR_WIDTH;
+    let faded = |[r, g, b, a]: [f32; 4]| [r, g, b, a * alpha];
+    push_quad(quads, screen, (x, top, SCROLLBAR_WIDTH, track), SOLID, faded(SCROLLBAR_TRACK_COLOR));
+    push_quad(quads, screen, (x, y, SCROLLBAR_WIDTH, height), SOLID, faded(SCROLLBAR_THUMB_COLOR));
+}
+
+/// Draw the search bar along the bottom of the window.
+#[allow(clippy::too_many_arguments)]
+fn push_search_bar(
+    quads: &mut Vec<Quad>,
+    queue: &Queue,
+    atlas: &mut GlyphAtlas,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    screen: Screen,
+    search: &SearchBar,
+) {
+    let height = LINE_HEIGHT + 2.0 * SEARCH_BAR_PADDING;
+    let y = screen.height - height;
+    push_quad(quads, screen, (0.0, y, screen.width, height), SOLID, OVERLAY_COLOR);
+
+    let prompt = if search.regex { "Regex" } else { "Find" };
+    let caret = if search.editing { "▏" } else { "" };
+    let status_x = (screen.width - SEARCH_STATUS_WIDTH).max(0.0);
+    push_label(
+        quads,
+        queue,
+        atlas,
+        rasterizer,
+        fs,
+        screen,
+        &format!("{}: {}{}", prompt, search.query, caret),
+        Family::Monospace,
+        (SEARCH_BAR_PADDING, y + SEARCH_BAR_PADDING),
+        status_x,
+        TEXT_COLOR,
+    );
+
+    let hint = if search.editing { "Enter, Alt+R regex" } else { "n/N, / to edit" };
+    push_label(
+        quads,
+        queue,
+        atlas,
+        rasterizer,
+        fs,
+        screen,
+        &format!("{}  {}", search.status(), hint),
+        Family::SansSerif,
+        (status_x + SEARCH_BAR_PADDING, y + SEARCH_BAR_PADDING),
+        screen.width - SEARCH_BAR_PADDING,
+        OVERLAY_HINT_COLOR,
+    );
+}
+
+/// Text of the rows in view, the generation it belongs to and the colors
+/// it is drawn in.
+#[derive(Clone, Copy)]
+struct TextRows<'a> {
+    rows: &'a [String],
+    generation: &'a Arc<AtomicU64>,
+    colors: &'a TextColors,
+}
+
+/// Shape and upload the rows that changed since `cache` was last updated.
+/// Returns the number of rows rewritten.
+#[allow(clippy::too_many_arguments)]
+fn update_rows(
+    device: &Device,
+    queue: &Queue,
+    state: &mut TerminalState,
+    rasterizer: &mut GlyphRasterizer,
+    fs: &mut FontSystem,
+    cache: &mut RowCache,
+    text: TextRows,
+    screen: Screen,
+    top: f32,
+) -> usize {
+    let rows: Vec<&str> = text.rows.iter().map(String::as_str).collect();
+    let colors: Vec<RowColors> = text
+        .colors
+        .rows
+        .iter()
+        .map(|runs| runs.iter().map(|(columns, color)| (columns.clone(), color.to_linear())).collect())
+        .collect();
+    let foreground = text.colors.default.to_linear();
+    cache.update(device, queue, &rows, &colors, screen, top, foreground, |hash, row| {
+        let glyphs = state.shaper.take(hash, row, text.generation)?;
+        Some(rasterize_glyphs(queue, &mut state.glyph_atlas, rasterizer, fs, &glyphs, screen.scale))
+    })
+}
+
+/// Write quads for the glyphs in `cache` too large for the atlas. Returns
+/// the texture each quad is drawn from.
+fn write_large_glyphs(
+    device: &Device,
+    queue: &Queue,
+    buffer: &mut QuadBuffer,
+    cache: &RowCache,
+    screen: Screen,
+    theme: Theme,
+) -> Vec<usize> {
+    let mut quads: Vec<Quad> = Vec::new();
+    let mut textures = Vec::new();
+    for glyph in cache.large_glyphs() {
+        push_quad(&mut quads, screen, glyph.rect, [0.0, 0.0, 1.0, 1.0], theme.foreground.to_linear());
+        textures.push(glyph.texture);
+    }
+    buffer.write(device, queue, &quads);
+    textures
+}
+
+/// Image pass: glyphs too large for the atlas, one draw per texture.
+fn draw_large_glyphs(rpass: &mut wgpu::RenderPass, buffer: &QuadBuffer, textures: &[usize], atlas: &GlyphAtlas) {
+    if textures.is_empty() {
+        return;
+    }
+    for (index, &texture) in textures.iter().enumerate() {
+        if let Some(bind_group) = atlas.large_glyph(texture) {
+            rpass.set_bind_group(0, bind_group, &[]);
+            let index = index as u32;
+            buffer.draw(rpass, Some(index..index + 1));
+        }
+    }
+    rpass.set_bind_group(0, atlas.bind_group(), &[]);
+}
+
+/// Underlines and strikethrough, over the text and under the overlays.
+fn draw_decorations(rpass: &mut wgpu::RenderPass, buffer: &QuadBuffer, gpu: &GpuResources) {
+    rpass.set_pipeline(&gpu.decoration_pipeline);
+    buffer.draw(rpass, None);
+    rpass.set_pipeline(&gpu.pipeline);
+}
+
+/// Draw a mirror window: the session's rows and cursor, without the tab
+/// bar or anything else that belongs to the main window.
+pub fn render_mirror(device: &Device, queue: &Queue, mirror: &mut Mirror, state: &mut TerminalState, session: &Session) {
+    let _span = trace_span!("render_mirror", session = session.id).entered();
+    let output = match mirror.window.surface.get_current_texture() {
+        Ok(frame) => frame,
+        Err(_) => {
+            warn!("Mirror surface texture error, reconfiguring surface");
+            mirror.window.configure_surface(device, &mirror.config);
+            mirror.window.window.request_redraw();
+            return;
+        }
+    };
+    let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let screen = Screen::new(&mirror.config, mirror.scale);
+    // The tab's colors, which a session rule may have changed
+    let theme = session.theme();
+    // A follow window keeps to the live screen while the tab is scrolled back
+    let (rows, foregrounds, backgrounds, decorations) = match &session.tail {
+        Some(tail) if mirror.follow => (&tail.rows, &tail.foregrounds, &tail.backgrounds, &tail.decorations),
+        _ => (&session.rows, &session.foregrounds, &session.backgrounds, &session.decorations),
+    };
+    let highlights = state.highlighter.annotate(rows);
+    let backgrounds = [backgrounds.as_slice(), &highlights.backgrounds].concat();
+    let reverse_screen = session.terminal.modes.lock().unwrap().reverse_screen;
+    let colors = TextColors::resolve(rows.len(), foregrounds, &backgrounds, theme, state.color_settings, reverse_screen);
+    let theme = colors.theme;
+
+    {
+        let font_system = Arc::clone(&state.font_system);
+        let Ok(mut fs) = font_system.lock() else {
+            error!("Failed to lock font system");
+            return;
+        };
+        let rasterizer = Arc::clone(&state.rasterizer);
+        let mut rasterizer = rasterizer.lock().unwrap();
+        let rows = TextRows { rows, generation: &session.text_generation, colors: &colors };
+        update_rows(device, queue, state, &mut rasterizer, &mut fs, &mut mirror.row_cache, rows, screen, 0.0);
+    }
+    mirror.drawn_generation = Some(session.snapshot_generation);
+
+    let mut background_quads: Vec<Quad> = Vec::new();
+    push_cell_backgrounds(&mut background_quads, screen, 0.0, theme, state.background_opacity >= 1.0, &colors.backgrounds);
+    mirror.background.write(device, queue, &background_quads);
+
+    // A steady cursor, since the mirror is only redrawn for new output
+    let mut quads: Vec<Quad> = Vec::new();
+    if !session.terminal.modes.lock().unwrap().cursor_hidden {
+        let (cursor_x, cursor_y) = session.cursor;
+        let cursor_style = *session.terminal.cursor_style.lock().unwrap();
+        let cursor_rect = (cursor_x, cursor_y, CELL_WIDTH, LINE_HEIGHT);
+        push_cursor(&mut quads, screen, cursor_rect, cursor_style, true, theme.foreground.to_linear());
+    }
+    mirror.overlay.write(device, queue, &quads);
+    let image_draws = write_large_glyphs(device, queue, &mut mirror.images, &mirror.row_cache, screen, theme);
+    let mut decoration_quads: Vec<Quad> = Vec::new();
+    let metrics = state.decoration_metrics;
+    let decorations = [decorations.as_slice(), &highlights.decorations].concat();
+    push_decorations(&mut decoration_quads, screen, 0.0, metrics, &decorations, theme.foreground);
+    mirror.decorations.write(device, queue, &decoration_quads);
+
+    let background = clear_color(state, theme);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Mirror Encoder"),
+    });
+    {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mirror Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(background),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        rpass.set_pipeline(&state.gpu_resources.pipeline);
+        rpass.set_bind_group(0, state.glyph_atlas.bind_group(), &[]);
+        state.gpu_resources.bind_quad(&mut rpass);
+        mirror.background.draw(&mut rpass, None);
+        mirror.row_cache.draw(&mut rpass);
+        draw_large_glyphs(&mut rpass, &mirror.images, &image_draws, &state.glyph_atlas);
+        draw_decorations(&mut rpass, &mirror.decorations, &state.gpu_resources);
+        mirror.overlay.draw(&mut rpass, None);
+    }
+    queue.submit(Some(encoder.finish()));
+    mirror.window.window.pre_present_notify();
+    output.present();
+}
 
 pub fn render_frame(
     device: &Device,
@@ -17,7 +1107,10 @@ pub fn render_frame(
     config: &SurfaceConfiguration,
     window: &TerminalWindow,
     state: &mut TerminalState,
-) {
+    sessions: &mut [Session],
+    active: usize,
+) -> bool {
+    let _span = trace_span!("render_frame", active).entered();
     let now = Instant::now();
     let _delta = now.duration_since(state.last_frame_time).as_secs_f32();
     state.last_frame_time = now;
@@ -26,185 +1119,329 @@ pub fn render_frame(
     let output = match window.surface.get_current_texture() {
         Ok(frame) => frame,
         Err(_) => {
-            eprintln!("Surface texture error, reconfiguring surface");
+            warn!("Surface texture error, reconfiguring surface");
             window.configure_surface(device, config);
             window.window.request_redraw();
-            return;
+            return false;
         }
     };
 
     let view = output
         .texture
         .create_view(&wgpu::TextureViewDescriptor::default());
-    
-    // Declare cursor variables here so they're available for debugging
-    let mut rendered_cursor = false;
-    let mut cursor_x = 0.0;
-    let mut cursor_y = 0.0;
-    
-    let (vertex_buffer, vertex_count) = if let Ok(mut buffer_lock) = state.buffer.lock() {
-        if let Ok(mut fs) = state.font_system.lock() {
-            // Shape the text buffer
-            buffer_lock.shape_until_scroll(&mut fs, true);
-            let mut verts: Vec<[f32; 4]> = Vec::new();
-
-            let (screen_width, screen_height) =
-                (config.width as f32, config.height as f32);
-            
-            let mut glyph_count = 0;
-            let mut skipped_glyphs = 0;
-            
-            for run in buffer_lock.layout_runs() {
-                for glyph in run.glyphs {
-                    // Skip zero-width glyphs (like space, control characters)
-                    if glyph.w == 0.0 {
-                        skipped_glyphs += 1;
-                        continue;
-                    }
-
-                    // Create glyph key
-                    let key = GlyphKey {
-                        font_id: glyph.font_id,
-                        glyph_id: glyph.glyph_id,
-                        font_size: glyph.font_size as u16,
-                    };
-
-                    // Create cache key for swash
-                    let cache_key = CacheKey::new(
-                        glyph.font_id,
-                        glyph.glyph_id,
-                        glyph.font_size,
-                        (0.0, 0.0),
-                        cosmic_text::CacheKeyFlags::empty(),
-                    );
-
-                    // Get the swash image
-                    if let Some(image) = state.swash_cache.lock().unwrap().get_image(&mut fs, cache_key.0) {
-                        // Skip zero-sized images
-                        if image.placement.width == 0 || image.placement.height == 0 {
-                            skipped_glyphs += 1;
-                            continue;
-                        }
-                        
-                        // Add to atlas or get existing
-                        match state.glyph_atlas.add_glyph(queue, key, &image) {
-                            Ok((x, y, w, h)) => {
-                                glyph_count += 1;
-                                
-                                // Calculate texture coordinates
-                                let atlas_x = x as f32 / ATLAS_SIZE as f32;
-                                let atlas_y = y as f32 / ATLAS_SIZE as f32;
-                                let atlas_w = w as f32 / ATLAS_SIZE as f32;
-                                let atlas_h = h as f32 / ATLAS_SIZE as f32;
-
-                                // Calculate screen position
-                                let screen_x = glyph.x;
-                                let screen_y = run.line_y + glyph.y - image.placement.top as f32;
-
-                                // Convert to normalized device coordinates
-                                let left = (screen_x / screen_width) * 2.0 - 1.0;
-                                let right = ((screen_x + w as f32) / screen_width) * 2.0 - 1.0;
-                                let top = 1.0 - (screen_y / screen_height) * 2.0;
-                                let bottom = 1.0 - ((screen_y + h as f32) / screen_height) * 2.0;
-
-                                // Create two triangles (6 vertices) for the glyph quad
-                                verts.push([left, top, atlas_x, atlas_y]);
-                                verts.push([right, top, atlas_x + atlas_w, atlas_y]);
-                                verts.push([left, bottom, atlas_x, atlas_y + atlas_h]);
-                                
-                                verts.push([right, top, atlas_x + atlas_w, atlas_y]);
-                                verts.push([right, bottom, atlas_x + atlas_w, atlas_y + atlas_h]);
-                                verts.push([left, bottom, atlas_x, atlas_y + atlas_h]);
-                            }
-                            Err(e) => {
-                                eprintln!("Glyph atlas error: {}", e);
-                                skipped_glyphs += 1;
-                            }
-                        }
-                    } else {
-                        skipped_glyphs += 1;
-                    }
-                }
-            }
 
-            // Get cursor position from state
-            cursor_x = *state.cursor_x.lock().unwrap();
-            cursor_y = *state.cursor_y.lock().unwrap();
-            let metrics = buffer_lock.metrics();
-
-            // Render cursor if visible and blinking
-            if state.cursor_visible && state.cursor_blink {
-                rendered_cursor = true;
-                let cursor_width = FONT_SIZE;
-                let cursor_height = LINE_HEIGHT;
-                
-                // Convert to normalized device coordinates
-                let left = (cursor_x / screen_width) * 2.0 - 1.0;
-                let right = ((cursor_x + cursor_width) / screen_width) * 2.0 - 1.0;
-                let top = 1.0 - (cursor_y / screen_height) * 2.0;
-                let bottom = 1.0 - ((cursor_y + cursor_height) / screen_height) * 2.0;
-                
-                // Create two triangles (6 vertices) for the cursor quad
-                // Using special texture coordinates (-1, -1) to indicate cursor
-                verts.push([left, top, -1.0, -1.0]);
-                verts.push([right, top, -1.0, -1.0]);
-                verts.push([left, bottom, -1.0, -1.0]);
-                verts.push([right, top, -1.0, -1.0]);
-                verts.push([right, bottom, -1.0, -1.0]);
-                verts.push([left, bottom, -1.0, -1.0]);
-            }
+    let screen = Screen::new(config, state.scale_factor);
+    // The tab bar is only shown once there is more than one tab
+    let content_top = if sessions.len() > 1 { TAB_BAR_HEIGHT } else { 0.0 };
+    // The active tab's colors, which a session rule may have changed
+    let theme = sessions[active].theme();
 
-            // Debug information
-            if state.local_dirty {
-                println!(
-                    "Rendering frame: {} glyphs, {} skipped, {} vertices, cursor: {}x{} at ({}, {})",
-                    glyph_count,
-                    skipped_glyphs,
-                    verts.len(),
-                    FONT_SIZE,
-                    LINE_HEIGHT,
-                    cursor_x,
-                    cursor_y
-                );
-            }
+    let font_system = Arc::clone(&state.font_system);
+    let Ok(mut fs) = font_system.lock() else {
+        error!("Failed to lock font system");
+        return false;
+    };
+    let rasterizer = Arc::clone(&state.rasterizer);
+    let mut rasterizer = rasterizer.lock().unwrap();
 
-            // Create vertex buffer if we have vertices
-            if !verts.is_empty() {
-                let vertex_buf = device.create_buffer_init(
-                    &wgpu::util::BufferInitDescriptor {
-                        label: Some("Glyph Vertices"),
-                        contents: bytemuck::cast_slice(&verts),
-                        usage: wgpu::BufferUsages::VERTEX,
-                    },
-                );
-                (Some(vertex_buf), verts.len() as u32)
-            } else {
-                (None, 0)
-            }
-        } else {
-            eprintln!("Failed to lock font system");
-            (None, 0)
-        }
-    } else {
-        eprintln!("Failed to lock text buffer");
-        (None, 0)
+    // Only rows that changed since the last frame are shaped and uploaded.
+    // Shaping happens on the shaper threads; rows still being shaped show
+    // their previous contents for a frame or two.
+    // Everything drawn with the rows moves with them while they glide
+    let rows_top = content_top + state.smooth_scroll.offset();
+    // Highlight rules go over the program's own colors
+    let highlights = state.highlighter.annotate(&sessions[active].rows);
+    // The selection goes over both
+    let session = &sessions[active];
+    let selected = session.selection.as_ref().map_or_else(Vec::new, |selection| {
+        let cols = *session.terminal.cols.lock().unwrap();
+        selection_spans(selection, session.last_view_top, session.rows.len(), cols)
+    });
+    let backgrounds = [session.backgrounds.as_slice(), &highlights.backgrounds, &selected].concat();
+    let reverse_screen = session.terminal.modes.lock().unwrap().reverse_screen;
+    let colors = TextColors::resolve(
+        session.rows.len(),
+        &session.foregrounds,
+        &backgrounds,
+        theme,
+        state.color_settings,
+        reverse_screen,
+    );
+    // The screen's colors from here on, swapped under DECSCNM
+    let theme = colors.theme;
+    let (rows_written, row_count) = {
+        let session = &mut sessions[active];
+        let rows = TextRows { rows: &session.rows, generation: &session.text_generation, colors: &colors };
+        let written = update_rows(device, queue, state, &mut rasterizer, &mut fs, &mut session.row_cache, rows, screen, rows_top);
+        (written, session.rows.len())
     };
 
+    let session = &mut sessions[active];
+    let published = session.terminal.snapshot_generation.load(Ordering::Acquire);
+    if published != session.snapshot_generation {
+        debug!(
+            "Drawing snapshot {} while {} is already published",
+            session.snapshot_generation, published
+        );
+    }
+    let previous_generation = session.drawn_generation.replace(session.snapshot_generation);
+
+    let session = &sessions[active];
+    let mut quads: Vec<Quad> = Vec::new();
+
+    // Drawn before the rows so the text stays readable on top
+    let mut background_quads: Vec<Quad> = Vec::new();
+    let opaque = state.background_opacity >= 1.0;
+    push_cell_backgrounds(&mut background_quads, screen, rows_top, theme, opaque, &colors.backgrounds);
+    if let Some(search) = &state.search {
+        push_search_matches(&mut background_quads, screen, rows_top, search, session.last_view_top, row_count);
+    }
+
+    // Get cursor position from the session
+    let (cursor_x, cursor_y) = session.cursor;
+    let cursor_y = cursor_y + rows_top;
+
+    // Render cursor unless the program hid it or it is in the off phase of
+    // a blink. Steady cursors and the unfocused hollow cursor never blink.
+    let cursor_style = *session.terminal.cursor_style.lock().unwrap();
+    let cursor_hidden = session.terminal.modes.lock().unwrap().cursor_hidden;
+    let blink_on = state.cursor_visible || !cursor_style.blinking || !state.focused || !state.cursor_blink;
+    if let Some(preedit) = &state.ime_preedit {
+        push_preedit(
+            &mut quads,
+            queue,
+            &mut state.glyph_atlas,
+            &mut rasterizer,
+            &mut fs,
+            screen,
+            preedit,
+            &state.fonts.attrs(),
+            (cursor_x, cursor_y),
+            theme,
+        );
+    } else if blink_on && !cursor_hidden {
+        let cursor_width = CELL_WIDTH;
+        let cursor_height = LINE_HEIGHT;
+
+        push_cursor(
+            &mut quads,
+            screen,
+            (cursor_x, cursor_y, cursor_width, cursor_height),
+            cursor_style,
+            state.focused,
+            theme.foreground.to_linear(),
+        );
+    }
+
+    // Underline every piece of the link under the mouse
+    if let Some(hovered) = &state.hovered_link {
+        for link in session.last_links.iter().filter(|link| link.same_link(hovered)) {
+            let x = link.columns.start as f32 * CELL_WIDTH;
+            let y = rows_top + (link.row + 1) as f32 * LINE_HEIGHT - LINK_UNDERLINE_THICKNESS;
+            let width = link.columns.len() as f32 * CELL_WIDTH;
+            push_quad(
+                &mut quads,
+                screen,
+                (x, y, width, LINK_UNDERLINE_THICKNESS),
+                SOLID,
+                theme.foreground.to_linear(),
+            );
+        }
+    }
+
+    if state.command_time.enabled {
+        push_command_timings(
+            &mut quads,
+            queue,
+            &mut state.glyph_atlas,
+            &mut rasterizer,
+            &mut fs,
+            screen,
+            rows_top,
+            session,
+            state.command_time,
+            theme,
+        );
+    }
+
+    if let Some(until) = state.scrollbar_until {
+        let remaining = until.saturating_duration_since(Instant::now());
+        let alpha = (remaining.as_secs_f32() / SCROLLBAR_FADE.as_secs_f32()).min(1.0);
+        push_scrollbar(&mut quads, screen, content_top, session, alpha);
+    }
+
+    // Keep the input method's candidate window next to the cursor
+    if state.ime_cursor_area != Some((cursor_x, cursor_y)) {
+        state.ime_cursor_area = Some((cursor_x, cursor_y));
+        window.window.set_ime_cursor_area(
+            LogicalPosition::new(cursor_x, cursor_y),
+            LogicalSize::new(CELL_WIDTH, LINE_HEIGHT),
+        );
+    }
+
+    if sessions.len() > 1 {
+        push_tab_bar(
+            &mut quads,
+            queue,
+            &mut state.glyph_atlas,
+            &mut rasterizer,
+            &mut fs,
+            screen,
+            sessions,
+            active,
+        );
+    }
+
+    if !state.notices.is_empty() {
+        push_notice_banner(
+            &mut quads,
+            queue,
+            &mut state.glyph_atlas,
+            &mut rasterizer,
+            &mut fs,
+            screen,
+            content_top,
+            &state.notices,
+        );
+    }
+
+    if let Some(search) = &state.search {
+        push_search_bar(&mut quads, queue, &mut state.glyph_atlas, &mut rasterizer, &mut fs, screen, search);
+    }
+
+    if let Some(job) = &session.paste_job {
+        let bottom = if state.search.is_some() { screen.height - LINE_HEIGHT - 2.0 * SEARCH_BAR_PADDING } else { screen.height };
+        push_paste_progress(&mut quads, queue, &mut state.glyph_atlas, &mut rasterizer, &mut fs, screen, bottom, job);
+    }
+
+    if let Some(picker) = &state.paste_picker {
+        push_paste_picker(
+            &mut quads,
+            queue,
+            &mut state.glyph_atlas,
+            &mut rasterizer,
+            &mut fs,
+            screen,
+            content_top,
+            picker,
+        );
+    }
+    if let Some(key_test) = &state.key_test {
+        push_key_test(
+            &mut quads,
+            queue,
+            &mut state.glyph_atlas,
+            &mut rasterizer,
+            &mut fs,
+            screen,
+            content_top,
+            key_test,
+        );
+    }
+    if let Some(list) = &state.keybinding_list {
+        push_keybinding_list(
+            &mut quads,
+            queue,
+            &mut state.glyph_atlas,
+            &mut rasterizer,
+            &mut fs,
+            screen,
+            content_top,
+            list,
+        );
+    }
+    if let Some(hints) = &state.hints {
+        push_hints(&mut quads, queue, &mut state.glyph_atlas, &mut rasterizer, &mut fs, screen, content_top, hints);
+    }
+    if let Some(palette) = &state.command_palette {
+        push_command_palette(
+            &mut quads,
+            queue,
+            &mut state.glyph_atlas,
+            &mut rasterizer,
+            &mut fs,
+            screen,
+            content_top,
+            palette,
+        );
+    }
+    if let Some(prompt) = &state.close_prompt {
+        let message = prompt.message();
+        let hint = "Enter to close, Esc to cancel";
+        push_prompt(&mut quads, queue, &mut state.glyph_atlas, &mut rasterizer, &mut fs, screen, prompt.title(), &message, hint);
+    } else if let Some(prompt) = &state.restore_prompt {
+        let title = "Nebula didn't shut down cleanly";
+        let message = prompt.message();
+        let hint = "Enter to reopen, Esc to dismiss";
+        push_prompt(&mut quads, queue, &mut state.glyph_atlas, &mut rasterizer, &mut fs, screen, title, &message, hint);
+    }
+    // Visual bell: tint the whole window with the text color
+    if state.bell_flash.is_some() {
+        let [r, g, b, _] = theme.foreground.to_linear();
+        push_quad(&mut quads, screen, (0.0, 0.0, screen.width, screen.height), SOLID, [r, g, b, BELL_FLASH_ALPHA]);
+    }
+    drop(rasterizer);
+    drop(fs);
+
+    // Debug information
+    if state.local_dirty {
+        trace!(
+            "Rendering frame: snapshot {} (previous {:?}), {} of {} rows rewritten, {} overlay quads, cursor: {}x{} at ({}, {})",
+            session.snapshot_generation,
+            previous_generation,
+            rows_written,
+            row_count,
+            quads.len(),
+            FONT_SIZE,
+            LINE_HEIGHT,
+            cursor_x,
+            cursor_y
+        );
+    }
+
+    let image_draws = write_large_glyphs(device, queue, &mut state.frame_buffers.images, &session.row_cache, screen, theme);
+    let mut decoration_quads: Vec<Quad> = Vec::new();
+    let metrics = state.decoration_metrics;
+    let decorations = [session.decorations.as_slice(), &highlights.decorations].concat();
+    push_decorations(&mut decoration_quads, screen, rows_top, metrics, &decorations, theme.foreground);
+    let buffers = &mut state.frame_buffers;
+    buffers.decorations.write(device, queue, &decoration_quads);
+    buffers.background.write(device, queue, &background_quads);
+    // Cursor, tab bar and overlays change every frame and are drawn on top
+    // of the cached rows
+    buffers.overlay.write(device, queue, &quads);
+
+    let background = clear_color(state, theme);
+
     // Create command encoder
     let mut encoder = device
         .create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
-    
+
+    // With a post-processing shader, everything is drawn offscreen for the
+    // shader to draw to the window last
+    let output_view = match &mut state.post_process {
+        Some(post_process) => post_process.frame_view(device, config),
+        None => &view,
+    };
+    // With the magnifier on, the frame is drawn offscreen and then copied
+    // with the lens on top
+    let target = if state.magnifier.enabled {
+        state.magnifier.frame_view(device, config, &state.glyph_atlas)
+    } else {
+        output_view
+    };
+
     // Begin render pass
     {
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
+                view: target,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    load: wgpu::LoadOp::Clear(background),
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -216,20 +1453,42 @@ pub fn render_frame(
         // Set pipeline and bindings
         rpass.set_pipeline(&state.gpu_resources.pipeline);
         rpass.set_bind_group(0, state.glyph_atlas.bind_group(), &[]);
+        state.gpu_resources.bind_quad(&mut rpass);
 
-        // Draw vertices if available
-        if let Some(ref vertex_buffer) = vertex_buffer {
-            rpass.set_vertex_buffer(0, vertex_buffer.slice(..));
-            rpass.draw(0..vertex_count, 0..1);
-        } else if state.local_dirty {
-            eprintln!("No vertices to draw");
-        }
+        let buffers = &state.frame_buffers;
+        buffers.background.draw(&mut rpass, None);
+
+        session.row_cache.draw(&mut rpass);
+
+        draw_large_glyphs(&mut rpass, &buffers.images, &image_draws, &state.glyph_atlas);
+        draw_decorations(&mut rpass, &buffers.decorations, &state.gpu_resources);
+
+        buffers.overlay.draw(&mut rpass, None);
+    }
+
+    if state.magnifier.enabled {
+        state.magnifier.draw(
+            device,
+            queue,
+            &mut encoder,
+            output_view,
+            &state.gpu_resources,
+            &state.glyph_atlas,
+            screen,
+            (cursor_x + CELL_WIDTH / 2.0, cursor_y + LINE_HEIGHT / 2.0),
+            theme.foreground.to_linear(),
+        );
+    }
+
+    if let Some(post_process) = &state.post_process {
+        post_process.draw(queue, &mut encoder, &view);
     }
 
     // Submit commands and present
     queue.submit(Some(encoder.finish()));
     output.present();
-    
+
     // Reset dirty flag
     state.local_dirty = false;
-}
\ No newline at end of file
+    true
+}
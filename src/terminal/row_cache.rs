@@ -0,0 +1,237 @@
+// src/terminal/row_cache.rs
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    mem::size_of,
+    ops::Range,
+    sync::Arc,
+};
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, Device, Queue, RenderPass};
+
+use crate::terminal::{
+    config::{CELL_WIDTH, LINE_HEIGHT},
+    gpu::QUAD_CORNERS,
+    render::{push_quad, Quad, Screen},
+};
+
+/// A rasterized glyph, positioned relative to the top-left corner of its row.
+#[derive(Debug, Clone, Copy)]
+pub struct PlacedGlyph {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    pub uv: [f32; 4],
+    /// Dedicated texture for glyphs too large for the atlas, drawn in a
+    /// separate pass instead of from the row's quads.
+    pub texture: Option<usize>,
+}
+
+/// A large glyph to draw from its own texture at `(x, y, w, h)` on screen.
+#[derive(Debug, Clone, Copy)]
+pub struct LargeGlyphDraw {
+    pub texture: usize,
+    pub rect: (f32, f32, f32, f32),
+}
+
+/// Runs of columns in a row drawn in a color other than the default.
+pub type RowColors = Vec<(Range<usize>, [f32; 4])>;
+
+/// Contents of one screen row as last written to the GPU.
+#[derive(Clone, Default)]
+struct Slot {
+    /// Hash of the row's text and colors.
+    hash: Option<u64>,
+    quad_count: u32,
+    large_glyphs: Vec<LargeGlyphDraw>,
+}
+
+/// Per-row cache of glyph quads for a session's terminal text.
+///
+/// Every screen row owns a fixed slot in one instance buffer. A row is only
+/// rewritten when its content hash changes, and rows that merely moved (for
+/// example after a scroll) reuse their shaped glyphs instead of being shaped
+/// again.
+#[derive(Default)]
+pub struct RowCache {
+    buffer: Option<Buffer>,
+    /// Quads reserved for each row.
+    slot_capacity: usize,
+    slots: Vec<Slot>,
+    shaped: HashMap<u64, Arc<Vec<PlacedGlyph>>>,
+    /// Screen size and scale, content offset and text color the slots were
+    /// built for.
+    layout: Option<(f32, f32, f32, f32, [f32; 4])>,
+}
+
+/// Quads for a row's glyphs with its top edge at `origin_y`, in `color`
+/// or the color of the run the glyph's middle falls in. Glyphs past the
+/// right edge of the screen are clipped.
+fn row_quads(glyphs: &[PlacedGlyph], screen: Screen, origin_y: f32, color: [f32; 4], runs: &[(Range<usize>, [f32; 4])]) -> Vec<Quad> {
+    let mut quads = Vec::with_capacity(glyphs.len());
+    let visible = glyphs.iter().filter(|g| g.texture.is_none() && g.x + g.w <= screen.width);
+    for glyph in visible {
+        let col = ((glyph.x + glyph.w / 2.0) / CELL_WIDTH) as usize;
+        let color = runs.iter().find(|(columns, _)| columns.contains(&col)).map_or(color, |(_, color)| *color);
+        push_quad(
+            &mut quads,
+            screen,
+            (glyph.x, origin_y + glyph.y, glyph.w, glyph.h),
+            glyph.uv,
+            color,
+        );
+    }
+    quads
+}
+
+fn row_large_glyphs(glyphs: &[PlacedGlyph], origin_y: f32) -> Vec<LargeGlyphDraw> {
+    glyphs
+        .iter()
+        .filter_map(|glyph| {
+            Some(LargeGlyphDraw {
+                texture: glyph.texture?,
+                rect: (glyph.x, origin_y + glyph.y, glyph.w, glyph.h),
+            })
+        })
+        .collect()
+}
+
+fn row_hash(row: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    row.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hash of a row's text, by its hash, and its colors.
+fn slot_hash(text: u64, runs: &[(Range<usize>, [f32; 4])]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    for (columns, color) in runs {
+        columns.hash(&mut hasher);
+        color.map(f32::to_bits).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+impl RowCache {
+    /// Bring the GPU copy of `rows` up to date, shaping rows that have not
+    /// been seen recently with `shape`. Text is drawn in `color` except
+    /// where `colors` has runs of another for its row. A row `shape` has no glyphs for yet
+    /// keeps its previous contents until a later update. Returns the number
+    /// of rows rewritten.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        rows: &[&str],
+        colors: &[RowColors],
+        screen: Screen,
+        top: f32,
+        color: [f32; 4],
+        mut shape: impl FnMut(u64, &str) -> Option<Vec<PlacedGlyph>>,
+    ) -> usize {
+        // Moving or resizing the text area, or a new theme, changes every quad
+        let layout = (screen.width, screen.height, screen.scale, top, color);
+        if self.layout != Some(layout) {
+            // Glyphs were rasterized for another scale
+            if self.layout.is_some_and(|old| old.2 != screen.scale) {
+                self.shaped.clear();
+            }
+            self.layout = Some(layout);
+            self.slots.fill(Slot::default());
+        }
+        self.slots.resize(rows.len(), Slot::default());
+
+        let hashes: Vec<u64> = rows.iter().map(|row| row_hash(row)).collect();
+        let runs = |index: usize| colors.get(index).map_or(&[][..], Vec::as_slice);
+
+        loop {
+            let mut pending = Vec::new();
+            for (index, (&row, &hash)) in rows.iter().zip(&hashes).enumerate() {
+                let slot_hash = slot_hash(hash, runs(index));
+                if self.slots[index].hash == Some(slot_hash) {
+                    continue;
+                }
+                let glyphs = match self.shaped.get(&hash) {
+                    Some(glyphs) => Arc::clone(glyphs),
+                    None => {
+                        let Some(glyphs) = shape(hash, row) else { continue };
+                        let glyphs = Arc::new(glyphs);
+                        self.shaped.insert(hash, Arc::clone(&glyphs));
+                        glyphs
+                    }
+                };
+                let origin_y = top + index as f32 * LINE_HEIGHT;
+                pending.push((
+                    index,
+                    slot_hash,
+                    row_quads(&glyphs, screen, origin_y, color, runs(index)),
+                    row_large_glyphs(&glyphs, origin_y),
+                ));
+            }
+
+            // A larger slot reallocates the buffer, and every row has to be
+            // written again
+            let needed = pending.iter().map(|(_, _, quads, _)| quads.len()).max().unwrap_or(0);
+            if !self.ensure_capacity(device, needed, rows.len()) {
+                continue;
+            }
+
+            let Some(buffer) = &self.buffer else { return 0 };
+            let rewritten = pending.len();
+            for (index, hash, quads, large_glyphs) in pending {
+                let offset = (index * self.slot_capacity * size_of::<Quad>()) as u64;
+                queue.write_buffer(buffer, offset, bytemuck::cast_slice(&quads));
+                self.slots[index] = Slot {
+                    hash: Some(hash),
+                    quad_count: quads.len() as u32,
+                    large_glyphs,
+                };
+            }
+
+            // Keep only shaped rows that are still on screen
+            self.shaped.retain(|hash, _| hashes.contains(hash));
+            return rewritten;
+        }
+    }
+
+    /// Make sure every row slot can hold `needed` quads. Returns false if
+    /// the buffer had to be reallocated, which marks all slots stale.
+    fn ensure_capacity(&mut self, device: &Device, needed: usize, rows: usize) -> bool {
+        let size = (rows * self.slot_capacity * size_of::<Quad>()) as u64;
+        let fits = self.slot_capacity >= needed.max(1)
+            && self.buffer.as_ref().is_some_and(|b| b.size() >= size);
+        if fits {
+            return true;
+        }
+
+        self.slot_capacity = self.slot_capacity.max(needed).max(1).next_power_of_two();
+        self.buffer = Some(device.create_buffer(&BufferDescriptor {
+            label: Some("Row Quads"),
+            size: (rows * self.slot_capacity * size_of::<Quad>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        self.slots.fill(Slot::default());
+        false
+    }
+
+    /// Glyphs on screen that live in their own textures.
+    pub fn large_glyphs(&self) -> impl Iterator<Item = &LargeGlyphDraw> {
+        self.slots.iter().flat_map(|slot| &slot.large_glyphs)
+    }
+
+    /// Issue one instanced draw per non-empty row. Expects the shared quad
+    /// to be bound already.
+    pub fn draw(&self, rpass: &mut RenderPass) {
+        let Some(buffer) = &self.buffer else { return };
+        rpass.set_vertex_buffer(1, buffer.slice(..));
+        for (index, slot) in self.slots.iter().enumerate() {
+            if slot.hash.is_some() && slot.quad_count > 0 {
+                let start = (index * self.slot_capacity) as u32;
+                rpass.draw(0..QUAD_CORNERS.len() as u32, start..start + slot.quad_count);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+// src/terminal/rules.rs
+use regex::Regex;
+
+use crate::terminal::{
+    config::{Config, SessionRule, Theme},
+    keybindings::Keymap,
+    notices,
+};
+
+/// What a matching session rule changes for its tab.
+#[derive(Debug)]
+pub struct SessionOverride {
+    pub theme: Option<Theme>,
+    pub title: Option<String>,
+    /// The configured keybindings with the rule's applied on top, if it
+    /// has any.
+    pub keymap: Option<Keymap>,
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    command: Option<Regex>,
+    host: Option<Regex>,
+    changes: SessionOverride,
+}
+
+/// The config's session rules, compiled.
+#[derive(Debug, Default)]
+pub struct SessionRules {
+    rules: Vec<CompiledRule>,
+}
+
+impl SessionRules {
+    /// Compile the rules in `config`, skipping with a warning any that
+    /// match nothing or whose patterns are invalid.
+    pub fn new(config: &Config) -> Self {
+        let rules = config
+            .session_rule
+            .iter()
+            .filter(|rule| {
+                let empty = rule.command.is_none() && rule.host.is_none();
+                if empty {
+                    notices::warn("Ignoring a session rule with neither a command nor a host");
+                }
+                !empty
+            })
+            .filter_map(|rule| compile(rule, config))
+            .collect();
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Index of the first rule matching the foreground `command` line and
+    /// the OSC 7 `host`.
+    pub fn find(&self, command: Option<&str>, host: Option<&str>) -> Option<usize> {
+        let matches = |regex: &Option<Regex>, text: Option<&str>| match regex {
+            Some(regex) => text.is_some_and(|text| regex.is_match(text)),
+            None => true,
+        };
+        self.rules
+            .iter()
+            .position(|rule| matches(&rule.command, command) && matches(&rule.host, host))
+    }
+
+    pub fn get(&self, index: usize) -> Option<&SessionOverride> {
+        self.rules.get(index).map(|rule| &rule.changes)
+    }
+}
+
+fn compile(rule: &SessionRule, config: &Config) -> Option<CompiledRule> {
+    let regex = |pattern: &Option<String>| match pattern.as_deref().map(Regex::new).transpose() {
+        Ok(regex) => Some(regex),
+        Err(e) => {
+            notices::warn(format!("Ignoring session rule {:?}: {}", pattern.as_deref().unwrap_or_default(), e));
+            None
+        }
+    };
+    let keymap = (!rule.keybindings.is_empty()).then(|| {
+        let mut bindings = config.keybindings.clone();
+        bindings.extend(rule.keybindings.clone());
+        Keymap::new(&bindings)
+    });
+    Some(CompiledRule {
+        command: regex(&rule.command)?,
+        host: regex(&rule.host)?,
+        changes: SessionOverride {
+            theme: rule.theme,
+            title: rule.title.clone(),
+            keymap,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_rules() {
+        let rule = |command: Option<&str>, host: Option<&str>| SessionRule {
+            command: command.map(String::from),
+            host: host.map(String::from),
+            ..Default::default()
+        };
+        let config = Config {
+            session_rule: vec![rule(Some(r"^ssh .*prod-"), None), rule(None, Some("^db")), rule(None, None)],
+            ..Default::default()
+        };
+        let rules = SessionRules::new(&config);
+        assert_eq!(rules.find(Some("ssh -t prod-web1"), None), Some(0));
+        assert_eq!(rules.find(Some("vim"), Some("db2")), Some(1));
+        assert_eq!(rules.find(Some("ssh staging"), Some("laptop")), None);
+    }
+}
@@ -0,0 +1,305 @@
+// src/terminal/scrollback.rs
+use memmap2::Mmap;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Seek, SeekFrom, Write},
+};
+use tracing::warn;
+
+use crate::terminal::config::ScrollbackConfig;
+
+/// Most lines moved to disk at a time.
+const CHUNK_LINES: usize = 1024;
+
+/// One line of history and whether it soft-wrapped into the next one.
+#[derive(Debug, Clone, Default)]
+pub struct Line {
+    pub text: String,
+    pub wrapped: bool,
+}
+
+/// Location of one spilled chunk inside the spill file.
+struct Chunk {
+    offset: u64,
+    len: u64,
+    first_line: usize,
+    line_count: usize,
+}
+
+/// Cold scrollback lines stored in an anonymous temp file and read back
+/// through a memory map. The file is unlinked on creation, so it disappears
+/// when the session ends, even after a crash.
+struct SpillFile {
+    file: File,
+    map: Option<Mmap>,
+    chunks: Vec<Chunk>,
+    // Most recently decoded chunk, so scrolling within it doesn't rescan
+    cached: Option<(usize, Vec<Line>)>,
+}
+
+impl SpillFile {
+    fn create() -> io::Result<Self> {
+        Ok(Self {
+            file: tempfile::tempfile()?,
+            map: None,
+            chunks: Vec::new(),
+            cached: None,
+        })
+    }
+
+    fn line_count(&self) -> usize {
+        self.chunks.last().map_or(0, |c| c.first_line + c.line_count)
+    }
+
+    fn append(&mut self, lines: Vec<Line>) -> io::Result<()> {
+        // The map must not outlive a change to the file length
+        self.map = None;
+
+        // Each line is terminated by '\r' if it wrapped, '\n' otherwise
+        let offset = self.chunks.last().map_or(0, |c| c.offset + c.len);
+        let mut data = String::new();
+        for line in &lines {
+            data.push_str(&line.text);
+            data.push(if line.wrapped { '\r' } else { '\n' });
+        }
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data.as_bytes())?;
+
+        self.chunks.push(Chunk {
+            offset,
+            len: data.len() as u64,
+            first_line: self.line_count(),
+            line_count: lines.len(),
+        });
+        Ok(())
+    }
+
+    fn read_chunk(&mut self, index: usize) -> io::Result<Vec<Line>> {
+        if self.map.is_none() {
+            // SAFETY: the file is private to this process (unlinked on creation)
+            // and is only resized through `append`/`pop_chunk`, which drop the
+            // map first.
+            self.map = Some(unsafe { Mmap::map(&self.file)? });
+        }
+        let map = self.map.as_ref().unwrap();
+        let chunk = &self.chunks[index];
+        let bytes = &map[chunk.offset as usize..(chunk.offset + chunk.len) as usize];
+        let data = String::from_utf8_lossy(bytes);
+        Ok(data
+            .split_inclusive(['\r', '\n'])
+            .map(|line| Line {
+                text: line[..line.len() - 1].to_string(),
+                wrapped: line.ends_with('\r'),
+            })
+            .collect())
+    }
+
+    fn get(&mut self, line: usize) -> Option<Line> {
+        let index = self
+            .chunks
+            .partition_point(|c| c.first_line + c.line_count <= line);
+        let chunk = self.chunks.get(index)?;
+        let first_line = chunk.first_line;
+
+        if self.cached.as_ref().map(|(i, _)| *i) != Some(index) {
+            match self.read_chunk(index) {
+                Ok(lines) => self.cached = Some((index, lines)),
+                Err(e) => {
+                    warn!("Failed to read scrollback from disk: {}", e);
+                    return None;
+                }
+            }
+        }
+        self.cached.as_ref()?.1.get(line - first_line).cloned()
+    }
+
+    /// Remove the newest chunk from disk and return its lines.
+    fn pop_chunk(&mut self) -> Option<Vec<Line>> {
+        let index = self.chunks.len().checked_sub(1)?;
+        let lines = match self.cached.take() {
+            Some((i, lines)) if i == index => lines,
+            _ => self.read_chunk(index).ok()?,
+        };
+        let chunk = self.chunks.pop()?;
+        self.map = None;
+        if let Err(e) = self.file.set_len(chunk.offset) {
+            warn!("Failed to truncate scrollback file: {}", e);
+        }
+        Some(lines)
+    }
+}
+
+/// Lines that have scrolled off the top of the screen. Recent lines stay in
+/// memory; older ones are spilled to disk when enabled, otherwise dropped
+/// once the in-memory limit is reached.
+pub struct Scrollback {
+    hot: VecDeque<Line>,
+    spill: Option<SpillFile>,
+    max_hot_lines: usize,
+    disk_spill: bool,
+    /// Lines dropped from the front so far, so lines can be numbered from
+    /// the start of the session.
+    dropped: usize,
+}
+
+impl Scrollback {
+    pub fn new(config: &ScrollbackConfig, private: bool) -> Self {
+        Self {
+            hot: VecDeque::new(),
+            spill: None,
+            max_hot_lines: config.lines,
+            disk_spill: config.disk_spill && !private,
+            dropped: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spilled_len() + self.hot.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn spilled_len(&self) -> usize {
+        self.spill.as_ref().map_or(0, SpillFile::line_count)
+    }
+
+    pub fn push(&mut self, line: Line) {
+        self.hot.push_back(line);
+        if self.hot.len() <= self.max_hot_lines {
+            return;
+        }
+
+        if self.disk_spill && self.spill.is_none() {
+            match SpillFile::create() {
+                Ok(spill) => self.spill = Some(spill),
+                Err(e) => {
+                    warn!("Failed to create scrollback spill file, disabling disk spill: {}", e);
+                    self.disk_spill = false;
+                }
+            }
+        }
+
+        let excess = self.hot.len() - self.max_hot_lines;
+        let Some(spill) = self.spill.as_mut().filter(|_| self.disk_spill) else {
+            self.hot.drain(..excess);
+            self.dropped += excess;
+            return;
+        };
+        // Spilled a chunk at a time rather than a line at a time, leaving
+        // room in memory until the next one
+        let count = self.hot.len().min(excess + CHUNK_LINES.min(self.max_hot_lines));
+        let cold: Vec<Line> = self.hot.drain(..count).collect();
+        if let Err(e) = spill.append(cold) {
+            // Everything older goes too, so lines keep their numbers
+            warn!("Failed to spill scrollback to disk, disabling disk spill: {}", e);
+            self.dropped += spill.line_count() + count;
+            self.spill = None;
+            self.disk_spill = false;
+        }
+    }
+
+    /// Remove and return the newest line.
+    pub fn pop(&mut self) -> Option<Line> {
+        if self.hot.is_empty() {
+            if let Some(lines) = self.spill.as_mut().and_then(SpillFile::pop_chunk) {
+                self.hot.extend(lines);
+            }
+        }
+        self.hot.pop_back()
+    }
+
+    /// Remove the lines kept in memory, oldest first, leaving those spilled
+    /// to disk. Pushing them back numbers them as before.
+    pub fn take_recent(&mut self) -> Vec<Line> {
+        self.hot.drain(..).collect()
+    }
+
+    /// Session-wide number of the oldest line still retained.
+    pub fn first_line(&self) -> usize {
+        self.dropped
+    }
+
+    /// Line `index`, counting from the oldest line still retained.
+    pub fn get(&mut self, index: usize) -> Option<Line> {
+        let spilled = self.spilled_len();
+        if index < spilled {
+            self.spill.as_mut()?.get(index)
+        } else {
+            self.hot.get(index - spilled).cloned()
+        }
+    }
+
+    /// Drop every line, numbering the lines that follow on from the ones
+    /// dropped.
+    pub fn discard(&mut self) {
+        self.dropped += self.len();
+        self.hot.clear();
+        self.spill = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.hot.clear();
+        self.dropped = 0;
+        // Dropping the spill file releases it immediately
+        self.spill = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(lines: usize, disk_spill: bool, count: usize) -> Scrollback {
+        let mut scrollback = Scrollback::new(&ScrollbackConfig { lines, disk_spill }, false);
+        for n in 0..count {
+            scrollback.push(Line { text: n.to_string(), wrapped: n % 3 == 0 });
+        }
+        scrollback
+    }
+
+    fn text(scrollback: &mut Scrollback, index: usize) -> Option<String> {
+        scrollback.get(index).map(|line| line.text)
+    }
+
+    #[test]
+    fn drops_past_limit() {
+        let mut scrollback = filled(10, false, 25);
+        assert_eq!(scrollback.len(), 10);
+        assert_eq!(scrollback.first_line(), 15);
+        assert_eq!(text(&mut scrollback, 0).as_deref(), Some("15"));
+        assert_eq!(text(&mut scrollback, 9).as_deref(), Some("24"));
+
+        let mut none = filled(0, false, 5);
+        assert!(none.is_empty());
+        assert_eq!(none.first_line(), 5);
+        assert_eq!(none.pop().map(|line| line.text), None);
+    }
+
+    #[test]
+    fn spills_past_limit() {
+        let mut scrollback = filled(10, true, 100);
+        assert!(scrollback.hot.len() <= 10);
+        assert_eq!(scrollback.len(), 100);
+        assert_eq!(scrollback.first_line(), 0);
+        assert_eq!(text(&mut scrollback, 0).as_deref(), Some("0"));
+        assert_eq!(text(&mut scrollback, 57).as_deref(), Some("57"));
+        assert!(scrollback.get(57).unwrap().wrapped);
+        assert_eq!(text(&mut scrollback, 99).as_deref(), Some("99"));
+
+        // Private sessions never touch the disk
+        let private = Scrollback::new(&ScrollbackConfig { lines: 10, disk_spill: true }, true);
+        assert!(!private.disk_spill);
+    }
+
+    #[test]
+    fn reloads_spilled_lines() {
+        let mut scrollback = filled(10, true, 100);
+        let popped: Vec<String> = std::iter::from_fn(|| scrollback.pop()).map(|line| line.text).collect();
+        let expected: Vec<String> = (0..100).rev().map(|n| n.to_string()).collect();
+        assert_eq!(popped, expected);
+        assert!(scrollback.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+// src/terminal/search.rs
+use regex::{Regex, RegexBuilder};
+use std::time::{Duration, Instant};
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, ModifiersState, NamedKey},
+};
+
+use crate::terminal::terminal::{SearchMatch, MAX_SEARCH_MATCHES};
+
+/// How often matches are refreshed while output keeps arriving.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+pub enum SearchEvent {
+    /// The query changed and has to be searched again.
+    Changed,
+    /// Move to the next match up the scrollback.
+    Next,
+    /// Move back down to the previous match.
+    Previous,
+    Close,
+    None,
+}
+
+/// Search bar shown at the bottom of the window. While editing, keys go to
+/// the query; once Enter is pressed n and N step through the matches, and /
+/// edits the query again.
+pub struct SearchBar {
+    pub query: String,
+    /// Treat the query as a regular expression instead of plain text.
+    pub regex: bool,
+    pub editing: bool,
+    pub matches: Vec<SearchMatch>,
+    pub current: Option<usize>,
+    /// Why the query couldn't be searched, such as an invalid regex.
+    pub error: Option<String>,
+    /// Snapshot the matches were found in.
+    pub generation: u64,
+    searched_at: Instant,
+}
+
+impl Default for SearchBar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SearchBar {
+    pub fn new() -> Self {
+        Self {
+            query: String::new(),
+            regex: false,
+            editing: true,
+            matches: Vec::new(),
+            current: None,
+            error: None,
+            generation: 0,
+            searched_at: Instant::now(),
+        }
+    }
+
+    pub fn handle_key(&mut self, key_event: &KeyEvent, modifiers: ModifiersState) -> SearchEvent {
+        if key_event.state != ElementState::Pressed {
+            return SearchEvent::None;
+        }
+
+        match key_event.logical_key.as_ref() {
+            Key::Named(NamedKey::Escape) => SearchEvent::Close,
+            Key::Named(NamedKey::ArrowUp) => SearchEvent::Next,
+            Key::Named(NamedKey::ArrowDown) => SearchEvent::Previous,
+            Key::Named(NamedKey::Enter) if self.editing => {
+                self.editing = false;
+                SearchEvent::None
+            }
+            Key::Named(NamedKey::Enter) if modifiers.shift_key() => SearchEvent::Previous,
+            Key::Named(NamedKey::Enter) => SearchEvent::Next,
+            Key::Named(NamedKey::Backspace) if self.editing => {
+                self.query.pop();
+                SearchEvent::Changed
+            }
+            Key::Character(ch) if modifiers.alt_key() && ch.eq_ignore_ascii_case("r") => {
+                self.regex = !self.regex;
+                SearchEvent::Changed
+            }
+            _ if self.editing => match &key_event.text {
+                Some(text) if !modifiers.control_key() && !text.chars().any(char::is_control) => {
+                    self.query.push_str(text);
+                    SearchEvent::Changed
+                }
+                _ => SearchEvent::None,
+            },
+            Key::Character("n") => SearchEvent::Next,
+            Key::Character("N") => SearchEvent::Previous,
+            Key::Character("/") => {
+                self.editing = true;
+                SearchEvent::None
+            }
+            _ => SearchEvent::None,
+        }
+    }
+
+    /// Case-insensitive pattern for the query, or None if there is nothing
+    /// to search for.
+    pub fn pattern(&mut self) -> Option<Regex> {
+        self.error = None;
+        if self.query.is_empty() {
+            return None;
+        }
+        let source = if self.regex { self.query.clone() } else { regex::escape(&self.query) };
+        match RegexBuilder::new(&source).case_insensitive(true).build() {
+            Ok(pattern) => Some(pattern),
+            Err(_) => {
+                self.error = Some(String::from("invalid regex"));
+                None
+            }
+        }
+    }
+
+    /// Take a new set of matches, staying on the current one if it is still
+    /// there and starting from the newest otherwise.
+    pub fn set_matches(&mut self, matches: Vec<SearchMatch>, generation: u64) {
+        let current = self.current_match().cloned();
+        self.current = current
+            .and_then(|current| matches.iter().position(|m| *m == current))
+            .or_else(|| matches.len().checked_sub(1));
+        self.matches = matches;
+        self.generation = generation;
+        self.searched_at = Instant::now();
+    }
+
+    /// Whether new output should be searched again. Throttled so streaming
+    /// output doesn't rescan the scrollback every frame.
+    pub fn is_stale(&self, generation: u64) -> bool {
+        generation != self.generation && self.searched_at.elapsed() >= REFRESH_INTERVAL
+    }
+
+    pub fn current_match(&self) -> Option<&SearchMatch> {
+        self.matches.get(self.current?)
+    }
+
+    /// Step to the next match up (`older`) or down, wrapping around.
+    pub fn step(&mut self, older: bool) -> Option<&SearchMatch> {
+        let count = self.matches.len();
+        let current = self.current?;
+        self.current = Some(if older { (current + count - 1) % count } else { (current + 1) % count });
+        self.current_match()
+    }
+
+    /// Status text such as "3/17", shown after the query.
+    pub fn status(&self) -> String {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        if self.query.is_empty() {
+            return String::new();
+        }
+        match self.current {
+            Some(current) => {
+                let more = if self.matches.len() >= MAX_SEARCH_MATCHES { "+" } else { "" };
+                format!("{}/{}{}", current + 1, self.matches.len(), more)
+            }
+            None => String::from("no matches"),
+        }
+    }
+}
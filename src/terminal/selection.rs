@@ -0,0 +1,75 @@
+// src/terminal/selection.rs
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::terminal::terminal::cluster_width;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectionKind {
+    /// Runs of text from one cell to another, wrapping at line ends.
+    #[default]
+    Linear,
+    /// The same columns on every line, for tables and aligned output.
+    Block,
+}
+
+/// Cells chosen by dragging the mouse, between the cell the drag started
+/// on and the one it is over. Positions are (session line, column), so the
+/// selection stays on its text as output scrolls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: (usize, usize),
+    pub head: (usize, usize),
+    pub kind: SelectionKind,
+}
+
+impl Selection {
+    pub fn new(point: (usize, usize), kind: SelectionKind) -> Self {
+        Self { anchor: point, head: point, kind }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// Session lines the selection touches.
+    pub fn lines(&self) -> Range<usize> {
+        self.anchor.0.min(self.head.0)..self.anchor.0.max(self.head.0) + 1
+    }
+
+    /// Columns selected on session line `line`. Ends at `usize::MAX` where
+    /// a linear selection runs on past the end of the line.
+    pub fn columns(&self, line: usize) -> Option<Range<usize>> {
+        if !self.lines().contains(&line) {
+            return None;
+        }
+        match self.kind {
+            SelectionKind::Block => Some(self.anchor.1.min(self.head.1)..self.anchor.1.max(self.head.1) + 1),
+            SelectionKind::Linear => {
+                let (start, end) = if self.anchor <= self.head { (self.anchor, self.head) } else { (self.head, self.anchor) };
+                let first = if line == start.0 { start.1 } else { 0 };
+                let last = if line == end.0 { end.1 + 1 } else { usize::MAX };
+                Some(first..last)
+            }
+        }
+    }
+}
+
+/// The part of a row's text in `columns`. Wide characters partly inside
+/// are taken whole.
+pub fn slice_columns(text: &str, columns: Range<usize>) -> &str {
+    let mut start = None;
+    let mut end = text.len();
+    let mut col = 0;
+    for (index, cluster) in text.grapheme_indices(true) {
+        if col >= columns.end {
+            end = index;
+            break;
+        }
+        col += cluster_width(cluster);
+        if start.is_none() && col > columns.start {
+            start = Some(index);
+        }
+    }
+    &text[start.unwrap_or(end)..end]
+}
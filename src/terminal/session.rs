@@ -0,0 +1,523 @@
+// src/terminal/session.rs
+use anyhow::{bail, Result};
+use std::{
+    ops::Range,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::NamedTempFile;
+use tracing::{info, warn};
+use winit::event_loop::EventLoopProxy;
+
+use crate::terminal::{
+    clipboard::SharedClipboard,
+    config::{Config, PasteFilter, Theme, CELL_WIDTH},
+    links::LinkSpan,
+    notices,
+    paste::{self, FilterJob, PasteJob, BACKGROUND_PASTE_SIZE},
+    row_cache::RowCache,
+    rules::{SessionOverride, SessionRules},
+    selection::Selection,
+    share,
+    terminal::{
+        color_scheme_report, BackgroundSpan, CommandTiming, DecorationSpan, ForegroundSpan, PtyChild, PtyWriter, Tail, Waker,
+        LINE_HEIGHT, PLACEHOLDER_TEXT,
+    },
+    titles::TitleRules,
+    usage::{ProcessUsage, UsageMonitor},
+    Terminal, UserEvent,
+};
+
+/// How long shutdown waits for the PTY reader thread to notice the hangup.
+const READER_EXIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Source of `Session::id`.
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Wakes the event loop through `proxy` when the terminal has output.
+fn output_waker(proxy: EventLoopProxy<UserEvent>) -> Waker {
+    Arc::new(move || {
+        let _ = proxy.send_event(UserEvent::Output);
+    })
+}
+
+/// One shell running in its own tab: the terminal state, its PTY handles
+/// and the title shown in the tab bar.
+pub struct Session {
+    /// Identifies the session while tabs move around, unique in the process.
+    pub id: u64,
+    pub terminal: Terminal,
+    pub input_writer: PtyWriter,
+    /// Keeps the child process alive. None for a shared window viewed
+    /// from here, which has no process of its own.
+    pub child_process: Option<PtyChild>,
+    /// Text of the rows in view, with the damage received so far applied.
+    pub rows: Vec<String>,
+    /// Generation of the newest damage applied to `rows`.
+    pub snapshot_generation: u64,
+    /// Generation of the snapshot on screen, for spotting frames drawn
+    /// from old data.
+    pub drawn_generation: Option<u64>,
+    /// Links in `rows`.
+    pub last_links: Vec<LinkSpan>,
+    /// Cells in `rows` with a text color other than the theme's.
+    pub foregrounds: Vec<ForegroundSpan>,
+    /// Cells in `rows` with a background other than the theme's.
+    pub backgrounds: Vec<BackgroundSpan>,
+    /// Underlined and struck through cells in `rows`.
+    pub decorations: Vec<DecorationSpan>,
+    /// Prompt rows in `rows` with the timing of the command before them.
+    pub command_timings: Vec<(usize, CommandTiming)>,
+    /// The live screen while `rows` is scrolled back, for follow windows.
+    pub tail: Option<Tail>,
+    /// Session line number of the first row of `rows`.
+    pub last_view_top: usize,
+    /// Session lines kept in the scrollback.
+    pub scrollback: Range<usize>,
+    /// Text chosen with the mouse, for copying.
+    pub selection: Option<Selection>,
+    /// Cursor position in pixels.
+    pub cursor: (f32, f32),
+    pub row_cache: RowCache,
+    /// Bumped whenever the text changes, so rows queued for shaping before
+    /// it can be skipped.
+    pub text_generation: Arc<AtomicU64>,
+    pub title: String,
+    /// Title given with --title, shown until the program sets its own.
+    pub base_title: Option<String>,
+    osc_title: Option<String>,
+    title_rules: TitleRules,
+    session_rules: SessionRules,
+    /// Index of the session rule in effect.
+    active_rule: Option<usize>,
+    /// Theme from the config, which a session rule can override.
+    theme: Theme,
+    /// Large paste still being written.
+    pub paste_job: Option<PasteJob>,
+    /// Paste still going through a filter, pasted once it comes out.
+    pub filter_job: Option<FilterJob>,
+    /// File the tab's program was started on, removed along with the tab.
+    pub temp_file: Option<NamedTempFile>,
+    /// CPU and memory use of the foreground command, when the resource
+    /// badge is on.
+    pub usage: Option<ProcessUsage>,
+}
+
+impl Session {
+    /// Start a shell, in `cwd` if given.
+    pub fn spawn(
+        config: Config,
+        clipboard: SharedClipboard,
+        cwd: Option<PathBuf>,
+        proxy: EventLoopProxy<UserEvent>,
+    ) -> Result<Self> {
+        let title_rules = TitleRules::new(&config.title_rule);
+        let session_rules = SessionRules::new(&config);
+        let mut terminal = Terminal::new(config, clipboard);
+        terminal.waker = Some(output_waker(proxy));
+        *terminal.cwd.lock().unwrap() = cwd;
+        let theme = *terminal.theme.lock().unwrap();
+        let (input_writer, child_process) = terminal.spawn_pty()?;
+        let snapshot_generation = terminal.snapshot_generation.load(Ordering::Acquire);
+        let rows = PLACEHOLDER_TEXT.split('\n').map(String::from).collect();
+
+        Ok(Self {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+            terminal,
+            input_writer,
+            child_process: Some(child_process),
+            rows,
+            snapshot_generation,
+            drawn_generation: None,
+            last_links: Vec::new(),
+            foregrounds: Vec::new(),
+            backgrounds: Vec::new(),
+            decorations: Vec::new(),
+            command_timings: Vec::new(),
+            tail: None,
+            last_view_top: 0,
+            scrollback: 0..0,
+            selection: None,
+            // After "$ " (2 cells) at line 1
+            cursor: (2.0 * CELL_WIDTH, LINE_HEIGHT),
+            row_cache: RowCache::default(),
+            text_generation: Arc::new(AtomicU64::new(0)),
+            title: String::from("Nebula"),
+            base_title: None,
+            osc_title: None,
+            title_rules,
+            session_rules,
+            active_rule: None,
+            theme,
+            paste_job: None,
+            filter_job: None,
+            temp_file: None,
+            usage: None,
+        })
+    }
+
+    /// View a window shared with `--share` at `address`. The tab is read-only
+    /// and stays open, showing the last frame, once the share ends.
+    pub fn join(
+        mut config: Config,
+        clipboard: SharedClipboard,
+        address: &str,
+        token: &str,
+        proxy: EventLoopProxy<UserEvent>,
+    ) -> Result<Self> {
+        config.shell.hold = true;
+        let mut terminal = Terminal::new(config, clipboard);
+        terminal.waker = Some(output_waker(proxy));
+        share::join(&terminal, address, token)?;
+        let theme = *terminal.theme.lock().unwrap();
+        let input_writer: PtyWriter = Arc::new(Mutex::new(std::io::sink()));
+
+        Ok(Self {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+            terminal,
+            input_writer,
+            child_process: None,
+            rows: Vec::new(),
+            snapshot_generation: 0,
+            drawn_generation: None,
+            last_links: Vec::new(),
+            foregrounds: Vec::new(),
+            backgrounds: Vec::new(),
+            decorations: Vec::new(),
+            command_timings: Vec::new(),
+            tail: None,
+            last_view_top: 0,
+            scrollback: 0..0,
+            selection: None,
+            cursor: (0.0, 0.0),
+            row_cache: RowCache::default(),
+            text_generation: Arc::new(AtomicU64::new(0)),
+            title: format!("{} (shared)", address),
+            base_title: None,
+            osc_title: None,
+            title_rules: TitleRules::default(),
+            session_rules: SessionRules::default(),
+            active_rule: None,
+            theme,
+            paste_job: None,
+            filter_job: None,
+            temp_file: None,
+            usage: None,
+        })
+    }
+
+    /// Returns true if the PTY thread produced new content since the last poll.
+    /// Called each time the event loop wakes.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+
+        // Check if shared dirty flag was set by the reader thread
+        if let Ok(mut dirty) = self.terminal.dirty.lock() {
+            changed |= *dirty;
+            *dirty = false;
+        }
+
+        // Apply all damage sent since the last frame. Only changed rows are
+        // copied and no lock shared with the reader is taken.
+        let mut rows_changed = false;
+        for damage in self.terminal.damage.try_iter() {
+            self.rows.resize(damage.row_count, String::new());
+            for (index, text) in damage.rows {
+                self.rows[index] = text;
+                rows_changed = true;
+            }
+            self.last_links = damage.links;
+            self.foregrounds = damage.foregrounds;
+            self.backgrounds = damage.backgrounds;
+            self.decorations = damage.decorations;
+            self.command_timings = damage.command_timings;
+            if damage.tail.is_some() || self.tail.is_some() {
+                rows_changed = true;
+            }
+            self.tail = damage.tail;
+            self.last_view_top = damage.view_top;
+            self.scrollback = damage.scrollback;
+            self.cursor = damage.cursor;
+            self.snapshot_generation = damage.generation;
+            changed = true;
+        }
+        if rows_changed {
+            self.text_generation.fetch_add(1, Ordering::SeqCst);
+        }
+
+        changed
+    }
+
+    /// Whether the view is scrolled back from the live screen.
+    pub fn scrolled_back(&self) -> bool {
+        self.last_view_top < self.scrollback.end
+    }
+
+    /// Update the tab title from OSC 0/2 as the title rules leave it,
+    /// falling back to the foreground process name. Returns true if the
+    /// title changed.
+    pub fn refresh_title(&mut self, check_process: bool) -> bool {
+        let osc_title = self.terminal.title.lock().unwrap().take();
+        if let Some(title) = osc_title {
+            if title.is_empty() {
+                self.osc_title = None;
+            } else {
+                let process = self.title_rules.need_process().then(|| self.terminal.foreground_process_name());
+                if let Some(title) = self.title_rules.apply(&title, process.flatten().as_deref()) {
+                    self.osc_title = Some(title).filter(|t| !t.is_empty());
+                }
+            }
+        } else if !check_process {
+            return false;
+        }
+
+        let title = self
+            .overrides()
+            .and_then(|overrides| overrides.title.clone())
+            .or_else(|| self.osc_title.clone())
+            .or_else(|| self.base_title.clone())
+            .or_else(|| self.terminal.foreground_process_name())
+            .unwrap_or_else(|| String::from("Nebula"));
+
+        if title != self.title {
+            self.title = title;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The foreground process, whose tree the resource badge measures.
+    pub fn usage_root(&self) -> Option<i32> {
+        #[cfg(unix)]
+        let root = self.terminal.master.lock().ok().and_then(|master| master.as_ref()?.process_group_leader());
+        #[cfg(not(unix))]
+        let root: Option<i32> = None;
+        root
+    }
+
+    /// Take the CPU and memory use of the foreground process tree from
+    /// `monitor`'s latest sample. Returns true if the badge text changed.
+    pub fn refresh_usage(&mut self, monitor: &UsageMonitor) -> bool {
+        let usage = self.usage_root().and_then(|root| monitor.usage(root));
+        let changed = usage.map(|usage| usage.badge()) != self.usage.map(|usage| usage.badge());
+        self.usage = usage;
+        changed
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.terminal.config.private
+    }
+
+    pub fn window_title(&self) -> String {
+        if self.is_private() {
+            format!("{} (private)", self.title)
+        } else {
+            self.title.clone()
+        }
+    }
+
+    /// Send text to the shell as if it had been typed, with line breaks
+    /// turned into carriage returns like a real keyboard sends them, and
+    /// bracketed if the program asked for it.
+    /// Large pastes are written in the background as a `PasteJob`.
+    pub fn paste(&mut self, text: &str) {
+        let mut text = text.replace("\r\n", "\r").replace('\n', "\r");
+        // Programs that asked for bracketed paste get the text marked
+        if self.terminal.modes.lock().unwrap().bracketed_paste {
+            text = paste::bracket(&text);
+        }
+        if text.len() > BACKGROUND_PASTE_SIZE {
+            self.start_paste_job(text, None);
+            return;
+        }
+        if let Ok(mut writer) = self.input_writer.lock() {
+            if let Err(e) = writer.write_all(text.as_bytes()).and_then(|_| writer.flush()) {
+                warn!("Failed to paste into PTY: {}", e);
+            }
+        }
+        *self.terminal.dirty.lock().unwrap() = true;
+    }
+
+    /// Input from a web viewer. A single key, as the viewer sends for each
+    /// key press, is written like a typed one; anything longer is pasted,
+    /// so it is bracketed when asked for and large text is written in the
+    /// background. Private tabs take no remote input.
+    pub fn remote_input(&mut self, text: &str) -> Result<()> {
+        if self.is_private() {
+            bail!("the tab is private");
+        }
+        let key = text.chars().count() == 1
+            || matches!(text.as_bytes(), [0x1B, b'[' | b'O', key] if key.is_ascii_alphabetic());
+        if !key {
+            self.paste(text);
+            return Ok(());
+        }
+        let mut writer = self.input_writer.lock().map_err(|_| anyhow::anyhow!("PTY writer poisoned"))?;
+        writer.write_all(text.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Type text into the shell one character at a time with `delay` after
+    /// each, for consoles that drop input arriving faster than a person
+    /// types. Never bracketed, since it is meant to look like typing.
+    pub fn paste_as_typed(&mut self, text: &str, delay: Duration) {
+        let text = text.replace("\r\n", "\r").replace('\n', "\r");
+        self.start_paste_job(text, Some(delay));
+    }
+
+    fn start_paste_job(&mut self, text: String, delay: Option<Duration>) {
+        if self.paste_job.is_some() {
+            notices::warn("Another paste is still in progress");
+            return;
+        }
+        let waker = self.terminal.waker.clone();
+        self.paste_job = Some(PasteJob::start(Arc::clone(&self.input_writer), text, delay, waker));
+    }
+
+    /// Paste `text` once `filter` has rewritten it, which happens on a
+    /// thread since a filter command can be slow.
+    pub fn paste_filtered(&mut self, filter: PasteFilter, text: String) {
+        if self.filter_job.is_some() {
+            notices::warn("Another paste is still being filtered");
+            return;
+        }
+        self.filter_job = Some(FilterJob::start(filter, text, self.terminal.waker.clone()));
+    }
+
+    /// Paste the filtered text if the filter is done. True if it was.
+    pub fn finish_filter(&mut self) -> bool {
+        let Some(result) = self.filter_job.as_ref().and_then(FilterJob::try_finish) else {
+            return false;
+        };
+        self.filter_job = None;
+        match result {
+            Ok(text) => {
+                self.paste(&text);
+                true
+            }
+            Err(e) => {
+                notices::warn(format!("{:#}", e));
+                false
+            }
+        }
+    }
+
+    /// What the session rule in effect changes, if one matches.
+    pub fn overrides(&self) -> Option<&SessionOverride> {
+        self.session_rules.get(self.active_rule?)
+    }
+
+    /// Match the session rules against the foreground command and host.
+    /// Returns true if a different rule, or none, now applies.
+    pub fn refresh_rules(&mut self) -> bool {
+        if self.session_rules.is_empty() {
+            return false;
+        }
+        let command = self.terminal.foreground_command_line();
+        let host = self.terminal.host.lock().unwrap().clone();
+        let rule = self.session_rules.find(command.as_deref(), host.as_deref());
+        if rule == self.active_rule {
+            return false;
+        }
+        if !self.is_private() {
+            info!("Session rule {:?} applies to {:?} on {:?}", rule, command, host);
+        }
+        self.active_rule = rule;
+        self.apply_theme();
+        true
+    }
+
+    /// Colors the tab is drawn with.
+    pub fn theme(&self) -> Theme {
+        *self.terminal.theme.lock().unwrap()
+    }
+
+    /// Switch to the config's `theme`, unless a session rule overrides it.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.apply_theme();
+    }
+
+    /// Switch colors, telling the application if it asked to hear about it.
+    fn apply_theme(&self) {
+        let theme = self.overrides().and_then(|overrides| overrides.theme).unwrap_or(self.theme);
+        let previous = std::mem::replace(&mut *self.terminal.theme.lock().unwrap(), theme);
+        if previous == theme || !self.terminal.report_color_scheme.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(mut writer) = self.input_writer.lock() {
+            let report = color_scheme_report(&theme);
+            if let Err(e) = writer.write_all(report.as_bytes()).and_then(|_| writer.flush()) {
+                warn!("Failed to report color scheme: {}", e);
+            }
+        }
+    }
+
+    /// Tell the program it gained or lost focus, if it asked to hear.
+    pub fn report_focus(&self, focused: bool) {
+        if !self.terminal.modes.lock().unwrap().focus_reporting {
+            return;
+        }
+        if let Ok(mut writer) = self.input_writer.lock() {
+            let report: &[u8] = if focused { b"\x1B[I" } else { b"\x1B[O" };
+            if let Err(e) = writer.write_all(report).and_then(|_| writer.flush()) {
+                warn!("Failed to report focus: {}", e);
+            }
+        }
+    }
+
+    /// Name of a command other than the shell running in the foreground,
+    /// if any. Only known on Unix.
+    pub fn running_command(&self) -> Option<String> {
+        #[cfg(unix)]
+        {
+            let shell = self.child_process.as_ref()?.lock().ok()?.process_id()?;
+            let foreground = self.terminal.master.lock().ok()?.as_ref()?.process_group_leader()?;
+            (foreground as u32 != shell).then(|| {
+                self.terminal
+                    .foreground_process_name()
+                    .unwrap_or_else(|| String::from("A command"))
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
+    /// Hang up the shell and stop the reader thread. The shell gets SIGHUP
+    /// and, with the PTY closed, so does whatever runs in its foreground.
+    pub fn shutdown(&self) {
+        if let Some(job) = &self.paste_job {
+            job.cancel();
+        }
+        self.terminal.shutdown.store(true, Ordering::SeqCst);
+        if let Some(Ok(mut child)) = self.child_process.as_ref().map(|child| child.lock()) {
+            if let Err(e) = child.kill() {
+                warn!("Failed to kill child process: {}", e);
+            }
+            let _ = child.wait();
+        }
+        self.terminal.master.lock().unwrap().take();
+
+        // Processes that kept the PTY open, such as disowned background
+        // jobs, can keep the reader blocked; don't hang waiting for them
+        let Some(reader) = self.terminal.reader_thread.lock().unwrap().take() else { return };
+        let started = Instant::now();
+        while !reader.is_finished() && started.elapsed() < READER_EXIT_TIMEOUT {
+            thread::sleep(Duration::from_millis(10));
+        }
+        if reader.is_finished() {
+            let _ = reader.join();
+        } else {
+            warn!("PTY reader thread is still blocked, leaving it behind");
+        }
+    }
+}
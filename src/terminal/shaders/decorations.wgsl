@@ -0,0 +1,56 @@
+// A corner of the shared quad, and the instance it is drawn for
+struct VertexInput {
+    @location(0) corner: vec2<f32>,
+    // Left, top, right and bottom edges in clip space
+    @location(1) rect: vec4<f32>,
+    // Style, line thickness, and the quad's width and height in pixels
+    @location(2) params: vec4<f32>,
+    @location(3) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Position inside the quad in pixels
+    @location(0) local: vec2<f32>,
+    @location(1) @interpolate(flat) params: vec4<f32>,
+    @location(2) color: vec4<f32>,
+};
+
+const PI: f32 = 3.14159265;
+
+@vertex
+fn vs_main(input: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    let position = mix(input.rect.xy, input.rect.zw, input.corner);
+    output.clip_position = vec4<f32>(position, 0.0, 1.0);
+    output.local = input.corner * input.params.zw;
+    output.params = input.params;
+    output.color = input.color;
+    return output;
+}
+
+// Straight, dotted and dashed lines fill their quad where they are drawn;
+// a curly line is a sine wave across a taller one
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let style = in.params.x;
+    let thickness = in.params.y;
+    let height = in.params.w;
+    var coverage = 1.0;
+    if (style == 1.0) {
+        let amplitude = (height - thickness) / 2.0;
+        let wavelength = max(height * 2.0, 4.0);
+        let phase = 2.0 * PI * in.local.x / wavelength;
+        let center = height / 2.0 + amplitude * sin(phase);
+        let slope = amplitude * 2.0 * PI / wavelength * cos(phase);
+        let distance = abs(in.local.y - center) / sqrt(1.0 + slope * slope);
+        coverage = clamp(thickness / 2.0 + 0.5 - distance, 0.0, 1.0);
+    } else if (style == 2.0) {
+        let period = thickness * 2.0;
+        coverage = step(in.local.x % period, thickness);
+    } else if (style == 3.0) {
+        let period = thickness * 6.0;
+        coverage = step(in.local.x % period, thickness * 4.0);
+    }
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
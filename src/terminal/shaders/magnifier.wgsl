@@ -0,0 +1,33 @@
+// A corner of the shared quad, and the instance it is drawn for
+struct VertexInput {
+    @location(0) corner: vec2<f32>,
+    // Left, top, right and bottom edges in clip space
+    @location(1) rect: vec4<f32>,
+    @location(2) tex_rect: vec4<f32>,
+    @location(3) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@group(0) @binding(0) var tex: texture_2d<f32>;
+@group(0) @binding(1) var samp: sampler;
+
+@vertex
+fn vs_main(input: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    let position = mix(input.rect.xy, input.rect.zw, input.corner);
+    output.clip_position = vec4<f32>(position, 0.0, 1.0);
+    output.tex_coord = mix(input.tex_rect.xy, input.tex_rect.zw, input.corner);
+    output.color = input.color;
+    return output;
+}
+
+// The rendered frame, sampled whole rather than as a coverage mask
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(tex, samp, in.tex_coord) * in.color;
+}
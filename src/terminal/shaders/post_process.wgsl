@@ -0,0 +1,30 @@
+// Put in front of a user's post-processing shader, which defines
+// `fs_main(in: VertexOutput) -> @location(0) vec4<f32>`
+
+struct Uniforms {
+    // Size of the window in pixels
+    resolution: vec2<f32>,
+    // Seconds since the shader was loaded
+    time: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // 0,0 at the top left of the frame and 1,1 at the bottom right
+    @location(0) uv: vec2<f32>,
+};
+
+// The rendered frame
+@group(0) @binding(0) var frame: texture_2d<f32>;
+@group(0) @binding(1) var frame_sampler: sampler;
+@group(0) @binding(2) var<uniform> uniforms: Uniforms;
+
+// One triangle covering the window, drawn without a vertex buffer
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var output: VertexOutput;
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    output.clip_position = vec4<f32>(corner.x * 2.0 - 1.0, 1.0 - corner.y * 2.0, 0.0, 1.0);
+    output.uv = corner;
+    return output;
+}
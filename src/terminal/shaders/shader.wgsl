@@ -1,11 +1,16 @@
+// A corner of the shared quad, and the instance it is drawn for
 struct VertexInput {
-    @location(0) position: vec2<f32>,
-    @location(1) tex_coord: vec2<f32>,
+    @location(0) corner: vec2<f32>,
+    // Left, top, right and bottom edges in clip space
+    @location(1) rect: vec4<f32>,
+    @location(2) tex_rect: vec4<f32>,
+    @location(3) color: vec4<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coord: vec2<f32>,
+    @location(1) color: vec4<f32>,
 };
 
 @group(0) @binding(0) var tex: texture_2d<f32>;
@@ -14,18 +19,20 @@ struct VertexOutput {
 @vertex
 fn vs_main(input: VertexInput) -> VertexOutput {
     var output: VertexOutput;
-    output.clip_position = vec4<f32>(input.position, 0.0, 1.0);
-    output.tex_coord = input.tex_coord;
+    let position = mix(input.rect.xy, input.rect.zw, input.corner);
+    output.clip_position = vec4<f32>(position, 0.0, 1.0);
+    output.tex_coord = mix(input.tex_rect.xy, input.tex_rect.zw, input.corner);
+    output.color = input.color;
     return output;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    // Cursor detection (using special UV values)
+    // Solid quads (cursor, tab bar) use special UV values
     if (in.tex_coord.x < 0.0 && in.tex_coord.y < 0.0) {
-        return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+        return in.color;
     }
     
     let color = textureSample(tex, samp, in.tex_coord);
-    return vec4<f32>(1.0, 1.0, 1.0, color.a);
-}
\ No newline at end of file
+    return vec4<f32>(in.color.rgb, in.color.a * color.a);
+}
@@ -0,0 +1,217 @@
+// src/terminal/shaper.rs
+use cosmic_text::{fontdb, Attrs, Buffer, FontSystem, LayoutGlyph, Metrics, Shaping};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+use unicode_segmentation::UnicodeSegmentation;
+use tracing::{error, trace_span};
+use winit::event_loop::EventLoopProxy;
+
+use crate::terminal::{
+    config::{CELL_WIDTH, FONT_SIZE, LINE_HEIGHT},
+    fonts::Fonts,
+    terminal::cluster_width,
+    UserEvent,
+};
+
+/// Threads shaping terminal rows.
+const SHAPER_THREADS: usize = 2;
+/// Finished rows kept for pickup. Rows that scrolled away before anyone
+/// collected them are dropped past this.
+const MAX_READY_ROWS: usize = 1024;
+
+/// A glyph laid out in its row but not rasterized yet. Positions are the
+/// glyph origin relative to the row's top-left corner.
+#[derive(Debug, Clone, Copy)]
+pub struct ShapedGlyph {
+    pub font_id: fontdb::ID,
+    pub glyph_id: u16,
+    pub font_size: f32,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Lay out every glyph of a shaped buffer. `glyph_x` picks each glyph's
+/// horizontal position before its offset is applied.
+pub fn layout_glyphs(buffer: &Buffer, glyph_x: impl Fn(&LayoutGlyph) -> f32) -> Vec<ShapedGlyph> {
+    let mut glyphs = Vec::new();
+    for run in buffer.layout_runs() {
+        for glyph in run.glyphs {
+            // Combining marks have no advance and rely on their offset to
+            // sit over their base
+            glyphs.push(ShapedGlyph {
+                font_id: glyph.font_id,
+                glyph_id: glyph.glyph_id,
+                font_size: glyph.font_size,
+                x: glyph_x(glyph) + glyph.x_offset * glyph.font_size,
+                y: run.line_y + glyph.y - glyph.y_offset * glyph.font_size,
+            });
+        }
+    }
+    glyphs
+}
+
+/// Shape one row of terminal text, snapping every grapheme cluster, or
+/// ligature, to its cells.
+pub fn shape_row(fs: &mut FontSystem, attrs: &Attrs, row: &str) -> Vec<ShapedGlyph> {
+    let _span = trace_span!("shape_row", bytes = row.len()).entered();
+    let mut buffer = Buffer::new(fs, Metrics::new(FONT_SIZE, LINE_HEIGHT));
+    buffer.set_text(fs, row, attrs, Shaping::Advanced);
+    buffer.shape_until_scroll(fs, true);
+
+    // Column and width of the cluster each byte belongs to
+    let mut cells = vec![(0, 1); row.len() + 1];
+    let mut col = 0;
+    for (index, cluster) in row.grapheme_indices(true) {
+        let width = cluster_width(cluster);
+        cells[index..index + cluster.len()].fill((col, width));
+        col += width;
+    }
+
+    // First column and number of cells of the text a glyph was shaped
+    // from. A ligature covers every character it joins.
+    let span = |glyph: &LayoutGlyph| {
+        let (col, width) = cells[glyph.start.min(row.len())];
+        let (last, last_width) = cells[glyph.end.saturating_sub(1).clamp(glyph.start, row.len())];
+        (col, (last + last_width).max(col + width) - col)
+    };
+
+    // Where each cluster starts in the shaped line, how far it advances and
+    // how many cells it covers, so its glyphs move into those cells together,
+    // centered. The row keeps one character per cell, so the cursor and
+    // selections still land on cells under a ligature.
+    let mut clusters: Vec<Option<(f32, f32, usize)>> = vec![None; col + 1];
+    for run in buffer.layout_runs() {
+        for glyph in run.glyphs {
+            let (col, covered) = span(glyph);
+            let (start, advance, width) = clusters[col].get_or_insert((glyph.x, 0.0, covered));
+            *start = start.min(glyph.x);
+            *advance += glyph.w;
+            *width = (*width).max(covered);
+        }
+    }
+
+    layout_glyphs(&buffer, |glyph| {
+        let (col, covered) = span(glyph);
+        let (start, advance, width) = clusters[col].unwrap_or((glyph.x, glyph.w, covered));
+        let padding = (width as f32 * CELL_WIDTH - advance).max(0.0) / 2.0;
+        col as f32 * CELL_WIDTH + padding + (glyph.x - start)
+    })
+}
+
+struct Job {
+    hash: u64,
+    generation: u64,
+    /// Newest generation of the session that asked, so jobs made obsolete
+    /// by later output are skipped instead of shaped.
+    latest: Arc<AtomicU64>,
+    text: String,
+}
+
+struct ShapedRow {
+    hash: u64,
+    generation: u64,
+    glyphs: Option<Vec<ShapedGlyph>>,
+}
+
+/// Shapes terminal rows on background threads so a burst of complex text
+/// never holds up input handling or presenting frames. Each worker owns a
+/// `FontSystem` built from the same font database and fonts as the UI, so
+/// font ids agree, and results are keyed by the row's content hash.
+pub struct ShaperPool {
+    jobs: Sender<Job>,
+    results: Receiver<ShapedRow>,
+    /// Generation each row was last requested for.
+    pending: HashMap<u64, u64>,
+    ready: HashMap<u64, Vec<ShapedGlyph>>,
+}
+
+impl ShaperPool {
+    pub fn new(locale: &str, db: &fontdb::Database, fonts: &Fonts, proxy: EventLoopProxy<UserEvent>) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        for index in 0..SHAPER_THREADS {
+            let jobs = Arc::clone(&job_receiver);
+            let results = result_sender.clone();
+            let proxy = proxy.clone();
+            let mut fs = fonts.font_system(locale.to_string(), db.clone());
+            let fonts = fonts.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("shaper-{}", index))
+                .spawn(move || loop {
+                    let Ok(job) = jobs.lock().unwrap().recv() else { break };
+                    let stale = job.generation < job.latest.load(Ordering::SeqCst);
+                    let glyphs = (!stale).then(|| shape_row(&mut fs, &fonts.attrs(), &job.text));
+                    let row = ShapedRow {
+                        hash: job.hash,
+                        generation: job.generation,
+                        glyphs,
+                    };
+                    if results.send(row).is_err() {
+                        break;
+                    }
+                    let _ = proxy.send_event(UserEvent::Shaped);
+                });
+            if let Err(e) = spawned {
+                error!("Failed to start shaper thread: {}", e);
+            }
+        }
+
+        Self {
+            jobs,
+            results,
+            pending: HashMap::new(),
+            ready: HashMap::new(),
+        }
+    }
+
+    /// Shaped glyphs for a row, if a worker has finished it. Otherwise the
+    /// row is queued, once per generation.
+    pub fn take(&mut self, hash: u64, text: &str, generation: &Arc<AtomicU64>) -> Option<Vec<ShapedGlyph>> {
+        if let Some(glyphs) = self.ready.remove(&hash) {
+            return Some(glyphs);
+        }
+
+        let generation_now = generation.load(Ordering::SeqCst);
+        if self.pending.get(&hash) != Some(&generation_now) {
+            self.pending.insert(hash, generation_now);
+            let job = Job {
+                hash,
+                generation: generation_now,
+                latest: Arc::clone(generation),
+                text: text.to_string(),
+            };
+            if self.jobs.send(job).is_err() {
+                error!("Shaper threads are gone");
+            }
+        }
+        None
+    }
+
+    /// Collect finished rows. Returns true if any arrived or have to be
+    /// requested again.
+    pub fn collect(&mut self) -> bool {
+        let mut arrived = false;
+        while let Ok(row) = self.results.try_recv() {
+            arrived = true;
+            // A skipped row is requested again if it is still on screen
+            if self.pending.get(&row.hash) == Some(&row.generation) {
+                self.pending.remove(&row.hash);
+            }
+            let Some(glyphs) = row.glyphs else { continue };
+            if self.ready.len() >= MAX_READY_ROWS {
+                self.ready.clear();
+            }
+            self.ready.insert(row.hash, glyphs);
+        }
+        arrived
+    }
+}
@@ -0,0 +1,262 @@
+// src/terminal/share.rs
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use tracing::{debug, info, warn};
+
+use crate::terminal::{
+    config::{CELL_WIDTH, LINE_HEIGHT},
+    notices,
+    terminal::Damage,
+    token, Session, Terminal,
+};
+
+/// First line a viewer sends, followed by the token.
+const GREETING: &str = "NEBULA-SHARE 1";
+/// How long a new connection has to authenticate.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// A viewer that can't take a frame for this long is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest greeting line read from a new connection, far more than the
+/// greeting and token need.
+const MAX_GREETING: u64 = 256;
+
+/// One update of the shared screen, sent as a line of JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareFrame {
+    pub generation: u64,
+    pub row_count: usize,
+    /// Rows that changed, by index from the top of the view. Every row for
+    /// a viewer that just joined.
+    pub rows: Vec<(usize, String)>,
+    /// Cursor column and row.
+    pub cursor: (usize, usize),
+    pub title: String,
+}
+
+struct Viewer {
+    /// Lines for the viewer's writer thread. Fails once it is gone.
+    sender: Sender<Arc<str>>,
+    /// Has had a full frame, so only changes are sent.
+    synced: bool,
+}
+
+/// Streams the active tab to viewers that connect with the token. Viewers
+/// only watch; nothing they send after the handshake is read.
+pub struct ShareServer {
+    viewers: Arc<Mutex<Vec<Viewer>>>,
+    /// Session and rows last sent, for sending only what changed.
+    session: Option<u64>,
+    generation: Option<u64>,
+    rows: Vec<String>,
+}
+
+impl ShareServer {
+    /// Listen on `address`, accepting viewers that present `token`.
+    pub fn start(address: &str, token: String) -> Result<Self> {
+        let listener = TcpListener::bind(address).with_context(|| format!("Failed to listen on {}", address))?;
+        info!("Sharing on {}", listener.local_addr()?);
+        let viewers = Arc::new(Mutex::new(Vec::new()));
+        let accepted = Arc::clone(&viewers);
+        let token: Arc<str> = token.into();
+        thread::Builder::new().name("share-listener".into()).spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Failed to accept viewer: {}", e);
+                        continue;
+                    }
+                };
+                let viewers = Arc::clone(&accepted);
+                let token = Arc::clone(&token);
+                thread::spawn(move || {
+                    let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+                    match authenticate(&stream, &token) {
+                        Ok(()) => {
+                            notices::warn(format!("{} is viewing this window", peer));
+                            add_viewer(stream, &viewers);
+                        }
+                        Err(e) => warn!("Refused viewer {}: {}", peer, e),
+                    }
+                });
+            }
+        })?;
+        Ok(Self { viewers, session: None, generation: None, rows: Vec::new() })
+    }
+
+    /// Send viewers what changed in `session` since the last call. Private
+    /// tabs are shown as blank.
+    pub fn publish(&mut self, session: &Session) {
+        let mut viewers = self.viewers.lock().unwrap();
+        if viewers.is_empty() {
+            return;
+        }
+        let generation = session.snapshot_generation;
+        let switched = self.session != Some(session.id);
+        let joined = viewers.iter().any(|viewer| !viewer.synced);
+        if !switched && !joined && self.generation == Some(generation) {
+            return;
+        }
+
+        let rows: Vec<String> = if session.is_private() {
+            vec![String::new(); session.rows.len()]
+        } else {
+            session.rows.clone()
+        };
+        let cursor = if session.is_private() {
+            (0, 0)
+        } else {
+            ((session.cursor.0 / CELL_WIDTH) as usize, (session.cursor.1 / LINE_HEIGHT) as usize)
+        };
+        let frame = |rows: Vec<(usize, String)>| ShareFrame {
+            generation,
+            row_count: session.rows.len(),
+            rows,
+            cursor,
+            title: session.window_title(),
+        };
+        let full = frame(rows.iter().cloned().enumerate().collect());
+        let changed = if switched {
+            full.clone()
+        } else {
+            let changed = rows.iter().enumerate().filter(|(index, row)| self.rows.get(*index) != Some(*row));
+            frame(changed.map(|(index, row)| (index, row.clone())).collect())
+        };
+        let (Ok(full), Ok(changed)) = (serde_json::to_string(&full), serde_json::to_string(&changed)) else {
+            return;
+        };
+        let (full, changed): (Arc<str>, Arc<str>) = (full.into(), changed.into());
+
+        viewers.retain_mut(|viewer| {
+            let line = if viewer.synced { Arc::clone(&changed) } else { Arc::clone(&full) };
+            viewer.synced = true;
+            viewer.sender.send(line).is_ok()
+        });
+        self.session = Some(session.id);
+        self.generation = Some(generation);
+        self.rows = rows;
+    }
+}
+
+/// Check the greeting and token a new connection sends.
+fn authenticate(stream: &TcpStream, token: &str) -> Result<()> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream.take(MAX_GREETING)).read_line(&mut line)?;
+    let presented = line.trim_end().strip_prefix(GREETING).map(str::trim);
+    let mut writer = stream;
+    if !presented.is_some_and(|presented| token::matches(presented, token)) {
+        let _ = writer.write_all(b"DENIED\n");
+        bail!("wrong token");
+    }
+    writer.write_all(b"OK\n")?;
+    stream.set_read_timeout(None)?;
+    Ok(())
+}
+
+/// Hand an authenticated connection a writer thread of its own, so a slow
+/// viewer never holds up the UI.
+fn add_viewer(stream: TcpStream, viewers: &Mutex<Vec<Viewer>>) {
+    let (sender, receiver) = mpsc::channel::<Arc<str>>();
+    if let Err(e) = stream.set_write_timeout(Some(WRITE_TIMEOUT)) {
+        warn!("Failed to set viewer write timeout: {}", e);
+    }
+    thread::spawn(move || {
+        let mut stream = stream;
+        for line in receiver {
+            if let Err(e) = stream.write_all(line.as_bytes()).and_then(|_| stream.write_all(b"\n")) {
+                debug!("Viewer went away: {}", e);
+                return;
+            }
+        }
+    });
+    viewers.lock().unwrap().push(Viewer { sender, synced: false });
+}
+
+/// Connect to a shared window and feed its frames to `terminal` as damage,
+/// in place of a PTY.
+pub fn join(terminal: &Terminal, address: &str, token: &str) -> Result<()> {
+    let socket = address
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("No address found for {}", address))?;
+    let mut stream = TcpStream::connect_timeout(&socket, HANDSHAKE_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {}", address))?;
+    stream.write_all(format!("{} {}\n", GREETING, token).as_bytes())?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut reply = String::new();
+    reader.read_line(&mut reply)?;
+    if reply.trim_end() != "OK" {
+        bail!("{} refused the token", address);
+    }
+    stream.set_read_timeout(None)?;
+    info!("Viewing {}", address);
+
+    let damage = terminal.damage_sender();
+    let generation = Arc::clone(&terminal.snapshot_generation);
+    let title = Arc::clone(&terminal.title);
+    let exited = Arc::clone(&terminal.exited);
+    let shutdown = Arc::clone(&terminal.shutdown);
+    let waker = terminal.waker.clone();
+    let address = address.to_string();
+    thread::Builder::new().name("share-viewer".into()).spawn(move || {
+        let mut last_title = String::new();
+        for line in reader.lines() {
+            if shutdown.load(Ordering::SeqCst) {
+                return;
+            }
+            let frame: ShareFrame = match line.map_err(anyhow::Error::from).and_then(|line| Ok(serde_json::from_str(&line)?)) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    warn!("Lost shared window {}: {}", address, e);
+                    break;
+                }
+            };
+            if frame.title != last_title {
+                *title.lock().unwrap() = Some(format!("{} (shared)", frame.title));
+                last_title = frame.title;
+            }
+            generation.store(frame.generation, Ordering::Release);
+            let (column, row) = frame.cursor;
+            let sent = damage.send(Damage {
+                generation: frame.generation,
+                row_count: frame.row_count,
+                rows: frame.rows,
+                links: Vec::new(),
+                cursor: (column as f32 * CELL_WIDTH, row as f32 * LINE_HEIGHT),
+                view_top: 0,
+                scrollback: 0..0,
+                foregrounds: Vec::new(),
+                backgrounds: Vec::new(),
+                decorations: Vec::new(),
+                command_timings: Vec::new(),
+                tail: None,
+            });
+            // The tab was closed
+            if sent.is_err() {
+                return;
+            }
+            if let Some(wake) = &waker {
+                wake();
+            }
+        }
+        notices::warn(format!("The shared window at {} was closed", address));
+        exited.store(true, Ordering::SeqCst);
+        if let Some(wake) = &waker {
+            wake();
+        }
+    })?;
+    Ok(())
+}
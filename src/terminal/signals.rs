@@ -0,0 +1,56 @@
+// src/terminal/signals.rs
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tracing::warn;
+
+/// Records termination signals (SIGHUP/SIGINT/SIGTERM) so the event loop
+/// can tear down the PTY instead of the process dying with the shell attached.
+pub struct SignalWatcher {
+    received: Arc<AtomicUsize>,
+}
+
+impl SignalWatcher {
+    #[cfg(unix)]
+    pub fn install() -> Self {
+        use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+        let received = Arc::new(AtomicUsize::new(0));
+        for signal in [SIGHUP, SIGINT, SIGTERM] {
+            if let Err(e) =
+                signal_hook::flag::register_usize(signal, Arc::clone(&received), signal as usize)
+            {
+                warn!("Failed to install handler for signal {}: {}", signal, e);
+            }
+        }
+        Self { received }
+    }
+
+    #[cfg(not(unix))]
+    pub fn install() -> Self {
+        Self {
+            received: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the number of the last termination signal received, if any.
+    pub fn take(&self) -> Option<i32> {
+        match self.received.swap(0, Ordering::SeqCst) {
+            0 => None,
+            signal => Some(signal as i32),
+        }
+    }
+}
+
+pub fn signal_name(signal: i32) -> &'static str {
+    #[cfg(unix)]
+    {
+        signal_hook::low_level::signal_name(signal).unwrap_or("signal")
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = signal;
+        "signal"
+    }
+}
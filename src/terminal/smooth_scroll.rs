@@ -0,0 +1,52 @@
+// src/terminal/smooth_scroll.rs
+use std::time::{Duration, Instant};
+
+use crate::terminal::config::LINE_HEIGHT;
+
+/// How long the rows take to glide to where a scroll put them.
+const GLIDE_DURATION: Duration = Duration::from_millis(150);
+
+/// Pixel offset of the rows while they glide into place after the wheel
+/// scrolls the view, for `mouse.smooth_scroll`. The view still moves by
+/// whole lines at once; the rows are drawn from where they were and eased
+/// to where they now are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmoothScroll {
+    /// Offset when the glide started.
+    from: f32,
+    started: Option<Instant>,
+}
+
+impl SmoothScroll {
+    /// The view moved `lines` back into the scrollback, or forward for
+    /// negative counts.
+    pub fn scrolled(&mut self, lines: isize) {
+        // Scrolling again mid-glide carries on from where the rows are
+        self.from = self.offset() - lines as f32 * LINE_HEIGHT;
+        self.started = Some(Instant::now());
+    }
+
+    /// How far down to draw the rows this frame.
+    pub fn offset(&self) -> f32 {
+        let Some(started) = self.started else { return 0.0 };
+        let progress = started.elapsed().as_secs_f32() / GLIDE_DURATION.as_secs_f32();
+        if progress >= 1.0 {
+            return 0.0;
+        }
+        // Ease out, fast at first and settling gently
+        self.from * (1.0 - progress).powi(3)
+    }
+
+    /// Whether the rows have to be drawn again: while gliding, and once
+    /// more when they land.
+    pub fn tick(&mut self) -> bool {
+        match self.started {
+            Some(started) if started.elapsed() >= GLIDE_DURATION => {
+                self.started = None;
+                true
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+}
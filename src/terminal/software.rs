@@ -0,0 +1,355 @@
+// src/terminal/software.rs
+use anyhow::{anyhow, Result};
+use cosmic_text::{CacheKey, CacheKeyFlags, FontSystem, SwashImage};
+use std::{
+    collections::HashMap,
+    num::NonZeroU32,
+    sync::{atomic::Ordering, Arc},
+};
+use swash::scale::image::Content;
+use tracing::{error, info, trace};
+use winit::{
+    application::ApplicationHandler,
+    dpi::LogicalSize,
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, EventLoop},
+    keyboard::ModifiersState,
+    window::{Window, WindowAttributes, WindowId},
+};
+
+use crate::terminal::{
+    clipboard::SharedClipboard,
+    colors::{ColorSettings, TextColors},
+    config::{Config, Rgb, CELL_WIDTH, LINE_HEIGHT},
+    fonts::Fonts,
+    input::handle_input,
+    keybindings::{Action, Keymap},
+    raster::GlyphRasterizer,
+    session::Session,
+    shaper::{shape_row, ShapedGlyph},
+    terminal::CursorShape,
+    UserEvent,
+};
+
+/// Rows kept shaped between frames before the cache starts over.
+const MAX_SHAPED_ROWS: usize = 1024;
+/// Width of the bar and underline cursors, and of the unfocused outline.
+const CURSOR_THICKNESS: usize = 2;
+
+struct SoftwareWindow {
+    window: Arc<Window>,
+    surface: softbuffer::Surface<Arc<Window>, Arc<Window>>,
+}
+
+/// Fallback for machines without a usable GPU: one tab, drawn on the CPU
+/// into a plain pixel buffer. Text, cell backgrounds and the cursor are
+/// drawn at one physical pixel per logical one; tabs, overlays and the
+/// mouse are left to the GPU renderer.
+pub struct SoftwareApp {
+    window: Option<SoftwareWindow>,
+    session: Session,
+    user_config: Config,
+    keymap: Keymap,
+    clipboard: SharedClipboard,
+    font_system: FontSystem,
+    fonts: Fonts,
+    rasterizer: GlyphRasterizer,
+    /// Glyphs of rows drawn recently, by their text.
+    shaped: HashMap<String, Vec<ShapedGlyph>>,
+    modifiers: ModifiersState,
+    focused: bool,
+}
+
+impl SoftwareApp {
+    pub fn new(user_config: Config, session: Session) -> Self {
+        let (locale, db) = FontSystem::new().into_locale_and_db();
+        let fonts = Fonts::resolve(&user_config.font, &db);
+        let font_system = fonts.font_system(locale, db);
+        Self {
+            window: None,
+            clipboard: Arc::clone(&session.terminal.clipboard),
+            session,
+            keymap: Keymap::new(&user_config.keybindings),
+            rasterizer: GlyphRasterizer::new(user_config.rendering.clone()),
+            user_config,
+            font_system,
+            fonts,
+            shaped: HashMap::new(),
+            modifiers: ModifiersState::empty(),
+            focused: true,
+        }
+    }
+
+    pub fn run(mut self, event_loop: EventLoop<UserEvent>) -> Result<()> {
+        info!("Drawing on the CPU");
+        event_loop.run_app(&mut self)?;
+        self.session.shutdown();
+        Ok(())
+    }
+
+    fn create_window(&mut self, event_loop: &ActiveEventLoop) -> Result<()> {
+        let geometry = self.user_config.geometry;
+        let size = LogicalSize::new(geometry.columns as f32 * CELL_WIDTH, geometry.rows as f32 * LINE_HEIGHT);
+        let attributes = WindowAttributes::default()
+            .with_title(format!("{} (software)", self.session.window_title()))
+            .with_inner_size(size);
+        let window = Arc::new(event_loop.create_window(attributes)?);
+        let context = softbuffer::Context::new(window.clone()).map_err(|e| anyhow!("{}", e))?;
+        let surface = softbuffer::Surface::new(&context, window.clone()).map_err(|e| anyhow!("{}", e))?;
+        self.window = Some(SoftwareWindow { window, surface });
+        Ok(())
+    }
+
+    /// Fit the terminal to the window, in whole cells.
+    fn fit_session(&self) {
+        let Some(window) = &self.window else { return };
+        let size = window.window.inner_size();
+        let cols = (size.width as f32 / CELL_WIDTH) as usize;
+        let rows = (size.height as f32 / LINE_HEIGHT) as usize;
+        self.session.terminal.resize(cols, rows);
+    }
+
+    fn draw(&mut self) {
+        let Some(window) = &mut self.window else { return };
+        let size = window.window.inner_size();
+        let (Some(width), Some(height)) = (NonZeroU32::new(size.width), NonZeroU32::new(size.height)) else {
+            return;
+        };
+        if let Err(e) = window.surface.resize(width, height) {
+            error!("Failed to resize the software surface: {}", e);
+            return;
+        }
+        let mut buffer = match window.surface.buffer_mut() {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                error!("Failed to get the software surface buffer: {}", e);
+                return;
+            }
+        };
+        let mut canvas = Canvas {
+            pixels: &mut buffer,
+            width: size.width as usize,
+            height: size.height as usize,
+        };
+
+        let session = &self.session;
+        let settings = ColorSettings::new(&self.user_config.theme);
+        let modes = *session.terminal.modes.lock().unwrap();
+        let colors = TextColors::resolve(
+            session.rows.len(),
+            &session.foregrounds,
+            &session.backgrounds,
+            session.theme(),
+            settings,
+            modes.reverse_screen,
+        );
+        let theme = colors.theme;
+        canvas.fill((0, 0, canvas.width, canvas.height), theme.background);
+        for span in &colors.backgrounds {
+            let x = (span.columns.start as f32 * CELL_WIDTH) as usize;
+            let width = (span.columns.len() as f32 * CELL_WIDTH) as usize;
+            canvas.fill((x, (span.row as f32 * LINE_HEIGHT) as usize, width, LINE_HEIGHT as usize), span.color);
+        }
+
+        if self.shaped.len() > MAX_SHAPED_ROWS {
+            self.shaped.clear();
+        }
+        let attrs = self.fonts.attrs();
+        for (index, row) in session.rows.iter().enumerate() {
+            if row.trim().is_empty() {
+                continue;
+            }
+            let glyphs = self
+                .shaped
+                .entry(row.clone())
+                .or_insert_with(|| shape_row(&mut self.font_system, &attrs, row));
+            let top = index as f32 * LINE_HEIGHT;
+            for glyph in glyphs.iter() {
+                let (key, _, _) = CacheKey::new(
+                    glyph.font_id,
+                    glyph.glyph_id,
+                    glyph.font_size,
+                    (0.0, 0.0),
+                    CacheKeyFlags::empty(),
+                );
+                let Some(image) = self.rasterizer.get_image(&mut self.font_system, key) else { continue };
+                let x = (glyph.x + image.placement.left as f32).round() as isize;
+                let y = (top + glyph.y - image.placement.top as f32).round() as isize;
+                let col = (glyph.x / CELL_WIDTH) as usize;
+                let color = colors.rows[index]
+                    .iter()
+                    .find(|(columns, _)| columns.contains(&col))
+                    .map_or(colors.default, |(_, color)| *color);
+                canvas.blend_glyph(x, y, image, color);
+            }
+        }
+
+        if !modes.cursor_hidden {
+            let style = *session.terminal.cursor_style.lock().unwrap();
+            let (x, y) = (session.cursor.0 as usize, session.cursor.1 as usize);
+            let (w, h, t) = (CELL_WIDTH as usize, LINE_HEIGHT as usize, CURSOR_THICKNESS);
+            let color = theme.foreground;
+            if !self.focused {
+                canvas.fill((x, y, w, 1), color);
+                canvas.fill((x, y + h - 1, w, 1), color);
+                canvas.fill((x, y, 1, h), color);
+                canvas.fill((x + w - 1, y, 1, h), color);
+            } else {
+                match style.shape {
+                    CursorShape::Block => canvas.fill((x, y, w, h), color),
+                    CursorShape::Underline => canvas.fill((x, y + h - t, w, t), color),
+                    CursorShape::Bar => canvas.fill((x, y, t, h), color),
+                }
+            }
+        }
+
+        window.window.pre_present_notify();
+        if let Err(e) = buffer.present() {
+            error!("Failed to present the software frame: {}", e);
+        }
+    }
+}
+
+impl ApplicationHandler<UserEvent> for SoftwareApp {
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: UserEvent) {
+        trace!("Woken by {:?}", event);
+    }
+
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_none() {
+            if let Err(e) = self.create_window(event_loop) {
+                error!("Failed to create window: {:#}", e);
+                event_loop.exit();
+            }
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::Resized(_) => {
+                self.fit_session();
+                if let Some(window) = &self.window {
+                    window.window.request_redraw();
+                }
+            }
+            WindowEvent::RedrawRequested => self.draw(),
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Focused(focused) => {
+                self.focused = focused;
+                self.session.report_focus(focused);
+                if let Some(window) = &self.window {
+                    window.window.request_redraw();
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+            WindowEvent::KeyboardInput { event, .. } => {
+                // Only pasting is available among the actions
+                match self.keymap.action_for(&event, self.modifiers) {
+                    Some(Action::Paste) => {
+                        let text = self.clipboard.lock().unwrap().get_text();
+                        if let Some(text) = text {
+                            self.session.paste(&text);
+                        }
+                        return;
+                    }
+                    Some(action) => {
+                        info!("{} isn't available when drawing on the CPU", action.name());
+                        return;
+                    }
+                    None => {}
+                }
+                let session = &self.session;
+                let modes = *session.terminal.modes.lock().unwrap();
+                if let Ok(mut writer) = session.input_writer.lock() {
+                    let _ = handle_input(
+                        &event,
+                        &mut *writer,
+                        &session.terminal.dirty,
+                        session.is_private(),
+                        self.modifiers,
+                        modes,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let session = &self.session;
+        if session.terminal.exited.load(Ordering::SeqCst) && !session.terminal.config.shell.hold {
+            event_loop.exit();
+            return;
+        }
+        let changed = self.session.poll();
+        let retitled = self.session.refresh_title(false);
+        let Some(window) = &self.window else { return };
+        if retitled {
+            window.window.set_title(&format!("{} (software)", self.session.window_title()));
+        }
+        if changed {
+            window.window.request_redraw();
+        }
+    }
+}
+
+/// A frame's pixels, as 0RGB words.
+struct Canvas<'a> {
+    pixels: &'a mut [u32],
+    width: usize,
+    height: usize,
+}
+
+impl Canvas<'_> {
+    fn fill(&mut self, (x, y, w, h): (usize, usize, usize, usize), color: Rgb) {
+        let word = pack(color.0, color.1, color.2);
+        for row in y.min(self.height)..(y + h).min(self.height) {
+            let start = row * self.width;
+            self.pixels[start + x.min(self.width)..start + (x + w).min(self.width)].fill(word);
+        }
+    }
+
+    /// Draw a rasterized glyph with its top-left corner at `(x, y)`. Masks
+    /// take `color`; color glyphs such as emoji keep their own.
+    fn blend_glyph(&mut self, x: isize, y: isize, image: &SwashImage, color: Rgb) {
+        let (width, height) = (image.placement.width as usize, image.placement.height as usize);
+        for row in 0..height {
+            let py = y + row as isize;
+            if py < 0 || py as usize >= self.height {
+                continue;
+            }
+            for col in 0..width {
+                let px = x + col as isize;
+                if px < 0 || px as usize >= self.width {
+                    continue;
+                }
+                let index = row * width + col;
+                let (source, alpha) = match image.content {
+                    Content::Color => {
+                        let rgba = &image.data[index * 4..index * 4 + 4];
+                        (Rgb(rgba[0], rgba[1], rgba[2]), rgba[3])
+                    }
+                    _ => (color, image.data[index]),
+                };
+                if alpha == 0 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[py as usize * self.width + px as usize];
+                *pixel = blend(*pixel, source, alpha);
+            }
+        }
+    }
+}
+
+fn pack(r: u8, g: u8, b: u8) -> u32 {
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+/// `color` over the 0RGB word `under` at coverage `alpha`.
+fn blend(under: u32, color: Rgb, alpha: u8) -> u32 {
+    let mix = |under: u32, over: u8| {
+        let (under, over, alpha) = (under & 0xff, over as u32, alpha as u32);
+        ((over * alpha + under * (255 - alpha)) / 255) as u8
+    };
+    pack(mix(under >> 16, color.0), mix(under >> 8, color.1), mix(under, color.2))
+}
@@ -0,0 +1,104 @@
+// src/terminal/state.rs
+use cosmic_text::FontSystem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::terminal::{
+    colors::ColorSettings,
+    config::{CommandTimeConfig, Theme},
+    decorations::DecorationMetrics,
+    fonts::Fonts,
+    highlight::Highlighter,
+    links::LinkSpan,
+    magnifier::Magnifier,
+    notices::Notice,
+    overlay::{ClosePrompt, CommandPalette, HintsMode, ImePreedit, KeyTest, KeybindingList, PastePicker, RestorePrompt},
+    post_process::PostProcess,
+    raster::GlyphRasterizer,
+    render::FrameBuffers,
+    search::SearchBar,
+    shaper::ShaperPool,
+    smooth_scroll::SmoothScroll,
+    GlyphAtlas, GpuResources,
+};
+
+pub struct TerminalState {
+    pub font_system: Arc<Mutex<FontSystem>>,
+    pub glyph_atlas: GlyphAtlas,
+    pub rasterizer: Arc<Mutex<GlyphRasterizer>>,
+    pub shaper: ShaperPool,
+    pub gpu_resources: GpuResources,
+    pub last_frame_time: Instant,
+    pub focused: bool,
+    pub local_dirty: bool,
+    /// The cursor is in the on phase of a blink.
+    pub cursor_visible: bool,
+    /// Blinking is on in the config.
+    pub cursor_blink: bool,
+    pub blink_interval: Duration,
+    /// When the cursor last changed phase, or was shown again by input or
+    /// output.
+    pub last_blink: Instant,
+    /// When the visual bell's flash ends. Cleared once it has.
+    pub bell_flash: Option<Instant>,
+    /// When the scrollbar finishes fading out, pushed back while the view
+    /// is scrolled. None once it's gone.
+    pub scrollbar_until: Option<Instant>,
+    /// Rows gliding into place after the wheel scrolled the view.
+    pub smooth_scroll: SmoothScroll,
+    pub paste_picker: Option<PastePicker>,
+    pub search: Option<SearchBar>,
+    pub close_prompt: Option<ClosePrompt>,
+    /// Offer to reopen windows left by a crash, shown at startup.
+    pub restore_prompt: Option<RestorePrompt>,
+    pub keybinding_list: Option<KeybindingList>,
+    pub command_palette: Option<CommandPalette>,
+    /// Labels over the targets in view while hints mode takes the keys.
+    pub hints: Option<HintsMode>,
+    /// Open for `nebula keytest`, taking every key.
+    pub key_test: Option<KeyTest>,
+    pub ime_preedit: Option<ImePreedit>,
+    /// Cursor rectangle last reported to the input method.
+    pub ime_cursor_area: Option<(f32, f32)>,
+    /// Link under the mouse while Ctrl is held, underlined and opened on click.
+    pub hovered_link: Option<LinkSpan>,
+    /// Warnings shown in the banner, copied when `notice_generation` is
+    /// behind.
+    pub notices: Vec<Notice>,
+    pub notice_generation: u64,
+    pub theme: Theme,
+    pub magnifier: Magnifier,
+    /// The user's shader from `rendering.shader`, if it loaded.
+    pub post_process: Option<PostProcess>,
+    pub frame_buffers: FrameBuffers,
+    /// Underline and strikethrough placement for the row font.
+    pub decoration_metrics: DecorationMetrics,
+    /// Configured fonts found at startup.
+    pub fonts: Fonts,
+    /// Styling from the config's highlight rules.
+    pub highlighter: Highlighter,
+    pub command_time: CommandTimeConfig,
+    pub color_settings: ColorSettings,
+    /// Scale factor of the monitor the main window is on.
+    pub scale_factor: f32,
+    /// Opacity the background is drawn with. 1 unless the config asks for
+    /// a translucent window and the surface supports it.
+    pub background_opacity: f32,
+    /// The surface expects colors multiplied by their alpha.
+    pub premultiplied_alpha: bool,
+}
+
+/// Sent to the event loop by other threads, which is otherwise asleep
+/// until the next window event or timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserEvent {
+    /// The PTY reader handled output, a shared window sent a frame or a
+    /// web client typed something.
+    Output,
+    /// Shaper threads finished rows.
+    Shaped,
+    /// A notice was reported.
+    Notice,
+    /// A `nebula` invocation asked the daemon for a window.
+    Open,
+}
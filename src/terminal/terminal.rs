@@ -1,30 +1,470 @@
 use anyhow::Result;
-use cosmic_text::{Attrs, Buffer, FontSystem, Metrics, Shaping};
-use portable_pty::{CommandBuilder, Child, NativePtySystem, PtySize, PtySystem};
+use tracing::{debug, error, info, trace, trace_span, warn};
+use regex::Regex;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Deserialize;
+use portable_pty::{CommandBuilder, Child, MasterPty, NativePtySystem, PtySize, PtySystem};
 use std::{
-    io::{Read, Write},
-    sync::{Arc, Mutex},
-    thread,
     collections::VecDeque,
+    io::{Read, Write},
+    ops::Range,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
 };
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 use vte::{Params, Perform};
-use crate::terminal::SwashCache;
-use cosmic_text::Family;
+use crate::terminal::{
+    capabilities,
+    clipboard::SharedClipboard,
+    config::{ansi_color, Config, Osc52Access, Rgb, ShellConfig, Theme, CELL_WIDTH},
+    encoding::{Encoding, EncodingWriter, OutputDecoder},
+    links::{column_at, HyperlinkTable, LinkSpan, UrlScanner},
+    mouse::{Highlight, MouseEncoding, MouseTracking},
+    scrollback::{Line, Scrollback},
+    selection::{slice_columns, Selection, SelectionKind},
+    terminfo::{self, Capability},
+};
 
-pub const FONT_SIZE: f32 = 14.0;
 pub const LINE_HEIGHT: f32 = 20.0;
 pub const DEFAULT_COLS: u16 = 80;
 pub const DEFAULT_ROWS: u16 = 24;
+/// Bytes read from the PTY at a time.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+/// Longest XTGETTCAP request kept, enough for dozens of names.
+const MAX_CAPABILITY_QUERY: usize = 4096;
+/// Columns between the default tab stops.
+const TAB_WIDTH: usize = 8;
+/// OSC 8 links remembered before ones no longer on screen are dropped.
+const MAX_HYPERLINKS: usize = 1024;
+/// Prompts remembered with the duration of the command before them.
+const MAX_COMMAND_TIMINGS: usize = 1024;
+/// Prompts remembered with the command run at them, for jumping between.
+const MAX_COMMAND_BLOCKS: usize = 1024;
+/// Matches kept per search, so a pattern like `.` can't exhaust memory on a
+/// long scrollback.
+pub const MAX_SEARCH_MATCHES: usize = 10_000;
+/// Sequences the parser didn't handle, kept until the frontend takes them.
+const MAX_UNSUPPORTED: usize = 64;
+/// Shown until the shell prints something.
+pub const PLACEHOLDER_TEXT: &str = "Nebula Terminal\n$ ";
+
+/// Called from other threads to wake whoever draws the terminal, such as
+/// after the PTY reader handled output.
+pub type Waker = Arc<dyn Fn() + Send + Sync>;
+
+/// A match in the grid or scrollback. `line` counts from the first line of
+/// the session, so it stays put as output scrolls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub line: usize,
+    pub columns: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CursorShape {
+    #[default]
+    Block,
+    Underline,
+    Bar,
+}
+
+/// Cursor appearance requested by the application through DECSCUSR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorStyle {
+    pub shape: CursorShape,
+    pub blinking: bool,
+}
+
+impl Default for CursorStyle {
+    fn default() -> Self {
+        Self {
+            shape: CursorShape::Block,
+            blinking: true,
+        }
+    }
+}
+
+impl CursorStyle {
+    /// Map a `CSI Ps SP q` parameter to a cursor style.
+    fn from_decscusr(ps: usize) -> Option<Self> {
+        let (shape, blinking) = match ps {
+            0 | 1 => (CursorShape::Block, true),
+            2 => (CursorShape::Block, false),
+            3 => (CursorShape::Underline, true),
+            4 => (CursorShape::Underline, false),
+            5 => (CursorShape::Bar, true),
+            6 => (CursorShape::Bar, false),
+            _ => return None,
+        };
+        Some(Self { shape, blinking })
+    }
+}
+
+/// Input modes set by the application that change what keys send.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TerminalModes {
+    /// DECCKM: cursor keys send `SS3 A` instead of `CSI A`.
+    pub app_cursor: bool,
+    /// DECKPAM/DECKPNM: the numeric keypad sends `SS3` sequences.
+    pub app_keypad: bool,
+    /// Modes 47, 1047 and 1049: the alternate screen is shown. The mouse
+    /// wheel sends arrow keys there.
+    pub alternate_screen: bool,
+    /// Mode 1004: focus changes are sent as `CSI I` and `CSI O`.
+    pub focus_reporting: bool,
+    /// Mode 2004: pastes are wrapped in `CSI 200 ~` and `CSI 201 ~`.
+    pub bracketed_paste: bool,
+    /// DECTCEM (mode 25) reset: the program hid the cursor, usually while
+    /// it redraws.
+    pub cursor_hidden: bool,
+    /// Modes 9 and 1000 to 1003: mouse events reported to the program.
+    pub mouse_tracking: MouseTracking,
+    /// Modes 1005, 1006 and 1015: how mouse reports are written.
+    pub mouse_encoding: MouseEncoding,
+    /// Highlight the program started under mode 1001, ended by the release.
+    pub highlight: Option<Highlight>,
+    /// DECSCNM (mode 5): the whole screen drawn in reverse video.
+    pub reverse_screen: bool,
+}
+
+/// Character set that can be designated into G0 or G1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Charset {
+    #[default]
+    Ascii,
+    /// DEC special graphics, used by curses programs for line drawing.
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    fn from_designator(byte: u8) -> Option<Self> {
+        match byte {
+            b'B' => Some(Charset::Ascii),
+            b'0' => Some(Charset::DecSpecialGraphics),
+            _ => None,
+        }
+    }
+
+    fn map(self, c: char) -> char {
+        if self == Charset::Ascii {
+            return c;
+        }
+        match c {
+            '_' => ' ',
+            '`' => '◆',
+            'a' => '▒',
+            'b' => '␉',
+            'c' => '␌',
+            'd' => '␍',
+            'e' => '␊',
+            'f' => '°',
+            'g' => '±',
+            'h' => '␤',
+            'i' => '␋',
+            'j' => '┘',
+            'k' => '┐',
+            'l' => '┌',
+            'm' => '└',
+            'n' => '┼',
+            'o' => '⎺',
+            'p' => '⎻',
+            'q' => '─',
+            'r' => '⎼',
+            's' => '⎽',
+            't' => '├',
+            'u' => '┤',
+            'v' => '┴',
+            'w' => '┬',
+            'x' => '│',
+            'y' => '≤',
+            'z' => '≥',
+            '{' => 'π',
+            '|' => '≠',
+            '}' => '£',
+            '~' => '·',
+            c => c,
+        }
+    }
+}
+
+/// Number of cells `c` occupies: 2 for wide East Asian characters and
+/// emoji, 1 for everything else.
+pub fn char_width(c: char) -> usize {
+    match c.width() {
+        Some(2) => 2,
+        _ => 1,
+    }
+}
+
+/// Number of cells a grapheme cluster occupies. Emoji sequences and flags
+/// are wide even when their first character isn't.
+pub fn cluster_width(cluster: &str) -> usize {
+    cluster.width().clamp(1, 2)
+}
+
+/// Cursor state stored by DECSC / `CSI s` and brought back by DECRC / `CSI u`.
+#[derive(Debug, Clone, Copy, Default)]
+struct SavedCursor {
+    x: usize,
+    y: usize,
+    wrap_pending: bool,
+    charsets: [Charset; 2],
+    active_charset: usize,
+    protected: bool,
+    style: CellStyle,
+    origin_mode: bool,
+}
+
+/// The main screen, kept aside while the alternate screen is shown.
+#[derive(Debug, Clone)]
+struct MainScreen {
+    cells: Vec<Vec<TerminalCell>>,
+    wrapped: Vec<bool>,
+    /// Whether the cursor was hidden on the main screen.
+    cursor_hidden: bool,
+}
+
+/// Line drawn under text, from SGR 4 and its subparameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnderlineStyle {
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+/// A text color set by SGR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellColor {
+    /// One of the 16 ANSI colors, by index. Bold can brighten 0 to 7.
+    Named(u8),
+    Rgb(Rgb),
+}
+
+impl CellColor {
+    /// The color to draw, taking 0 to 7 to their bright versions if
+    /// `brighten` is set.
+    pub fn rgb(self, brighten: bool) -> Rgb {
+        match self {
+            Self::Named(index @ 0..=7) if brighten => ansi_color(index + 8),
+            Self::Named(index) => ansi_color(index),
+            Self::Rgb(color) => color,
+        }
+    }
+}
+
+/// Attributes set by SGR that apply to printed cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct CellStyle {
+    /// `None` for the theme's.
+    foreground: Option<CellColor>,
+    bold: bool,
+    /// SGR 2, drawn closer to the background.
+    dim: bool,
+    /// SGR 7, text and background colors swapped.
+    reverse: bool,
+    /// `None` for the theme's.
+    background: Option<Rgb>,
+    underline: Option<UnderlineStyle>,
+    /// SGR 58. `None` draws the underline in the text color.
+    underline_color: Option<Rgb>,
+    strikethrough: bool,
+}
 
 #[derive(Debug, Clone)]
 struct TerminalCell {
     character: char,
-    // Add attributes later: bold, italic, color, etc.
+    /// Characters that form one grapheme cluster with `character`, such as
+    /// combining accents, emoji modifiers and ZWJ sequences, or the second
+    /// half of a flag. Boxed so plain cells stay small.
+    combining: Option<Box<str>>,
+    /// Right half of a double-width character in the cell before it. It has
+    /// no text of its own.
+    spacer: bool,
+    /// OSC 8 link the cell was printed under, an id into the grid's
+    /// hyperlink table.
+    hyperlink: Option<u32>,
+    /// Printed with DECSCA protection on, so selective erase leaves it.
+    protected: bool,
+    style: CellStyle,
+    // Add attributes later: italic, etc.
+}
+
+impl TerminalCell {
+    fn new(character: char) -> Self {
+        Self { character, combining: None, spacer: false, hyperlink: None, protected: false, style: CellStyle::default() }
+    }
+
+    fn from_cluster(cluster: &str) -> Self {
+        let mut chars = cluster.chars();
+        let character = chars.next().unwrap_or(' ');
+        let rest = chars.as_str();
+        Self {
+            character,
+            combining: (!rest.is_empty()).then(|| rest.into()),
+            spacer: false,
+            hyperlink: None,
+            protected: false,
+            style: CellStyle::default(),
+        }
+    }
+
+    fn spacer() -> Self {
+        Self { character: ' ', combining: None, spacer: true, hyperlink: None, protected: false, style: CellStyle::default() }
+    }
+
+    fn push_text(&self, text: &mut String) {
+        text.push(self.character);
+        if let Some(combining) = &self.combining {
+            text.push_str(combining);
+        }
+    }
+
+    fn width(&self) -> usize {
+        match &self.combining {
+            Some(_) => {
+                let mut cluster = String::new();
+                self.push_text(&mut cluster);
+                cluster_width(&cluster)
+            }
+            None => char_width(self.character),
+        }
+    }
+
+    fn is_wide(&self) -> bool {
+        !self.spacer && self.width() == 2
+    }
+
+    /// Nothing printed and nothing to show: a space without a background,
+    /// decoration or link.
+    fn is_blank(&self) -> bool {
+        self.character == ' '
+            && self.combining.is_none()
+            && !self.spacer
+            && self.hyperlink.is_none()
+            && self.style == CellStyle::default()
+    }
+}
+
+/// Cells for `text`, one grapheme cluster per cell and two for double-width
+/// clusters.
+fn text_cells(text: &str) -> Vec<TerminalCell> {
+    let mut cells = Vec::with_capacity(text.len());
+    for cluster in text.graphemes(true) {
+        cells.push(TerminalCell::from_cluster(cluster));
+        if cluster_width(cluster) == 2 {
+            cells.push(TerminalCell::spacer());
+        }
+    }
+    cells
+}
+
+/// Split `text` into rows of at most `cols` cells. A double-width cluster
+/// that doesn't fit at the end of a row starts the next one.
+fn split_text(text: &str, cols: usize) -> Vec<String> {
+    let mut rows = vec![String::new()];
+    let mut width = 0;
+    for cluster in text.graphemes(true) {
+        let cluster_width = cluster_width(cluster);
+        if width > 0 && width + cluster_width > cols {
+            rows.push(String::new());
+            width = 0;
+        }
+        rows.last_mut().unwrap().push_str(cluster);
+        width += cluster_width;
+    }
+    rows
+}
+
+/// Split the cells of a logical line into rows of `cols` cells, as
+/// `split_text` does. Also returns where the cell at index `cursor` ended
+/// up, counting on past the end for a cursor beyond the last cell.
+fn split_cells(
+    cells: &[TerminalCell],
+    cols: usize,
+    cursor: Option<usize>,
+) -> (Vec<Vec<TerminalCell>>, Option<(usize, usize)>) {
+    let mut rows: Vec<Vec<TerminalCell>> = vec![Vec::with_capacity(cols)];
+    let mut placed = None;
+    for (index, cell) in cells.iter().enumerate() {
+        // Spacers are added back after their double-width character
+        if cell.spacer {
+            if cursor == Some(index) {
+                let row = rows.len() - 1;
+                placed = Some((row, rows[row].len().saturating_sub(1)));
+            }
+            continue;
+        }
+        let width = if cell.is_wide() && cols >= 2 { 2 } else { 1 };
+        if rows.last().unwrap().len() + width > cols {
+            rows.push(Vec::with_capacity(cols));
+        }
+        let row = rows.len() - 1;
+        if cursor == Some(index) {
+            placed = Some((row, rows[row].len()));
+        }
+        rows[row].push(cell.clone());
+        if width == 2 {
+            rows[row].push(TerminalCell::spacer());
+        }
+    }
+    if let Some(cursor) = cursor.filter(|&cursor| cursor >= cells.len()) {
+        let row = rows.len() - 1;
+        let col = rows[row].len() + cursor - cells.len();
+        placed = Some((row + col / cols, col % cols));
+    }
+    if let Some((row, _)) = placed {
+        rows.resize_with(rows.len().max(row + 1), Vec::new);
+    }
+    for row in &mut rows {
+        row.resize_with(cols, TerminalCell::default);
+    }
+    (rows, placed)
+}
+
+/// A row laid out again by `TerminalGrid::reflow`: text for rows made from
+/// scrollback lines, cells for rows made from the screen.
+enum ReflowRow {
+    Text(String),
+    Cells(Vec<TerminalCell>),
+}
+
+impl ReflowRow {
+    fn text(&self) -> String {
+        match self {
+            ReflowRow::Text(text) => text.clone(),
+            ReflowRow::Cells(cells) => {
+                let mut text = String::new();
+                for cell in cells.iter().filter(|cell| !cell.spacer) {
+                    cell.push_text(&mut text);
+                }
+                text
+            }
+        }
+    }
+
+    fn into_cells(self, cols: usize) -> Vec<TerminalCell> {
+        let mut cells = match self {
+            ReflowRow::Text(text) => text_cells(&text),
+            ReflowRow::Cells(cells) => cells,
+        };
+        cells.resize_with(cols, TerminalCell::default);
+        cells
+    }
 }
 
 impl Default for TerminalCell {
     fn default() -> Self {
-        Self { character: ' ' }
+        Self::new(' ')
     }
 }
 
@@ -34,13 +474,53 @@ struct TerminalGrid {
     cells: Vec<Vec<TerminalCell>>,
     cursor_x: usize,
     cursor_y: usize,
-    scrollback: VecDeque<String>,
+    /// The last column was just printed; the next printable character wraps
+    /// to a new line first (DECAWM deferred wrap).
+    wrap_pending: bool,
+    /// Autowrap mode (DECAWM). When off, printing at the margin overwrites
+    /// the last column.
+    auto_wrap: bool,
+    /// Rows that soft-wrapped into the row below.
+    wrapped: Vec<bool>,
+    /// G0 and G1 character sets, and which one is invoked (SI/SO).
+    charsets: [Charset; 2],
+    active_charset: usize,
+    saved_cursor: Option<SavedCursor>,
+    tab_stops: Vec<bool>,
+    /// First and last rows of the scroll region (DECSTBM), inclusive.
+    /// Line feeds at the bottom margin scroll only these rows.
+    scroll_top: usize,
+    scroll_bottom: usize,
+    /// Origin mode (DECOM): cursor addressing is relative to the scroll
+    /// region and stays within it.
+    origin_mode: bool,
+    scrollback: Scrollback,
     scroll_offset: usize,
+    /// Set while the alternate screen is shown. Lines scrolled off it
+    /// don't go to the scrollback.
+    main_screen: Option<MainScreen>,
+    hyperlinks: HyperlinkTable,
+    /// Link opened by OSC 8 that printed text is attached to.
+    active_hyperlink: Option<u32>,
+    /// DECSCA: printed characters are protected from selective erase.
+    protected: bool,
+    /// Attributes set by SGR for printed cells. Erased cells take the
+    /// background only.
+    style: CellStyle,
+    url_scanner: UrlScanner,
+    /// When the running command started, from OSC 133 C.
+    command_started: Option<Instant>,
+    /// The last command to finish, kept for the prompt after it.
+    finished_command: Option<CommandTiming>,
+    /// Prompts by session line number, with the command that ran before.
+    command_timings: VecDeque<(usize, CommandTiming)>,
+    /// Prompts marked by the shell, oldest first.
+    command_blocks: VecDeque<CommandBlock>,
     dirty: bool,
 }
 
 impl TerminalGrid {
-    fn new(rows: usize, cols: usize) -> Self {
+    fn new(rows: usize, cols: usize, scrollback: Scrollback) -> Self {
         let mut cells = Vec::with_capacity(rows);
         for _ in 0..rows {
             let mut row = Vec::with_capacity(cols);
@@ -56,167 +536,1460 @@ impl TerminalGrid {
             cells,
             cursor_x: 0,
             cursor_y: 0,
-            scrollback: VecDeque::new(),
+            wrap_pending: false,
+            auto_wrap: true,
+            wrapped: vec![false; rows],
+            charsets: [Charset::Ascii; 2],
+            active_charset: 0,
+            saved_cursor: None,
+            tab_stops: (0..cols).map(|col| col % TAB_WIDTH == 0).collect(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            origin_mode: false,
+            scrollback,
             scroll_offset: 0,
+            main_screen: None,
+            hyperlinks: HyperlinkTable::default(),
+            active_hyperlink: None,
+            protected: false,
+            style: CellStyle::default(),
+            url_scanner: UrlScanner::default(),
+            command_started: None,
+            finished_command: None,
+            command_timings: VecDeque::new(),
+            command_blocks: VecDeque::new(),
             dirty: true,
         }
     }
 
+    /// An empty cell as erasing leaves it, in the current background.
+    fn blank(&self) -> TerminalCell {
+        let style = CellStyle { background: self.style.background, ..CellStyle::default() };
+        TerminalCell { style, ..TerminalCell::default() }
+    }
+
+    /// Switch to a blank alternate screen, keeping the main one aside.
+    fn enter_alternate_screen(&mut self, cursor_hidden: bool) {
+        if self.main_screen.is_some() {
+            return;
+        }
+        let blank = vec![vec![TerminalCell::default(); self.cols]; self.rows];
+        self.main_screen = Some(MainScreen {
+            cells: std::mem::replace(&mut self.cells, blank),
+            wrapped: std::mem::replace(&mut self.wrapped, vec![false; self.rows]),
+            cursor_hidden,
+        });
+        self.scroll_offset = 0;
+        self.wrap_pending = false;
+        self.dirty = true;
+    }
+
+    /// Switch back to the main screen as it was left. Returns whether the
+    /// cursor was hidden there, or None if the main screen was showing.
+    fn leave_alternate_screen(&mut self) -> Option<bool> {
+        let main = self.main_screen.take()?;
+        self.cells = main.cells;
+        self.wrapped = main.wrapped;
+        self.wrap_pending = false;
+        self.dirty = true;
+        Some(main.cursor_hidden)
+    }
+
+    /// Forget the scrollback, leaving the screen as it is (CSI 3 J).
+    fn clear_scrollback(&mut self) {
+        self.scrollback.discard();
+        let first_line = self.scrollback.first_line();
+        self.command_timings.retain(|(line, _)| *line >= first_line);
+        self.command_blocks.retain(|block| block.prompt.0 >= first_line);
+        self.scroll_offset = 0;
+        self.dirty = true;
+    }
+
     fn clear_screen(&mut self) {
+        let blank = self.blank();
         for row in 0..self.rows {
-            for col in 0..self.cols {
-                self.cells[row][col] = TerminalCell::default();
-            }
+            self.cells[row].fill(blank.clone());
         }
+        self.wrapped.fill(false);
         self.cursor_x = 0;
         self.cursor_y = 0;
+        self.wrap_pending = false;
         self.dirty = true;
     }
 
     fn clear_line(&mut self, from: usize) {
         let row = self.cursor_y;
         if row < self.rows {
+            let blank = self.blank();
             for col in from..self.cols {
-                self.cells[row][col] = TerminalCell::default();
+                self.cells[row][col] = blank.clone();
             }
+            self.fix_wide_chars(row, from.saturating_sub(1)..from + 1);
+            // With its end erased the row no longer runs on into the next
+            self.wrapped[row] = false;
             self.dirty = true;
         }
     }
 
+    /// EL 1: blank the cursor row up to and including the cursor.
+    fn clear_line_start(&mut self) {
+        let (row, col) = (self.cursor_y, self.cursor_x.min(self.cols - 1));
+        let blank = self.blank();
+        self.cells[row][..=col].fill(blank);
+        self.fix_wide_chars(row, col..col + 2);
+        self.dirty = true;
+    }
+
+    /// DECALN: fill the screen with `E`s and home the cursor, for checking
+    /// alignment.
+    fn alignment_pattern(&mut self) {
+        for row in 0..self.rows {
+            self.cells[row].fill(TerminalCell::new('E'));
+        }
+        self.wrapped.fill(false);
+        self.move_cursor(0, 0);
+    }
+
+    /// Blank any half of a double-width character in `cols` whose other
+    /// half was overwritten or moved away.
+    fn fix_wide_chars(&mut self, row: usize, cols: Range<usize>) {
+        for col in cols.start..cols.end.min(self.cols) {
+            let cell = &self.cells[row][col];
+            let orphaned = if cell.spacer {
+                col == 0 || !self.cells[row][col - 1].is_wide()
+            } else {
+                cell.is_wide() && !self.cells[row].get(col + 1).is_some_and(|next| next.spacer)
+            };
+            if orphaned {
+                self.cells[row][col] = TerminalCell::default();
+            }
+        }
+    }
+
     fn newline(&mut self) {
-        if self.cursor_y == self.rows - 1 {
+        self.index();
+        self.cursor_x = 0;
+    }
+
+    /// IND: move down a row, scrolling the region at its bottom margin.
+    /// Below the region the cursor stops at the bottom of the screen.
+    fn index(&mut self) {
+        if self.cursor_y == self.scroll_bottom {
             self.scroll_up();
-        } else {
+        } else if self.cursor_y < self.rows - 1 {
             self.cursor_y += 1;
         }
-        self.cursor_x = 0;
+        self.wrap_pending = false;
+        self.dirty = true;
+    }
+
+    /// RI: move up a row, pushing the region down at its top margin.
+    fn reverse_index(&mut self) {
+        if self.cursor_y == self.scroll_top {
+            self.scroll_down();
+        } else if self.cursor_y > 0 {
+            self.cursor_y -= 1;
+        }
+        self.wrap_pending = false;
         self.dirty = true;
     }
 
     fn carriage_return(&mut self) {
         self.cursor_x = 0;
+        self.wrap_pending = false;
         self.dirty = true;
     }
 
+    /// Move left a cell without erasing it; shells erase with `\b \b`.
     fn backspace(&mut self) {
+        self.wrap_pending = false;
         if self.cursor_x > 0 {
             self.cursor_x -= 1;
-            self.cells[self.cursor_y][self.cursor_x] = TerminalCell::default();
             self.dirty = true;
         }
     }
 
-    fn scroll_up(&mut self) {
-        // Collect top line as string
-        let top_line: String = self.cells[0]
-            .iter()
-            .map(|cell| cell.character)
+    /// Change the grid size. Rows that no longer fit above the cursor go to
+    /// the scrollback. On the main screen lines are rewrapped at the new
+    /// width; the alternate screen's are cut, as its program redraws them.
+    fn resize(&mut self, rows: usize, cols: usize) {
+        let (rows, cols) = (rows.max(1), cols.max(1));
+        if (rows, cols) == (self.rows, self.cols) {
+            return;
+        }
+        let reflow = cols != self.cols && self.main_screen.is_none();
+        if reflow {
+            self.reflow(cols);
+        }
+
+        // Keep the cursor on screen by scrolling the top off
+        let overflow = (self.cursor_y + 1).saturating_sub(rows);
+        for row in self.cells.drain(..overflow) {
+            let wrapped = self.wrapped.remove(0);
+            if self.main_screen.is_some() {
+                continue;
+            }
+            let mut text = String::new();
+            for cell in row.iter().filter(|cell| !cell.spacer) {
+                cell.push_text(&mut text);
+            }
+            self.scrollback.push(Line { text, wrapped });
+        }
+        self.cursor_y -= overflow;
+
+        // The main screen keeps its top rows, to be redrawn by the shell
+        if let Some(main) = &mut self.main_screen {
+            main.cells.resize_with(rows, || vec![TerminalCell::default(); cols]);
+            main.wrapped.resize(rows, false);
+            for row in &mut main.cells {
+                row.resize_with(cols, TerminalCell::default);
+            }
+        }
+
+        self.cells.resize_with(rows, || vec![TerminalCell::default(); cols]);
+        self.wrapped.resize(rows, false);
+        for row in 0..rows {
+            self.cells[row].resize_with(cols, TerminalCell::default);
+        }
+        self.tab_stops = (0..cols)
+            .map(|col| self.tab_stops.get(col).copied().unwrap_or(col % TAB_WIDTH == 0))
             .collect();
-        self.scrollback.push_back(top_line);
-        
-        // Shift lines up
-        for row in 0..self.rows - 1 {
-            for col in 0..self.cols {
-                self.cells[row][col] = self.cells[row + 1][col].clone();
+        self.rows = rows;
+        self.cols = cols;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        for row in 0..rows {
+            self.fix_wide_chars(row, cols - 1..cols);
+        }
+
+        self.cursor_x = self.cursor_x.min(cols - 1);
+        self.wrap_pending &= reflow;
+        self.dirty = true;
+    }
+
+    /// Rewrap the screen and the scrollback held in memory at `cols`
+    /// columns. Rows that soft-wrapped are joined back into their lines and
+    /// split again at the new width, keeping the cursor on the same
+    /// character and prompts with their timings. Lines already spilled to
+    /// disk keep the width they were written at.
+    fn reflow(&mut self, cols: usize) {
+        let history = self.scrollback.take_recent();
+        // Session line number of the first row laid out again
+        let base = self.scrollback.first_line() + self.scrollback.len();
+        let mut rows: Vec<(ReflowRow, bool)> = Vec::new();
+        // For each old row, the new row its line starts at
+        let mut starts: Vec<usize> = Vec::new();
+
+        // Scrollback lines are only text, so they are rewrapped as text
+        let mut text = String::new();
+        let mut line_rows = 0;
+        for (index, line) in history.iter().enumerate() {
+            line_rows += 1;
+            if !line.wrapped {
+                text.push_str(line.text.trim_end_matches(' '));
+                starts.extend(std::iter::repeat_n(rows.len(), line_rows));
+                let split = split_text(&text, cols);
+                let last = split.len() - 1;
+                rows.extend(
+                    split.into_iter().enumerate().map(|(row, text)| (ReflowRow::Text(text), row < last)),
+                );
+                text.clear();
+                line_rows = 0;
+                continue;
+            }
+            // A double-width character that wrapped early left a blank at
+            // the end of the row before it
+            let next_wide = history
+                .get(index + 1)
+                .and_then(|next| next.text.graphemes(true).next())
+                .is_some_and(|first| cluster_width(first) == 2);
+            match line.text.strip_suffix(' ') {
+                Some(trimmed) if next_wide => text.push_str(trimmed),
+                _ => text.push_str(&line.text),
             }
         }
-        
-        // Clear bottom line
-        for col in 0..self.cols {
-            self.cells[self.rows - 1][col] = TerminalCell::default();
+
+        // Screen rows keep their styles and links. Blank rows below the
+        // cursor are dropped, and come back as the screen is filled
+        let mut cells = text_cells(&text);
+        let mut cursor = None;
+        let mut placed = None;
+        let last = (0..self.rows)
+            .rev()
+            .find(|&row| row == self.cursor_y || self.cells[row].iter().any(|cell| !cell.is_blank()))
+            .unwrap_or(0);
+        for row in 0..=last {
+            line_rows += 1;
+            if row == self.cursor_y {
+                cursor = Some(cells.len() + self.cursor_x);
+            }
+            let row_cells = &self.cells[row];
+            if self.wrapped[row] && row < last {
+                let padded =
+                    row_cells.last().is_some_and(TerminalCell::is_blank) && self.cells[row + 1][0].is_wide();
+                cells.extend_from_slice(&row_cells[..row_cells.len() - padded as usize]);
+                continue;
+            }
+            let end = row_cells.iter().rposition(|cell| !cell.is_blank()).map_or(0, |col| col + 1);
+            cells.extend_from_slice(&row_cells[..end]);
+            let (split, position) = split_cells(&cells, cols, cursor.take());
+            if let Some((row, col)) = position {
+                placed = Some((rows.len() + row, col));
+            }
+            starts.extend(std::iter::repeat_n(rows.len(), line_rows));
+            let last = split.len() - 1;
+            rows.extend(
+                split.into_iter().enumerate().map(|(row, cells)| (ReflowRow::Cells(cells), row < last)),
+            );
+            cells.clear();
+            line_rows = 0;
+        }
+        starts.extend((0..self.rows - last - 1).map(|row| rows.len() + row));
+
+        // The screen shows the newest rows, as long as the cursor stays on it
+        let (cursor_row, mut cursor_col) = placed.unwrap_or((rows.len().saturating_sub(1), 0));
+        let top = rows.len().saturating_sub(self.rows).min(cursor_row);
+        let mut rows = rows.into_iter();
+        for (row, wrapped) in rows.by_ref().take(top) {
+            self.scrollback.push(Line { text: row.text(), wrapped });
+        }
+        for index in 0..self.rows {
+            let (row, wrapped) = rows.next().unwrap_or((ReflowRow::Cells(Vec::new()), false));
+            self.cells[index] = row.into_cells(cols);
+            self.wrapped[index] = wrapped;
         }
+
+        // A cursor waiting to wrap after the last column stays after the
+        // character it followed
+        if self.wrap_pending {
+            cursor_col += 1;
+        }
+        self.wrap_pending = cursor_col >= cols;
+        self.cursor_x = cursor_col.min(cols - 1);
+        self.cursor_y = cursor_row - top;
+        let remap = |line: &mut usize| {
+            if let Some(&start) = line.checked_sub(base).and_then(|row| starts.get(row)) {
+                *line = base + start;
+            }
+        };
+        for (line, _) in &mut self.command_timings {
+            remap(line);
+        }
+        for block in &mut self.command_blocks {
+            for (line, col) in block.marks_mut() {
+                remap(line);
+                *col = (*col).min(cols - 1);
+            }
+        }
+        self.cols = cols;
+        self.scroll_offset = 0;
         self.dirty = true;
     }
 
+    /// Move to the next tab stop, or the last column if there is none.
+    fn tab(&mut self) {
+        let next = (self.cursor_x + 1..self.cols).find(|&col| self.tab_stops[col]);
+        self.cursor_x = next.unwrap_or(self.cols - 1);
+        self.wrap_pending = false;
+        self.dirty = true;
+    }
+
+    /// CBT: move back to the previous tab stop, or the first column.
+    fn back_tab(&mut self) {
+        let previous = (0..self.cursor_x).rev().find(|&col| self.tab_stops[col]);
+        self.cursor_x = previous.unwrap_or(0);
+        self.wrap_pending = false;
+        self.dirty = true;
+    }
+
+    /// HTS: set a tab stop at the cursor column.
+    fn set_tab_stop(&mut self) {
+        if self.cursor_x < self.cols {
+            self.tab_stops[self.cursor_x] = true;
+        }
+    }
+
+    /// TBC: clear the tab stop at the cursor column, or every tab stop.
+    fn clear_tab_stops(&mut self, all: bool) {
+        if all {
+            self.tab_stops.fill(false);
+        } else if self.cursor_x < self.cols {
+            self.tab_stops[self.cursor_x] = false;
+        }
+    }
+
+    fn row_text(&self, row: usize) -> String {
+        let mut text = String::with_capacity(self.cols);
+        for cell in self.cells[row].iter().filter(|cell| !cell.spacer) {
+            cell.push_text(&mut text);
+        }
+        text
+    }
+
+    /// Whether the scroll region covers the whole screen.
+    fn full_region(&self) -> bool {
+        self.scroll_top == 0 && self.scroll_bottom == self.rows - 1
+    }
+
+    /// DECSTBM: scroll only rows `top..=bottom`, then home the cursor. A
+    /// region of less than two rows is ignored.
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.rows - 1);
+        if top >= bottom {
+            return;
+        }
+        self.scroll_top = top;
+        self.scroll_bottom = bottom;
+        self.goto(0, 0);
+    }
+
+    /// Scroll the region up a row. Rows leaving a full-screen region on the
+    /// main screen go to the scrollback; a partial region just loses them.
+    fn scroll_up(&mut self) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        if self.main_screen.is_none() && self.full_region() {
+            let top_line = Line {
+                text: self.row_text(0),
+                wrapped: self.wrapped[0],
+            };
+            self.scrollback.push(top_line);
+            // Keep a scrolled-back view on the same lines
+            if self.scroll_offset > 0 {
+                self.scroll_offset += 1;
+            }
+        }
+        
+        // Shift the region up and clear its bottom line
+        self.cells[top..=bottom].rotate_left(1);
+        self.wrapped[top..=bottom].rotate_left(1);
+        self.clear_row(bottom);
+    }
+
+    /// Scroll the region down a row, blanking its top line.
     fn scroll_down(&mut self) {
-        if self.scroll_offset > 0 {
-            self.scroll_offset -= 1;
-            if let Some(bottom_line) = self.scrollback.pop_back() {
-                // Shift lines down
-                for row in (1..self.rows).rev() {
-                    for col in 0..self.cols {
-                        self.cells[row][col] = self.cells[row - 1][col].clone();
-                    }
-                }
-                
-                // Set top line from scrollback
-                for (col, c) in bottom_line.chars().enumerate().take(self.cols) {
-                    self.cells[0][col] = TerminalCell { character: c };
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        self.cells[top..=bottom].rotate_right(1);
+        self.wrapped[top..=bottom].rotate_right(1);
+        self.clear_row(top);
+    }
+
+    /// IL: insert blank lines at the cursor row, pushing the rows below down
+    /// and off the bottom margin. Does nothing outside the scroll region.
+    fn insert_lines(&mut self, count: usize) {
+        let (row, bottom) = (self.cursor_y, self.scroll_bottom);
+        if row < self.scroll_top || row > bottom {
+            return;
+        }
+        let count = count.min(bottom + 1 - row);
+        self.cells[row..=bottom].rotate_right(count);
+        self.wrapped[row..=bottom].rotate_right(count);
+        for line in row..row + count {
+            self.clear_row(line);
+        }
+        self.carriage_return();
+    }
+
+    /// DL: delete lines at the cursor row, pulling the rows below up and
+    /// leaving blank lines at the bottom margin. Does nothing outside the
+    /// scroll region.
+    fn delete_lines(&mut self, count: usize) {
+        let (row, bottom) = (self.cursor_y, self.scroll_bottom);
+        if row < self.scroll_top || row > bottom {
+            return;
+        }
+        let count = count.min(bottom + 1 - row);
+        self.cells[row..=bottom].rotate_left(count);
+        self.wrapped[row..=bottom].rotate_left(count);
+        for line in bottom + 1 - count..=bottom {
+            self.clear_row(line);
+        }
+        self.carriage_return();
+    }
+
+    /// ICH: insert blank cells at the cursor, shifting the rest of the line
+    /// right. Cells pushed past the margin are lost.
+    fn insert_chars(&mut self, count: usize) {
+        let (row, col) = (self.cursor_y, self.cursor_x);
+        let count = count.min(self.cols - col);
+        self.cells[row][col..].rotate_right(count);
+        let blank = self.blank();
+        self.cells[row][col..col + count].fill(blank);
+        self.fix_wide_chars(row, 0..self.cols);
+        self.wrap_pending = false;
+        self.dirty = true;
+    }
+
+    /// DCH: delete cells at the cursor, shifting the rest of the line left.
+    fn delete_chars(&mut self, count: usize) {
+        let (row, col) = (self.cursor_y, self.cursor_x);
+        let count = count.min(self.cols - col);
+        self.cells[row][col..].rotate_left(count);
+        let blank = self.blank();
+        self.cells[row][self.cols - count..].fill(blank);
+        self.fix_wide_chars(row, 0..self.cols);
+        self.wrap_pending = false;
+        self.dirty = true;
+    }
+
+    /// ECH: blank cells starting at the cursor without moving anything.
+    fn erase_chars(&mut self, count: usize) {
+        let (row, col) = (self.cursor_y, self.cursor_x);
+        let end = (col + count).min(self.cols);
+        let blank = self.blank();
+        self.cells[row][col..end].fill(blank);
+        self.fix_wide_chars(row, 0..self.cols);
+        self.wrap_pending = false;
+        self.dirty = true;
+    }
+
+    /// DECSED/DECSEL/DECSERA: blank the cells of `rows` and `cols` that
+    /// weren't printed under DECSCA protection.
+    fn selective_erase(&mut self, rows: Range<usize>, cols: Range<usize>) {
+        let cols = cols.start.min(self.cols)..cols.end.min(self.cols);
+        let rows = rows.start.min(self.rows)..rows.end.min(self.rows);
+        // An inverted rectangle covers nothing
+        if cols.is_empty() || rows.is_empty() {
+            return;
+        }
+        let blank = self.blank();
+        for row in rows {
+            for cell in &mut self.cells[row][cols.clone()] {
+                if !cell.protected {
+                    *cell = blank.clone();
                 }
-                self.dirty = true;
             }
+            self.fix_wide_chars(row, 0..self.cols);
         }
+        self.wrap_pending = false;
+        self.dirty = true;
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        let blank = self.blank();
+        self.cells[row].fill(blank);
+        self.wrapped[row] = false;
+        self.dirty = true;
     }
 
     fn move_cursor(&mut self, x: usize, y: usize) {
         self.cursor_x = x.min(self.cols - 1);
         self.cursor_y = y.min(self.rows - 1);
+        self.wrap_pending = false;
         self.dirty = true;
     }
 
+    /// Move to `x`, `y` as addressed by CUP and VPA: from the top of the
+    /// scroll region and kept inside it in origin mode.
+    fn goto(&mut self, x: usize, y: usize) {
+        if self.origin_mode {
+            self.move_cursor(x, (self.scroll_top + y).min(self.scroll_bottom));
+        } else {
+            self.move_cursor(x, y);
+        }
+    }
+
+    /// Move by `dx`, `dy`. Vertical moves starting inside the scroll region
+    /// stop at its margins.
     fn move_cursor_relative(&mut self, dx: i32, dy: i32) {
         let new_x = (self.cursor_x as i32 + dx).max(0) as usize;
-        let new_y = (self.cursor_y as i32 + dy).max(0) as usize;
+        let mut new_y = (self.cursor_y as i32 + dy).max(0) as usize;
+        if self.cursor_y >= self.scroll_top && dy < 0 {
+            new_y = new_y.max(self.scroll_top);
+        }
+        if self.cursor_y <= self.scroll_bottom && dy > 0 {
+            new_y = new_y.min(self.scroll_bottom);
+        }
         self.move_cursor(new_x, new_y);
     }
 
+    /// DECSC: remember the cursor position and character set state.
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor {
+            x: self.cursor_x,
+            y: self.cursor_y,
+            wrap_pending: self.wrap_pending,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+            protected: self.protected,
+            style: self.style,
+            origin_mode: self.origin_mode,
+        });
+    }
+
+    /// DECRC: restore the state saved by DECSC, or reset to the home
+    /// position if nothing was saved.
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor.unwrap_or_default();
+        self.move_cursor(saved.x, saved.y);
+        self.wrap_pending = saved.wrap_pending;
+        self.charsets = saved.charsets;
+        self.active_charset = saved.active_charset;
+        self.protected = saved.protected;
+        self.style = saved.style;
+        self.origin_mode = saved.origin_mode;
+    }
+
     fn print_char(&mut self, c: char) {
-        if self.cursor_y < self.rows && self.cursor_x < self.cols {
-            self.cells[self.cursor_y][self.cursor_x] = TerminalCell { character: c };
-            self.cursor_x += 1;
-            self.dirty = true;
-        }
-        
-        // Only wrap when at column boundary
-        if self.cursor_x >= self.cols {
-            self.carriage_return();
+        // Wrap only once another character arrives after the last column
+        if self.wrap_pending && self.auto_wrap {
+            self.wrapped[self.cursor_y] = true;
             self.newline();
         }
+
+        let c = self.charsets[self.active_charset].map(c);
+        if !c.is_ascii() && self.combine(c) {
+            return;
+        }
+        // A zero-width character with nothing to attach to takes no cell
+        if c.width() == Some(0) {
+            return;
+        }
+        let width = char_width(c).min(self.cols);
+
+        // A double-width character that doesn't fit in the last column
+        // wraps early, or overwrites the last two columns without autowrap
+        if self.cursor_x + width > self.cols {
+            if self.auto_wrap {
+                self.clear_line(self.cursor_x);
+                self.wrapped[self.cursor_y] = true;
+                self.newline();
+            } else {
+                self.cursor_x = self.cols - width;
+            }
+        }
+
+        let (row, col) = (self.cursor_y, self.cursor_x);
+        self.cells[row][col] = TerminalCell::new(c);
+        if width == 2 {
+            self.cells[row][col + 1] = TerminalCell::spacer();
+        }
+        self.fix_wide_chars(row, col.saturating_sub(1)..col + width + 1);
+        for cell in &mut self.cells[row][col..col + width] {
+            cell.hyperlink = self.active_hyperlink;
+            cell.protected = self.protected;
+            cell.style = self.style;
+        }
+
+        if self.cursor_x + width < self.cols {
+            self.cursor_x += width;
+        } else {
+            self.cursor_x = self.cols - 1;
+            self.wrap_pending = self.auto_wrap;
+        }
+        self.dirty = true;
+    }
+
+    /// Add `c` to the grapheme cluster just before the cursor if it
+    /// continues it. Returns false if `c` starts a new cluster.
+    fn combine(&mut self, c: char) -> bool {
+        let row = self.cursor_y;
+        let mut col = if self.wrap_pending {
+            self.cursor_x
+        } else {
+            match self.cursor_x.checked_sub(1) {
+                Some(col) => col,
+                None => return false,
+            }
+        };
+        if self.cells[row][col].spacer && col > 0 {
+            col -= 1;
+        }
+
+        let cell = &self.cells[row][col];
+        let old_width = cell.width();
+        let mut cluster = String::new();
+        cell.push_text(&mut cluster);
+        cluster.push(c);
+        if cluster.graphemes(true).nth(1).is_some() {
+            return false;
+        }
+
+        // A cluster that turns wide (a flag, or an emoji presentation
+        // selector) needs the cell after it, which must still be free
+        let grows = cluster_width(&cluster) > old_width;
+        let at_margin = col + 1 == self.cols;
+        if grows && !at_margin && (self.wrap_pending || col + 1 != self.cursor_x) {
+            return false;
+        }
+
+        let (hyperlink, protected, style) = (cell.hyperlink, cell.protected, cell.style);
+        self.cells[row][col] = TerminalCell { hyperlink, protected, style, ..TerminalCell::from_cluster(&cluster) };
+        if grows && !at_margin {
+            self.cells[row][col + 1] = TerminalCell { hyperlink, protected, style, ..TerminalCell::spacer() };
+            self.fix_wide_chars(row, col..col + 3);
+            if col + 2 < self.cols {
+                self.cursor_x = col + 2;
+            } else {
+                self.cursor_x = self.cols - 1;
+                self.wrap_pending = self.auto_wrap;
+            }
+        }
+        self.dirty = true;
+        true
     }
 
     fn print_str(&mut self, s: &str) {
         for c in s.chars() {
-            self.print_char(c);
+            if c == '\n' {
+                self.newline();
+            } else {
+                self.print_char(c);
+            }
         }
     }
 
-    fn to_string(&self) -> String {
-        let mut output = String::new();
-        
-        // Add scrollback lines
-        for line in self.scrollback.iter().skip(self.scroll_offset) {
-            output.push_str(line);
-            output.push('\n');
+    /// Start or end (with an empty URI) an OSC 8 hyperlink.
+    fn set_hyperlink(&mut self, uri: &str, id: Option<&str>) {
+        if uri.is_empty() {
+            self.active_hyperlink = None;
+            return;
         }
-        
-        // Add current screen content
-        for row in 0..self.rows {
-            let line: String = self.cells[row]
-                .iter()
-                .map(|cell| cell.character)
+        if self.hyperlinks.len() >= MAX_HYPERLINKS {
+            let cells = &self.cells;
+            self.hyperlinks
+                .retain(|id| cells.iter().flatten().any(|cell| cell.hyperlink == Some(id)));
+        }
+        self.active_hyperlink = Some(self.hyperlinks.insert(uri, id));
+    }
+
+    /// Index of the first line in view, counting scrollback lines first and
+    /// then the rows of the live screen.
+    fn view_top(&self) -> usize {
+        let history = self.scrollback.len();
+        history - self.scroll_offset.min(history)
+    }
+
+    /// A scrollback line or, past the end of the scrollback, a row of the
+    /// live screen.
+    fn line(&mut self, index: usize) -> Line {
+        let history = self.scrollback.len();
+        if index < history {
+            self.scrollback.get(index).unwrap_or_default()
+        } else {
+            Line {
+                text: self.row_text(index - history),
+                wrapped: self.wrapped[index - history],
+            }
+        }
+    }
+
+    /// The rows currently in view, `scroll_offset` lines back from the live
+    /// screen.
+    fn visible_lines(&mut self) -> Vec<Line> {
+        let top = self.view_top();
+        (top..top + self.rows).map(|index| self.line(index)).collect()
+    }
+
+    /// Text of session lines `lines`, with rows that soft-wrapped joined to
+    /// the next so a long line comes out as one. Hard line ends lose their
+    /// padding and become newlines.
+    fn logical_text(&mut self, lines: Range<usize>) -> String {
+        let first_line = self.scrollback.first_line();
+        let end = self.scrollback.len() + self.rows;
+        let mut text = String::new();
+        let mut continues = true;
+        for index in lines.start.saturating_sub(first_line)..lines.end.saturating_sub(first_line).min(end) {
+            let line = self.line(index);
+            if !continues {
+                text.push('\n');
+            }
+            if line.wrapped {
+                text.push_str(&line.text);
+            } else {
+                text.push_str(line.text.trim_end_matches(' '));
+            }
+            continues = line.wrapped;
+        }
+        text
+    }
+
+    /// Text under `selection`. Linear selections join soft-wrapped rows
+    /// as `logical_text` does; block selections give each line's columns
+    /// on a line of their own, so they stay aligned.
+    fn selected_text(&mut self, selection: &Selection) -> String {
+        let first_line = self.scrollback.first_line();
+        let end = first_line + self.scrollback.len() + self.rows;
+        let lines = selection.lines();
+        let mut text = String::new();
+        let mut continues = true;
+        for number in lines.start.max(first_line)..lines.end.min(end) {
+            let line = self.line(number - first_line);
+            let columns = selection.columns(number).unwrap_or_default();
+            let part = slice_columns(&line.text, columns.clone());
+            match selection.kind {
+                SelectionKind::Block => {
+                    if number != lines.start.max(first_line) {
+                        text.push('\n');
+                    }
+                    text.push_str(part.trim_end_matches(' '));
+                }
+                SelectionKind::Linear => {
+                    if !continues {
+                        text.push('\n');
+                    }
+                    let to_end = columns.end == usize::MAX;
+                    if line.wrapped && to_end {
+                        text.push_str(part);
+                    } else {
+                        text.push_str(part.trim_end_matches(' '));
+                    }
+                    continues = line.wrapped && to_end;
+                }
+            }
+        }
+        text
+    }
+
+    /// Find `pattern` in the scrollback and on the screen, oldest first.
+    fn search(&mut self, pattern: &Regex) -> Vec<SearchMatch> {
+        let first_line = self.scrollback.first_line();
+        let mut matches = Vec::new();
+        for index in 0..self.scrollback.len() + self.rows {
+            // Rows are padded out to the full width; `$` should match where
+            // the text ends
+            let text = self.line(index).text;
+            let text = text.trim_end_matches(' ');
+            for found in pattern.find_iter(text).filter(|found| !found.is_empty()) {
+                matches.push(SearchMatch {
+                    line: first_line + index,
+                    columns: column_at(text, found.start())..column_at(text, found.end()),
+                });
+            }
+            // Keep the newest matches if there are too many
+            if matches.len() > MAX_SEARCH_MATCHES {
+                matches.drain(..matches.len() - MAX_SEARCH_MATCHES);
+            }
+        }
+        matches
+    }
+
+    /// Move the view `lines` further back into the scrollback, or forward
+    /// for negative counts. Returns how far it moved before reaching either
+    /// end.
+    fn scroll_view(&mut self, lines: isize) -> isize {
+        let offset = self.scroll_offset.saturating_add_signed(lines).min(self.scrollback.len());
+        let moved = offset as isize - self.scroll_offset as isize;
+        if offset != self.scroll_offset {
+            self.scroll_offset = offset;
+            self.dirty = true;
+        }
+        moved
+    }
+
+    /// Scroll so session line `line` is the top row, as far as the
+    /// scrollback reaches.
+    fn scroll_view_to(&mut self, line: usize) {
+        let history = self.scrollback.len();
+        let offset = history - line.saturating_sub(self.scrollback.first_line()).min(history);
+        if offset != self.scroll_offset {
+            self.scroll_offset = offset;
+            self.dirty = true;
+        }
+    }
+
+    /// Scroll back so session line `line` is in view, a third of the way
+    /// down. Lines on the live screen return the view to the bottom.
+    fn scroll_to_line(&mut self, line: usize) {
+        let history = self.scrollback.len();
+        let index = line.saturating_sub(self.scrollback.first_line());
+        let offset = if index >= history { 0 } else { (history - index + self.rows / 3).min(history) };
+        if offset != self.scroll_offset {
+            self.scroll_offset = offset;
+            self.dirty = true;
+        }
+    }
+
+    /// Runs of cells in the rows from line `top` sharing a value of
+    /// `attribute`, skipping cells where it is `None`. Scrollback keeps
+    /// only text, so lines scrolled back into view have none.
+    fn runs_from<T: PartialEq>(&self, top: usize, attribute: impl Fn(&CellStyle) -> Option<T>) -> Vec<(usize, Range<usize>, T)> {
+        let history = self.scrollback.len();
+        let mut runs = Vec::new();
+        for index in 0..self.rows {
+            let Some(row) = (top + index).checked_sub(history).filter(|&row| row < self.rows) else {
+                continue;
+            };
+            let cells = &self.cells[row];
+            let mut col = 0;
+            while col < cells.len() {
+                let Some(value) = attribute(&cells[col].style) else {
+                    col += 1;
+                    continue;
+                };
+                let start = col;
+                while col < cells.len() && attribute(&cells[col].style).as_ref() == Some(&value) {
+                    col += 1;
+                }
+                runs.push((index, start..col, value));
+            }
+        }
+        runs
+    }
+
+    /// Handle an OSC 133 shell integration mark: the prompt starts (`A`),
+    /// the command line starts (`B`), the command runs (`C`) and finishes
+    /// (`D`), with its exit status in `argument`. Each is recorded at the
+    /// cursor in the current prompt's block, and a prompt after a command
+    /// that ran and finished gets its timing.
+    fn shell_mark(&mut self, mark: u8, argument: Option<&[u8]>) {
+        let position = (self.scrollback.first_line() + self.scrollback.len() + self.cursor_y, self.cursor_x);
+        // Marks on the alternate screen don't point at lines that stay
+        let block = match mark {
+            _ if self.main_screen.is_some() => None,
+            b'A' => {
+                if self.command_blocks.len() >= MAX_COMMAND_BLOCKS {
+                    self.command_blocks.pop_front();
+                }
+                self.command_blocks.push_back(CommandBlock { prompt: position, ..Default::default() });
+                None
+            }
+            _ => self.command_blocks.back_mut(),
+        };
+        match mark {
+            b'A' => {
+                let Some(timing) = self.finished_command.take() else { return };
+                if self.command_timings.len() >= MAX_COMMAND_TIMINGS {
+                    self.command_timings.pop_front();
+                }
+                self.command_timings.push_back((position.0, timing));
+                self.dirty = true;
+            }
+            b'B' => {
+                if let Some(block) = block {
+                    block.input.get_or_insert(position);
+                }
+            }
+            b'C' => {
+                if let Some(block) = block {
+                    block.output.get_or_insert(position);
+                }
+                self.command_started = Some(Instant::now());
+            }
+            b'D' => {
+                if let Some(block) = block.filter(|block| block.end.is_none()) {
+                    block.end = Some(position);
+                    block.exit_status = argument.and_then(|status| std::str::from_utf8(status).ok()?.parse().ok());
+                }
+                if let Some(started) = self.command_started.take() {
+                    self.finished_command = Some(CommandTiming { duration: started.elapsed(), finished: SystemTime::now() });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Scroll the nearest prompt above or below the top of the view to the
+    /// top. Past the last prompt, the view returns to the live screen.
+    fn jump_to_prompt(&mut self, forward: bool) {
+        let first_line = self.scrollback.first_line();
+        let top = first_line + self.view_top();
+        let prompts = self.command_blocks.iter().map(|block| block.prompt.0).filter(|&line| line >= first_line);
+        let target = if forward {
+            prompts.filter(|&line| line > top).min()
+        } else {
+            prompts.filter(|&line| line < top).max()
+        };
+        let offset = match target {
+            Some(line) => self.scrollback.len().saturating_sub(line - first_line),
+            None if forward => 0,
+            None => return,
+        };
+        if offset != self.scroll_offset {
+            self.scroll_offset = offset;
+            self.dirty = true;
+        }
+    }
+
+    /// Lines printed by the last command that finished, from its `C` mark
+    /// to `D`, or to the next prompt if the shell sent no `D`.
+    fn last_command_output(&mut self) -> Option<String> {
+        let blocks = &self.command_blocks;
+        let (start, end) = blocks.iter().enumerate().rev().find_map(|(index, block)| {
+            let end = block.end.or_else(|| blocks.get(index + 1).map(|next| next.prompt))?;
+            Some((block.output?, end))
+        })?;
+        // Output not ended by a newline shares its last line with the mark
+        Some(self.logical_text(start.0..end.0 + (end.1 > 0) as usize))
+    }
+
+    /// Command lines typed at the latest prompts, newest first and without
+    /// repeats: the text from each `B` mark to its `C` mark.
+    fn recent_commands(&mut self, limit: usize) -> Vec<String> {
+        let first_line = self.scrollback.first_line();
+        let marks: Vec<((usize, usize), usize)> = self
+            .command_blocks
+            .iter()
+            .rev()
+            .filter_map(|block| Some((block.input?, block.output?.0)))
+            .filter(|(input, _)| input.0 >= first_line)
+            .collect();
+        let mut commands: Vec<String> = Vec::new();
+        for ((line, col), output_line) in marks {
+            let text = self.logical_text(line..output_line.max(line + 1));
+            // Leave out the prompt, which is measured in cells
+            let mut width = 0;
+            let command: String = text
+                .graphemes(true)
+                .skip_while(|cluster| {
+                    width += cluster_width(cluster);
+                    width <= col
+                })
                 .collect();
-            output.push_str(&line);
-            if row < self.rows - 1 {
-                output.push('\n');
+            let command = command.trim();
+            if !command.is_empty() && !commands.iter().any(|known| known == command) {
+                commands.push(command.to_string());
+                if commands.len() == limit {
+                    break;
+                }
             }
         }
-        
-        output
+        commands
+    }
+
+    /// The block of the last prompt at or above session line `line`.
+    fn command_block_at(&self, line: usize) -> Option<CommandBlock> {
+        self.command_blocks.iter().rev().find(|block| block.prompt.0 <= line).copied()
+    }
+
+    /// Prompt rows in view with the timing of the command before them.
+    fn visible_command_timings(&self) -> Vec<(usize, CommandTiming)> {
+        let top = self.scrollback.first_line() + self.view_top();
+        self.command_timings
+            .iter()
+            .filter(|(line, _)| (top..top + self.rows).contains(line))
+            .map(|&(line, timing)| (line - top, timing))
+            .collect()
+    }
+
+    /// Runs of cells in view with a color of their own, bold, dim or in
+    /// reverse video.
+    fn visible_foregrounds(&self) -> Vec<ForegroundSpan> {
+        self.foregrounds_from(self.view_top())
+    }
+
+    fn foregrounds_from(&self, top: usize) -> Vec<ForegroundSpan> {
+        let text = |style: &CellStyle| {
+            (style.foreground.is_some() || style.bold || style.dim || style.reverse)
+                .then_some((style.foreground, style.bold, style.dim, style.reverse))
+        };
+        self.runs_from(top, text)
+            .into_iter()
+            .map(|(row, columns, (color, bold, dim, reverse))| ForegroundSpan { row, columns, color, bold, dim, reverse })
+            .collect()
+    }
+
+    /// Runs of cells in view with a background other than the theme's.
+    fn visible_backgrounds(&self) -> Vec<BackgroundSpan> {
+        self.backgrounds_from(self.view_top())
+    }
+
+    fn backgrounds_from(&self, top: usize) -> Vec<BackgroundSpan> {
+        self.runs_from(top, |style| style.background)
+            .into_iter()
+            .map(|(row, columns, color)| BackgroundSpan { row, columns, color })
+            .collect()
+    }
+
+    /// Runs of cells in view that are underlined or struck through.
+    fn visible_decorations(&self) -> Vec<DecorationSpan> {
+        self.decorations_from(self.view_top())
+    }
+
+    fn decorations_from(&self, top: usize) -> Vec<DecorationSpan> {
+        let decoration = |style: &CellStyle| {
+            (style.underline.is_some() || style.strikethrough)
+                .then_some((style.underline, style.underline_color, style.strikethrough))
+        };
+        self.runs_from(top, decoration)
+            .into_iter()
+            .map(|(row, columns, (underline, underline_color, strikethrough))| DecorationSpan {
+                row,
+                columns,
+                underline,
+                underline_color,
+                strikethrough,
+            })
+            .collect()
+    }
+
+    /// The live screen, whatever the view is scrolled to.
+    fn tail(&self) -> Tail {
+        Tail {
+            rows: (0..self.rows).map(|row| self.row_text(row)).collect(),
+            foregrounds: self.foregrounds_from(self.scrollback.len()),
+            backgrounds: self.backgrounds_from(self.scrollback.len()),
+            decorations: self.decorations_from(self.scrollback.len()),
+        }
+    }
+
+    /// Links in the rows in view: OSC 8 hyperlinks on the live screen and
+    /// URLs found in the text of any row.
+    fn visible_links(&mut self, lines: &[String]) -> Vec<LinkSpan> {
+        let top = self.view_top();
+        let history = self.scrollback.len();
+        let mut links = Vec::new();
+
+        for (index, urls) in self.url_scanner.scan(lines).into_iter().enumerate() {
+            let explicit_start = links.len();
+            if let Some(row) = (top + index).checked_sub(history).filter(|&row| row < self.rows) {
+                let cells = &self.cells[row];
+                let mut col = 0;
+                while col < cells.len() {
+                    let Some(id) = cells[col].hyperlink else {
+                        col += 1;
+                        continue;
+                    };
+                    let start = col;
+                    while col < cells.len() && cells[col].hyperlink == Some(id) {
+                        col += 1;
+                    }
+                    if let Some(link) = self.hyperlinks.get(id) {
+                        links.push(LinkSpan {
+                            row: index,
+                            columns: start..col,
+                            uri: Arc::clone(&link.uri),
+                            hyperlink: Some(id),
+                        });
+                    }
+                }
+            }
+
+            // Text that is already an explicit link isn't detected again
+            for (columns, uri) in urls {
+                let overlaps = links[explicit_start..]
+                    .iter()
+                    .any(|link| link.columns.start < columns.end && columns.start < link.columns.end);
+                if !overlaps {
+                    links.push(LinkSpan { row: index, columns, uri, hyperlink: None });
+                }
+            }
+        }
+
+        links
     }
 }
 
 struct TerminalPerformer {
     grid: TerminalGrid,
     writer: Arc<Mutex<dyn Write + Send>>,  // Add writer for escape sequence responses
+    title: Arc<Mutex<Option<String>>>,
+    cwd: Arc<Mutex<Option<PathBuf>>>,
+    host: Arc<Mutex<Option<String>>>,
+    cursor_style: Arc<Mutex<CursorStyle>>,
+    modes: Arc<Mutex<TerminalModes>>,
+    clipboard: SharedClipboard,
+    osc52: Osc52Access,
+    private: bool,
+    theme: Arc<Mutex<Theme>>,
+    report_color_scheme: Arc<AtomicBool>,
+    bell: Arc<AtomicBool>,
+    unsupported: Arc<Mutex<Vec<String>>>,
+    sink: SnapshotSink,
+    /// Payload of an XTGETTCAP request (`DCS + q`) being received.
+    capability_query: Option<Vec<u8>>,
+    /// A request for the capability manifest (`DCS + c`) is being received.
+    manifest_query: bool,
 }
 
 impl TerminalPerformer {
-    fn new(rows: usize, cols: usize, writer: Arc<Mutex<dyn Write + Send>>) -> Self {
+    fn new(rows: usize, cols: usize, writer: Arc<Mutex<dyn Write + Send>>, terminal: &Terminal) -> Self {
         Self {
-            grid: TerminalGrid::new(rows, cols),
+            grid: TerminalGrid::new(
+                rows,
+                cols,
+                Scrollback::new(&terminal.config.scrollback, terminal.config.private),
+            ),
             writer,
+            title: Arc::clone(&terminal.title),
+            cwd: Arc::clone(&terminal.cwd),
+            host: Arc::clone(&terminal.host),
+            cursor_style: Arc::clone(&terminal.cursor_style),
+            modes: Arc::clone(&terminal.modes),
+            clipboard: Arc::clone(&terminal.clipboard),
+            osc52: terminal.config.clipboard.osc52,
+            private: terminal.config.private,
+            theme: Arc::clone(&terminal.theme),
+            report_color_scheme: Arc::clone(&terminal.report_color_scheme),
+            bell: Arc::clone(&terminal.bell),
+            unsupported: Arc::clone(&terminal.unsupported),
+            sink: SnapshotSink::new(terminal),
+            capability_query: None,
+            manifest_query: false,
+        }
+    }
+
+    /// Send the renderer whatever changed in view since the last publish.
+    fn publish(&mut self, log_output: bool) {
+        self.sink.publish(&mut self.grid, log_output);
+    }
+
+    /// SGR. Colors, intensity, reverse video, underline and strikethrough
+    /// are kept so far; other attributes are accepted and ignored.
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        let style = &mut self.grid.style;
+        let mut params = params.iter();
+        while let Some(param) = params.next() {
+            match param[0] {
+                0 => *style = CellStyle::default(),
+                1 => style.bold = true,
+                2 => style.dim = true,
+                // 4:0 to 4:5 pick the style; a plain 4 is a single line
+                4 => {
+                    style.underline = match param.get(1).copied().unwrap_or(1) {
+                        0 => None,
+                        2 => Some(UnderlineStyle::Double),
+                        3 => Some(UnderlineStyle::Curly),
+                        4 => Some(UnderlineStyle::Dotted),
+                        5 => Some(UnderlineStyle::Dashed),
+                        _ => Some(UnderlineStyle::Single),
+                    }
+                }
+                7 => style.reverse = true,
+                9 => style.strikethrough = true,
+                21 => style.underline = Some(UnderlineStyle::Double),
+                22 => (style.bold, style.dim) = (false, false),
+                24 => style.underline = None,
+                27 => style.reverse = false,
+                29 => style.strikethrough = false,
+                39 => style.foreground = None,
+                49 => style.background = None,
+                59 => style.underline_color = None,
+                code @ 30..=37 => style.foreground = Some(CellColor::Named(code as u8 - 30)),
+                code @ 90..=97 => style.foreground = Some(CellColor::Named(code as u8 - 90 + 8)),
+                code @ 40..=47 => style.background = Some(ansi_color(code as u8 - 40)),
+                code @ 100..=107 => style.background = Some(ansi_color(code as u8 - 100 + 8)),
+                // Extended colors, either as colon subparameters or as the
+                // parameters that follow
+                code @ (38 | 48 | 58) => {
+                    let color = if param.len() > 1 {
+                        extended_color(&param[1..])
+                    } else {
+                        let mode = params.next().map(|p| p[0]);
+                        let count = if mode == Some(2) { 3 } else { 1 };
+                        let values: Vec<u16> = mode.into_iter().chain(params.by_ref().take(count).map(|p| p[0])).collect();
+                        extended_color(&values)
+                    };
+                    match code {
+                        38 => style.foreground = color.map(CellColor::Rgb),
+                        48 => style.background = color,
+                        _ => style.underline_color = color,
+                    }
+                }
+                _ => (),
+            }
         }
+        self.grid.dirty = true;
     }
+
+    fn respond(&self, response: &[u8]) {
+        if let Ok(mut w) = self.writer.lock() {
+            let _ = w.write_all(response);
+            let _ = w.flush();
+        }
+    }
+
+    /// Whether a DEC private mode is set, for DECRQM. None for modes Nebula
+    /// doesn't recognize.
+    fn private_mode(&self, mode: u16) -> Option<bool> {
+        match mode {
+            1 => Some(self.modes.lock().unwrap().app_cursor),
+            5 => Some(self.modes.lock().unwrap().reverse_screen),
+            6 => Some(self.grid.origin_mode),
+            7 => Some(self.grid.auto_wrap),
+            25 => Some(!self.modes.lock().unwrap().cursor_hidden),
+            9 | 1000..=1003 => Some(tracking_mode(mode) == Some(self.modes.lock().unwrap().mouse_tracking)),
+            1005 | 1006 | 1015 => Some(encoding_mode(mode) == Some(self.modes.lock().unwrap().mouse_encoding)),
+            47 | 1047 | 1049 => Some(self.grid.main_screen.is_some()),
+            1004 => Some(self.modes.lock().unwrap().focus_reporting),
+            2004 => Some(self.modes.lock().unwrap().bracketed_paste),
+            2031 => Some(self.report_color_scheme.load(Ordering::SeqCst)),
+            _ => None,
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            1 => self.modes.lock().unwrap().app_cursor = enable,
+            5 => {
+                self.modes.lock().unwrap().reverse_screen = enable;
+                self.grid.dirty = true;
+            }
+            6 => {
+                self.grid.origin_mode = enable;
+                self.grid.goto(0, 0);
+            }
+            7 => {
+                self.grid.auto_wrap = enable;
+                if !enable {
+                    self.grid.wrap_pending = false;
+                }
+            }
+            25 => self.modes.lock().unwrap().cursor_hidden = !enable,
+            // Resetting the mode in effect turns tracking off, and the
+            // encoding back to X10
+            9 | 1000..=1003 => {
+                let mut modes = self.modes.lock().unwrap();
+                let tracking = tracking_mode(mode).unwrap_or_default();
+                if enable {
+                    modes.mouse_tracking = tracking;
+                } else if modes.mouse_tracking == tracking {
+                    modes.mouse_tracking = MouseTracking::Off;
+                }
+                modes.highlight = None;
+            }
+            1005 | 1006 | 1015 => {
+                let mut modes = self.modes.lock().unwrap();
+                let encoding = encoding_mode(mode).unwrap_or_default();
+                if enable {
+                    modes.mouse_encoding = encoding;
+                } else if modes.mouse_encoding == encoding {
+                    modes.mouse_encoding = MouseEncoding::X10;
+                }
+            }
+            // Alternate screen; 1049 also saves and restores the cursor,
+            // and 1047 clears the alternate screen on the way out
+            47 | 1047 | 1049 => {
+                if enable {
+                    if mode == 1049 {
+                        self.grid.save_cursor();
+                    }
+                    let cursor_hidden = self.modes.lock().unwrap().cursor_hidden;
+                    self.grid.enter_alternate_screen(cursor_hidden);
+                    if mode == 1049 {
+                        self.grid.clear_screen();
+                        self.grid.restore_cursor();
+                    }
+                } else {
+                    if mode == 1047 {
+                        self.grid.clear_screen();
+                    }
+                    if let Some(cursor_hidden) = self.grid.leave_alternate_screen() {
+                        self.modes.lock().unwrap().cursor_hidden = cursor_hidden;
+                    }
+                    if mode == 1049 {
+                        self.grid.restore_cursor();
+                    }
+                }
+                self.modes.lock().unwrap().alternate_screen = self.grid.main_screen.is_some();
+            }
+            1004 => self.modes.lock().unwrap().focus_reporting = enable,
+            2004 => self.modes.lock().unwrap().bracketed_paste = enable,
+            // ConPTY asks for win32-input-mode key events; plain VT input is
+            // what it gets without them
+            9001 => {}
+            // Report color scheme changes with CSI ? 997 ; Ps n
+            2031 => self.report_color_scheme.store(enable, Ordering::SeqCst),
+            _ => self.unsupported(format!(
+                "Unsupported private mode {} ({})",
+                mode,
+                if enable { "set" } else { "reset" }
+            )),
+        }
+    }
+
+    /// Log a sequence that was skipped and keep it for the frontend.
+    fn unsupported(&self, message: String) {
+        warn!("{}", message);
+        let mut unsupported = self.unsupported.lock().unwrap();
+        if unsupported.len() < MAX_UNSUPPORTED {
+            unsupported.push(message);
+        }
+    }
+
+    // OSC 52 ; <selection> ; <base64 data | ?>
+    fn handle_clipboard(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        let selection = params.get(1).copied().unwrap_or(b"c");
+        let Some(data) = params.get(2) else { return };
+        // 'p' and 's' name the primary selection; everything else the clipboard
+        let is_primary = matches!(selection.first(), Some(b'p' | b's'));
+
+        if *data == b"?" {
+            if !self.osc52.can_paste() {
+                info!("Ignoring OSC 52 clipboard query (disabled in config)");
+                return;
+            }
+            let mut clipboard = self.clipboard.lock().unwrap();
+            let text = if is_primary {
+                clipboard.get_primary()
+            } else {
+                clipboard.get_text()
+            };
+            drop(clipboard);
+            let text = text.unwrap_or_default();
+            let terminator: &[u8] = if bell_terminated { b"\x07" } else { b"\x1B\\" };
+            let mut response = b"\x1B]52;".to_vec();
+            response.extend_from_slice(selection);
+            response.push(b';');
+            response.extend_from_slice(BASE64.encode(text).as_bytes());
+            response.extend_from_slice(terminator);
+            self.respond(&response);
+        } else {
+            if !self.osc52.can_copy() {
+                info!("Ignoring OSC 52 clipboard write (disabled in config)");
+                return;
+            }
+            match BASE64.decode(data).map(String::from_utf8) {
+                Ok(Ok(text)) if is_primary => self.clipboard.lock().unwrap().set_primary(text),
+                Ok(Ok(text)) => self.clipboard.lock().unwrap().copy(text, self.private),
+                _ => warn!("Invalid OSC 52 clipboard payload"),
+            }
+        }
+    }
+}
+
+/// Mouse tracking turned on by private mode `mode`.
+fn tracking_mode(mode: u16) -> Option<MouseTracking> {
+    match mode {
+        9 => Some(MouseTracking::X10),
+        1000 => Some(MouseTracking::Normal),
+        1001 => Some(MouseTracking::Highlight),
+        1002 => Some(MouseTracking::ButtonEvent),
+        1003 => Some(MouseTracking::AnyEvent),
+        _ => None,
+    }
+}
+
+/// Mouse report encoding chosen by private mode `mode`.
+fn encoding_mode(mode: u16) -> Option<MouseEncoding> {
+    match mode {
+        1005 => Some(MouseEncoding::Utf8),
+        1006 => Some(MouseEncoding::Sgr),
+        1015 => Some(MouseEncoding::Urxvt),
+        _ => None,
+    }
+}
+
+/// Parse an OSC 7 `file://host/path` URI into a local path.
+fn parse_cwd_uri(uri: &[u8]) -> Option<PathBuf> {
+    let rest = uri.strip_prefix(b"file://")?;
+    let path_start = rest.iter().position(|&b| b == b'/')?;
+    let path = percent_decode(&rest[path_start..]);
+    Some(PathBuf::from(String::from_utf8(path).ok()?))
+}
+
+/// The host of an OSC 7 URI, if it names one.
+fn parse_cwd_host(uri: &[u8]) -> Option<String> {
+    let rest = uri.strip_prefix(b"file://")?;
+    let host = &rest[..rest.iter().position(|&b| b == b'/')?];
+    (!host.is_empty()).then(|| String::from_utf8_lossy(host).into_owned())
+}
+
+pub fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' && i + 2 < input.len() {
+            let hex = std::str::from_utf8(&input[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                output.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        output.push(input[i]);
+        i += 1;
+    }
+    output
 }
 
 impl Perform for TerminalPerformer {
@@ -226,11 +1999,14 @@ impl Perform for TerminalPerformer {
 
     fn execute(&mut self, byte: u8) {
         match byte {
+            0x07 => self.bell.store(true, Ordering::SeqCst), // Bell
             0x08 => self.grid.backspace(),    // Backspace
-            0x09 => self.grid.print_str("    "), // Tab (4 spaces)
-            0x0A => self.grid.newline(),      // Line feed
+            0x09 => self.grid.tab(),          // Horizontal tab
+            0x0A => self.grid.index(),        // Line feed
             0x0D => self.grid.carriage_return(), // Carriage return
             0x0C => self.grid.clear_screen(), // Form feed (clear screen)
+            0x0E => self.grid.active_charset = 1, // Shift out (invoke G1)
+            0x0F => self.grid.active_charset = 0, // Shift in (invoke G0)
             _ => (),
         }
     }
@@ -238,7 +2014,7 @@ impl Perform for TerminalPerformer {
     fn csi_dispatch(
         &mut self,
         params: &Params,
-        _intermediates: &[u8],
+        intermediates: &[u8],
         _ignore: bool,
         action: char,
     ) {
@@ -250,94 +2026,222 @@ impl Perform for TerminalPerformer {
 
         match action {
             // Cursor movement
-            'A' => self.grid.move_cursor_relative(0, -(get_param(0) as i32)), // Up
-            'B' => self.grid.move_cursor_relative(0, get_param(0) as i32),   // Down
-            'C' => self.grid.move_cursor_relative(get_param(0) as i32, 0),   // Right
-            'D' => self.grid.move_cursor_relative(-(get_param(0) as i32), 0), // Left
+            // A missing or zero count moves one cell; ConPTY relies on it
+            'A' => self.grid.move_cursor_relative(0, -(get_param(0).max(1) as i32)), // Up
+            'B' => self.grid.move_cursor_relative(0, get_param(0).max(1) as i32),   // Down
+            'C' => self.grid.move_cursor_relative(get_param(0).max(1) as i32, 0),   // Right
+            'D' => self.grid.move_cursor_relative(-(get_param(0).max(1) as i32), 0), // Left
+            'I' => { // Cursor forward tabulation (CHT)
+                for _ in 0..get_param(0).max(1) {
+                    self.grid.tab();
+                }
+            }
+            'Z' => { // Cursor backward tabulation (CBT)
+                for _ in 0..get_param(0).max(1) {
+                    self.grid.back_tab();
+                }
+            }
+            'G' | '`' => { // Cursor horizontal absolute (CHA/HPA)
+                let col = get_param(0).saturating_sub(1);
+                self.grid.move_cursor(col, self.grid.cursor_y);
+            }
+            'd' => { // Line position absolute (VPA)
+                let row = get_param(0).saturating_sub(1);
+                self.grid.goto(self.grid.cursor_x, row);
+            }
             'H' | 'f' => { // Cursor position
                 let row = get_param(0).saturating_sub(1);
                 let col = get_param(1).saturating_sub(1);
-                self.grid.move_cursor(col, row);
+                self.grid.goto(col, row);
             },
             
+            // Selective erase (DECSED/DECSEL) skips protected cells
+            'J' if intermediates == b"?" => {
+                let (x, y) = (self.grid.cursor_x, self.grid.cursor_y);
+                let (rows, cols) = (self.grid.rows, self.grid.cols);
+                match get_param(0) {
+                    0 => {
+                        self.grid.selective_erase(y..y + 1, x..cols);
+                        self.grid.selective_erase(y + 1..rows, 0..cols);
+                    }
+                    1 => {
+                        self.grid.selective_erase(0..y, 0..cols);
+                        self.grid.selective_erase(y..y + 1, 0..x + 1);
+                    }
+                    2 => self.grid.selective_erase(0..rows, 0..cols),
+                    _ => (),
+                }
+            }
+            'K' if intermediates == b"?" => {
+                let (x, y) = (self.grid.cursor_x, self.grid.cursor_y);
+                let cols = match get_param(0) {
+                    0 => x..self.grid.cols,
+                    1 => 0..x + 1,
+                    2 => 0..self.grid.cols,
+                    _ => return,
+                };
+                self.grid.selective_erase(y..y + 1, cols);
+            }
+            // DECSERA: selective erase of a rectangle, 1-based and inclusive
+            '{' if intermediates == b"$" => {
+                // Missing or zero edges default to the whole screen
+                let edge = |index: usize, default: usize| match params.iter().nth(index).and_then(|p| p.first().copied()) {
+                    None | Some(0) => default,
+                    Some(n) => n as usize,
+                };
+                let (top, left) = (edge(0, 1) - 1, edge(1, 1) - 1);
+                let (bottom, right) = (edge(2, self.grid.rows), edge(3, self.grid.cols));
+                self.grid.selective_erase(top..bottom, left..right);
+            }
+
             // Screen clearing
             'J' => match get_param(0) {
                 0 => { // Clear from cursor to end of screen
                     self.grid.clear_line(self.grid.cursor_x);
                     for y in self.grid.cursor_y + 1..self.grid.rows {
-                        for x in 0..self.grid.cols {
-                            self.grid.cells[y][x] = TerminalCell::default();
-                        }
+                        self.grid.clear_row(y);
                     }
                 },
                 1 => { // Clear from beginning to cursor
                     for y in 0..self.grid.cursor_y {
-                        for x in 0..self.grid.cols {
-                            self.grid.cells[y][x] = TerminalCell::default();
-                        }
+                        self.grid.clear_row(y);
                     }
-                    self.grid.clear_line(0);
+                    self.grid.clear_line_start();
                 },
                 2 => self.grid.clear_screen(), // Clear entire screen
+                3 => self.grid.clear_scrollback(), // Clear scrollback (xterm)
                 _ => (),
             },
             'K' => match get_param(0) {
                 0 => self.grid.clear_line(self.grid.cursor_x), // Clear to end of line
-                1 => self.grid.clear_line(0), // Clear from beginning of line
-                2 => { // Clear entire line
-                    for x in 0..self.grid.cols {
-                        self.grid.cells[self.grid.cursor_y][x] = TerminalCell::default();
-                    }
-                },
+                1 => self.grid.clear_line_start(), // Clear from beginning of line
+                2 => self.grid.clear_row(self.grid.cursor_y), // Clear entire line
                 _ => (),
             },
             
             // Scrolling
-            'S' => { // Scroll up
+            'S' => { // Scroll up (SU)
                 for _ in 0..get_param(0) {
                     self.grid.scroll_up();
                 }
             },
-            'T' => { // Scroll down
+            // Highlight mouse tracking: the program answers a press with
+            // func ; startx ; starty ; firstrow ; lastrow
+            'T' if intermediates.is_empty() && params.len() >= 5 => {
+                let mut modes = self.modes.lock().unwrap();
+                let value = |index: usize| get_param(index).saturating_sub(1);
+                modes.highlight = (modes.mouse_tracking == MouseTracking::Highlight && get_param(0) != 0).then(|| Highlight {
+                    start: (value(1), value(2)),
+                    first_row: value(3),
+                    last_row: value(4),
+                });
+            }
+            'T' => { // Scroll down (SD)
                 for _ in 0..get_param(0) {
                     self.grid.scroll_down();
                 }
             },
             
             // Character deletion
-            'P' => { // Delete character
-                let row = self.grid.cursor_y;
-                let start = self.grid.cursor_x;
-                let count = get_param(0);
-                let end = (start + count).min(self.grid.cols);
-                
-                // Shift characters left
-                for x in start..(self.grid.cols - count) {
-                    if x + count < self.grid.cols {
-                        self.grid.cells[row][x] = self.grid.cells[row][x + count].clone();
-                    }
-                }
-                
-                // Clear remaining characters
-                for x in (self.grid.cols - count)..self.grid.cols {
-                    self.grid.cells[row][x] = TerminalCell::default();
-                }
-            },
+            'P' => self.grid.delete_chars(get_param(0).max(1)), // Delete characters
+            '@' => self.grid.insert_chars(get_param(0).max(1)), // Insert characters
+            'X' => self.grid.erase_chars(get_param(0).max(1)),  // Erase characters
+
+            // Line insertion and deletion
+            'L' => self.grid.insert_lines(get_param(0).max(1)), // Insert lines
+            'M' => self.grid.delete_lines(get_param(0).max(1)), // Delete lines
+
+            // Set the scroll region (DECSTBM); missing or zero margins mean
+            // the edges of the screen
+            'r' if intermediates.is_empty() => {
+                let margin = |index: usize, default: usize| match params.iter().nth(index).and_then(|p| p.first().copied()) {
+                    None | Some(0) => default,
+                    Some(n) => n as usize,
+                };
+                let (top, bottom) = (margin(0, 1), margin(1, self.grid.rows));
+                self.grid.set_scroll_region(top - 1, bottom - 1);
+            }
             
             // Handle Device Status Report (DSR)
-            'n' => {
-                if get_param(0) == 6 {
-                    // Respond with cursor position report
-                    let response = format!(
-                        "\x1B[{};{}R",
-                        self.grid.cursor_y + 1,
-                        self.grid.cursor_x + 1
-                    );
-                    if let Ok(mut w) = self.writer.lock() {
-                        let _ = w.write_all(response.as_bytes());
-                        let _ = w.flush();
-                        println!("Responded to DSR: {}", response);
-                    }
+            // Color scheme query: 1 for dark, 2 for light
+            'n' if intermediates == b"?" && get_param(0) == 996 => {
+                let theme = *self.theme.lock().unwrap();
+                self.respond(color_scheme_report(&theme).as_bytes());
+            }
+            'n' if get_param(0) == 6 => {
+                // Respond with cursor position report, from the top of the
+                // scroll region in origin mode
+                let top = if self.grid.origin_mode { self.grid.scroll_top } else { 0 };
+                let response = format!(
+                    "\x1B[{};{}R",
+                    self.grid.cursor_y - top + 1,
+                    self.grid.cursor_x + 1
+                );
+                self.respond(response.as_bytes());
+                debug!("Responded to DSR: {}", response);
+            }
+
+            // Primary device attributes (DA1): a VT220 with ANSI color
+            'c' if intermediates.is_empty() && get_param(0) <= 1 => self.respond(b"\x1B[?62;22c"),
+            // Secondary device attributes (DA2): terminal type and version
+            'c' if intermediates == b">" && get_param(0) <= 1 => {
+                let response = format!("\x1B[>1;{};0c", version_number());
+                self.respond(response.as_bytes());
+            }
+            // Terminal name and version (XTVERSION)
+            'q' if intermediates == b">" && get_param(0) <= 1 => {
+                let response = format!("\x1BP>|Nebula {}\x1B\\", env!("CARGO_PKG_VERSION"));
+                self.respond(response.as_bytes());
+            }
+            // Mode queries (DECRQM): 1 set, 2 reset, 0 not recognized. No
+            // ANSI modes are supported
+            'p' if intermediates == b"?$" => {
+                let mode = get_param(0) as u16;
+                let state = match self.private_mode(mode) {
+                    Some(true) => 1,
+                    Some(false) => 2,
+                    None => 0,
+                };
+                self.respond(format!("\x1B[?{};{}$y", mode, state).as_bytes());
+            }
+            'p' if intermediates == b"$" => {
+                self.respond(format!("\x1B[{};0$y", get_param(0)).as_bytes());
+            }
+
+            // Save/restore cursor (SCOSC/SCORC)
+            's' if intermediates.is_empty() => self.grid.save_cursor(),
+            'u' if intermediates.is_empty() => self.grid.restore_cursor(),
+
+            // Private mode set/reset (DECSET/DECRST)
+            'h' | 'l' if intermediates == b"?" => {
+                let enable = action == 'h';
+                for param in params.iter() {
+                    self.set_private_mode(param[0], enable);
+                }
+            }
+
+            // Tab clear (TBC)
+            'g' => match get_param(0) {
+                0 => self.grid.clear_tab_stops(false),
+                3 => self.grid.clear_tab_stops(true),
+                _ => (),
+            },
+
+            // Select graphic rendition (SGR)
+            'm' if intermediates.is_empty() => self.select_graphic_rendition(params),
+
+            // Select character protection attribute (DECSCA)
+            'q' if intermediates == b"\"" => match get_param(0) {
+                0 | 2 => self.grid.protected = false,
+                1 => self.grid.protected = true,
+                _ => (),
+            },
+
+            // Set cursor style (DECSCUSR)
+            'q' if intermediates == b" " => {
+                if let Some(style) = CursorStyle::from_decscusr(get_param(0)) {
+                    *self.cursor_style.lock().unwrap() = style;
+                    self.grid.dirty = true;
                 }
             }
             
@@ -346,111 +2250,349 @@ impl Perform for TerminalPerformer {
     }
 
     // Required trait methods
-    fn hook(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _action: char) {}
-    fn put(&mut self, _byte: u8) {}
-    fn unhook(&mut self) {}
-    fn osc_dispatch(&mut self, _params: &[&[u8]], _bell_terminated: bool) {}
-    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, _byte: u8) {}
+    fn hook(&mut self, _params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
+        // XTGETTCAP: DCS + q names ST, with names hex-encoded
+        if intermediates == b"+" && action == 'q' {
+            self.capability_query = Some(Vec::new());
+        }
+        // Nebula's capability manifest: DCS + c ST
+        self.manifest_query = intermediates == b"+" && action == 'c';
+    }
+    fn put(&mut self, byte: u8) {
+        if let Some(query) = self.capability_query.as_mut().filter(|query| query.len() < MAX_CAPABILITY_QUERY) {
+            query.push(byte);
+        }
+    }
+    fn unhook(&mut self) {
+        if std::mem::take(&mut self.manifest_query) {
+            let response = format!("\x1BP1+c{}\x1B\\", capabilities::json());
+            self.respond(response.as_bytes());
+        }
+        let Some(query) = self.capability_query.take() else { return };
+        let query = String::from_utf8_lossy(&query);
+        let mut response = String::new();
+        // Each name gets its own reply, 1 with its value or 0 when unknown
+        for hex_name in query.split(';') {
+            let found = hex_decode(hex_name).and_then(|name| terminfo::lookup(&name));
+            match found {
+                Some(Capability::Flag) => response.push_str(&format!("\x1BP1+r{}\x1B\\", hex_name)),
+                Some(Capability::Value(value)) => {
+                    response.push_str(&format!("\x1BP1+r{}={}\x1B\\", hex_name, hex_encode(value.as_bytes())))
+                }
+                None => response.push_str(&format!("\x1BP0+r{}\x1B\\", hex_name)),
+            }
+        }
+        self.respond(response.as_bytes());
+    }
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        let Some(&command) = params.first() else { return };
+
+        match command {
+            // Window title (0 also sets the icon name, which we treat the same)
+            b"0" | b"1" | b"2" => {
+                let title = params[1..].join(&b';');
+                *self.title.lock().unwrap() = Some(String::from_utf8_lossy(&title).into_owned());
+            }
+            // Current working directory
+            b"7" => {
+                if let Some(cwd) = params.get(1).and_then(|uri| parse_cwd_uri(uri)) {
+                    if !self.private {
+                        debug!("Working directory: {}", cwd.display());
+                    }
+                    *self.cwd.lock().unwrap() = Some(cwd);
+                    *self.host.lock().unwrap() = params.get(1).and_then(|uri| parse_cwd_host(uri));
+                }
+            }
+            // Foreground and background color queries
+            b"10" | b"11" if params.get(1) == Some(&&b"?"[..]) => {
+                let theme = *self.theme.lock().unwrap();
+                let color = if command == b"10" { theme.foreground } else { theme.background };
+                let terminator = if bell_terminated { "\x07" } else { "\x1B\\" };
+                let response = format!(
+                    "\x1B]{};rgb:{:02x}{:02x}/{:02x}{:02x}/{:02x}{:02x}{}",
+                    String::from_utf8_lossy(command),
+                    color.0, color.0, color.1, color.1, color.2, color.2,
+                    terminator
+                );
+                self.respond(response.as_bytes());
+            }
+            // Hyperlink: OSC 8 ; params ; URI, with an empty URI ending it
+            b"8" if params.len() >= 3 => {
+                let options = String::from_utf8_lossy(params[1]);
+                let id = options
+                    .split(':')
+                    .find_map(|option| option.strip_prefix("id="))
+                    .filter(|id| !id.is_empty());
+                let uri = params[2..].join(&b';');
+                self.grid.set_hyperlink(&String::from_utf8_lossy(&uri), id);
+            }
+            // Clipboard
+            b"52" => self.handle_clipboard(params, bell_terminated),
+            // Shell integration marks
+            b"133" => {
+                if let Some(&mark) = params.get(1).and_then(|mark| mark.first()) {
+                    self.grid.shell_mark(mark, params.get(2).copied());
+                }
+            }
+            _ => self.unsupported(format!("Unsupported OSC {}", String::from_utf8_lossy(command))),
+        }
+    }
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            ([], b'H') => self.grid.set_tab_stop(), // Horizontal tab set (HTS)
+            ([], b'D') => self.grid.index(),        // IND
+            ([], b'E') => self.grid.newline(),      // NEL
+            ([], b'M') => self.grid.reverse_index(), // RI
+            ([b'#'], b'8') => self.grid.alignment_pattern(), // DECALN
+            ([], b'7') => self.grid.save_cursor(),  // DECSC
+            ([], b'8') => self.grid.restore_cursor(), // DECRC
+            ([], b'=') => self.modes.lock().unwrap().app_keypad = true, // DECKPAM
+            ([], b'>') => self.modes.lock().unwrap().app_keypad = false, // DECKPNM
+            // Designate G0/G1 character set
+            ([b'('], designator) | ([b')'], designator) => {
+                if let Some(charset) = Charset::from_designator(designator) {
+                    let slot = if intermediates[0] == b'(' { 0 } else { 1 };
+                    self.grid.charsets[slot] = charset;
+                }
+            }
+            _ => (),
+        }
+    }
 }
 
-pub struct Terminal {
-    pub font_system: Arc<Mutex<FontSystem>>,
-    pub buffer: Arc<Mutex<Buffer>>,
-    pub text_content: Arc<Mutex<String>>,
-    pub cursor_x: Arc<Mutex<f32>>,
-    pub cursor_y: Arc<Mutex<f32>>,
-    pub dirty: Arc<Mutex<bool>>,
-    pub cols: Arc<Mutex<usize>>,
-    pub rows: Arc<Mutex<usize>>,
-    pub swash_cache: Arc<Mutex<SwashCache>>,
+/// `text` as uppercase hex digits, as XTGETTCAP replies use.
+fn hex_encode(text: &[u8]) -> String {
+    text.iter().map(|byte| format!("{:02X}", byte)).collect()
 }
 
-impl Terminal {
-    pub fn new() -> Self {
-        let mut font_system = FontSystem::new();
-        // Load system fonts for proper rendering
-        font_system.db_mut().load_system_fonts();
-        
-        let metrics = Metrics::new(FONT_SIZE, LINE_HEIGHT);
-        let mut buffer = Buffer::new(&mut font_system, metrics);
-        
-        let initial_text = "Nebula Terminal\n$ ";
-        buffer.set_text(
-            &mut font_system, 
-            initial_text, 
-            &Attrs::new(), 
-            Shaping::Advanced
-        );
-        
-        let buffer = Arc::new(Mutex::new(buffer));
-        {
-            let mut buffer_lock = buffer.lock().unwrap();
-            buffer_lock.set_size(
-                &mut font_system,
-                Some(1600.0),
-                Some(900.0),
-            );
-        }
+fn hex_decode(hex: &str) -> Option<String> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
 
-        let text_content = Arc::new(Mutex::new(String::from(initial_text)));
-        // After "$ " (2 characters * FONT_SIZE) at line 1
-        let cursor_x = Arc::new(Mutex::new(2.0 * FONT_SIZE));
-        let cursor_y = Arc::new(Mutex::new(1.0 * LINE_HEIGHT));
-        let dirty = Arc::new(Mutex::new(true));
-        let cols = Arc::new(Mutex::new(DEFAULT_COLS as usize));
-        let rows = Arc::new(Mutex::new(DEFAULT_ROWS as usize));
-        let swash_cache = Arc::new(Mutex::new(SwashCache::new()));
-        
+/// The package version as one number for DA2, such as 10203 for 1.2.3.
+fn version_number() -> u32 {
+    env!("CARGO_PKG_VERSION")
+        .split('.')
+        .take(3)
+        .map(|part| part.parse::<u32>().unwrap_or(0))
+        .fold(0, |number, part| number * 100 + part)
+}
+
+/// `CSI ? 997 ; Ps n` telling the application whether the theme is dark (1)
+/// or light (2).
+pub fn color_scheme_report(theme: &Theme) -> String {
+    format!("\x1B[?997;{}n", if theme.is_dark() { 1 } else { 2 })
+}
+
+/// What changed in view since the previous snapshot, sent from whichever
+/// thread changed the grid to the UI thread.
+#[derive(Debug, Clone)]
+pub struct Damage {
+    pub generation: u64,
+    /// Rows in view. The receiver drops or adds rows to match.
+    pub row_count: usize,
+    /// Rows whose text changed, by index from the top of the view.
+    pub rows: Vec<(usize, String)>,
+    pub links: Vec<LinkSpan>,
+    /// Cursor position in pixels.
+    pub cursor: (f32, f32),
+    /// Session line number of the first row in view, counting scrollback.
+    pub view_top: usize,
+    /// Session lines kept in the scrollback.
+    pub scrollback: Range<usize>,
+    /// Cells in view with their own text color. Sent whole each time, as
+    /// it is usually short.
+    pub foregrounds: Vec<ForegroundSpan>,
+    /// Cells in view with their own background, also sent whole.
+    pub backgrounds: Vec<BackgroundSpan>,
+    /// Cells in view with underlines or strikethrough, also sent whole.
+    pub decorations: Vec<DecorationSpan>,
+    /// Prompt rows in view with the timing of the command before them.
+    pub command_timings: Vec<(usize, CommandTiming)>,
+    /// The live screen while the view is scrolled back, if a follow window
+    /// asked for it.
+    pub tail: Option<Tail>,
+}
+
+/// Rows of the live screen and their styling, sent whole.
+#[derive(Debug, Clone)]
+pub struct Tail {
+    pub rows: Vec<String>,
+    pub foregrounds: Vec<ForegroundSpan>,
+    pub backgrounds: Vec<BackgroundSpan>,
+    pub decorations: Vec<DecorationSpan>,
+}
+
+/// Where a prompt, the command line typed at it and the command's output
+/// are, from OSC 133 marks. Positions are session line numbers and
+/// columns; later marks are missing until the shell sends them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandBlock {
+    /// The prompt starts (`A`).
+    pub prompt: (usize, usize),
+    /// The prompt ends and the command line starts (`B`).
+    pub input: Option<(usize, usize)>,
+    /// The command ran and its output starts (`C`).
+    pub output: Option<(usize, usize)>,
+    /// The command finished and its output ends (`D`).
+    pub end: Option<(usize, usize)>,
+    pub exit_status: Option<i32>,
+}
+
+impl CommandBlock {
+    fn marks_mut(&mut self) -> impl Iterator<Item = &mut (usize, usize)> {
+        std::iter::once(&mut self.prompt)
+            .chain(self.input.as_mut())
+            .chain(self.output.as_mut())
+            .chain(self.end.as_mut())
+    }
+}
+
+/// How long a command ran and when it finished, from shell integration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandTiming {
+    pub duration: Duration,
+    pub finished: SystemTime,
+}
+
+/// A run of cells on one row in view with the same lines drawn over them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecorationSpan {
+    pub row: usize,
+    pub columns: Range<usize>,
+    pub underline: Option<UnderlineStyle>,
+    pub underline_color: Option<Rgb>,
+    pub strikethrough: bool,
+}
+
+/// A run of cells on one row in view printed in a color of their own,
+/// bold, dim or in reverse video.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForegroundSpan {
+    pub row: usize,
+    pub columns: Range<usize>,
+    /// `None` for the theme's.
+    pub color: Option<CellColor>,
+    pub bold: bool,
+    pub dim: bool,
+    pub reverse: bool,
+}
+
+/// A run of cells on one row in view sharing a background color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundSpan {
+    pub row: usize,
+    pub columns: Range<usize>,
+    pub color: Rgb,
+}
+
+/// Sends damage for the grid it belongs to. Lives next to the grid, so it is
+/// only used with the grid locked.
+struct SnapshotSink {
+    sender: Sender<Damage>,
+    generation: Arc<AtomicU64>,
+    /// Row texts as the receiver has them after the last send.
+    sent: Vec<String>,
+    follow_tail: Arc<AtomicBool>,
+}
+
+impl SnapshotSink {
+    fn new(terminal: &Terminal) -> Self {
         Self {
-            font_system: Arc::new(Mutex::new(font_system)),
-            buffer,
-            text_content,
-            cursor_x,
-            cursor_y,
-            dirty,
-            cols,
-            rows,
-            swash_cache
+            sender: terminal.damage_sender.clone(),
+            generation: Arc::clone(&terminal.snapshot_generation),
+            sent: PLACEHOLDER_TEXT.split('\n').map(String::from).collect(),
+            follow_tail: Arc::clone(&terminal.follow_tail),
         }
     }
 
-    pub fn spawn_pty(&self) -> Result<(Arc<Mutex<dyn Write + Send>>, Arc<Mutex<Box<dyn Child + Send>>>)> {
-    let pty_system = NativePtySystem::default();
-    let pair = pty_system.openpty(PtySize {
-        rows: DEFAULT_ROWS,
-        cols: DEFAULT_COLS,
-        pixel_width: 0,
-        pixel_height: 0,
-    })?;
-    
-    println!("PTY created successfully");
-
-    // For Unix systems, set raw mode
-    #[cfg(unix)]
-    {
-        use portable_pty::MasterPty;
-        if let Err(e) = pair.master.set_raw_mode() {
-            eprintln!("Failed to set PTY raw mode: {}", e);
-        } else {
-            println!("PTY raw mode enabled");
+    /// Send the rows that differ from what was sent last, along with the
+    /// links and cursor. The grid stays locked only for the diff; nothing
+    /// the renderer holds is touched.
+    fn publish(&mut self, grid: &mut TerminalGrid, log_output: bool) {
+        let lines: Vec<String> = grid.visible_lines().into_iter().map(|line| line.text).collect();
+        let links = grid.visible_links(&lines);
+        if log_output {
+            trace!("Grid content:\n{}", lines.join("\n"));
+        }
+
+        self.sent.resize(lines.len(), String::new());
+        let rows: Vec<(usize, String)> = lines
+            .into_iter()
+            .enumerate()
+            .filter(|(index, line)| self.sent[*index] != *line)
+            .collect();
+        for (index, line) in &rows {
+            self.sent[*index].clone_from(line);
         }
+
+        let generation = self.generation.fetch_add(1, Ordering::Release) + 1;
+        let damage = Damage {
+            generation,
+            row_count: self.sent.len(),
+            rows,
+            links,
+            cursor: (grid.cursor_x as f32 * CELL_WIDTH, grid.cursor_y as f32 * LINE_HEIGHT),
+            view_top: grid.scrollback.first_line() + grid.view_top(),
+            scrollback: grid.scrollback.first_line()..grid.scrollback.first_line() + grid.scrollback.len(),
+            foregrounds: grid.visible_foregrounds(),
+            backgrounds: grid.visible_backgrounds(),
+            decorations: grid.visible_decorations(),
+            command_timings: grid.visible_command_timings(),
+            tail: (self.follow_tail.load(Ordering::Relaxed) && grid.scroll_offset > 0).then(|| grid.tail()),
+        };
+        // The receiver is gone once the tab is closing
+        let _ = self.sender.send(damage);
+        grid.dirty = false;
     }
+}
 
-    // Create a command with proper shell initialization
-    let mut cmd = if cfg!(target_os = "windows") {
-        let mut cmd = CommandBuilder::new("cmd.exe");
-        cmd.arg("/K");
-        cmd.env("PROMPT", "$G$S"); // Simplify prompt
-        cmd
-    } else {
-        let mut cmd = CommandBuilder::new("bash");
-        // Use --login for proper initialization
-        cmd.args(&["--login", "-i"]);
-        cmd
+/// Color from the parameters after SGR 38, 48 or 58: `5;index` or
+/// `2;r;g;b`, the latter with an optional color space id before the
+/// channels when given as subparameters.
+fn extended_color(values: &[u16]) -> Option<Rgb> {
+    let channel = |value: &u16| u8::try_from(*value).ok();
+    match values {
+        [5, index, ..] => Some(ansi_color(u8::try_from(*index).ok()?)),
+        [2, _, r, g, b, ..] | [2, r, g, b] => Some(Rgb(channel(r)?, channel(g)?, channel(b)?)),
+        _ => None,
+    }
+}
+
+/// Command line for the program a tab runs, started in `cwd` if given and
+/// otherwise in the configured working directory.
+fn shell_command(shell: &ShellConfig, cwd: Option<PathBuf>) -> CommandBuilder {
+    let mut cmd = match &shell.program {
+        Some(program) => {
+            let mut cmd = CommandBuilder::new(program);
+            cmd.args(&shell.args);
+            cmd
+        }
+        // PowerShell 7 if installed, then Windows PowerShell, then cmd.exe
+        None if cfg!(target_os = "windows") => match ["pwsh.exe", "powershell.exe"].into_iter().find_map(find_in_path) {
+            Some(powershell) => {
+                let mut cmd = CommandBuilder::new(powershell);
+                cmd.arg("-NoLogo");
+                cmd
+            }
+            None => CommandBuilder::new("cmd.exe"),
+        },
+        None => {
+            let mut cmd = CommandBuilder::new("bash");
+            // Use --login for proper initialization
+            cmd.args(["--login", "-i"]);
+            cmd
+        }
     };
     
     // Set essential environment variables
-    //cmd.env_clear();
     if cfg!(target_os = "windows") {
         cmd.env("SystemRoot", std::env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string()));
         cmd.env("PATH", std::env::var("PATH").unwrap_or_default());
@@ -459,69 +2601,398 @@ impl Terminal {
     } else {
         cmd.env("HOME", std::env::var("HOME").unwrap_or_default());
         cmd.env("PATH", std::env::var("PATH").unwrap_or_default());
-        cmd.env("TERM", "xterm-256color");
-        cmd.env("COLORTERM", "truecolor");
+        cmd.env("TERM", terminfo::resolve(&shell.term));
+        if shell.colorterm {
+            cmd.env("COLORTERM", "truecolor");
+        }
         cmd.env("SHELL", std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string()));
         cmd.env("USER", std::env::var("USER").unwrap_or_default());
-        cmd.env("LANG", "en_US.UTF-8");
+        cmd.env("LANG", shell.encoding.locale());
+        // A legacy encoding has to win over any UTF-8 locale inherited from
+        // the environment, or the program writes what isn't decoded
+        if shell.encoding != Encoding::Utf8 {
+            cmd.env("LC_CTYPE", shell.encoding.locale());
+            cmd.env_remove("LC_ALL");
+        }
     };
+    for (key, value) in &shell.env {
+        cmd.env(key, value);
+    }
+
+    let configured = shell.working_directory.as_deref().map(expand_home);
+    if let Some(cwd) = cwd.or(configured) {
+        cmd.cwd(cwd);
+    }
+    cmd
+}
+
+/// Full path of a program found on PATH.
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Expand a leading `~` to the home directory.
+fn expand_home(path: &std::path::Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub type PtyWriter = Arc<Mutex<dyn Write + Send>>;
+pub type PtyChild = Arc<Mutex<Box<dyn Child + Send>>>;
+pub type PtyMaster = Arc<Mutex<Option<Box<dyn MasterPty + Send>>>>;
+
+pub struct Terminal {
+    /// Changes to what is in view, sent by the reader thread and by UI
+    /// calls that scroll the grid.
+    pub damage: Receiver<Damage>,
+    damage_sender: Sender<Damage>,
+    /// Set by input handling to ask for a redraw. New output arrives as
+    /// `damage` instead.
+    pub dirty: Arc<Mutex<bool>>,
+    /// Number of the newest snapshot sent, counting from 0 for the
+    /// placeholder text before the shell starts.
+    pub snapshot_generation: Arc<AtomicU64>,
+    pub cols: Arc<Mutex<usize>>,
+    pub rows: Arc<Mutex<usize>>,
+    pub master: PtyMaster,
+    pub title: Arc<Mutex<Option<String>>>,
+    pub cwd: Arc<Mutex<Option<PathBuf>>>,
+    /// Host named in the last OSC 7 working directory, for session rules.
+    pub host: Arc<Mutex<Option<String>>>,
+    pub cursor_style: Arc<Mutex<CursorStyle>>,
+    pub modes: Arc<Mutex<TerminalModes>>,
+    pub clipboard: SharedClipboard,
+    pub config: Config,
+    /// Set when the app is going away so the reader stops respawning the shell.
+    pub shutdown: Arc<AtomicBool>,
+    /// Set by the reader when the program exited and isn't restarted.
+    pub exited: Arc<AtomicBool>,
+    /// Colors in use, shared with the parser so it can answer OSC 10/11.
+    pub theme: Arc<Mutex<Theme>>,
+    /// The application asked to hear about theme changes (mode 2031).
+    pub report_color_scheme: Arc<AtomicBool>,
+    /// Set by the parser on BEL until the UI rings the bell.
+    pub bell: Arc<AtomicBool>,
+    /// Sequences the parser skipped, for `take_unsupported`.
+    unsupported: Arc<Mutex<Vec<String>>>,
+    /// A follow window shows this tab, so the live screen is sent along
+    /// with the view while it is scrolled back.
+    pub follow_tail: Arc<AtomicBool>,
+    /// When the newest PTY read returned. Updated after its snapshot is
+    /// published, so the next poll is guaranteed to see that output.
+    pub last_output: Arc<Mutex<Option<Instant>>>,
+    /// Parser state, shared with the reader thread once the PTY is up so
+    /// the UI can scroll and search the grid.
+    performer: Mutex<Option<Arc<Mutex<TerminalPerformer>>>>,
+    /// The PTY reader thread, joined on shutdown.
+    pub reader_thread: Mutex<Option<JoinHandle<()>>>,
+    /// Called after the reader handled output. Set before the PTY is
+    /// spawned; without it the UI has to poll.
+    pub waker: Option<Waker>,
+}
+
+impl Terminal {
+    pub fn new(
+        config: Config,
+        clipboard: SharedClipboard,
+    ) -> Self {
+        let (damage_sender, damage) = mpsc::channel();
+        let dirty = Arc::new(Mutex::new(true));
+        let cols = Arc::new(Mutex::new(config.geometry.columns as usize));
+        let rows = Arc::new(Mutex::new(config.geometry.rows as usize));
+        let theme = config.theme.resolve(None);
+        
+        Self {
+            damage,
+            damage_sender,
+            dirty,
+            snapshot_generation: Arc::new(AtomicU64::new(0)),
+            cols,
+            rows,
+            master: Arc::new(Mutex::new(None)),
+            title: Arc::new(Mutex::new(None)),
+            cwd: Arc::new(Mutex::new(None)),
+            host: Arc::new(Mutex::new(None)),
+            cursor_style: Arc::new(Mutex::new(CursorStyle::default())),
+            modes: Arc::new(Mutex::new(TerminalModes::default())),
+            clipboard,
+            config,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            exited: Arc::new(AtomicBool::new(false)),
+            last_output: Arc::new(Mutex::new(None)),
+            theme: Arc::new(Mutex::new(theme)),
+            report_color_scheme: Arc::new(AtomicBool::new(false)),
+            bell: Arc::new(AtomicBool::new(false)),
+            unsupported: Arc::new(Mutex::new(Vec::new())),
+            follow_tail: Arc::new(AtomicBool::new(false)),
+            performer: Mutex::new(None),
+            reader_thread: Mutex::new(None),
+            waker: None,
+        }
+    }
+
+    /// Run `f` on the grid, publishing a new snapshot if it changed what is
+    /// in view. None if the PTY isn't running.
+    fn with_grid<R>(&self, f: impl FnOnce(&mut TerminalGrid) -> R) -> Option<R> {
+        let performer = self.performer.lock().unwrap().clone()?;
+        let mut performer = performer.lock().unwrap();
+        let result = f(&mut performer.grid);
+        if performer.grid.dirty {
+            performer.publish(false);
+        }
+        Some(result)
+    }
+
+    /// Sequences the parser skipped since the last call, such as private
+    /// modes it doesn't know.
+    pub fn take_unsupported(&self) -> Vec<String> {
+        std::mem::take(&mut *self.unsupported.lock().unwrap())
+    }
+
+    /// For feeding damage from somewhere other than a PTY, such as a shared
+    /// window.
+    pub fn damage_sender(&self) -> Sender<Damage> {
+        self.damage_sender.clone()
+    }
+
+    /// Matches for `pattern` in the scrollback and on screen, oldest first.
+    pub fn search(&self, pattern: &Regex) -> Vec<SearchMatch> {
+        self.with_grid(|grid| grid.search(pattern)).unwrap_or_default()
+    }
+
+    /// Scroll so session line `line` is in view.
+    pub fn scroll_to_line(&self, line: usize) {
+        self.with_grid(|grid| grid.scroll_to_line(line));
+    }
+
+    /// Scroll to the previous or next prompt marked by the shell.
+    pub fn jump_to_prompt(&self, forward: bool) {
+        self.with_grid(|grid| grid.jump_to_prompt(forward));
+    }
+
+    /// The prompt at or above session line `line`, with its command and
+    /// output as far as the shell has marked them.
+    pub fn command_block_at(&self, line: usize) -> Option<CommandBlock> {
+        self.with_grid(|grid| grid.command_block_at(line)).flatten()
+    }
+
+    /// Output of the last command that finished, if the shell marked it.
+    pub fn last_command_output(&self) -> Option<String> {
+        self.with_grid(|grid| grid.last_command_output()).flatten()
+    }
+
+    /// Up to `limit` commands run at the latest marked prompts, newest first.
+    pub fn recent_commands(&self, limit: usize) -> Vec<String> {
+        self.with_grid(|grid| grid.recent_commands(limit)).unwrap_or_default()
+    }
+
+    /// Text of session lines `lines` with soft-wrapped rows joined.
+    pub fn logical_text(&self, lines: Range<usize>) -> String {
+        self.with_grid(|grid| grid.logical_text(lines)).unwrap_or_default()
+    }
+
+    /// Text under `selection`, block selections keeping their columns.
+    pub fn selected_text(&self, selection: &Selection) -> String {
+        self.with_grid(|grid| grid.selected_text(selection)).unwrap_or_default()
+    }
+
+    /// Start or stop sending the live screen for a follow window.
+    pub fn set_follow_tail(&self, follow: bool) {
+        if self.follow_tail.swap(follow, Ordering::SeqCst) != follow {
+            self.with_grid(|grid| grid.dirty = true);
+        }
+    }
+
+    /// Scroll the view `lines` back into the scrollback, or forward for
+    /// negative counts. Returns how far it moved.
+    pub fn scroll_view(&self, lines: isize) -> isize {
+        self.with_grid(|grid| grid.scroll_view(lines)).unwrap_or(0)
+    }
+
+    /// Scroll so session line `line` is at the top of the view.
+    pub fn scroll_view_to(&self, line: usize) {
+        self.with_grid(|grid| grid.scroll_view_to(line));
+    }
+
+    /// Forget the scrollback, as `CSI 3 J` does.
+    pub fn clear_scrollback(&self) {
+        self.with_grid(|grid| grid.clear_scrollback());
+    }
+
+    /// Return the view to the live screen.
+    pub fn scroll_to_bottom(&self) {
+        self.with_grid(|grid| {
+            if grid.scroll_offset != 0 {
+                grid.scroll_offset = 0;
+                grid.dirty = true;
+            }
+        });
+    }
+
+    /// Resize the grid and tell the program through the PTY (SIGWINCH, or a
+    /// ConPTY resize on Windows).
+    pub fn resize(&self, cols: usize, rows: usize) {
+        let (cols, rows) = (cols.clamp(1, u16::MAX as usize), rows.clamp(1, u16::MAX as usize));
+        if (*self.cols.lock().unwrap(), *self.rows.lock().unwrap()) == (cols, rows) {
+            return;
+        }
+        *self.cols.lock().unwrap() = cols;
+        *self.rows.lock().unwrap() = rows;
+
+        self.with_grid(|grid| grid.resize(rows, cols));
+        if let Some(master) = self.master.lock().unwrap().as_ref() {
+            let size = PtySize {
+                rows: rows as u16,
+                cols: cols as u16,
+                pixel_width: 0,
+                pixel_height: 0,
+            };
+            if let Err(e) = master.resize(size) {
+                warn!("Failed to resize PTY: {}", e);
+            }
+        }
+    }
+
+    /// Directory the shell is in: the last one it reported through OSC 7,
+    /// otherwise the working directory of the foreground process.
+    pub fn current_dir(&self) -> Option<PathBuf> {
+        if let Some(cwd) = self.cwd.lock().unwrap().clone() {
+            return Some(cwd);
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let pid = self.master.lock().ok()?.as_ref()?.process_group_leader()?;
+            std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            None
+        }
+    }
+
+    /// Name of the process currently in the foreground of the PTY, if known.
+    pub fn foreground_process_name(&self) -> Option<String> {
+        #[cfg(target_os = "linux")]
+        {
+            let pid = self.master.lock().ok()?.as_ref()?.process_group_leader()?;
+            let name = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+            Some(name.trim().to_string())
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            None
+        }
+    }
+
+    /// Command line of the foreground process, with its arguments where
+    /// the platform tells them.
+    pub fn foreground_command_line(&self) -> Option<String> {
+        #[cfg(target_os = "linux")]
+        {
+            let pid = self.master.lock().ok()?.as_ref()?.process_group_leader()?;
+            let args = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+            let args: Vec<_> = args.split(|&b| b == 0).filter(|arg| !arg.is_empty()).map(String::from_utf8_lossy).collect();
+            if !args.is_empty() {
+                return Some(args.join(" "));
+            }
+        }
+        self.foreground_process_name()
+    }
+
+    pub fn spawn_pty(&self) -> Result<(PtyWriter, PtyChild)> {
+    let pty_system = NativePtySystem::default();
+    let cols = *self.cols.lock().unwrap();
+    let rows = *self.rows.lock().unwrap();
+    let pair = pty_system.openpty(PtySize {
+        rows: rows as u16,
+        cols: cols as u16,
+        pixel_width: 0,
+        pixel_height: 0,
+    })?;
+    
+    debug!("PTY created successfully");
+
+    let cmd = shell_command(&self.config.shell, self.cwd.lock().unwrap().clone());
     
-    println!("Spawning command: {:?}", cmd);
+    info!("Spawning command: {:?}", cmd);
     let child: Box<dyn Child + Send> = match pair.slave.spawn_command(cmd) {
         Ok(child) => child,
         Err(e) => {
-            eprintln!("Failed to spawn child process: {}", e);
-            return Err(e.into());
+            error!("Failed to spawn child process: {}", e);
+            return Err(e);
         }
     };
-    println!("Child process spawned: {:?}", child);
+    debug!("Child process spawned: {:?}", child);
     
     let child_ref = Arc::new(Mutex::new(child));
-    let master = pair.master;
-    let master_ref = Arc::new(Mutex::new(master));
-    let reader = master_ref.lock().unwrap().try_clone_reader()?;
-    let writer = master_ref.lock().unwrap().take_writer()?;
+    let reader = pair.master.try_clone_reader()?;
+    let writer = EncodingWriter::new(pair.master.take_writer()?, self.config.shell.encoding);
+    *self.master.lock().unwrap() = Some(pair.master);
     
     // Clone shared state
-    let buffer_clone = Arc::clone(&self.buffer);
-    let font_system_clone = Arc::clone(&self.font_system);
-    let text_content_clone = Arc::clone(&self.text_content);
-    let cursor_x_clone = Arc::clone(&self.cursor_x);
-    let cursor_y_clone = Arc::clone(&self.cursor_y);
-    let dirty_clone = Arc::clone(&self.dirty);
-    let cols_clone = Arc::clone(&self.cols);
-    let rows_clone = Arc::clone(&self.rows);
-    let swash_cache_clone = Arc::clone(&self.swash_cache);
+    let cwd_clone = Arc::clone(&self.cwd);
+    let shutdown_clone = Arc::clone(&self.shutdown);
+    let exited_clone = Arc::clone(&self.exited);
+    let shell_config = self.config.shell.clone();
+    let last_output_clone = Arc::clone(&self.last_output);
+    let waker = self.waker.clone();
+    let wake = move || {
+        if let Some(wake) = &waker {
+            wake();
+        }
+    };
     
     // Create inner references that can be cloned in the loop
     let child_ref_inner = child_ref.clone();
-    let master_ref_inner = master_ref.clone();
+    let master_ref_inner = Arc::clone(&self.master);
 
     // Create a writer for escape sequence responses
     let writer_arc = Arc::new(Mutex::new(writer));
     let response_writer = Arc::clone(&writer_arc);
+    let shared_performer = Arc::new(Mutex::new(TerminalPerformer::new(rows, cols, response_writer, self)));
+    *self.performer.lock().unwrap() = Some(Arc::clone(&shared_performer));
+    // Private sessions keep terminal contents out of the logs
+    let log_output = !self.config.private;
 
-    thread::spawn(move || {
-        println!("PTY reader thread started");
+    let reader_thread = thread::spawn(move || {
+        debug!("PTY reader thread started");
         let mut reader = reader;
-        let mut buffer = [0; 4096];
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
         let mut parser = vte::Parser::new();
+        let mut decoder = OutputDecoder::new(shell_config.encoding);
         
-        let cols = *cols_clone.lock().unwrap();
-        let rows = *rows_clone.lock().unwrap();
-        let mut performer = TerminalPerformer::new(rows, cols, response_writer);
-        
-        performer.grid.print_str("Nebula Terminal\n$ ");
+        shared_performer.lock().unwrap().grid.print_str(PLACEHOLDER_TEXT);
         
         loop {
-            match reader.read(&mut buffer) {
+            let read = reader.read(&mut buffer);
+            // Held while the read is handled; the UI only takes it briefly to
+            // scroll or search
+            let mut performer = shared_performer.lock().unwrap();
+            match read {
+                Ok(0) if shutdown_clone.load(Ordering::SeqCst) => break,
+                // One-shot commands aren't restarted; the tab closes instead,
+                // unless it is held open
+                Ok(0) | Err(_) if !shell_config.restart => {
+                    info!("Shell exited");
+                    if shell_config.hold {
+                        performer.grid.print_str("\n[Process exited]");
+                        performer.publish(false);
+                    }
+                    exited_clone.store(true, Ordering::SeqCst);
+                    break;
+                }
                 Ok(0) => {
-                    println!("Shell exited, restarting...");
+                    info!("Shell exited, restarting...");
                     performer.grid.print_str("\n[Shell exited, restarting...]\n");
                     
                     let new_pair = match pty_system.openpty(PtySize {
-                        rows: DEFAULT_ROWS,
-                        cols: DEFAULT_COLS,
+                        rows: performer.grid.rows as u16,
+                        cols: performer.grid.cols as u16,
                         pixel_width: 0,
                         pixel_height: 0,
                     }) {
@@ -532,31 +3003,8 @@ impl Terminal {
                         }
                     };
                     
-                    let mut cmd = if cfg!(target_os = "windows") {
-                        let mut cmd = CommandBuilder::new("cmd.exe");
-                        cmd.arg("/K");
-                        cmd.env("PROMPT", "$G$S");
-                        cmd
-                    } else {
-                        let mut cmd = CommandBuilder::new("bash");
-                        cmd.args(&["--login", "-i"]);
-                        cmd
-                    };
-                    
-                    cmd.env_clear();
-                    if cfg!(target_os = "windows") {
-                        cmd.env("SystemRoot", std::env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string()));
-                        cmd.env("PATH", std::env::var("PATH").unwrap_or_default());
-                        cmd.env("COMSPEC", std::env::var("COMSPEC").unwrap_or_else(|_| "C:\\Windows\\System32\\cmd.exe".to_string()));
-                        cmd.env("TEMP", std::env::var("TEMP").unwrap_or_else(|_| "C:\\Windows\\Temp".to_string()));
-                    } else {
-                        cmd.env("HOME", std::env::var("HOME").unwrap_or_default());
-                        cmd.env("PATH", std::env::var("PATH").unwrap_or_default());
-                        cmd.env("TERM", "xterm-256color");
-                        cmd.env("SHELL", std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string()));
-                        cmd.env("USER", std::env::var("USER").unwrap_or_default());
-                        cmd.env("LANG", "en_US.UTF-8");
-                    };
+                    // Restart in the last directory reported through OSC 7
+                    let cmd = shell_command(&shell_config, cwd_clone.lock().unwrap().clone());
                     
                     let new_child = match new_pair.slave.spawn_command(cmd) {
                         Ok(child) => child,
@@ -566,18 +3014,20 @@ impl Terminal {
                         }
                     };
                     
-                    // Update references using inner clones
-                    *child_ref_inner.lock().unwrap() = new_child;
-                    *master_ref_inner.lock().unwrap() = new_pair.master;
-                    
                     // Recreate reader from new master
-                    reader = match master_ref.lock().unwrap().try_clone_reader() {
+                    reader = match new_pair.master.try_clone_reader() {
                         Ok(reader) => reader,
                         Err(e) => {
                             performer.grid.print_str(&format!("\n[Failed to clone reader: {}]\n", e));
                             break;
                         }
                     };
+
+                    decoder = OutputDecoder::new(shell_config.encoding);
+
+                    // Update references using inner clones
+                    *child_ref_inner.lock().unwrap() = new_child;
+                    *master_ref_inner.lock().unwrap() = Some(new_pair.master);
                     
                     // Reset terminal state
                     performer.grid.clear_screen();
@@ -585,89 +3035,66 @@ impl Terminal {
                     performer.grid.cursor_y = 0;
                     performer.grid.scrollback.clear();
                     performer.grid.scroll_offset = 0;
+                    performer.grid.main_screen = None;
+                    performer.grid.charsets = [Charset::Ascii; 2];
+                    performer.grid.active_charset = 0;
+                    performer.grid.saved_cursor = None;
+                    performer.grid.protected = false;
+                    performer.grid.hyperlinks.clear();
+                    performer.grid.active_hyperlink = None;
+                    *performer.modes.lock().unwrap() = TerminalModes::default();
+                    performer.report_color_scheme.store(false, Ordering::SeqCst);
                     performer.grid.dirty = true;
                     
                     // Print fresh prompt
-                    performer.grid.print_str("Nebula Terminal\n$ ");
-                    
-                    // Update state to reflect new prompt
-                    let new_text = performer.grid.to_string();
-                    let cursor_x = performer.grid.cursor_x as f32 * FONT_SIZE;
-                    let cursor_y = performer.grid.cursor_y as f32 * LINE_HEIGHT;
-                    
-                    {
-                        let mut text_lock = text_content_clone.lock().unwrap();
-                        *text_lock = new_text.clone();
-                    }
+                    performer.grid.print_str(PLACEHOLDER_TEXT);
                     
-                    {
-                        let mut buffer_lock = buffer_clone.lock().unwrap();
-                        if let Ok(mut fs) = font_system_clone.lock() {
-                            buffer_lock.set_text(
-                                &mut fs, 
-                                &new_text, 
-                                &Attrs::new().family(Family::Monospace),
-                                Shaping::Advanced
-                            );
-                            buffer_lock.shape_until_scroll(&mut fs, true);
-                        }
-                    }
-                    
-                    *cursor_x_clone.lock().unwrap() = cursor_x;
-                    *cursor_y_clone.lock().unwrap() = cursor_y;
-                    *dirty_clone.lock().unwrap() = true;
+                    performer.publish(false);
                 }
                 Ok(n) => {
+                    let read_at = Instant::now();
                     let data = &buffer[..n];
-                    println!("PTY received {} bytes: {:?}", n, data);
-                    
-                    for &byte in data {
-                        parser.advance(&mut performer, &[byte]);
+                    if log_output {
+                        trace!("PTY received {} bytes: {:?}", n, data);
                     }
-                    
+
+                    let _span = trace_span!("parse", bytes = n).entered();
+                    parser.advance(&mut *performer, decoder.decode(data));
+
+                    // Once per read. The UI applies all damage queued since its
+                    // last frame before drawing, so output arriving faster than
+                    // the display refreshes never draws intermediate frames.
                     if performer.grid.dirty {
-                        println!("Grid dirty - cursor: ({}, {})", 
-                            performer.grid.cursor_x, performer.grid.cursor_y);
-                        println!("Grid content:\n{}", performer.grid.to_string());
-                        
-                        let new_text = performer.grid.to_string();
-                        let cursor_x = performer.grid.cursor_x as f32 * FONT_SIZE;
-                        let cursor_y = performer.grid.cursor_y as f32 * LINE_HEIGHT;
-                        
-                        {
-                            let mut text_lock = text_content_clone.lock().unwrap();
-                            *text_lock = new_text.clone();
-                        }
-                        
-                        {
-                            let mut buffer_lock = buffer_clone.lock().unwrap();
-                            if let Ok(mut fs) = font_system_clone.lock() {
-                                buffer_lock.set_text(
-                                    &mut fs, 
-                                    &new_text, 
-                                    &Attrs::new(), 
-                                    Shaping::Advanced
-                                );
-                                buffer_lock.shape_until_scroll(&mut fs, true);
-                            }
-                        }
-                        
-                        *cursor_x_clone.lock().unwrap() = cursor_x;
-                        *cursor_y_clone.lock().unwrap() = cursor_y;
-                        *dirty_clone.lock().unwrap() = true;
-                        performer.grid.dirty = false;
+                        performer.publish(log_output);
                     }
+                    *last_output_clone.lock().unwrap() = Some(read_at);
                 }
+                // The PTY going away is expected once we hung up on the shell
+                Err(_) if shutdown_clone.load(Ordering::SeqCst) => break,
                 Err(e) => {
-                    eprintln!("PTY read error: {}", e);
+                    error!("PTY read error: {}", e);
+                    performer.grid.print_str(&format!("\n[PTY read error: {}]\n", e));
+                    performer.publish(false);
                     break;
                 }
             }
+            drop(performer);
+            // Also for output that left the grid alone, such as a new title
+            wake();
         }
-        println!("PTY reader thread exiting");
+        // So an exited tab closes or shows it was held
+        wake();
+        debug!("PTY reader thread exiting");
     });
+    *self.reader_thread.lock().unwrap() = Some(reader_thread);
 
-    println!("Returning PTY writer and child reference");
+    debug!("Returning PTY writer and child reference");
     Ok((writer_arc, child_ref))
 }
-}
\ No newline at end of file
+}
+
+mod emulator;
+#[cfg(test)]
+mod tests;
+
+pub use emulator::{Cell, Cursor, TermEmulator};
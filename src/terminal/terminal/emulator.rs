@@ -0,0 +1,163 @@
+// src/terminal/terminal/emulator.rs
+//! The grid and parser on their own, for tests, multiplexers and other
+//! frontends that bring their own PTY and drawing.
+use super::*;
+use crate::terminal::clipboard::Clipboard;
+
+/// One cell of the screen as a frontend draws it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    /// The grapheme cluster in the cell, empty for the right half of a wide
+    /// character.
+    pub text: String,
+    /// Takes this cell and the one after it.
+    pub wide: bool,
+    /// `None` for the theme's.
+    pub foreground: Option<CellColor>,
+    pub bold: bool,
+    /// SGR 2, drawn closer to the background.
+    pub dim: bool,
+    /// SGR 7, text and background colors swapped.
+    pub reverse: bool,
+    /// `None` for the theme's.
+    pub background: Option<Rgb>,
+    pub underline: Option<UnderlineStyle>,
+    pub underline_color: Option<Rgb>,
+    pub strikethrough: bool,
+    /// Printed under DECSCA protection.
+    pub protected: bool,
+    /// OSC 8 link the cell was printed under.
+    pub hyperlink: Option<Arc<str>>,
+}
+
+/// Where the cursor is and how it is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub col: usize,
+    pub row: usize,
+    /// The last column was printed and the next character wraps first.
+    pub wrap_pending: bool,
+    pub style: CursorStyle,
+}
+
+/// A terminal screen driven by bytes, without a window, GPU or PTY.
+/// Replies the program would read back, such as cursor position reports,
+/// are collected for [`TermEmulator::take_responses`].
+pub struct TermEmulator {
+    terminal: Terminal,
+    performer: TerminalPerformer,
+    parser: vte::Parser,
+    responses: Arc<Mutex<Vec<u8>>>,
+}
+
+impl TermEmulator {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self::with_config(rows, cols, Config::default())
+    }
+
+    /// An emulator using `config`'s scrollback, clipboard and theme
+    /// settings. Clipboard history is off, as there is no one to pick from it.
+    pub fn with_config(rows: usize, cols: usize, config: Config) -> Self {
+        let terminal = Terminal::new(config, Clipboard::shared(0));
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let writer: Arc<Mutex<dyn Write + Send>> = responses.clone();
+        let performer = TerminalPerformer::new(rows.max(1), cols.max(1), writer, &terminal);
+        Self { terminal, performer, parser: vte::Parser::new(), responses }
+    }
+
+    /// Parse output of the program, updating the screen.
+    pub fn advance(&mut self, bytes: &[u8]) {
+        self.parser.advance(&mut self.performer, bytes);
+    }
+
+    pub fn rows(&self) -> usize {
+        self.performer.grid.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.performer.grid.cols
+    }
+
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        self.performer.grid.resize(rows, cols);
+    }
+
+    /// Text of screen row `row`, with a cell per column.
+    pub fn row_text(&self, row: usize) -> Option<String> {
+        (row < self.rows()).then(|| self.performer.grid.row_text(row))
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<Cell> {
+        let grid = &self.performer.grid;
+        let cell = grid.cells.get(row)?.get(col)?;
+        let mut text = String::new();
+        if !cell.spacer {
+            cell.push_text(&mut text);
+        }
+        let hyperlink = cell.hyperlink.and_then(|id| grid.hyperlinks.get(id));
+        Some(Cell {
+            text,
+            wide: cell.is_wide(),
+            foreground: cell.style.foreground,
+            bold: cell.style.bold,
+            dim: cell.style.dim,
+            reverse: cell.style.reverse,
+            background: cell.style.background,
+            underline: cell.style.underline,
+            underline_color: cell.style.underline_color,
+            strikethrough: cell.style.strikethrough,
+            protected: cell.protected,
+            hyperlink: hyperlink.map(|link| Arc::clone(&link.uri)),
+        })
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        let grid = &self.performer.grid;
+        Cursor {
+            col: grid.cursor_x,
+            row: grid.cursor_y,
+            wrap_pending: grid.wrap_pending,
+            style: *self.terminal.cursor_style.lock().unwrap(),
+        }
+    }
+
+    /// Key modes the program set, which change what keys should send.
+    pub fn modes(&self) -> TerminalModes {
+        *self.terminal.modes.lock().unwrap()
+    }
+
+    /// DECAWM: printing past the last column wraps to the next row.
+    pub fn auto_wrap(&self) -> bool {
+        self.performer.grid.auto_wrap
+    }
+
+    /// Mode 2031: the program wants to hear about theme changes.
+    pub fn reports_color_scheme(&self) -> bool {
+        self.terminal.report_color_scheme.load(Ordering::SeqCst)
+    }
+
+    /// Title set with OSC 0 or 2.
+    pub fn title(&self) -> Option<String> {
+        self.terminal.title.lock().unwrap().clone()
+    }
+
+    /// Working directory reported with OSC 7.
+    pub fn current_dir(&self) -> Option<PathBuf> {
+        self.terminal.cwd.lock().unwrap().clone()
+    }
+
+    /// Lines scrolled off the top of the screen.
+    pub fn scrollback_len(&self) -> usize {
+        self.performer.grid.scrollback.len()
+    }
+
+    /// Bytes written back to the program since the last call.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut *self.responses.lock().unwrap())
+    }
+
+    /// Sequences skipped as unsupported since the last call.
+    pub fn take_unsupported(&mut self) -> Vec<String> {
+        self.terminal.take_unsupported()
+    }
+}
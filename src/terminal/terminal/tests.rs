@@ -0,0 +1,672 @@
+// src/terminal/terminal/tests.rs
+//! Replays captured TUI startup output through the parser and compares the
+//! final screen with a known-good snapshot. See tests/fixtures/README.md for
+//! how the fixtures were made.
+use super::*;
+use crate::terminal::clipboard::Clipboard;
+
+fn replay(bytes: &[u8]) -> TerminalGrid {
+    let terminal = Terminal::new(Config::default(), Clipboard::shared(0));
+    let writer: Arc<Mutex<dyn Write + Send>> = Arc::new(Mutex::new(std::io::sink()));
+    let mut performer = TerminalPerformer::new(24, 80, writer, &terminal);
+    let mut parser = vte::Parser::new();
+    parser.advance(&mut performer, bytes);
+    performer.grid
+}
+
+fn screen(grid: &TerminalGrid) -> String {
+    (0..grid.rows)
+        .map(|row| grid.row_text(row).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end_matches('\n')
+        .to_string()
+}
+
+/// Cursor position as tmux reports it: a pending wrap shows as one column
+/// past the right margin.
+fn cursor(grid: &TerminalGrid) -> (usize, usize) {
+    (grid.cursor_x + grid.wrap_pending as usize, grid.cursor_y)
+}
+
+fn assert_fixture(name: &str, bytes: &[u8], expected: &str, expected_cursor: (usize, usize)) {
+    let grid = replay(bytes);
+    assert_eq!(cursor(&grid), expected_cursor, "{name} cursor position");
+
+    let actual = screen(&grid);
+    let expected = expected.trim_end_matches('\n');
+    if actual != expected {
+        let (actual, expected): (Vec<_>, Vec<_>) = (actual.split('\n').collect(), expected.split('\n').collect());
+        let diff: Vec<String> = (0..actual.len().max(expected.len()))
+            .map(|row| (row, actual.get(row).unwrap_or(&""), expected.get(row).unwrap_or(&"")))
+            .filter(|(_, a, e)| a != e)
+            .map(|(row, a, e)| format!("row {row}:\n  got:      {a:?}\n  expected: {e:?}"))
+            .collect();
+        panic!("{name} screen differs from snapshot\n{}", diff.join("\n"));
+    }
+}
+
+macro_rules! fixture_test {
+    ($(#[$attr:meta])* $test:ident, $name:literal, $cursor:expr) => {
+        #[test]
+        $(#[$attr])*
+        fn $test() {
+            assert_fixture(
+                $name,
+                include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/", $name, ".bin")),
+                include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/", $name, ".screen")),
+                $cursor,
+            );
+        }
+    };
+}
+
+fixture_test!(vim_startup, "vim", (0, 0));
+fixture_test!(tmux_startup, "tmux", (2, 0));
+fixture_test!(top_startup, "top", (80, 23));
+fixture_test!(less_startup, "less", (17, 23));
+
+// Escape sequence cases adapted from vttest and esctest
+fixture_test!(vt_cursor_movement, "cursor", (51, 14));
+fixture_test!(vt_erase, "erase", (39, 17));
+fixture_test!(vt_scroll_region, "scroll_region", (4, 21));
+fixture_test!(vt_sgr, "sgr", (15, 15));
+
+#[test]
+fn wide_characters() {
+    let grid = replay(
+        "a中b\r\n\
+         xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx中文\r\n\
+         中中\r\x1B[1Cz\r\n\
+         中文\r\x1B[P"
+            .as_bytes(),
+    );
+    let expected = [
+        "a中b",
+        "xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx",
+        // Didn't fit in the last column, so it wrapped early
+        "中文",
+        // Overwriting either half of a wide character blanks the other
+        " z中",
+        // Deleting the left half leaves the right half blank
+        " 文",
+    ];
+    assert_eq!(screen(&grid), expected.join("\n"));
+    assert!(grid.wrapped[1]);
+    assert_eq!(cursor(&grid), (0, 4));
+}
+
+#[test]
+fn grapheme_clusters() {
+    let grid = replay("e\u{301}x\r\n🇺🇸x\r\n❤\u{FE0F}x\r\n👩\u{200D}💻x\r\n\u{301}x".as_bytes());
+    // Each cluster stays in one cell (two for wide ones); a stray combining
+    // mark with nothing before it is dropped
+    assert_eq!(
+        screen(&grid),
+        "e\u{301}x\n🇺🇸x\n❤\u{FE0F}x\n👩\u{200D}💻x\nx"
+    );
+    let columns: Vec<usize> = (0..4)
+        .map(|row| grid.cells[row].iter().position(|cell| cell.character == 'x').unwrap())
+        .collect();
+    assert_eq!(columns, [1, 2, 2, 2]);
+}
+
+#[test]
+fn hyperlinks() {
+    let mut grid = replay(
+        "\x1B]8;id=a;https://example.com/x\x1B\\one\x1B]8;;\x1B\\ (see https://nebula.dev/docs).\r\n\
+         \x1B]8;id=a;https://example.com/x\x07two\x1B]8;;\x07 mailto:me@example.com"
+            .as_bytes(),
+    );
+    let lines: Vec<String> = grid.visible_lines().into_iter().map(|line| line.text).collect();
+    let links: Vec<_> = grid
+        .visible_links(&lines)
+        .into_iter()
+        .map(|link| (link.row, link.columns, link.uri.to_string(), link.hyperlink))
+        .collect();
+    // Both pieces of the OSC 8 link share an id; trailing punctuation isn't
+    // part of a detected URL
+    let id = links[0].3;
+    assert!(id.is_some());
+    assert_eq!(
+        links,
+        [
+            (0, 0..3, "https://example.com/x".to_string(), id),
+            (0, 9..32, "https://nebula.dev/docs".to_string(), None),
+            (1, 0..3, "https://example.com/x".to_string(), id),
+            (1, 4..25, "mailto:me@example.com".to_string(), None),
+        ]
+    );
+}
+
+#[test]
+fn file_paths() {
+    let mut grid = replay(b"src/main.rs:12:5: error, see ~/notes.txt and (/etc/hosts).\r\nand/or 10/12 foo:3 lib.rs:7 ./run");
+    let lines: Vec<String> = grid.visible_lines().into_iter().map(|line| line.text).collect();
+    let links: Vec<_> = grid
+        .visible_links(&lines)
+        .into_iter()
+        .map(|link| (link.row, link.columns, link.uri.to_string()))
+        .collect();
+    // Words with a slash but no file name, and lines after words that
+    // aren't file names, aren't paths
+    assert_eq!(
+        links,
+        [
+            (0, 0..16, "src/main.rs:12:5".to_string()),
+            (0, 29..40, "~/notes.txt".to_string()),
+            (0, 46..56, "/etc/hosts".to_string()),
+            (1, 19..27, "lib.rs:7".to_string()),
+            (1, 28..33, "./run".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn search_scrollback() {
+    let lines: Vec<String> = (0..30).map(|n| format!("Line {n}")).collect();
+    let mut grid = replay(lines.join("\r\n").as_bytes());
+    let pattern = regex::RegexBuilder::new(r"line 2\d").case_insensitive(true).build().unwrap();
+    let matches = grid.search(&pattern);
+    assert_eq!(matches.len(), 10);
+    assert_eq!(matches[0], SearchMatch { line: 20, columns: 0..7 });
+
+    // Lines 0-5 are in the scrollback; bringing one into view shows it at
+    // the top when there's nothing older to show above it
+    let pattern = regex::Regex::new("Line 3$").unwrap();
+    let found = grid.search(&pattern);
+    assert_eq!(found, [SearchMatch { line: 3, columns: 0..6 }]);
+    grid.scroll_to_line(3);
+    assert_eq!(grid.visible_lines()[3].text.trim_end(), "Line 3");
+    grid.scroll_to_line(29);
+    assert_eq!(grid.visible_lines()[23].text.trim_end(), "Line 29");
+}
+
+#[test]
+fn soft_wrap() {
+    let long = "x".repeat(100);
+    let mut grid = replay(format!("{long}\r\nshort\r\n{long}\x1B[1A\x1B[K").as_bytes());
+    // A wrapped line copies as one line; erasing its end breaks the wrap
+    assert_eq!(grid.wrapped[..5], [true, false, false, false, false]);
+    assert_eq!(grid.logical_text(0..3), format!("{long}\nshort"));
+    assert_eq!(grid.logical_text(3..5), format!("{}\n{}", &long[..20], &long[..20]));
+}
+
+#[test]
+fn selective_erase() {
+    // "Name:" and "Age:" are protected labels of a form; DECSED, DECSEL and
+    // DECSERA clear only the fields, while plain EL clears everything
+    let grid = replay(
+        b"\x1B[1\"qName:\x1B[0\"q Ada\r\n\
+          \x1B[1\"qAge:\x1B[0\"q 36\r\n\
+          \x1B[1\"qCity:\x1B[0\"q Paris\r\n\
+          Note: \x1B[1\"qkept\x1B[0\"q\r\n\
+          gone \x1B[1\"qtoo\x1B[0\"q\
+          \x1B[1;1H\x1B[?K\x1B[2;1;3;80${\x1B[4;1H\x1B[?J\x1B[5;1H\x1B[K",
+    );
+    assert_eq!(screen(&grid), "Name:\nAge:\nCity:\n      kept");
+
+    // Left past right, or top below bottom, erase nothing
+    let grid = replay(b"kept\x1B[1;50;24;10${\x1B[20;1;5;80${");
+    assert_eq!(screen(&grid), "kept");
+}
+
+#[test]
+fn resize() {
+    let lines: Vec<String> = (0..10).map(|n| format!("Line {n} {}", "x".repeat(70))).collect();
+    let mut grid = replay(lines.join("\r\n").as_bytes());
+
+    // Narrowing rewraps each line over four rows, and rows above the
+    // cursor move to the scrollback
+    grid.resize(5, 20);
+    assert_eq!((grid.rows, grid.cols, grid.cursor_y, grid.cursor_x), (5, 20, 4, 17));
+    assert_eq!(grid.scrollback.len(), 35);
+    assert_eq!(screen(&grid).lines().nth(1), Some("Line 9 xxxxxxxxxxxxx"));
+    assert_eq!(grid.logical_text(36..40), lines[9]);
+
+    // Widening joins them again, scrollback included, and growing adds
+    // blank rows below
+    grid.resize(8, 40);
+    assert_eq!((grid.rows, grid.cols, grid.cursor_y, grid.cursor_x), (8, 40, 4, 37));
+    assert_eq!(grid.scrollback.len(), 15);
+    assert_eq!(grid.row_text(3).trim_end(), &lines[9][..40]);
+    assert_eq!(grid.row_text(7).trim_end(), "");
+    grid.resize(24, 80);
+    assert_eq!(screen(&grid), lines[2..].join("\n"));
+    assert_eq!((grid.cursor_x, grid.cursor_y), (77, 7));
+}
+
+#[test]
+fn reflow() {
+    // A wide character that didn't fit at the end of a row moves back up
+    // once there's room
+    let mut grid = replay("abcdefgh\u{4E16}z".as_bytes());
+    grid.resize(24, 9);
+    assert_eq!(screen(&grid), "abcdefgh\n\u{4E16}z");
+    assert_eq!(cursor(&grid), (3, 1));
+    grid.resize(24, 10);
+    assert_eq!(screen(&grid), "abcdefgh\u{4E16}\nz");
+    assert_eq!(cursor(&grid), (1, 1));
+
+    // A cursor waiting to wrap still waits after the same character
+    let mut grid = replay("x".repeat(80).as_bytes());
+    grid.resize(24, 40);
+    assert_eq!(screen(&grid), format!("{0}\n{0}", "x".repeat(40)));
+    assert_eq!(cursor(&grid), (40, 1));
+}
+
+#[test]
+fn cursor_movement() {
+    // VPA and CHA are 1-based; a bare CUF moves one cell
+    let grid = replay(b"\x1B[2d\x1B[3Gab\x1B[Cc");
+    assert_eq!(grid.row_text(1).trim_end(), "  ab c");
+}
+
+#[test]
+fn auto_wrap() {
+    let full = "x".repeat(80);
+    // Filling the last column leaves the wrap pending, shown as the cursor
+    // one past the margin, without moving to the next line yet
+    let grid = replay(full.as_bytes());
+    assert_eq!((cursor(&grid), grid.wrapped[0]), ((80, 0), false));
+    // The next printable character wraps and marks the row as wrapped
+    let grid = replay(format!("{full}y").as_bytes());
+    assert_eq!(grid.row_text(1).trim_end(), "y");
+    assert_eq!((cursor(&grid), grid.wrapped[0]), ((1, 1), true));
+    // A carriage return or cursor move cancels the pending wrap
+    let grid = replay(format!("{full}\ry\r\n{full}\x1B[Dz").as_bytes());
+    assert_eq!(grid.row_text(0), format!("y{}", &full[1..]));
+    assert_eq!(grid.row_text(1), format!("{}zx", &full[2..]));
+    assert_eq!(cursor(&grid), (79, 1));
+    assert!(!grid.wrapped[0] && !grid.wrapped[1]);
+
+    // With DECAWM reset, printing at the margin overwrites the last column
+    let grid = replay(format!("\x1B[?7l{full}yz").as_bytes());
+    assert_eq!(grid.row_text(0), format!("{}z", &full[1..]));
+    assert_eq!((cursor(&grid), grid.wrapped[0]), ((79, 0), false));
+    assert_eq!(screen(&grid).lines().count(), 1);
+}
+
+#[test]
+fn line_editing() {
+    let numbered: String = (1..=24).map(|n| format!("{n}\r\n")).collect();
+    let numbered = numbered.trim_end();
+    // IL pushes the rows below down and off the bottom, DL pulls them up;
+    // both return the carriage
+    let grid = replay(format!("{numbered}\x1B[2;3H\x1B[2L").as_bytes());
+    assert_eq!(screen(&grid).lines().take(5).collect::<Vec<_>>(), ["1", "", "", "2", "3"]);
+    assert_eq!((grid.row_text(23).trim_end(), cursor(&grid)), ("22", (0, 1)));
+    let grid = replay(format!("{numbered}\x1B[2;3H\x1B[2M").as_bytes());
+    assert_eq!(screen(&grid).lines().take(3).collect::<Vec<_>>(), ["1", "4", "5"]);
+    assert_eq!(screen(&grid).lines().count(), 22);
+
+    // Counts past the bottom of the screen stop there
+    let grid = replay(format!("{numbered}\x1B[24H\x1B[99L").as_bytes());
+    assert_eq!(screen(&grid).lines().count(), 23);
+    let grid = replay(format!("{numbered}\x1B[23H\x1B[99M").as_bytes());
+    assert_eq!(screen(&grid).lines().last(), Some("22"));
+}
+
+#[test]
+fn character_editing() {
+    let line = "abcdefghij".repeat(8);
+    // ICH shifts the rest right, losing what passes the margin
+    let grid = replay(format!("{line}\x1B[3G\x1B[2@").as_bytes());
+    assert_eq!(grid.row_text(0), format!("ab  {}", &line[2..78]));
+    // ... and at the last column only blanks it
+    let grid = replay(format!("{line}\x1B[80G\x1B[5@").as_bytes());
+    assert_eq!(grid.row_text(0).trim_end(), &line[..79]);
+    // ECH blanks without shifting, stopping at the margin
+    let grid = replay(format!("{line}\x1B[3G\x1B[2X").as_bytes());
+    assert_eq!(grid.row_text(0), format!("ab  {}", &line[4..]));
+    let grid = replay(format!("{line}\x1B[76G\x1B[99X").as_bytes());
+    assert_eq!(grid.row_text(0).trim_end(), &line[..75]);
+    // DCH pulls the rest left, filling the end with blanks
+    let grid = replay(format!("{line}\x1B[79G\x1B[99P").as_bytes());
+    assert_eq!(grid.row_text(0).trim_end(), &line[..78]);
+    // None of them move the cursor
+    assert_eq!(cursor(&grid), (78, 0));
+}
+
+#[test]
+fn save_restore_cursor() {
+    // DECRC brings back the position, SGR attributes and character sets
+    // saved by DECSC, whatever changed in between
+    let grid = replay(b"\x1B[5;10H\x1B[1;31m\x1B(0\x1B7\x1B[m\x1B(B\x1B[Hplain\x1B8q");
+    assert_eq!(grid.row_text(0).trim_end(), "plain");
+    assert_eq!(grid.row_text(4).trim_end(), format!("{}─", " ".repeat(9)));
+    let cell = &grid.cells[4][9];
+    assert_eq!((cell.style.bold, cell.style.foreground), (true, Some(CellColor::Named(1))));
+    assert!(!grid.cells[0][0].style.bold);
+    assert_eq!(cursor(&grid), (10, 4));
+
+    // CSI s and CSI u do the same, and a pending wrap is saved with the
+    // position
+    let full = "x".repeat(80);
+    let grid = replay(format!("{full}\x1B[s\x1B[3;3H\x1B[uy").as_bytes());
+    assert_eq!((grid.row_text(1).trim_end(), grid.wrapped[0]), ("y", true));
+
+    // With nothing saved, DECRC homes the cursor and resets the attributes
+    let grid = replay(b"\x1B[10;10H\x1B[1mbold\x1B8plain");
+    assert_eq!(grid.row_text(0).trim_end(), "plain");
+    assert!(!grid.cells[0][0].style.bold);
+
+    // Origin mode is saved too, so the cursor stays in the scroll region
+    let grid = replay(b"\x1B[5;10r\x1B[?6h\x1B7\x1B[?6l\x1B8\x1B[Hx");
+    assert_eq!((cursor(&grid), grid.origin_mode), ((1, 4), true));
+}
+
+#[test]
+fn tab_stops() {
+    // Stops every eight columns to begin with; past the last one a tab
+    // stops at the right margin
+    let grid = replay(b"a\tb\tc\r\n\x1B[75G\tx");
+    assert_eq!(grid.row_text(0).trim_end(), "a       b       c");
+    assert_eq!(grid.row_text(1).trim_end(), format!("{}x", " ".repeat(79)));
+
+    // HTS sets a stop at the cursor, TBC clears the one at the cursor or all
+    let grid = replay(b"\x1B[5G\x1BH\x1B[9G\x1B[g\r\tx\tx\r\n\x1B[3g\tz");
+    assert_eq!(grid.row_text(0).trim_end(), "    x           x");
+    assert_eq!(grid.row_text(1).trim_end(), format!("{}z", " ".repeat(79)));
+
+    // CHT and CBT move by a count of stops, CBT stopping at the first column
+    let grid = replay(b"\x1B[2Ia\x1B[28G\x1B[Zb\x1B[5Zc");
+    assert_eq!(grid.row_text(0).trim_end(), "c               a       b");
+}
+
+#[test]
+fn damage() {
+    let terminal = Terminal::new(Config::default(), Clipboard::shared(0));
+    let writer: Arc<Mutex<dyn Write + Send>> = Arc::new(Mutex::new(std::io::sink()));
+    let mut performer = TerminalPerformer::new(24, 80, writer, &terminal);
+    let mut parser = vte::Parser::new();
+
+    // Every row differs from the placeholder at first, after that only the
+    // rows that changed are sent
+    parser.advance(&mut performer, b"one\r\ntwo\r\n");
+    performer.publish(false);
+    parser.advance(&mut performer, b"three");
+    performer.publish(false);
+
+    let damage: Vec<Damage> = terminal.damage.try_iter().collect();
+    assert_eq!(damage.len(), 2);
+    let changed = |damage: &Damage| damage.rows.iter().map(|(index, _)| *index).collect::<Vec<_>>();
+    assert_eq!(changed(&damage[0]), (0..24).collect::<Vec<_>>());
+    assert_eq!(changed(&damage[1]), [2]);
+    assert_eq!(damage[1].rows[0].1.trim_end(), "three");
+    assert_eq!((damage[1].row_count, damage[1].generation), (24, 2));
+    assert_eq!(damage[1].cursor, (5.0 * CELL_WIDTH, 2.0 * LINE_HEIGHT));
+}
+
+#[test]
+fn cell_backgrounds() {
+    // Printed cells take the SGR background and erased ones keep it (BCE)
+    let grid = replay(b"a\x1B[41mbc\x1B[48;2;1;2;3m\x1B[K\x1B[0md\r\n\x1B[48:5:21mx");
+    let spans = grid.visible_backgrounds();
+    let red = ansi_color(1);
+    assert_eq!(
+        spans,
+        [
+            BackgroundSpan { row: 0, columns: 1..3, color: red },
+            BackgroundSpan { row: 0, columns: 4..80, color: Rgb(1, 2, 3) },
+            BackgroundSpan { row: 1, columns: 0..1, color: Rgb(0, 0, 0xff) },
+        ]
+    );
+}
+
+#[test]
+fn decorations() {
+    // SGR 4:3 with an SGR 58 color, then strikethrough with the underline off
+    let grid = replay(b"\x1B[4:3;58:2::255:0:0ma\x1B[24;9mb\x1B[0mc\x1B[21md");
+    let red = Some(Rgb(255, 0, 0));
+    let span = |columns: Range<usize>, underline, underline_color, strikethrough| DecorationSpan {
+        row: 0,
+        columns,
+        underline,
+        underline_color,
+        strikethrough,
+    };
+    assert_eq!(
+        grid.visible_decorations(),
+        [
+            span(0..1, Some(UnderlineStyle::Curly), red, false),
+            span(1..2, None, red, true),
+            span(3..4, Some(UnderlineStyle::Double), None, false),
+        ]
+    );
+}
+
+#[test]
+fn command_timings() {
+    // Only a prompt after a command that started and finished gets a timing
+    let grid = replay(b"\x1B]133;A\x07$ \x1B]133;B\x07ls\r\n\x1B]133;C\x07out\r\n\x1B]133;D;0\x07\x1B]133;A\x07$ ");
+    let rows: Vec<usize> = grid.visible_command_timings().iter().map(|(row, _)| *row).collect();
+    assert_eq!(rows, [2]);
+}
+
+#[test]
+fn prompt_marks() {
+    // Three commands of 30 lines of output each, then a fresh prompt
+    let mut bytes = String::new();
+    for n in 0..3 {
+        bytes += &format!("\x1B]133;A\x07$ \x1B]133;B\x07cmd{n}\r\n\x1B]133;C\x07");
+        bytes += &"out\r\n".repeat(30);
+        bytes += &format!("\x1B]133;D;{n}\x07");
+    }
+    bytes += "\x1B]133;A\x07$ ";
+    let mut grid = replay(bytes.as_bytes());
+    assert_eq!(
+        grid.command_block_at(40),
+        Some(CommandBlock {
+            prompt: (31, 0),
+            input: Some((31, 2)),
+            output: Some((32, 0)),
+            end: Some((62, 0)),
+            exit_status: Some(1),
+        })
+    );
+    assert_eq!(grid.last_command_output(), Some(["out"; 30].join("\n")));
+    assert_eq!(grid.recent_commands(2), ["cmd2", "cmd1"]);
+
+    // Each jump puts the prompt at the top of the view
+    let tops: Vec<usize> = [false, false, false, false, true, true, true]
+        .into_iter()
+        .map(|forward| {
+            grid.jump_to_prompt(forward);
+            grid.view_top()
+        })
+        .collect();
+    assert_eq!(tops, [62, 31, 0, 0, 31, 62, 70]);
+}
+
+#[test]
+fn emulator() {
+    // Driven by bytes alone, answering queries through take_responses
+    let mut emulator = TermEmulator::new(5, 20);
+    emulator.advance("\x1B]2;build\x07\x1B[?1h\x1B[41m界\x1B[0mx\x1B[6n".as_bytes());
+    assert_eq!(emulator.take_responses(), b"\x1B[1;4R");
+    assert_eq!(emulator.row_text(0).unwrap().trim_end(), "界x");
+    let wide = emulator.cell(0, 0).unwrap();
+    assert_eq!((wide.text.as_str(), wide.wide, wide.background), ("界", true, Some(ansi_color(1))));
+    assert_eq!(emulator.cell(0, 1).unwrap().text, "");
+    assert_eq!((emulator.cursor().col, emulator.cursor().row), (3, 0));
+
+    // SGR color and attributes show through to frontends
+    emulator.advance(b"\x1B[1;2;7;32ma\x1B[0;38;2;1;2;3mb\x1B[0mc");
+    let styled = emulator.cell(0, 3).unwrap();
+    assert_eq!(styled.foreground, Some(CellColor::Named(2)));
+    assert!(styled.bold && styled.dim && styled.reverse);
+    let rgb = emulator.cell(0, 4).unwrap();
+    assert_eq!(rgb.foreground, Some(CellColor::Rgb(Rgb(1, 2, 3))));
+    assert!(!rgb.bold && !rgb.dim && !rgb.reverse);
+    assert_eq!(emulator.cell(0, 5).unwrap().foreground, None);
+    assert!(emulator.modes().app_cursor);
+    assert_eq!(emulator.title().as_deref(), Some("build"));
+}
+
+#[test]
+fn device_queries() {
+    let mut emulator = TermEmulator::new(5, 20);
+    emulator.advance(b"\x1B[c\x1B[0c");
+    assert_eq!(emulator.take_responses(), b"\x1B[?62;22c\x1B[?62;22c");
+    emulator.advance(b"\x1B[>c");
+    assert_eq!(emulator.take_responses(), b"\x1B[>1;100;0c");
+    emulator.advance(b"\x1B[>q");
+    assert_eq!(emulator.take_responses(), b"\x1BP>|Nebula 0.1.0\x1B\\");
+    // Set, reset and unknown private modes, then an ANSI mode
+    emulator.advance(b"\x1B[?1h\x1B[?1$p\x1B[?7l\x1B[?7$p\x1B[?1049$p\x1B[?9999$p\x1B[4$p");
+    assert_eq!(emulator.take_responses(), b"\x1B[?1;1$y\x1B[?7;2$y\x1B[?1049;2$y\x1B[?9999;0$y\x1B[4;0$y");
+    emulator.advance(b"\x1B]11;?\x1B\\");
+    assert!(emulator.take_responses().starts_with(b"\x1B]11;rgb:"));
+}
+
+#[test]
+fn capability_queries() {
+    // XTGETTCAP names and values are hex: TN, RGB, Co and an unknown one
+    let mut emulator = TermEmulator::new(5, 20);
+    emulator.advance(b"\x1BP+q544E;524742;436F;78797A\x1B\\");
+    assert_eq!(
+        emulator.take_responses(),
+        b"\x1BP1+r544E=6E6562756C61\x1B\\\x1BP1+r524742\x1B\\\x1BP1+r436F=323536\x1B\\\x1BP0+r78797A\x1B\\"
+    );
+    emulator.advance(b"\x1B[?2004h\x1B[?2004$p");
+    assert_eq!(emulator.take_responses(), b"\x1B[?2004;1$y");
+    assert!(emulator.modes().bracketed_paste);
+    // The manifest is JSON, and every mode it lists is one DECRQM knows
+    emulator.advance(b"\x1BP+c\x1B\\");
+    let response = String::from_utf8(emulator.take_responses()).unwrap();
+    let json = response.strip_prefix("\x1BP1+c").and_then(|rest| rest.strip_suffix("\x1B\\")).unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(json).unwrap()["name"], "nebula");
+    for mode in crate::terminal::capabilities::MANIFEST.private_modes {
+        emulator.advance(format!("\x1B[?{}$p", mode).as_bytes());
+        assert!(!emulator.take_responses().ends_with(b";0$y"), "mode {mode} is unknown to DECRQM");
+    }
+}
+
+#[test]
+fn clear_scrollback() {
+    // CSI 3 J empties the scrollback but leaves the screen, and later lines
+    // keep counting from the ones dropped
+    let lines: Vec<String> = (0..30).map(|n| format!("Line {n}")).collect();
+    let mut grid = replay(format!("{}\x1B[3J", lines.join("\r\n")).as_bytes());
+    assert!(grid.scrollback.is_empty());
+    assert_eq!(grid.visible_lines()[0].text.trim_end(), "Line 6");
+    let pattern = regex::Regex::new("Line 29").unwrap();
+    assert_eq!(grid.search(&pattern), [SearchMatch { line: 29, columns: 0..7 }]);
+}
+
+#[test]
+fn alternate_screen() {
+    // 1049 keeps the main screen and cursor aside; nothing scrolled off the
+    // alternate screen reaches the scrollback
+    let lines: Vec<String> = (0..30).map(|n| format!("{n}")).collect();
+    let full_screen = format!("shell$ ls\x1B[?1049h\x1B[H{}", lines.join("\r\n"));
+    let grid = replay(full_screen.as_bytes());
+    assert!(grid.main_screen.is_some());
+    assert!(grid.scrollback.is_empty());
+
+    let mut grid = replay(format!("{}\x1B[?1049l", full_screen).as_bytes());
+    assert!(grid.main_screen.is_none());
+    assert_eq!(grid.visible_lines()[0].text.trim_end(), "shell$ ls");
+    assert_eq!(cursor(&grid), (9, 0));
+}
+
+#[test]
+fn hidden_cursor() {
+    // The cursor hidden on the main screen comes back hidden, whatever the
+    // full-screen program did with it
+    let mut emulator = TermEmulator::new(5, 20);
+    emulator.advance(b"\x1B[?25l\x1B[?25$p");
+    assert_eq!(emulator.take_responses(), b"\x1B[?25;2$y");
+    emulator.advance(b"\x1B[?1049h\x1B[?25h");
+    assert!(!emulator.modes().cursor_hidden);
+    emulator.advance(b"\x1B[?1049l");
+    assert!(emulator.modes().cursor_hidden);
+}
+
+#[test]
+#[cfg(unix)]
+fn shell_locale() {
+    use crate::terminal::encoding::Encoding;
+    use std::ffi::OsStr;
+
+    let mut shell = ShellConfig::default();
+    let command = shell_command(&shell, None);
+    assert_eq!(command.get_env("LANG"), Some(OsStr::new("en_US.UTF-8")));
+    shell.encoding = Encoding::ShiftJis;
+    let command = shell_command(&shell, None);
+    assert_eq!(command.get_env("LANG"), Some(OsStr::new("ja_JP.SJIS")));
+    assert_eq!(command.get_env("LC_CTYPE"), Some(OsStr::new("ja_JP.SJIS")));
+    assert_eq!(command.get_env("LC_ALL"), None);
+}
+
+#[test]
+fn selections() {
+    let mut grid = replay(b"name   size\r\nfoo.rs   12\r\nbar.rs  345");
+    let block = Selection { anchor: (0, 7), head: (2, 10), kind: SelectionKind::Block };
+    assert_eq!(grid.selected_text(&block), "size\n  12\n 345");
+    // Dragged upwards, running past the ends of the lines between
+    let linear = Selection { anchor: (2, 3), head: (0, 7), kind: SelectionKind::Linear };
+    assert_eq!(grid.selected_text(&linear), "size\nfoo.rs   12\nbar.");
+}
+
+#[test]
+fn cwd_host() {
+    assert_eq!(parse_cwd_host(b"file://db2/home/me"), Some(String::from("db2")));
+    assert_eq!(parse_cwd_host(b"file:///home/me"), None);
+}
+
+#[test]
+fn mouse_modes() {
+    use crate::terminal::mouse::{Highlight, MouseEncoding, MouseTracking};
+
+    let mut emulator = TermEmulator::new(5, 20);
+    emulator.advance(b"\x1B[?1003h\x1B[?1006h\x1B[?1006$p");
+    assert_eq!(emulator.take_responses(), b"\x1B[?1006;1$y");
+    let modes = emulator.modes();
+    assert_eq!((modes.mouse_tracking, modes.mouse_encoding), (MouseTracking::AnyEvent, MouseEncoding::Sgr));
+    // Resetting the encoding goes back to X10; tracking modes replace
+    // each other
+    emulator.advance(b"\x1B[?1006l\x1B[?1003l\x1B[?1002h\x1B[?1005h");
+    let modes = emulator.modes();
+    assert_eq!((modes.mouse_tracking, modes.mouse_encoding), (MouseTracking::ButtonEvent, MouseEncoding::Utf8));
+
+    // The program answers a press under 1001 with the rows it allows
+    emulator.advance(b"\x1B[?1001h\x1B[1;3;2;1;4T");
+    let highlight = emulator.modes().highlight.unwrap();
+    assert_eq!(highlight, Highlight { start: (2, 1), first_row: 0, last_row: 3 });
+    emulator.advance(b"\x1B[0;3;2;1;4T");
+    assert_eq!(emulator.modes().highlight, None);
+}
+
+#[test]
+fn text_attributes() {
+    // Cells sharing SGR colors and attributes are spanned together
+    let grid = replay(b"\x1b[31mred\x1b[39m plain \x1b[38;5;234mdark\x1b[0m");
+    let span = |columns, color| ForegroundSpan { row: 0, columns, color: Some(color), bold: false, dim: false, reverse: false };
+    let expected = [span(0..3, CellColor::Named(1)), span(10..14, CellColor::Rgb(ansi_color(234)))];
+    assert_eq!(grid.visible_foregrounds(), expected);
+    let grid = replay(b"\x1b[1;32mok\x1b[22m \x1b[2mfaint");
+    let green = span(0..2, CellColor::Named(2));
+    let expected = [
+        ForegroundSpan { bold: true, ..green.clone() },
+        ForegroundSpan { columns: 2..3, ..green.clone() },
+        ForegroundSpan { columns: 3..8, dim: true, ..green },
+    ];
+    assert_eq!(grid.visible_foregrounds(), expected);
+
+    // SGR 7 marks cells reversed until 27; their own colors stay as set
+    let grid = replay(b"\x1b[7mon\x1b[27m \x1b[7;31;42mx");
+    let reversed = ForegroundSpan { row: 0, columns: 0..2, color: None, bold: false, dim: false, reverse: true };
+    let expected = [reversed.clone(), ForegroundSpan { columns: 3..4, color: Some(CellColor::Named(1)), ..reversed }];
+    assert_eq!(grid.visible_foregrounds(), expected);
+    assert_eq!(grid.visible_backgrounds(), [BackgroundSpan { row: 0, columns: 3..4, color: ansi_color(2) }]);
+
+    // DECSCNM reverses the whole screen
+    let mut emulator = TermEmulator::new(5, 20);
+    emulator.advance(b"\x1B[?5h\x1B[?5$p");
+    assert_eq!(emulator.take_responses(), b"\x1B[?5;1$y");
+    assert!(emulator.modes().reverse_screen);
+    emulator.advance(b"\x1B[?5l");
+    assert!(!emulator.modes().reverse_screen);
+}
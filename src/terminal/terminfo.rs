@@ -0,0 +1,107 @@
+// src/terminal/terminfo.rs
+use anyhow::{bail, Context, Result};
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+use tracing::info;
+
+/// Name of Nebula's own terminfo entry.
+pub const NAME: &str = "nebula";
+/// Used for `TERM` when Nebula's entry isn't installed.
+const FALLBACK: &str = "xterm-256color";
+
+/// Source of the entry, compiled with `tic`.
+pub const SOURCE: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/terminfo/nebula.terminfo"));
+
+/// A capability of Nebula's entry, as XTGETTCAP reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Capability {
+    /// A boolean that is set, such as `RGB`.
+    Flag,
+    /// A number or string, with escapes decoded.
+    Value(String),
+}
+
+/// Look up `name` among the capabilities Nebula adds, by terminfo or
+/// termcap name. Ones inherited from xterm-256color aren't answered.
+pub fn lookup(name: &str) -> Option<Capability> {
+    match name {
+        "TN" | "name" => return Some(Capability::Value(NAME.to_string())),
+        "Co" => return lookup("colors"),
+        _ => {}
+    }
+    own_capabilities().find_map(|capability| {
+        if capability == name {
+            return Some(Capability::Flag);
+        }
+        let value = capability.strip_prefix(name)?.strip_prefix(['=', '#'])?;
+        Some(Capability::Value(value.replace("\\E", "\x1B")))
+    })
+}
+
+/// Capabilities in `SOURCE` after the names line, leaving out `use=`.
+fn own_capabilities() -> impl Iterator<Item = &'static str> {
+    SOURCE
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .skip(1)
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|capability| !capability.is_empty() && !capability.starts_with("use="))
+}
+
+/// `TERM` for programs asking for `term`. Nebula's own entry is only used
+/// once installed, since programs can't start without one.
+pub fn resolve(term: &str) -> &str {
+    if term == NAME && !is_installed(NAME) {
+        info!("No terminfo entry for {}, using {}", NAME, FALLBACK);
+        return FALLBACK;
+    }
+    term
+}
+
+/// Whether a compiled terminfo entry for `name` exists where ncurses looks.
+pub fn is_installed(name: &str) -> bool {
+    let Some(first) = name.chars().next() else { return false };
+    search_path().iter().any(|dir| {
+        // Linux files entries under their first letter, macOS under its
+        // hex code
+        dir.join(first.to_string()).join(name).exists() || dir.join(format!("{:x}", first as u32)).join(name).exists()
+    })
+}
+
+fn search_path() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = std::env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = std::env::var_os("TERMINFO_DIRS") {
+        dirs.extend(std::env::split_paths(&list).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    dirs.extend(["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo", "/usr/lib/terminfo"].map(PathBuf::from));
+    dirs
+}
+
+/// Compile the entry into `~/.terminfo` with `tic`. Returns the directory.
+pub fn install() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").context("HOME is not set")?;
+    let dir = PathBuf::from(home).join(".terminfo");
+    let mut child = Command::new("tic")
+        .args(["-x", "-o"])
+        .arg(&dir)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to run tic; is ncurses installed?")?;
+    child.stdin.take().context("No stdin for tic")?.write_all(SOURCE.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("tic failed with {}", status);
+    }
+    Ok(dir)
+}
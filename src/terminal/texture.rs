@@ -4,12 +4,18 @@ use cosmic_text::SwashImage;
 use std::collections::HashMap;
 use wgpu::{
     BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
-    BindingResource, BindingType, Device, Extent3d, ImageCopyTexture, ImageDataLayout, Queue,
-    Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, Texture, TextureDescriptor,
-    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
-    TextureViewDescriptor, TextureViewDimension,
+    BindingResource, BindingType, Device, Extent3d, Queue, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderStages, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension,
 };
 
+use crate::terminal::notices;
+
+/// Glyphs larger than this in either dimension get their own texture rather
+/// than a slot in the atlas, where they would waste a whole row or not fit.
+const MAX_ATLAS_GLYPH_SIZE: u32 = 256;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct GlyphKey {
     pub font_id: cosmic_text::fontdb::ID,
@@ -17,36 +23,38 @@ pub struct GlyphKey {
     pub font_size: u16,
 }
 
+/// Where a rasterized glyph lives on the GPU.
+#[derive(Debug, Clone, Copy)]
+pub enum GlyphLocation {
+    /// Pixel rectangle `(x, y, w, h)` inside the atlas.
+    Atlas(u32, u32, u32, u32),
+    /// Index of a dedicated texture (see `GlyphAtlas::large_glyph`) and its size.
+    Texture(usize, u32, u32),
+}
+
 pub struct GlyphAtlas {
+    device: Device,
     texture: Texture,
-    view: TextureView,
-    sampler: Sampler,
     bind_group: BindGroup,
     bind_group_layout: BindGroupLayout,
-    cache: HashMap<GlyphKey, (u32, u32, u32, u32)>,
+    sampler: Sampler,
+    cache: HashMap<GlyphKey, GlyphLocation>,
+    /// Bind groups for glyphs too large for the atlas.
+    large_glyphs: Vec<BindGroup>,
     current_x: u32,
     current_y: u32,
     row_height: u32,
     atlas_size: u32,
+    /// Size the atlas grows back to after `shrink`.
+    max_size: u32,
+    /// Bumped whenever glyphs move, which leaves texture coordinates
+    /// computed before it pointing at the wrong texels.
+    generation: u64,
 }
 
 impl GlyphAtlas {
     pub fn new(device: &Device, atlas_size: u32) -> Self {
-        let texture = device.create_texture(&TextureDescriptor {
-            label: Some("Glyph Atlas"),
-            size: Extent3d {
-                width: atlas_size,
-                height: atlas_size,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8Unorm,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
+        let texture = create_atlas_texture(device, atlas_size);
         let view = texture.create_view(&TextureViewDescriptor::default());
         
         let sampler = device.create_sampler(&SamplerDescriptor {
@@ -81,35 +89,54 @@ impl GlyphAtlas {
             ],
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Glyph Atlas Bind Group"),
-            layout: &bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&view),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
+        let bind_group = create_bind_group(device, &bind_group_layout, &view, &sampler);
 
         GlyphAtlas {
+            device: device.clone(),
             texture,
-            view,
-            sampler,
             bind_group,
             bind_group_layout,
+            sampler,
             cache: HashMap::new(),
+            large_glyphs: Vec::new(),
             current_x: 0,
             current_y: 0,
             row_height: 0,
             atlas_size,
+            max_size: atlas_size,
+            generation: 0,
         }
     }
 
+    /// Width and height of the atlas texture in pixels.
+    pub fn size(&self) -> u32 {
+        self.atlas_size
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Drop every glyph and start again with an empty atlas of `size`,
+    /// releasing the textures of large glyphs as well. The atlas grows back
+    /// to its original size once glyphs no longer fit.
+    pub fn shrink(&mut self, size: u32) {
+        self.reset(size.min(self.max_size));
+    }
+
+    fn reset(&mut self, size: u32) {
+        self.texture = create_atlas_texture(&self.device, size);
+        let view = self.texture.create_view(&TextureViewDescriptor::default());
+        self.bind_group = create_bind_group(&self.device, &self.bind_group_layout, &view, &self.sampler);
+        self.cache.clear();
+        self.large_glyphs.clear();
+        self.current_x = 0;
+        self.current_y = 0;
+        self.row_height = 0;
+        self.atlas_size = size;
+        self.generation += 1;
+    }
+
     pub fn bind_group_layout(&self) -> &BindGroupLayout {
         &self.bind_group_layout
     }
@@ -118,14 +145,24 @@ impl GlyphAtlas {
         &self.bind_group
     }
 
+    /// Bind group sampling another texture the way glyphs are sampled.
+    pub fn bind_texture(&self, view: &TextureView) -> BindGroup {
+        create_bind_group(&self.device, &self.bind_group_layout, view, &self.sampler)
+    }
+
+    /// Bind group of a glyph stored in its own texture.
+    pub fn large_glyph(&self, index: usize) -> Option<&BindGroup> {
+        self.large_glyphs.get(index)
+    }
+
     pub fn add_glyph(
         &mut self,
         queue: &Queue,
         key: GlyphKey,
         image: &SwashImage,
-    ) -> Result<(u32, u32, u32, u32)> {
-        if let Some(rect) = self.cache.get(&key) {
-            return Ok(*rect);
+    ) -> Result<GlyphLocation> {
+        if let Some(location) = self.cache.get(&key) {
+            return Ok(*location);
         }
 
         let width = image.placement.width;
@@ -136,13 +173,26 @@ impl GlyphAtlas {
             return Err(anyhow!("Zero-sized glyph"));
         }
 
+        if width > MAX_ATLAS_GLYPH_SIZE || height > MAX_ATLAS_GLYPH_SIZE {
+            let location = self.add_large_glyph(queue, image);
+            self.cache.insert(key, location);
+            return Ok(location);
+        }
+
         if self.current_x + width > self.atlas_size {
             self.current_x = 0;
             self.current_y += self.row_height;
             self.row_height = 0;
         }
 
+        // A shrunk atlas starts over at full size. Glyphs placed before
+        // this are redrawn once the new generation is noticed
+        if self.current_y + height > self.atlas_size && self.atlas_size < self.max_size {
+            self.reset(self.max_size);
+            return self.add_glyph(queue, key, image);
+        }
         if self.current_y + height > self.atlas_size {
+            notices::warn("Glyph atlas is full, some characters can't be drawn");
             return Err(anyhow!("Glyph atlas out of space"));
         }
 
@@ -150,39 +200,110 @@ impl GlyphAtlas {
             self.row_height = height;
         }
 
-        let mut rgba_data = Vec::with_capacity((width * height * 4) as usize);
-        for &alpha in image.data.iter() {
-            rgba_data.extend_from_slice(&[255, 255, 255, alpha]);
-        }
+        let origin = wgpu::Origin3d {
+            x: self.current_x,
+            y: self.current_y,
+            z: 0,
+        };
+        write_glyph(queue, &self.texture, origin, image);
 
-        queue.write_texture(
-            ImageCopyTexture {
-                texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d {
-                    x: self.current_x,
-                    y: self.current_y,
-                    z: 0,
-                },
-                aspect: wgpu::TextureAspect::All,
-            },
-            &rgba_data,
-            ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * width),
-                rows_per_image: Some(height),
-            },
-            Extent3d {
+        let location = GlyphLocation::Atlas(self.current_x, self.current_y, width, height);
+        self.cache.insert(key, location);
+        self.current_x += width;
+
+        Ok(location)
+    }
+
+    /// Upload a glyph into a texture of its own.
+    fn add_large_glyph(&mut self, queue: &Queue, image: &SwashImage) -> GlyphLocation {
+        let (width, height) = (image.placement.width, image.placement.height);
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some("Large Glyph"),
+            size: Extent3d {
                 width,
                 height,
                 depth_or_array_layers: 1,
             },
-        );
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        write_glyph(queue, &texture, wgpu::Origin3d::ZERO, image);
 
-        let rect = (self.current_x, self.current_y, width, height);
-        self.cache.insert(key, rect);
-        self.current_x += width;
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let bind_group = create_bind_group(&self.device, &self.bind_group_layout, &view, &self.sampler);
+        self.large_glyphs.push(bind_group);
+        GlyphLocation::Texture(self.large_glyphs.len() - 1, width, height)
+    }
+}
 
-        Ok(rect)
+fn create_atlas_texture(device: &Device, size: u32) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("Glyph Atlas"),
+        size: Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+fn create_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    view: &TextureView,
+    sampler: &Sampler,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Glyph Bind Group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+/// Copy a glyph's coverage into `texture` at `origin` as white RGBA texels.
+fn write_glyph(queue: &Queue, texture: &Texture, origin: wgpu::Origin3d, image: &SwashImage) {
+    let (width, height) = (image.placement.width, image.placement.height);
+    let mut rgba_data = Vec::with_capacity((width * height * 4) as usize);
+    for &alpha in image.data.iter() {
+        rgba_data.extend_from_slice(&[255, 255, 255, alpha]);
     }
+
+    queue.write_texture(
+        TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin,
+            aspect: wgpu::TextureAspect::All,
+        },
+        &rgba_data,
+        TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
 }
\ No newline at end of file
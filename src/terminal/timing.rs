@@ -0,0 +1,55 @@
+// src/terminal/timing.rs
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::terminal::terminal::CommandTiming;
+
+/// What is shown at a prompt for the command before it, such as
+/// `1m 05s  14:03:22`.
+pub fn annotation(timing: &CommandTiming, finish_time: bool) -> String {
+    let duration = format_duration(timing.duration);
+    if finish_time {
+        format!("{}  {}", duration, clock_time(timing.finished))
+    } else {
+        duration
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0 => format!("{}ms", duration.as_millis()),
+        1..60 => format!("{:.1}s", duration.as_secs_f32()),
+        60..3600 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+/// `HH:MM:SS` of `time` in the local time zone.
+fn clock_time(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let (hour, minute, second) = local_time(seconds);
+    format!("{:02}:{:02}:{:02}", hour, minute, second)
+}
+
+#[cfg(unix)]
+fn local_time(seconds: i64) -> (i64, i64, i64) {
+    let time = seconds as libc::time_t;
+    // SAFETY: a zeroed `tm` is a valid value, and `localtime_r` only writes
+    // to the one passed in, unlike `localtime`.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return utc_time(seconds);
+    }
+    (tm.tm_hour as i64, tm.tm_min as i64, tm.tm_sec as i64)
+}
+
+// No time zone database without another dependency; shown in UTC
+#[cfg(not(unix))]
+fn local_time(seconds: i64) -> (i64, i64, i64) {
+    utc_time(seconds)
+}
+
+fn utc_time(seconds: i64) -> (i64, i64, i64) {
+    let of_day = seconds.rem_euclid(86_400);
+    (of_day / 3600, of_day / 60 % 60, of_day % 60)
+}
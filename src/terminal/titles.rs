@@ -0,0 +1,109 @@
+// src/terminal/titles.rs
+use regex::Regex;
+
+use crate::terminal::{config::TitleRule, notices};
+
+/// The config's title rules, compiled.
+#[derive(Debug, Default)]
+pub struct TitleRules {
+    rules: Vec<(Option<Regex>, TitleRule)>,
+}
+
+impl TitleRules {
+    /// Compile `rules`, skipping with a warning any that match nothing or
+    /// whose pattern is invalid.
+    pub fn new(rules: &[TitleRule]) -> Self {
+        let rules = rules
+            .iter()
+            .filter(|rule| {
+                let empty = rule.pattern.is_none() && rule.process.is_none();
+                if empty {
+                    notices::warn("Ignoring a title rule with neither a pattern nor a process");
+                }
+                !empty
+            })
+            .filter_map(|rule| match rule.pattern.as_deref().map(Regex::new).transpose() {
+                Ok(regex) => Some((regex, rule.clone())),
+                Err(e) => {
+                    notices::warn(format!("Ignoring title rule {:?}: {}", rule.pattern.as_deref().unwrap_or_default(), e));
+                    None
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Whether any rule looks at the foreground process, which costs a
+    /// lookup per title change.
+    pub fn need_process(&self) -> bool {
+        self.rules.iter().any(|(_, rule)| rule.process.is_some())
+    }
+
+    /// `title` as the first matching rule leaves it, or None if that rule
+    /// drops it. `process` is the foreground process name.
+    pub fn apply(&self, title: &str, process: Option<&str>) -> Option<String> {
+        let matching = self.rules.iter().find(|(regex, rule)| {
+            let process_matches = match &rule.process {
+                Some(name) => process.is_some_and(|process| process.eq_ignore_ascii_case(name)),
+                None => true,
+            };
+            process_matches && regex.as_ref().is_none_or(|regex| regex.is_match(title))
+        });
+        let Some((regex, rule)) = matching else {
+            return Some(title.to_string());
+        };
+        let replacement = rule.replace.as_deref()?;
+        Some(match regex {
+            Some(regex) => regex.replace_all(title, replacement).into_owned(),
+            None => replacement.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: Option<&str>, process: Option<&str>, replace: Option<&str>) -> TitleRule {
+        TitleRule {
+            pattern: pattern.map(str::to_string),
+            process: process.map(str::to_string),
+            replace: replace.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn title_rules() {
+        let rules = TitleRules::new(&[
+            rule(Some(r"^\d+% done$"), None, None),
+            rule(Some(r"^(\w+)@[\w.-]+: (.*)$"), None, Some("$1: $2")),
+            rule(None, Some("vim"), Some("editor")),
+            rule(Some("build"), Some("make"), None),
+        ]);
+        assert!(rules.need_process());
+        // Dropped titles leave the tab with the one it had
+        assert_eq!(rules.apply("42% done", None), None);
+        assert_eq!(rules.apply("ada@host.example: ~/src", None).as_deref(), Some("ada: ~/src"));
+        // Process names match without regard to case
+        assert_eq!(rules.apply("main.rs", Some("VIM")).as_deref(), Some("editor"));
+        // A rule with both needs both to match
+        assert_eq!(rules.apply("build 3/9", Some("make")), None);
+        assert_eq!(rules.apply("build 3/9", Some("cargo")).as_deref(), Some("build 3/9"));
+        assert_eq!(rules.apply("build 3/9", None).as_deref(), Some("build 3/9"));
+        // The first matching rule wins
+        assert_eq!(rules.apply("1% done", Some("vim")), None);
+    }
+
+    #[test]
+    fn invalid_title_rules() {
+        // Rules that can't match anything are skipped, keeping the rest
+        let rules = TitleRules::new(&[
+            rule(None, None, Some("x")),
+            rule(Some("(unclosed"), None, None),
+            rule(Some("b"), None, Some("c")),
+        ]);
+        assert!(!rules.need_process());
+        assert_eq!(rules.apply("abc", None).as_deref(), Some("acc"));
+        assert_eq!(TitleRules::default().apply("abc", None).as_deref(), Some("abc"));
+    }
+}
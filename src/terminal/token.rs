@@ -0,0 +1,16 @@
+// src/terminal/token.rs
+use anyhow::Result;
+
+/// A random token for viewers of a shared window to present, as hex.
+pub fn generate() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| anyhow::anyhow!("Failed to generate token: {}", e))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Compare tokens in time that doesn't depend on where they differ, so the
+/// token can't be guessed a byte at a time.
+pub fn matches(presented: &str, token: &str) -> bool {
+    let (presented, token) = (presented.as_bytes(), token.as_bytes());
+    presented.len() == token.len() && presented.iter().zip(token).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
@@ -0,0 +1,99 @@
+// src/terminal/touch.rs
+use std::time::{Duration, Instant};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{Touch, TouchPhase},
+};
+
+/// How long a finger has to stay put to start a selection.
+const LONG_PRESS: Duration = Duration::from_millis(500);
+/// How far in pixels a finger can wander and still be held still.
+const PRESS_SLOP: f64 = 12.0;
+
+pub enum TouchEvent {
+    /// Two fingers moved: scroll by `scroll` pixels, as a touchpad reports
+    /// them, and zoom by the factor the fingers spread apart.
+    Pan { scroll: f64, zoom: f64 },
+    /// The finger of a long press moved, dragging the selection end.
+    ExtendSelection(PhysicalPosition<f64>),
+    /// The finger of a long press lifted.
+    FinishSelection,
+    None,
+}
+
+/// Fingers on a touchscreen, turned into scrolling, zooming and selecting.
+#[derive(Default)]
+pub struct Touches {
+    fingers: Vec<(u64, PhysicalPosition<f64>)>,
+    /// Where and when a lone finger went down, until it moves away or is
+    /// held long enough.
+    press: Option<(PhysicalPosition<f64>, Instant)>,
+    /// A long press started a selection the finger is dragging.
+    selecting: bool,
+}
+
+impl Touches {
+    pub fn handle(&mut self, touch: &Touch) -> TouchEvent {
+        let location = touch.location;
+        match touch.phase {
+            TouchPhase::Started => {
+                self.fingers.push((touch.id, location));
+                self.press = (self.fingers.len() == 1).then(|| (location, Instant::now()));
+                TouchEvent::None
+            }
+            TouchPhase::Moved => {
+                let before = self.two_fingers();
+                let Some(finger) = self.fingers.iter_mut().find(|(id, _)| *id == touch.id) else {
+                    return TouchEvent::None;
+                };
+                finger.1 = location;
+                if self.press.is_some_and(|(start, _)| distance(start, location) > PRESS_SLOP) {
+                    self.press = None;
+                }
+                if self.selecting {
+                    return TouchEvent::ExtendSelection(location);
+                }
+                match (before, self.two_fingers()) {
+                    (Some((old_centre, old_spread)), Some((centre, spread))) => TouchEvent::Pan {
+                        scroll: centre.y - old_centre.y,
+                        zoom: if old_spread > 0.0 { spread / old_spread } else { 1.0 },
+                    },
+                    _ => TouchEvent::None,
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.fingers.retain(|(id, _)| *id != touch.id);
+                self.press = None;
+                if self.selecting && self.fingers.is_empty() {
+                    self.selecting = false;
+                    return TouchEvent::FinishSelection;
+                }
+                TouchEvent::None
+            }
+        }
+    }
+
+    /// Where a lone finger has been held still long enough to start
+    /// selecting. Checked on a timer, as nothing else happens meanwhile.
+    pub fn long_press(&mut self) -> Option<PhysicalPosition<f64>> {
+        let (location, _) = self.press.filter(|(_, at)| at.elapsed() >= LONG_PRESS)?;
+        self.press = None;
+        self.selecting = true;
+        Some(location)
+    }
+
+    /// When the finger down now becomes a long press.
+    pub fn long_press_at(&self) -> Option<Instant> {
+        self.press.map(|(_, at)| at + LONG_PRESS)
+    }
+
+    /// Centre of the first two fingers down and the distance between them.
+    fn two_fingers(&self) -> Option<(PhysicalPosition<f64>, f64)> {
+        let [(_, a), (_, b), ..] = self.fingers[..] else { return None };
+        Some((PhysicalPosition::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0), distance(a, b)))
+    }
+}
+
+fn distance(a: PhysicalPosition<f64>, b: PhysicalPosition<f64>) -> f64 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
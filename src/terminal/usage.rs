@@ -0,0 +1,256 @@
+// src/terminal/usage.rs
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::error;
+
+/// CPU and memory use of a tab's foreground process and its descendants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessUsage {
+    /// Share of one core, so a build on 8 cores can reach 800.
+    pub cpu_percent: f32,
+    /// Resident memory in bytes.
+    pub memory: u64,
+}
+
+impl ProcessUsage {
+    /// Short text for the tab bar, such as `"312% 1.4G"`.
+    pub fn badge(&self) -> String {
+        format!("{:.0}% {}", self.cpu_percent, format_bytes(self.memory))
+    }
+}
+
+/// `bytes` in binary units, such as `"1.4G"`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+/// CPU and memory use of process trees, sampled on a thread of its own so
+/// reading /proc never holds up the window. Every tab's foreground process
+/// is read in one pass per interval, and tabs look up the latest result.
+pub struct UsageMonitor {
+    shared: Arc<Mutex<Sampled>>,
+    interval: Duration,
+    /// Dropped with the monitor, which stops the thread.
+    _stop: Sender<()>,
+}
+
+/// Roots the UI asked for and the usage last sampled for them.
+#[derive(Debug, Default)]
+struct Sampled {
+    roots: Vec<i32>,
+    usage: HashMap<i32, ProcessUsage>,
+}
+
+impl UsageMonitor {
+    /// Start sampling every `interval`.
+    pub fn start(interval: Duration) -> Self {
+        let shared = Arc::new(Mutex::new(Sampled::default()));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let sampled = Arc::clone(&shared);
+        let spawned = thread::Builder::new().name("usage-sampler".into()).spawn(move || {
+            let mut sampler = UsageSampler::default();
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let roots = sampled.lock().unwrap().roots.clone();
+                let usage = sampler.sample(&roots);
+                sampled.lock().unwrap().usage = usage;
+            }
+        });
+        if let Err(e) = spawned {
+            error!("Failed to start usage sampler thread: {}", e);
+        }
+        Self { shared, interval, _stop: stop }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Sample the trees under `roots` from the next interval on.
+    pub fn watch(&self, roots: Vec<i32>) {
+        self.shared.lock().unwrap().roots = roots;
+    }
+
+    /// Latest usage of the tree under `root`. None until it has been
+    /// sampled twice, and on platforms without /proc.
+    pub fn usage(&self, root: i32) -> Option<ProcessUsage> {
+        self.shared.lock().unwrap().usage.get(&root).copied()
+    }
+}
+
+/// Turns the CPU time process trees have used into rates between samples.
+#[derive(Debug, Default)]
+struct UsageSampler {
+    /// When each root was last sampled and the clock ticks used by then.
+    last: HashMap<i32, (Instant, u64)>,
+}
+
+impl UsageSampler {
+    /// Usage of each of `roots` and its descendants since the last call,
+    /// reading /proc once for all of them. Roots seen for the first time
+    /// have no rate yet and are left out.
+    fn sample(&mut self, roots: &[i32]) -> HashMap<i32, ProcessUsage> {
+        let now = Instant::now();
+        let Some(tree) = ProcessTree::read() else {
+            return HashMap::new();
+        };
+        let previous = std::mem::take(&mut self.last);
+        let mut usage = HashMap::new();
+        for &root in roots {
+            let Some((ticks, memory)) = tree.usage(root) else { continue };
+            self.last.insert(root, (now, ticks));
+            let Some(&(last_time, last_ticks)) = previous.get(&root) else { continue };
+            let elapsed = now.duration_since(last_time).as_secs_f32();
+            if elapsed <= 0.0 {
+                continue;
+            }
+            let seconds = ticks.saturating_sub(last_ticks) as f32 / clock_ticks_per_second();
+            usage.insert(root, ProcessUsage {
+                cpu_percent: seconds / elapsed * 100.0,
+                memory,
+            });
+        }
+        usage
+    }
+}
+
+#[derive(Debug)]
+struct Stat {
+    parent: i32,
+    ticks: u64,
+    pages: u64,
+}
+
+/// Every process, read from /proc in one pass.
+#[derive(Debug, Default)]
+struct ProcessTree {
+    processes: HashMap<i32, Stat>,
+    children: HashMap<i32, Vec<i32>>,
+}
+
+impl ProcessTree {
+    #[cfg(target_os = "linux")]
+    fn read() -> Option<Self> {
+        let mut processes = HashMap::new();
+        for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<i32>().ok()) else {
+                continue;
+            };
+            // Processes can exit between listing and reading
+            let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else { continue };
+            // The command name is in parentheses and may itself contain spaces
+            // and parentheses, so fields are counted from after the last one
+            let Some((_, fields)) = stat.rsplit_once(')') else { continue };
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            let field = |index: usize| fields.get(index).and_then(|value| value.parse::<u64>().ok());
+            let (Some(parent), Some(utime), Some(stime), Some(cutime), Some(cstime), Some(pages)) =
+                (field(1), field(11), field(12), field(13), field(14), field(21))
+            else {
+                continue;
+            };
+            processes.insert(
+                pid,
+                Stat {
+                    parent: parent as i32,
+                    ticks: utime + stime + cutime + cstime,
+                    pages,
+                },
+            );
+        }
+
+        let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+        for (&pid, stat) in &processes {
+            children.entry(stat.parent).or_default().push(pid);
+        }
+        Some(Self { processes, children })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read() -> Option<Self> {
+        None
+    }
+
+    /// Clock ticks and resident bytes used by `root` and every process
+    /// below it. Ticks include children already waited for, so time spent
+    /// by short lived compiler processes isn't lost when they exit.
+    fn usage(&self, root: i32) -> Option<(u64, u64)> {
+        self.processes.get(&root)?;
+        let page_size = page_size();
+        let (mut ticks, mut memory) = (0, 0);
+        let mut pending = vec![root];
+        while let Some(pid) = pending.pop() {
+            if let Some(stat) = self.processes.get(&pid) {
+                ticks += stat.ticks;
+                memory += stat.pages * page_size;
+            }
+            pending.extend(self.children.get(&pid).into_iter().flatten());
+        }
+        Some((ticks, memory))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn page_size() -> u64 {
+    (unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).max(1) as u64
+}
+
+#[cfg(not(target_os = "linux"))]
+fn page_size() -> u64 {
+    4096
+}
+
+#[cfg(target_os = "linux")]
+fn clock_ticks_per_second() -> f32 {
+    (unsafe { libc::sysconf(libc::_SC_CLK_TCK) }).max(1) as f32
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clock_ticks_per_second() -> f32 {
+    100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badge_text() {
+        assert_eq!(format_bytes(512 * 1024), "512K");
+        assert_eq!(format_bytes(1536 * 1024 * 1024), "1.5G");
+        let usage = ProcessUsage { cpu_percent: 312.4, memory: 3 * 1024 * 1024 };
+        assert_eq!(usage.badge(), "312% 3.0M");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn samples_process_trees() {
+        let me = std::process::id() as i32;
+        let mut sampler = UsageSampler::default();
+        // The first sample only sets the baseline for the rate
+        assert!(sampler.sample(&[me, -1]).is_empty());
+        thread::sleep(Duration::from_millis(20));
+        let usage = sampler.sample(&[me, -1]);
+        assert_eq!(usage.keys().collect::<Vec<_>>(), [&me]);
+        assert!(usage[&me].memory > 0);
+        // A root no longer asked for starts over
+        assert!(sampler.sample(&[]).is_empty());
+        assert!(sampler.sample(&[me]).is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+// src/terminal/web.rs
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    io::ErrorKind,
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use tracing::{debug, info, warn};
+use tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+    Message, WebSocket,
+};
+use winit::event_loop::EventLoopProxy;
+
+use crate::terminal::{
+    config::{CELL_WIDTH, LINE_HEIGHT},
+    notices,
+    terminal::{DecorationSpan, UnderlineStyle},
+    token, Session, UserEvent,
+};
+
+/// How long a client's thread waits for input before checking for frames
+/// to send.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// How long a client gets to finish the WebSocket handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long sending a frame may stall before the client is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Frames queued for a client before it counts as fallen behind and is
+/// dropped.
+const FRAME_BACKLOG: usize = 64;
+
+/// One row as a web client draws it: the text, then runs of cells with a
+/// background or lines over them. Field names are kept short since every
+/// changed row is sent whole.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WireRow {
+    #[serde(rename = "i")]
+    pub index: usize,
+    #[serde(rename = "t")]
+    pub text: String,
+    /// Start column, end column and `#rrggbb` color.
+    #[serde(rename = "bg", skip_serializing_if = "Vec::is_empty")]
+    pub backgrounds: Vec<(usize, usize, String)>,
+    /// Start column, end column, and `u` single, `uu` double, `~` curly,
+    /// `.` dotted, `-` dashed and `s` struck through, space separated.
+    #[serde(rename = "dec", skip_serializing_if = "Vec::is_empty")]
+    pub decorations: Vec<(usize, usize, String)>,
+}
+
+/// What changed on screen: rows whose text or styling changed, the cursor
+/// cell and the title. A client that just connected gets every row.
+#[derive(Debug, Clone, Serialize)]
+pub struct WireFrame {
+    #[serde(rename = "g")]
+    pub generation: u64,
+    #[serde(rename = "n")]
+    pub row_count: usize,
+    #[serde(rename = "rows")]
+    pub rows: Vec<WireRow>,
+    /// Column and row.
+    #[serde(rename = "c")]
+    pub cursor: (usize, usize),
+    #[serde(rename = "title")]
+    pub title: String,
+}
+
+/// Rows of `session` as web clients see them. Private tabs are blank.
+pub fn wire_rows(session: &Session) -> Vec<WireRow> {
+    let private = session.is_private();
+    let mut rows: Vec<WireRow> = session
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(index, text)| WireRow {
+            index,
+            text: if private { String::new() } else { text.trim_end().to_string() },
+            backgrounds: Vec::new(),
+            decorations: Vec::new(),
+        })
+        .collect();
+    if private {
+        return rows;
+    }
+    for span in &session.backgrounds {
+        if let Some(row) = rows.get_mut(span.row) {
+            row.backgrounds.push((span.columns.start, span.columns.end, span.color.to_hex()));
+        }
+    }
+    for span in &session.decorations {
+        if let Some(row) = rows.get_mut(span.row) {
+            row.decorations.push((span.columns.start, span.columns.end, decoration_code(span)));
+        }
+    }
+    rows
+}
+
+fn decoration_code(span: &DecorationSpan) -> String {
+    let underline = span.underline.map(|style| match style {
+        UnderlineStyle::Single => "u",
+        UnderlineStyle::Double => "uu",
+        UnderlineStyle::Curly => "~",
+        UnderlineStyle::Dotted => ".",
+        UnderlineStyle::Dashed => "-",
+    });
+    let strikethrough = span.strikethrough.then_some("s");
+    underline.into_iter().chain(strikethrough).collect::<Vec<_>>().join(" ")
+}
+
+struct Client {
+    sender: SyncSender<Arc<str>>,
+    /// Has had every row, so only changes are sent.
+    synced: bool,
+}
+
+/// Serves the active tab over WebSocket to clients presenting the token,
+/// such as `web/viewer.html`. With input allowed, text they send is typed
+/// into the tab.
+pub struct WebServer {
+    clients: Arc<Mutex<Vec<Client>>>,
+    input: Receiver<String>,
+    session: Option<u64>,
+    generation: Option<u64>,
+    rows: Vec<WireRow>,
+}
+
+impl WebServer {
+    pub fn start(address: &str, token: String, allow_input: bool, proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        let listener = TcpListener::bind(address).with_context(|| format!("Failed to listen on {}", address))?;
+        info!("Serving WebSocket clients on {}", listener.local_addr()?);
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = Arc::clone(&clients);
+        let (input_sender, input) = mpsc::channel();
+        let token: Arc<str> = token.into();
+        thread::Builder::new().name("web-listener".into()).spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Failed to accept WebSocket client: {}", e);
+                        continue;
+                    }
+                };
+                let clients = Arc::clone(&accepted);
+                let token = Arc::clone(&token);
+                let input = allow_input.then(|| (input_sender.clone(), proxy.clone()));
+                thread::spawn(move || serve(stream, &token, &clients, input));
+            }
+        })?;
+        Ok(Self { clients, input, session: None, generation: None, rows: Vec::new() })
+    }
+
+    /// Text typed by clients since the last call, for the active tab.
+    pub fn take_input(&self) -> Vec<String> {
+        self.input.try_iter().collect()
+    }
+
+    /// Send clients what changed in `session` since the last call.
+    pub fn publish(&mut self, session: &Session) {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.is_empty() {
+            return;
+        }
+        let generation = session.snapshot_generation;
+        let switched = self.session != Some(session.id);
+        let joined = clients.iter().any(|client| !client.synced);
+        if !switched && !joined && self.generation == Some(generation) {
+            return;
+        }
+
+        let rows = wire_rows(session);
+        let cursor = if session.is_private() {
+            (0, 0)
+        } else {
+            ((session.cursor.0 / CELL_WIDTH) as usize, (session.cursor.1 / LINE_HEIGHT) as usize)
+        };
+        let frame = |rows: Vec<WireRow>| WireFrame {
+            generation,
+            row_count: session.rows.len(),
+            rows,
+            cursor,
+            title: session.window_title(),
+        };
+        let full = frame(rows.clone());
+        let changed = if switched {
+            full.clone()
+        } else {
+            frame(rows.iter().filter(|row| self.rows.get(row.index) != Some(*row)).cloned().collect())
+        };
+        let (Ok(full), Ok(changed)) = (serde_json::to_string(&full), serde_json::to_string(&changed)) else {
+            return;
+        };
+        let (full, changed): (Arc<str>, Arc<str>) = (full.into(), changed.into());
+
+        clients.retain_mut(|client| {
+            let message = if client.synced { Arc::clone(&changed) } else { Arc::clone(&full) };
+            client.synced = true;
+            // A client too slow to keep up is dropped rather than queued for
+            // without bound.
+            client.sender.try_send(message).is_ok()
+        });
+        self.session = Some(session.id);
+        self.generation = Some(generation);
+        self.rows = rows;
+    }
+}
+
+/// Complete the handshake, checking the `token` query parameter, then pass
+/// frames to the client and its input back until it disconnects.
+// The handshake callback's error type is fixed by tungstenite
+#[allow(clippy::result_large_err)]
+fn serve(
+    stream: TcpStream,
+    token: &str,
+    clients: &Mutex<Vec<Client>>,
+    input: Option<(Sender<String>, EventLoopProxy<UserEvent>)>,
+) {
+    let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+    let check_token = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        let query = request.uri().query().unwrap_or_default();
+        let presented = query.split('&').find_map(|pair| pair.strip_prefix("token="));
+        if presented.is_some_and(|presented| token::matches(presented, token)) {
+            Ok(response)
+        } else {
+            let mut refusal = ErrorResponse::new(Some(String::from("wrong token")));
+            *refusal.status_mut() = StatusCode::UNAUTHORIZED;
+            Err(refusal)
+        }
+    };
+    // A client that never finishes the handshake doesn't hold the thread
+    // forever.
+    let timeouts = stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)));
+    if let Err(e) = timeouts {
+        warn!("Failed to set WebSocket client timeouts: {}", e);
+        return;
+    }
+    let mut socket = match tungstenite::accept_hdr(stream, check_token) {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("Refused WebSocket client {}: {}", peer, e);
+            return;
+        }
+    };
+    if let Err(e) = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)) {
+        warn!("Failed to set WebSocket read timeout: {}", e);
+        return;
+    }
+    notices::warn(format!("{} is viewing this window in a browser", peer));
+
+    let (sender, frames) = mpsc::sync_channel(FRAME_BACKLOG);
+    clients.lock().unwrap().push(Client { sender, synced: false });
+    if let Err(e) = pump(&mut socket, &frames, input) {
+        debug!("WebSocket client {} went away: {}", peer, e);
+    }
+}
+
+fn pump(
+    socket: &mut WebSocket<TcpStream>,
+    frames: &Receiver<Arc<str>>,
+    input: Option<(Sender<String>, EventLoopProxy<UserEvent>)>,
+) -> Result<()> {
+    loop {
+        loop {
+            match frames.try_recv() {
+                Ok(frame) => socket.send(Message::text(frame.as_ref()))?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                if let Some((sender, proxy)) = &input {
+                    let _ = sender.send(text.to_string());
+                    let _ = proxy.send_event(UserEvent::Output);
+                }
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => (),
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
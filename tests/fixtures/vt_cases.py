@@ -0,0 +1,107 @@
+"""Writes the escape sequence fixtures (cursor, erase, scroll_region, sgr)
+as NAME.bin in the current directory. See README.md."""
+E='\x1b'
+def csi(s): return E+'['+s
+def cup(r,c): return csi(f'{r};{c}H')
+out={}
+
+# vttest 1: cursor movements, drawing a border with CUP, CUF/CUB, IND, RI and NEL
+s=E+'#8'                       # DECALN fills the screen with E
+s+=cup(9,10)+csi('1J')         # erase above
+s+=cup(18,60)+csi('0J')+csi('1K')
+s+=cup(9,71)+csi('0K')
+for r in range(10,17):
+    s+=cup(r,10)+csi('1K')+cup(r,71)+csi('0K')
+s+=cup(17,30)+csi('2K')
+for c in range(1,81):
+    s+=cup(24,c)+'*'+cup(1,c)+'*'
+s+=cup(2,2)
+for r in range(2,24):
+    s+='+'+csi('1D')+E+'D'     # IND
+s+=cup(23,79)
+for r in range(23,1,-1):
+    s+='+'+csi('1D')+E+'M'     # RI
+s+=cup(2,1)
+for r in range(2,24):
+    s+='*'+cup(r,80)+'*'+csi('10D')+E+'E'   # NEL
+s+=cup(2,10)+csi('42D')+csi('2C')
+for c in range(3,79):
+    s+='+'+csi('0C')+csi('2D')+csi('1C')
+s+=cup(23,70)+csi('42C')+csi('2D')
+for c in range(78,2,-1):
+    s+='+'+csi('1D')+csi('1C')+csi('0D')+'\x08'
+s+=cup(1,1)+csi('10A')+csi('1A')+csi('0A')+csi('24B')+csi('1B')+csi('0B')
+s+=cup(10,12)
+for r in range(10,16):
+    s+=' '*58+csi('1B')+csi('58D')
+s+=cup(12,14)+'The screen should be cleared,  and have an unbroken bor-'
+s+=cup(13,14)+'der of *\'s and +\'s around the edge,   and exactly in the'
+s+=cup(14,14)+'middle  there should be a frame of E\'s around this  text'
+s+=cup(15,14)+'with  one (1) free position around it.'
+out['cursor']=s
+
+# esctest-style erase and character editing: ED, EL, ECH, DCH, ICH, IL, DL
+s=E+'#8'+cup(3,6)+csi('1J')       # ED 1 on a screen of E's, through the cursor
+for r in range(4,16):
+    s+=cup(r,1)+f'{r:02d} abcdefghijklmnopqrstuvwxyz'+csi('K')
+s+=cup(4,10)+csi('K')            # EL 0
+s+=cup(5,10)+csi('1K')           # EL 1
+s+=cup(6,10)+csi('2K')           # EL 2
+s+=cup(7,5)+csi('3X')            # ECH
+s+=cup(8,5)+csi('3P')            # DCH
+s+=cup(9,5)+csi('3@')+'XYZ'      # ICH
+s+=cup(10,1)+csi('2L')           # IL pushes the rows below down
+s+=cup(13,1)+csi('1M')           # DL
+s+=cup(14,3)+csi('100P')         # DCH past the margin
+s+=cup(15,78)+csi('5@')+'Q'      # ICH at the right edge
+s+=cup(16,1)+csi('100X')         # ECH past the margin
+s+=cup(20,1)+'twenty'+cup(20,3)+csi('J')   # ED 0
+s+=cup(18,40)
+out['erase']=s
+
+# Scroll regions: DECSTBM, LF and RI at the margins, SU/SD, IL/DL inside the
+# region and DECOM addressing
+s=cup(1,1)+csi('2J')
+for r in range(1,25):
+    s+=cup(r,1)+f'row {r}'
+s+=csi('5;10r')                  # homes the cursor
+s+=cup(10,1)+'\n\nbottom'        # scrolls rows 5-10 twice
+s+=cup(5,1)+E+'M'+'top'          # RI at the top margin scrolls down
+s+=csi('2S')+csi('1T')           # SU then SD within the region
+s+=cup(7,1)+csi('L')+'inserted'  # IL inside
+s+=cup(6,1)+csi('M')             # DL inside
+s+=csi('?6h')+cup(1,20)+'origin' # DECOM: row 1 is the top margin
+s+=cup(20,1)+'clamped'           # clamped to the bottom margin
+s+=csi('?6l')+csi('r')           # reset both
+s+=cup(24,1)+'\nlast'            # LF at the bottom scrolls the whole screen
+s+=cup(12,1)+csi('2;3r')+cup(12,1)+'\n\nbelow'+csi('K')   # LF below the region moves down, no scroll
+s+=csi('r')+cup(22,5)
+out['scroll_region']=s
+
+# SGR: attributes and colors in every syntax, including malformed and
+# unknown parameters, must never print
+s=cup(1,1)+csi('2J')
+lines=[
+ ('1;2;3;4;5;7;8;9','attributes on'),
+ ('22;23;24;25;27;28;29','attributes off'),
+ ('31;42','ansi colors'),
+ ('38;5;196;48;5;21','256 colors'),
+ ('38;2;10;20;30;48;2;40;50;60','truecolor semicolons'),
+ ('38:2::10:20:30;48:2::40:50:60','truecolor colons'),
+ ('38:5:196','256 colors colons'),
+ ('4:3;58:2::255:0:0','curly red underline'),
+ ('999;1000','unknown parameters'),
+ ('38;5','truncated 256'),
+ ('38;2;1','truncated truecolor'),
+ (';;;1;;','empty parameters'),
+ ('1;'+';'.join(['0']*40),'many parameters'),
+ ('90;101','bright colors'),
+ ('','reset'),
+]
+for i,(p,text) in enumerate(lines):
+    s+=cup(i+1,1)+csi(p+'m')+text+csi('0m')
+s+=cup(16,1)+csi('7m')+'reversed'+csi('27m')+' normal'+csi('m')
+out['sgr']=s
+
+for name,s in out.items():
+    open(f'{name}.bin','wb').write(s.encode('latin-1'))